@@ -0,0 +1,265 @@
+//! Hot paths on generated documents: `cargo bench --bench conversion`
+//!
+//! The fixtures are built in memory so no binaries have to be checked in,
+//! each stands for a kind of document that stresses a different stage:
+//! long running text (XML parsing, wrapping, PDF writing), many images
+//! (decoding and re-encoding) and tables (deeply nested XML).
+
+use std::hint::black_box;
+use std::io::{Cursor, Write};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::{ImageFormat, RgbImage};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use docx2pdf::{convert_document, convert_to_vec, read_document, Options};
+
+const NAMESPACES: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:pic="http://schemas.openxmlformats.org/drawingml/2006/picture""#;
+
+const LOREM: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+    eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, \
+    quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. ";
+
+struct Fixture {
+    name: &'static str,
+    docx: Vec<u8>,
+}
+
+fn paragraph(text: &str, font: Option<&str>) -> String {
+    let props = font
+        .map(|font| format!(r#"<w:rPr><w:rFonts w:ascii="{}"/><w:b/></w:rPr>"#, font))
+        .unwrap_or_default();
+    format!("<w:p><w:r>{}<w:t>{}</w:t></w:r></w:p>", props, text)
+}
+
+fn image_paragraph(rid: &str) -> String {
+    format!(
+        r#"<w:p><w:r><w:drawing><wp:inline><wp:extent cx="5486400" cy="4114800"/><a:graphic><a:graphicData><pic:pic><pic:blipFill><a:blip r:embed="{}"/></pic:blipFill></pic:pic></a:graphicData></a:graphic></wp:inline></w:drawing></w:r></w:p>"#,
+        rid
+    )
+}
+
+/// A DOCX with `body` and the media listed as (entry name, relationship id, bytes)
+fn docx(body: &str, media: &[(String, String, Vec<u8>)]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default();
+    let mut add = |name: &str, data: &[u8]| {
+        zip.start_file(name, options).unwrap();
+        zip.write_all(data).unwrap();
+    };
+    add(
+        "[Content_Types].xml",
+        br#"<?xml version="1.0" encoding="UTF-8"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Default Extension="png" ContentType="image/png"/><Default Extension="jpeg" ContentType="image/jpeg"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/></Types>"#,
+    );
+    add(
+        "_rels/.rels",
+        br#"<?xml version="1.0" encoding="UTF-8"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#,
+    );
+    let mut rels = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    );
+    for (name, rid, _) in media {
+        rels.push_str(&format!(
+            r#"<Relationship Id="{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="{}"/>"#,
+            rid,
+            name.trim_start_matches("word/")
+        ));
+    }
+    rels.push_str("</Relationships>");
+    add("word/_rels/document.xml.rels", rels.as_bytes());
+    for (name, _, data) in media {
+        add(name, data);
+    }
+    let document = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><w:document {}><w:body>{}<w:sectPr><w:pgSz w:w="12240" w:h="15840"/></w:sectPr></w:body></w:document>"#,
+        NAMESPACES, body
+    );
+    add("word/document.xml", document.as_bytes());
+    zip.finish().unwrap().into_inner()
+}
+
+/// Some 2000 paragraphs of running text in a few fonts, a few hundred pages
+fn text_heavy() -> Fixture {
+    let fonts = [None, Some("Times New Roman"), Some("Courier New")];
+    let body: String = (0..2000)
+        .map(|i| paragraph(&LOREM.repeat(1 + i % 4), fonts[i % fonts.len()]))
+        .collect();
+    Fixture {
+        name: "text_heavy",
+        docx: docx(&body, &[]),
+    }
+}
+
+/// A gradient with some noise so the encoders have work to do
+fn test_image(width: u32, height: u32, seed: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        let noise = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729) ^ seed) % 32;
+        image::Rgb([
+            (x * 255 / width) as u8,
+            (y * 255 / height) as u8,
+            ((x + y + noise) % 256) as u8,
+        ])
+    })
+}
+
+fn encode(image: &RgbImage, format: ImageFormat) -> Vec<u8> {
+    let mut data = Cursor::new(Vec::new());
+    image.write_to(&mut data, format).unwrap();
+    data.into_inner()
+}
+
+/// 24 PNGs that get re-encoded and 8 large JPEGs, between short captions
+fn image_heavy() -> Fixture {
+    let mut media = Vec::new();
+    let mut body = String::new();
+    for i in 0..32u32 {
+        let (name, data) = if i % 4 == 3 {
+            let image = test_image(2400, 1800, i);
+            (
+                format!("word/media/image{}.jpeg", i),
+                encode(&image, ImageFormat::Jpeg),
+            )
+        } else {
+            let image = test_image(800, 600, i);
+            (
+                format!("word/media/image{}.png", i),
+                encode(&image, ImageFormat::Png),
+            )
+        };
+        let rid = format!("rId{}", i + 10);
+        body.push_str(&paragraph(&format!("Figure {}", i + 1), None));
+        body.push_str(&image_paragraph(&rid));
+        media.push((name, rid, data));
+    }
+    Fixture {
+        name: "image_heavy",
+        docx: docx(&body, &media),
+    }
+}
+
+/// 40 tables of 30 rows by 5 cells, each cell a paragraph in nested table markup
+fn table_heavy() -> Fixture {
+    let cell = |text: &str| {
+        format!(
+            r#"<w:tc><w:tcPr><w:tcW w:w="1800" w:type="dxa"/></w:tcPr>{}</w:tc>"#,
+            paragraph(text, None)
+        )
+    };
+    let mut body = String::new();
+    for table in 0..40 {
+        body.push_str(&paragraph(&format!("Table {}", table + 1), Some("Arial")));
+        body.push_str(r#"<w:tbl><w:tblPr><w:tblW w:w="9000" w:type="dxa"/></w:tblPr>"#);
+        for row in 0..30 {
+            body.push_str("<w:tr>");
+            for column in 0..5 {
+                body.push_str(&cell(&format!(
+                    "row {} column {} {}",
+                    row,
+                    column,
+                    &LOREM[..40]
+                )));
+            }
+            body.push_str("</w:tr>");
+        }
+        body.push_str("</w:tbl>");
+    }
+    Fixture {
+        name: "table_heavy",
+        docx: docx(&body, &[]),
+    }
+}
+
+fn fixtures() -> Vec<Fixture> {
+    vec![text_heavy(), image_heavy(), table_heavy()]
+}
+
+/// word/document.xml into the document model
+fn parsing(c: &mut Criterion, fixtures: &[Fixture]) {
+    let mut group = c.benchmark_group("parse");
+    for fixture in fixtures {
+        group.throughput(Throughput::Bytes(fixture.docx.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(fixture.name),
+            &fixture.docx,
+            |b, docx| b.iter(|| read_document(Cursor::new(black_box(docx))).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+/// Decoding and re-encoding the media, the rest of the conversion is small next to it
+fn image_preprocessing(c: &mut Criterion, fixtures: &[Fixture]) {
+    let mut group = c.benchmark_group("images");
+    group.sample_size(10);
+    let fixture = fixtures.iter().find(|f| f.name == "image_heavy").unwrap();
+    let settings = [
+        ("reencode", Options::default()),
+        ("downsample_150dpi", Options::default().image_dpi(150)),
+        ("dropped", Options::default().drop_images(true)),
+    ];
+    for (name, options) in &settings {
+        group.bench_with_input(BenchmarkId::from_parameter(name), options, |b, options| {
+            b.iter(|| convert_to_vec(Cursor::new(&fixture.docx), options).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Layout and the PDF objects from an already parsed document
+fn pdf_writing(c: &mut Criterion, fixtures: &[Fixture]) {
+    let mut group = c.benchmark_group("write");
+    group.sample_size(20);
+    let plain = Options::default().drop_images(true).compress(false);
+    let compressed = Options::default().drop_images(true);
+    for fixture in fixtures {
+        let document = read_document(Cursor::new(&fixture.docx)).unwrap();
+        for (variant, options) in [("plain", &plain), ("compressed", &compressed)] {
+            group.bench_with_input(
+                BenchmarkId::new(fixture.name, variant),
+                &document,
+                |b, document| {
+                    b.iter(|| {
+                        let mut output = Cursor::new(Vec::new());
+                        convert_document(
+                            document,
+                            Cursor::new(&fixture.docx),
+                            &mut output,
+                            options,
+                        )
+                        .unwrap();
+                        output.into_inner()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+/// Everything from the DOCX bytes to the PDF bytes
+fn end_to_end(c: &mut Criterion, fixtures: &[Fixture]) {
+    let mut group = c.benchmark_group("convert");
+    group.sample_size(10);
+    let options = Options::default();
+    for fixture in fixtures {
+        group.throughput(Throughput::Bytes(fixture.docx.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(fixture.name),
+            &fixture.docx,
+            |b, docx| b.iter(|| convert_to_vec(Cursor::new(black_box(docx)), &options).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    let fixtures = fixtures();
+    parsing(c, &fixtures);
+    image_preprocessing(c, &fixtures);
+    pdf_writing(c, &fixtures);
+    end_to_end(c, &fixtures);
+}
+
+criterion_group!(conversion, benches);
+criterion_main!(conversion);
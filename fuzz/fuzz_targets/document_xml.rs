@@ -0,0 +1,11 @@
+//! Arbitrary bytes as word/document.xml, past the ZIP checks
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(sections) = docx2pdf::fuzzing::parse_document_xml(data) {
+        // a body without any w:sectPr still makes one section
+        assert!(sections > 0);
+    }
+});
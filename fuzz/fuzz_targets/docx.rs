@@ -0,0 +1,11 @@
+//! Whole files through the conversion, mostly stopped early by the ZIP reader
+#![no_main]
+
+use std::io::Cursor;
+
+use docx2pdf::{check, Options};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = check(Cursor::new(data), &Options::default());
+});
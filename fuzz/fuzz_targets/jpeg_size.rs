@@ -0,0 +1,11 @@
+//! The JPEG frame header reader deciding what is passed through untouched
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((width, height, components)) = docx2pdf::fuzzing::parse_jpeg_size(data) {
+        assert!(width > 0 && height > 0);
+        assert!(matches!(components, 1 | 3));
+    }
+});
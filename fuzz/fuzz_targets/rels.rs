@@ -0,0 +1,8 @@
+//! Arbitrary bytes as word/_rels/document.xml.rels
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = docx2pdf::fuzzing::build_rel_map(data);
+});
@@ -0,0 +1,8 @@
+//! Arbitrary bytes as word/styles.xml
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = docx2pdf::fuzzing::read_styles(data);
+});
@@ -0,0 +1,184 @@
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::PathBuf;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyUserWarning, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyDict};
+
+use docx2pdf::{
+    convert_to_vec, ConvertError, FontDirectory, Options, PageRanges, PdfVersion, PrintProfile,
+    Report, Zoom,
+};
+
+create_exception!(
+    docx2pdf,
+    ConversionError,
+    PyException,
+    "The DOCX could not be converted"
+);
+create_exception!(
+    docx2pdf,
+    ConversionWarning,
+    PyUserWarning,
+    "Content that was left out or rendered differently"
+);
+
+/// Converts a DOCX, given as a path or its bytes, and returns the PDF bytes
+///
+/// The GIL is released while converting.
+#[pyfunction]
+#[pyo3(signature = (source, **options))]
+fn convert<'py>(
+    py: Python<'py>,
+    source: &Bound<'py, PyAny>,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let opts = match options {
+        Some(options) => read_options(options)?,
+        None => Options::default(),
+    };
+    let converted = if let Ok(bytes) = source.downcast::<PyBytes>() {
+        let data = bytes.as_bytes();
+        py.allow_threads(|| convert_to_vec(Cursor::new(data), &opts))
+    } else if let Ok(bytes) = source.downcast::<PyByteArray>() {
+        let data = bytes.to_vec();
+        py.allow_threads(|| convert_to_vec(Cursor::new(data), &opts))
+    } else {
+        let path: PathBuf = source
+            .extract()
+            .map_err(|_| PyTypeError::new_err("source must be a path, bytes or bytearray"))?;
+        py.allow_threads(|| {
+            let input = BufReader::new(File::open(path)?);
+            convert_to_vec(input, &opts)
+        })
+    };
+    let (pdf, report) = converted.map_err(to_py_err)?;
+    warn(py, &report)?;
+    Ok(PyBytes::new(py, &pdf))
+}
+
+/// Builds the options from the keyword arguments, unknown ones are a TypeError
+fn read_options(options: &Bound<PyDict>) -> PyResult<Options> {
+    let mut opts = Options::default();
+    for (key, value) in options {
+        let key: String = key.extract()?;
+        match key.as_str() {
+            "input_password" => opts.input_password = value.extract()?,
+            "user_password" => opts.user_password = value.extract()?,
+            "owner_password" => opts.owner_password = value.extract()?,
+            "protect_no_copy" => opts.protect_no_copy = value.extract()?,
+            "protect_no_print" => opts.protect_no_print = value.extract()?,
+            "ignore_protection" => opts.ignore_protection = value.extract()?,
+            "linearize" => opts.linearize = value.extract()?,
+            "compress" => opts.compress = value.extract()?,
+            "deduplicate" => opts.deduplicate = value.extract()?,
+            "deterministic" => opts.deterministic = value.extract()?,
+            "strict_xml" => opts.strict_xml = value.extract()?,
+            "max_entry_bytes" => opts.limits.max_entry_bytes = value.extract()?,
+            "max_total_bytes" => opts.limits.max_total_bytes = value.extract()?,
+            "max_entries" => opts.limits.max_entries = value.extract()?,
+            "max_image_pixels" => opts.limits.max_image_pixels = value.extract()?,
+            "attach_source" => opts.attach_source = value.extract()?,
+            "signature_field" => opts.signature_field = value.extract()?,
+            "pdf_version" => {
+                let version: String = value.extract()?;
+                opts.pdf_version = PdfVersion::parse(&version)
+                    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            }
+            "pdf_x" => {
+                let pdf_x: bool = value.extract()?;
+                opts.print = pdf_x.then(|| opts.print.take().unwrap_or_default());
+            }
+            "bleed" => opts.print.get_or_insert_with(PrintProfile::new).bleed = value.extract()?,
+            "rotate" => opts.rotate = value.extract()?,
+            "pages" => {
+                let pages: String = value.extract()?;
+                let pages = PageRanges::parse(&pages)
+                    .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?;
+                opts = opts.pages(pages);
+            }
+            "page_size" => {
+                let (width, height) = value.extract()?;
+                opts = opts.page_size(width, height);
+            }
+            "margin" => opts = opts.margin(value.extract()?),
+            "image_quality" => opts = opts.image_quality(value.extract()?),
+            "image_dpi" => opts.image_dpi = value.extract()?,
+            "recompress_images" => opts.recompress_images = value.extract()?,
+            "drop_images" => opts.drop_images = value.extract()?,
+            "mark_unsupported" => opts.mark_unsupported = value.extract()?,
+            "font_dir" => {
+                let dir: PathBuf = value.extract()?;
+                opts = opts.font_provider(FontDirectory::new(dir)?);
+            }
+            "title" => opts.title = value.extract()?,
+            "author" => opts.author = value.extract()?,
+            "subject" => opts.subject = value.extract()?,
+            "keywords" => opts.keywords = value.extract()?,
+            "creation_date" => opts.creation_date = value.extract()?,
+            "fit_window" => opts.viewer.fit_window = value.extract()?,
+            "hide_toolbar" => opts.viewer.hide_toolbar = value.extract()?,
+            "two_page" => opts.viewer.two_page = value.extract()?,
+            "open_page" => opts.viewer.open_page = value.extract()?,
+            "zoom" => {
+                opts.viewer.zoom = match value.extract::<String>() {
+                    Ok(zoom) if zoom == "fit" => Some(Zoom::FitPage),
+                    Ok(zoom) if zoom == "width" => Some(Zoom::FitWidth),
+                    Ok(zoom) => {
+                        return Err(PyValueError::new_err(format!(
+                            "zoom must be \"fit\", \"width\" or a percentage, not {:?}",
+                            zoom
+                        )));
+                    }
+                    Err(_) => Some(Zoom::Percent(value.extract()?)),
+                }
+            }
+            _ => {
+                return Err(PyTypeError::new_err(format!(
+                    "convert() got an unexpected keyword argument '{}'",
+                    key
+                )));
+            }
+        }
+    }
+    Ok(opts)
+}
+
+fn to_py_err(err: ConvertError) -> PyErr {
+    match err {
+        ConvertError::InvalidOptions(message) => PyValueError::new_err(message),
+        ConvertError::Io(err) => err.into(),
+        err => {
+            // the message with its causes, like the warnings
+            let mut message = err.to_string();
+            let mut source = err.source();
+            while let Some(cause) = source {
+                message.push_str(": ");
+                message.push_str(&cause.to_string());
+                source = cause.source();
+            }
+            ConversionError::new_err(message)
+        }
+    }
+}
+
+fn warn(py: Python, report: &Report) -> PyResult<()> {
+    let category = py.get_type::<ConversionWarning>();
+    for warning in &report.warnings {
+        let message = std::ffi::CString::new(warning.to_string())?;
+        PyErr::warn(py, &category, &message, 1)?;
+    }
+    Ok(())
+}
+
+#[pymodule]
+#[pyo3(name = "docx2pdf")]
+fn docx2pdf_module(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    m.add("ConversionError", m.py().get_type::<ConversionError>())?;
+    m.add("ConversionWarning", m.py().get_type::<ConversionWarning>())?;
+    Ok(())
+}
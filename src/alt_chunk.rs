@@ -0,0 +1,351 @@
+use super::document_model::{Inline, Paragraph, Run};
+use super::docx_decryption::base64_decode;
+
+/// Elements whose content is never shown
+const HIDDEN: [&str; 5] = ["head", "script", "style", "title", "xml"];
+
+/// Elements that start a new paragraph when they open or close
+const BLOCKS: [&str; 21] = [
+    "address",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// What a format chunk is written in, told apart by its content since the
+/// content type and file name are up to the producer
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkFormat {
+    Html,
+    /// a MIME message with the HTML and what it links to, as mail clients save
+    Mhtml,
+    Rtf,
+}
+
+impl ChunkFormat {
+    pub fn sniff(text: &str) -> Self {
+        let start = text.trim_start();
+        let (headers, _) = split_headers(start);
+        let multipart = header(headers, "Content-Type")
+            .is_some_and(|kind| kind.to_ascii_lowercase().starts_with("multipart/"));
+        if start.starts_with("{\\rtf") {
+            Self::Rtf
+        } else if multipart {
+            Self::Mhtml
+        } else {
+            Self::Html
+        }
+    }
+}
+
+/// The bytes of a chunk as text, Word writes UTF-16 with a byte order mark,
+/// other producers UTF-8 or a legacy code page taken as Latin-1
+pub fn decode_text(data: &[u8]) -> String {
+    let utf16 = |data: &[u8], unit: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = data.chunks_exact(2).map(|c| unit([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units)
+    };
+    match data {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        _ => match std::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => data.iter().map(|&b| b as char).collect(),
+        },
+    }
+}
+
+/// The HTML part of an MHTML message, None when it has none
+pub fn mhtml_html(message: &str) -> Option<String> {
+    let (headers, body) = split_headers(message);
+    let boundary = header_param(headers, "boundary")?;
+    let delimiter = format!("--{}", boundary);
+    for part in body.split(delimiter.as_str()).skip(1) {
+        let (headers, body) = split_headers(part.trim_start_matches(['\r', '\n']));
+        let content_type = header(headers, "Content-Type").unwrap_or_default();
+        if !content_type.to_ascii_lowercase().starts_with("text/html") {
+            continue;
+        }
+        let bytes = match header(headers, "Content-Transfer-Encoding")
+            .map(|encoding| encoding.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("quoted-printable") => decode_quoted_printable(body),
+            Some("base64") => base64_decode(body.as_bytes())?,
+            _ => body.as_bytes().to_vec(),
+        };
+        return Some(decode_text(&bytes));
+    }
+    None
+}
+
+fn split_headers(message: &str) -> (&str, &str) {
+    for separator in ["\r\n\r\n", "\n\n"] {
+        if let Some(end) = message.find(separator) {
+            return (&message[..end], &message[end + separator.len()..]);
+        }
+    }
+    (message, "")
+}
+
+/// The value of header `name`, continuation lines joined
+fn header(headers: &str, name: &str) -> Option<String> {
+    let mut value: Option<String> = None;
+    for line in headers.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some(value) = value.as_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if value.is_some() {
+            break;
+        } else if let Some((key, rest)) = line.split_once(':')
+            && key.trim().eq_ignore_ascii_case(name)
+        {
+            value = Some(rest.trim().to_string());
+        }
+    }
+    value
+}
+
+/// `param` of the Content-Type header, quotes taken off
+fn header_param(headers: &str, param: &str) -> Option<String> {
+    let content_type = header(headers, "Content-Type")?;
+    content_type.split(';').find_map(|field| {
+        let (key, value) = field.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(param)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn decode_quoted_printable(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'=' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let rest = &bytes[i + 1..];
+        if rest.starts_with(b"\r\n") {
+            i += 3; // soft line break
+        } else if rest.starts_with(b"\n") {
+            i += 2;
+        } else if let Some(byte) = rest
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(b'=');
+            i += 1;
+        }
+    }
+    out
+}
+
+/// The text of `html` as paragraphs, one per block element, with the bold
+/// and italic of `b`, `strong`, `i` and `em`; everything else about the
+/// formatting is dropped
+pub fn html_paragraphs(html: &str) -> Vec<Paragraph> {
+    let mut reader = HtmlText::default();
+    let mut rest = html;
+    while let Some(lt) = rest.find('<') {
+        reader.text(&rest[..lt]);
+        rest = &rest[lt..];
+        // `a < b` is text, a tag starts with a name, `/`, `!` or `?`
+        if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || "/!?".contains(c)) {
+            reader.text("<");
+            rest = &rest[1..];
+            continue;
+        }
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map(|end| end + 3)
+        } else {
+            rest.find('>').map(|end| end + 1)
+        };
+        let Some(end) = end else {
+            // an unterminated tag is taken as text, as browsers do
+            break;
+        };
+        reader.tag(&rest[1..end - 1]);
+        rest = &rest[end..];
+    }
+    reader.text(rest);
+    reader.end_paragraph();
+    reader.paragraphs
+}
+
+#[derive(Default)]
+struct HtmlText {
+    paragraphs: Vec<Paragraph>,
+    content: Vec<Inline>,
+    hidden: usize,
+    bold: usize,
+    italic: usize,
+}
+
+impl HtmlText {
+    fn tag(&mut self, tag: &str) {
+        if tag.starts_with(['!', '?']) {
+            return;
+        }
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == ':')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        // Word's own HTML has <o:p> and the like, those are inline
+        let name = name.as_str();
+        let self_closing = tag.ends_with('/');
+        let delta = |count: &mut usize| {
+            if closing {
+                *count = count.saturating_sub(1);
+            } else if !self_closing {
+                *count += 1;
+            }
+        };
+        if HIDDEN.contains(&name) {
+            delta(&mut self.hidden);
+        } else if matches!(name, "b" | "strong") {
+            delta(&mut self.bold);
+        } else if matches!(name, "i" | "em") {
+            delta(&mut self.italic);
+        } else if BLOCKS.contains(&name) {
+            self.end_paragraph();
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.hidden > 0 || text.is_empty() {
+            return;
+        }
+        // runs are written with a space between them, as `w:t` are
+        let text = unescape(text);
+        let run = text
+            .split(|c: char| c.is_whitespace() && c != '\u{a0}')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !run.is_empty() {
+            self.content.push(Inline::Run(Run {
+                text: run,
+                font: None,
+                bold: self.bold > 0,
+                italic: self.italic > 0,
+            }));
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        if !self.content.is_empty() {
+            let content = std::mem::take(&mut self.content);
+            self.paragraphs.push(Paragraph {
+                content,
+                ..Paragraph::default()
+            });
+        }
+    }
+}
+
+/// Replaces the character references and the common named entities
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&semi| semi <= 10)
+            .and_then(|semi| Some((entity(&rest[1..=semi])?, semi + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "agrave" => 'à',
+        "aacute" => 'á',
+        "auml" => 'ä',
+        "Auml" => 'Ä',
+        "ccedil" => 'ç',
+        "egrave" => 'è',
+        "eacute" => 'é',
+        "Eacute" => 'É',
+        "ouml" => 'ö',
+        "Ouml" => 'Ö',
+        "uuml" => 'ü',
+        "Uuml" => 'Ü',
+        "szlig" => 'ß',
+        "laquo" => '«',
+        "raquo" => '»',
+        "deg" => '°',
+        "sect" => '§',
+        "middot" => '·',
+        "times" => '×',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bull" => '•',
+        "euro" => '€',
+        _ => return None,
+    })
+}
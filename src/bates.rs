@@ -0,0 +1,85 @@
+use super::page_hooks::{PageCanvas, PageHooks};
+
+/// How far in from the edges of the page a stamp goes, in points
+pub(crate) const STAMP_INSET: f32 = 24.0;
+
+/// Where on the page a stamp goes, in from its corner or centered along
+/// its top or bottom edge
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StampPosition {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    #[default]
+    BottomRight,
+}
+
+/// Stamps every page written with the next Bates number: `prefix` and the
+/// number padded with zeros to `digits`, e.g. ACME000042
+///
+/// Numbers follow the pages of the PDF, pages left out by `Options::pages`
+/// don't take one and merged documents carry on from the one before.
+#[derive(Clone, Debug)]
+pub struct BatesNumbering {
+    pub prefix: String,
+    /// the number of the first page
+    pub start: u64,
+    pub digits: usize,
+    pub position: StampPosition,
+    pub font_size: f32,
+}
+
+impl BatesNumbering {
+    /// numbers from 1, six digits, in the bottom right corner
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            start: 1,
+            digits: 6,
+            position: StampPosition::default(),
+            font_size: 10.0,
+        }
+    }
+
+    pub fn start(mut self, start: u64) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn digits(mut self, digits: usize) -> Self {
+        self.digits = digits;
+        self
+    }
+
+    pub fn position(mut self, position: StampPosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// What the `page`th page of the PDF is stamped with, 1 based
+    pub fn number(&self, page: usize) -> String {
+        let number = self.start.saturating_add(page.saturating_sub(1) as u64);
+        format!("{}{:0digits$}", self.prefix, number, digits = self.digits)
+    }
+}
+
+impl PageHooks for BatesNumbering {
+    fn on_page_end(&self, page: &mut PageCanvas) {
+        use StampPosition::*;
+        let text = self.number(page.output_page_number());
+        let width = page.text_width(&text, self.font_size);
+        let x = match self.position {
+            TopLeft | BottomLeft => STAMP_INSET,
+            TopCenter | BottomCenter => (page.width() - width) / 2.0,
+            TopRight | BottomRight => page.width() - STAMP_INSET - width,
+        };
+        let y = match self.position {
+            TopLeft | TopCenter | TopRight => page.height() - STAMP_INSET - self.font_size,
+            BottomLeft | BottomCenter | BottomRight => STAMP_INSET,
+        };
+        page.set_fill_color(0.0, 0.0, 0.0);
+        page.text(x, y, self.font_size, &text);
+    }
+}
@@ -0,0 +1,60 @@
+use super::document_model::{Border, BorderStyle};
+use super::pdf_document::num;
+
+/// How much room a border line takes across, both lines of a double one
+/// with the gap between them
+pub fn thickness(border: &Border) -> f32 {
+    match border.style {
+        BorderStyle::Double => border.width * 3.0,
+        _ => border.width,
+    }
+}
+
+/// Content stream operators stroking `border` along the horizontal or
+/// vertical line from `from` to `to`, centered on it
+pub fn draw_border(border: &Border, from: (f32, f32), to: (f32, f32)) -> String {
+    let mut content = String::from("q ");
+    let [r, g, b] = border.color.unwrap_or([0, 0, 0]);
+    let c = |v: u8| num(v as f32 / 255.0);
+    content.push_str(&format!(
+        "{} {} {} RG {} w",
+        c(r),
+        c(g),
+        c(b),
+        num(border.width)
+    ));
+    match border.style {
+        BorderStyle::Dotted => {
+            content.push_str(&format!(" 1 J [0 {}] 0 d", num(border.width * 2.0)))
+        }
+        BorderStyle::Dashed => content.push_str(&format!(
+            " [{} {}] 0 d",
+            num(border.width * 3.0),
+            num(border.width * 3.0)
+        )),
+        _ => {}
+    }
+    let mut stroke = |(x1, y1): (f32, f32), (x2, y2): (f32, f32)| {
+        content.push_str(&format!(
+            " {} {} m {} {} l S",
+            num(x1),
+            num(y1),
+            num(x2),
+            num(y2)
+        ));
+    };
+    if border.style == BorderStyle::Double {
+        // the two lines a width apart on either side of the middle
+        let (dx, dy) = if from.1 == to.1 {
+            (0.0, border.width)
+        } else {
+            (border.width, 0.0)
+        };
+        stroke((from.0 - dx, from.1 - dy), (to.0 - dx, to.1 - dy));
+        stroke((from.0 + dx, from.1 + dy), (to.0 + dx, to.1 + dy));
+    } else {
+        stroke(from, to);
+    }
+    content.push_str(" Q");
+    content
+}
@@ -0,0 +1,34 @@
+use super::error::ConvertError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag for stopping a conversion from another thread
+///
+/// Clones share the flag, so the caller keeps one and hands a clone
+/// to the conversion through `Options::cancel`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// errors out once cancelled, checked between units of work
+    pub fn check(&self) -> Result<(), ConvertError> {
+        if self.is_cancelled() {
+            return Err(ConvertError::Cancelled);
+        }
+        Ok(())
+    }
+}
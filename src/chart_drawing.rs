@@ -0,0 +1,449 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::fmt::Write as _;
+
+use super::document_model::{Chart, ChartKind};
+use super::font_metrics::StandardFont;
+use super::pdf_document::num;
+use super::pdf_string::{string_operand, win_ansi_bytes};
+
+/// The accent colors of the default Office theme, what series without a
+/// color of their own are drawn in
+pub(crate) const PALETTE: [[u8; 3]; 6] = [
+    [0x44, 0x72, 0xC4],
+    [0xED, 0x7D, 0x31],
+    [0xA5, 0xA5, 0xA5],
+    [0xFF, 0xC0, 0x00],
+    [0x5B, 0x9B, 0xD5],
+    [0x70, 0xAD, 0x47],
+];
+
+const TITLE_SIZE: f32 = 11.0;
+const LABEL_SIZE: f32 = 8.0;
+const PADDING: f32 = 6.0;
+const GRID_GRAY: f32 = 0.85;
+
+/// Content stream operators drawing `chart` into `rect` (x, y of the bottom
+/// left corner, width, height), text is set in `font` as /F1
+pub fn draw_chart(chart: &Chart, rect: (f32, f32, f32, f32), font: StandardFont) -> String {
+    let (x, y, w, h) = rect;
+    let mut canvas = Canvas::new(font);
+    canvas.push("q");
+    let mut top = y + h - PADDING;
+    if let Some(title) = chart
+        .title
+        .as_deref()
+        .filter(|title| !title.trim().is_empty())
+    {
+        top -= TITLE_SIZE;
+        canvas.text_centered(x + w / 2.0, top, TITLE_SIZE, title.trim(), w);
+        top -= PADDING;
+    }
+
+    // pies have a legend entry per slice, the other kinds one per series
+    let legend: Vec<(String, [u8; 3])> = if chart.kind == ChartKind::Pie {
+        chart
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(i, category)| (category.clone(), PALETTE[i % PALETTE.len()]))
+            .collect()
+    } else if chart.series.len() > 1 {
+        chart
+            .series
+            .iter()
+            .enumerate()
+            .map(|(i, series)| {
+                let name = series
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("Series {}", i + 1));
+                (name, series_color(chart, i))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut bottom = y + PADDING;
+    if !legend.is_empty() {
+        canvas.legend(x + PADDING, bottom, w - 2.0 * PADDING, &legend);
+        bottom += LABEL_SIZE + PADDING;
+    }
+
+    let plot = (x + PADDING, bottom, w - 2.0 * PADDING, top - bottom);
+    if plot.2 > 0.0 && plot.3 > 0.0 {
+        match chart.kind {
+            ChartKind::Column | ChartKind::Line => canvas.columns_or_lines(chart, plot),
+            ChartKind::Bar => canvas.bars(chart, plot),
+            ChartKind::Pie => canvas.pie(chart, plot),
+        }
+    }
+    canvas.push("Q");
+    canvas.content
+}
+
+fn series_color(chart: &Chart, index: usize) -> [u8; 3] {
+    chart.series[index]
+        .color
+        .unwrap_or(PALETTE[index % PALETTE.len()])
+}
+
+/// Evenly spaced values from at most `min` to at least `max` with a round
+/// step, the value axis of bar and line charts
+fn axis_ticks(min: f64, max: f64) -> Vec<f64> {
+    let (min, max) = (min.min(0.0), max.max(0.0));
+    if max - min <= 0.0 || !(max - min).is_finite() {
+        return vec![0.0, 1.0];
+    }
+    let rough = (max - min) / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|&step| step >= rough)
+        .unwrap_or(10.0 * magnitude);
+    let first = (min / step).floor() as i64;
+    let last = (max / step).ceil() as i64;
+    (first..=last).map(|i| i as f64 * step).collect()
+}
+
+fn tick_label(value: f64) -> String {
+    // a step of 0.1 adds up to 0.30000000000000004
+    let rounded = (value * 1e6).round() / 1e6;
+    if rounded.fract() == 0.0 {
+        format!("{}", rounded as i64)
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+fn value_range(chart: &Chart) -> (f64, f64) {
+    let values = chart.series.iter().flat_map(|s| s.values.iter().flatten());
+    values.fold((0.0, 0.0), |(min, max), &v| (min.min(v), max.max(v)))
+}
+
+fn category_count(chart: &Chart) -> usize {
+    let longest = chart.series.iter().map(|s| s.values.len()).max();
+    longest.unwrap_or(0).max(chart.categories.len()).max(1)
+}
+
+/// Content stream operators being put together, text in the body font /F1
+pub(crate) struct Canvas {
+    pub content: String,
+    font: StandardFont,
+    pub text_gray: f32, // 0 for black text, 1 for white
+}
+
+impl Canvas {
+    pub fn new(font: StandardFont) -> Self {
+        Self {
+            content: String::new(),
+            font,
+            text_gray: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, operators: &str) {
+        self.content.push_str(operators);
+        self.content.push('\n');
+    }
+
+    pub fn fill_color(&mut self, [r, g, b]: [u8; 3]) {
+        let c = |v: u8| num(v as f32 / 255.0);
+        let _ = writeln!(self.content, "{} {} {} rg", c(r), c(g), c(b));
+    }
+
+    pub fn stroke_color(&mut self, [r, g, b]: [u8; 3]) {
+        let c = |v: u8| num(v as f32 / 255.0);
+        let _ = writeln!(self.content, "{} {} {} RG", c(r), c(g), c(b));
+    }
+
+    pub fn gray_stroke(&mut self, gray: f32) {
+        let _ = writeln!(self.content, "{} G", num(gray));
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) {
+        let _ = writeln!(
+            self.content,
+            "{} {} {} {} re f",
+            num(x),
+            num(y),
+            num(w),
+            num(h)
+        );
+    }
+
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        let _ = writeln!(
+            self.content,
+            "{} {} m {} {} l S",
+            num(x1),
+            num(y1),
+            num(x2),
+            num(y2)
+        );
+    }
+
+    pub fn width(&self, text: &str, size: f32) -> f32 {
+        self.font.measure(text, size)
+    }
+
+    /// `text` cut short with an ellipsis to fit `max_width`
+    pub fn fit(&self, text: &str, size: f32, max_width: f32) -> String {
+        if self.width(text, size) <= max_width {
+            return text.to_string();
+        }
+        let mut fitted: String = text.to_string();
+        while !fitted.is_empty() && self.width(&format!("{}...", fitted), size) > max_width {
+            fitted.pop();
+        }
+        if fitted.is_empty() {
+            String::new()
+        } else {
+            format!("{}...", fitted.trim_end())
+        }
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let _ = writeln!(
+            self.content,
+            "{} g BT /F1 {} Tf {} {} Td {} Tj ET",
+            num(self.text_gray),
+            num(size),
+            num(x),
+            num(y),
+            string_operand(&win_ansi_bytes(text))
+        );
+    }
+
+    pub fn text_centered(&mut self, cx: f32, y: f32, size: f32, text: &str, max_width: f32) {
+        let text = self.fit(text, size, max_width);
+        let width = self.width(&text, size);
+        self.text(cx - width / 2.0, y, size, &text);
+    }
+
+    pub fn text_right(&mut self, right: f32, y: f32, size: f32, text: &str) {
+        let width = self.width(text, size);
+        self.text(right - width, y, size, text);
+    }
+
+    /// A swatch and a name per entry in one row, as many as fit
+    fn legend(&mut self, x: f32, y: f32, width: f32, entries: &[(String, [u8; 3])]) {
+        let slot = (width / entries.len() as f32).max(40.0);
+        for (i, (name, color)) in entries.iter().enumerate() {
+            let left = x + i as f32 * slot;
+            if left + slot > x + width + 0.5 {
+                break;
+            }
+            self.fill_color(*color);
+            self.rect(left, y, LABEL_SIZE * 0.8, LABEL_SIZE * 0.8);
+            let name = self.fit(name, LABEL_SIZE, slot - LABEL_SIZE * 1.5);
+            self.text(left + LABEL_SIZE * 1.2, y, LABEL_SIZE, &name);
+        }
+    }
+
+    /// The value gridlines and their labels left of `plot`, the plot area
+    /// without the label column, and how to map a value to a height in it
+    fn value_axis(
+        &mut self,
+        chart: &Chart,
+        plot: (f32, f32, f32, f32),
+        vertical: bool,
+    ) -> ((f32, f32, f32, f32), impl Fn(f64) -> f32 + use<>) {
+        let (min, max) = value_range(chart);
+        let ticks = axis_ticks(min, max);
+        let (low, high) = (ticks[0], ticks[ticks.len() - 1]);
+        let labels: Vec<String> = ticks.iter().map(|&t| tick_label(t)).collect();
+        let (x, y, w, h) = plot;
+        let area = if vertical {
+            let label_width = labels
+                .iter()
+                .map(|label| self.width(label, LABEL_SIZE))
+                .fold(0.0, f32::max);
+            let left = x + label_width + 4.0;
+            // room for the category labels below
+            (
+                left,
+                y + LABEL_SIZE + 4.0,
+                (x + w - left).max(1.0),
+                (h - LABEL_SIZE - 4.0).max(1.0),
+            )
+        } else {
+            let longest = chart
+                .categories
+                .iter()
+                .map(|category| self.width(category, LABEL_SIZE))
+                .fold(0.0, f32::max)
+                .min(w / 3.0);
+            let left = x + longest + 4.0;
+            (
+                left,
+                y + LABEL_SIZE + 4.0,
+                (x + w - left).max(1.0),
+                (h - LABEL_SIZE - 4.0).max(1.0),
+            )
+        };
+        let (ax, ay, aw, ah) = area;
+        let length = if vertical { ah } else { aw };
+        let scale = move |value: f64| ((value - low) / (high - low)) as f32 * length;
+
+        self.push("0.5 w");
+        self.gray_stroke(GRID_GRAY);
+        for (tick, label) in ticks.iter().zip(&labels) {
+            let offset = scale(*tick);
+            if vertical {
+                self.line(ax, ay + offset, ax + aw, ay + offset);
+                self.text_right(ax - 3.0, ay + offset - LABEL_SIZE / 3.0, LABEL_SIZE, label);
+            } else {
+                self.line(ax + offset, ay, ax + offset, ay + ah);
+                let width = self.width(label, LABEL_SIZE);
+                self.text(ax + offset - width / 2.0, y, LABEL_SIZE, label);
+            }
+        }
+        (area, scale)
+    }
+
+    fn columns_or_lines(&mut self, chart: &Chart, plot: (f32, f32, f32, f32)) {
+        let ((x, y, w, _), scale) = self.value_axis(chart, plot, true);
+        let count = category_count(chart);
+        let slot = w / count as f32;
+        let zero = y + scale(0.0);
+
+        for (i, category) in chart.categories.iter().enumerate() {
+            let cx = x + (i as f32 + 0.5) * slot;
+            self.text_centered(cx, plot.1, LABEL_SIZE, category, slot - 2.0);
+        }
+
+        let series_count = chart.series.len().max(1);
+        for (s, series) in chart.series.iter().enumerate() {
+            let color = series_color(chart, s);
+            if chart.kind == ChartKind::Line {
+                self.stroke_color(color);
+                self.push("1.5 w 1 J 1 j");
+                let mut path = String::new();
+                let mut open = false;
+                for (i, value) in series.values.iter().enumerate() {
+                    let Some(value) = value else {
+                        open = false;
+                        continue;
+                    };
+                    let px = x + (i as f32 + 0.5) * slot;
+                    let py = y + scale(*value);
+                    let op = if open { "l" } else { "m" };
+                    let _ = write!(path, "{} {} {} ", num(px), num(py), op);
+                    open = true;
+                }
+                // a lone point has no segment, a dot marks it
+                path.push('S');
+                self.push(&path);
+                self.fill_color(color);
+                for (i, value) in series.values.iter().enumerate() {
+                    if let Some(value) = value {
+                        let px = x + (i as f32 + 0.5) * slot;
+                        let py = y + scale(*value);
+                        self.rect(px - 1.5, py - 1.5, 3.0, 3.0);
+                    }
+                }
+            } else {
+                // the bars of a category fill 70% of its slot side by side
+                let bar = slot * 0.7 / series_count as f32;
+                self.fill_color(color);
+                for (i, value) in series.values.iter().enumerate() {
+                    let Some(value) = value else { continue };
+                    let left = x + i as f32 * slot + slot * 0.15 + s as f32 * bar;
+                    let top = y + scale(*value);
+                    self.rect(left, zero.min(top), bar, (top - zero).abs());
+                }
+            }
+        }
+        self.push("0.75 w");
+        self.gray_stroke(0.5);
+        self.line(x, zero, x + w, zero);
+    }
+
+    fn bars(&mut self, chart: &Chart, plot: (f32, f32, f32, f32)) {
+        let ((x, y, _, h), scale) = self.value_axis(chart, plot, false);
+        let count = category_count(chart);
+        let slot = h / count as f32;
+        let zero = x + scale(0.0);
+        let label_width = x - plot.0 - 4.0;
+
+        // the first category at the top, as Word draws them with a reversed axis
+        let slot_bottom = |i: usize| y + h - (i as f32 + 1.0) * slot;
+        for (i, category) in chart.categories.iter().enumerate() {
+            let label = self.fit(category, LABEL_SIZE, label_width);
+            let cy = slot_bottom(i) + slot / 2.0 - LABEL_SIZE / 3.0;
+            self.text_right(x - 3.0, cy, LABEL_SIZE, &label);
+        }
+
+        let series_count = chart.series.len().max(1);
+        let bar = slot * 0.7 / series_count as f32;
+        for (s, series) in chart.series.iter().enumerate() {
+            self.fill_color(series_color(chart, s));
+            for (i, value) in series.values.iter().enumerate() {
+                let Some(value) = value else { continue };
+                let bottom = slot_bottom(i) + slot * 0.85 - (s as f32 + 1.0) * bar;
+                let end = x + scale(*value);
+                self.rect(zero.min(end), bottom, (end - zero).abs(), bar);
+            }
+        }
+        self.push("0.75 w");
+        self.gray_stroke(0.5);
+        self.line(zero, y, zero, y + h);
+    }
+
+    /// The slices of the first series, clockwise from twelve o'clock
+    fn pie(&mut self, chart: &Chart, plot: (f32, f32, f32, f32)) {
+        let Some(series) = chart.series.first() else {
+            return;
+        };
+        let values: Vec<f64> = series
+            .values
+            .iter()
+            .map(|v| v.unwrap_or(0.0).max(0.0))
+            .collect();
+        let total: f64 = values.iter().sum();
+        if total <= 0.0 {
+            return;
+        }
+        let (x, y, w, h) = plot;
+        let radius = w.min(h) / 2.0;
+        let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+        let mut angle = FRAC_PI_2;
+        self.push("1 G 0.75 w 1 j");
+        for (i, value) in values.iter().enumerate() {
+            let sweep = (*value / total) as f32 * TAU;
+            if sweep <= 0.0 {
+                continue;
+            }
+            self.fill_color(PALETTE[i % PALETTE.len()]);
+            let mut path = format!("{} {} m ", num(cx), num(cy));
+            let start = (cx + radius * angle.cos(), cy + radius * angle.sin());
+            let _ = write!(path, "{} {} l ", num(start.0), num(start.1));
+            // cubic Béziers of at most a quarter circle each
+            let segments = (sweep / FRAC_PI_2).ceil().max(1.0) as usize;
+            let step = sweep / segments as f32;
+            let k = 4.0 / 3.0 * (step / 4.0).tan() * radius;
+            for _ in 0..segments {
+                let end = angle - step;
+                let (a_cos, a_sin) = (angle.cos(), angle.sin());
+                let (e_cos, e_sin) = (end.cos(), end.sin());
+                let _ = write!(
+                    path,
+                    "{} {} {} {} {} {} c ",
+                    num(cx + radius * a_cos + k * a_sin),
+                    num(cy + radius * a_sin - k * a_cos),
+                    num(cx + radius * e_cos - k * e_sin),
+                    num(cy + radius * e_sin + k * e_cos),
+                    num(cx + radius * e_cos),
+                    num(cy + radius * e_sin)
+                );
+                angle = end;
+            }
+            path.push_str("h b");
+            self.push(&path);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::document_model::{Chart, ChartKind, ChartSeries};
+use super::report::Warning;
+use super::xml_errors::XmlErrors;
+
+/// Points past this index are left out, the cache gives the index of each
+/// and a crafted one would otherwise take any amount of memory
+const MAX_POINTS: usize = 100_000;
+
+/// The chart type elements of a plot area that can be drawn, by local name
+fn chart_kind(name: &[u8]) -> Option<ChartKind> {
+    Some(match name {
+        b"barChart" | b"bar3DChart" => ChartKind::Column,
+        b"lineChart" | b"line3DChart" | b"areaChart" | b"area3DChart" => ChartKind::Line,
+        b"pieChart" | b"pie3DChart" | b"doughnutChart" | b"ofPieChart" => ChartKind::Pie,
+        _ => return None,
+    })
+}
+
+fn attribute(e: &BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.as_ref() == key)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// Where the reader is inside a chart part
+#[derive(Default)]
+struct ChartState {
+    chart: Chart,
+    path: Vec<Vec<u8>>, // local names of the open elements
+    // depth of the chart type element whose series are read, the first one
+    // that can be drawn; combination charts lose the others
+    plot_depth: Option<usize>,
+    done: bool,
+    point: usize,                 // `idx` of the `c:pt` being read
+    categories: Vec<Vec<String>>, // of each series, the first ones that exist are used
+}
+
+impl ChartState {
+    fn inside(&self, name: &[u8]) -> bool {
+        self.path.iter().any(|open| open == name)
+    }
+
+    /// The open elements end with `names`
+    fn at(&self, names: &[&[u8]]) -> bool {
+        self.path.len() >= names.len()
+            && self.path[self.path.len() - names.len()..]
+                .iter()
+                .zip(names)
+                .all(|(open, name)| open == name)
+    }
+
+    fn in_series(&self) -> bool {
+        self.plot_depth.is_some() && self.inside(b"ser")
+    }
+
+    fn start(&mut self, e: &BytesStart, empty: bool) {
+        let name = e.local_name();
+        let name = name.as_ref();
+        if self.plot_depth.is_none()
+            && !self.done
+            && !empty
+            && self.inside(b"plotArea")
+            && let Some(kind) = chart_kind(name)
+        {
+            self.chart.kind = kind;
+            self.plot_depth = Some(self.path.len());
+        }
+        if self.plot_depth.is_some() {
+            match name {
+                b"barDir" if attribute(e, b"val").as_deref() == Some("bar") => {
+                    self.chart.kind = ChartKind::Bar
+                }
+                b"ser" if !empty => {
+                    self.chart.series.push(ChartSeries::default());
+                    self.categories.push(Vec::new());
+                }
+                b"pt" => {
+                    self.point = attribute(e, b"idx")
+                        .and_then(|idx| idx.parse().ok())
+                        .unwrap_or(0)
+                }
+                // the fill of the series, not of one of its data points
+                b"srgbClr" if self.at(&[b"ser", b"spPr", b"solidFill"]) => {
+                    if let Some(series) = self.chart.series.last_mut() {
+                        series.color = attribute(e, b"val").and_then(|hex| parse_color(&hex));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !empty {
+            self.path.push(name.to_vec());
+        }
+    }
+
+    fn end(&mut self) {
+        self.path.pop();
+        if self.plot_depth == Some(self.path.len()) {
+            self.plot_depth = None;
+            self.done = true;
+        }
+    }
+
+    fn text(&mut self, text: String) {
+        // the title of the chart, not of an axis
+        if self.at(&[b"t"]) && self.path.iter().any(|open| open == b"title") {
+            let in_chart_title = self
+                .path
+                .windows(2)
+                .any(|pair| pair[0] == b"chart" && pair[1] == b"title");
+            if in_chart_title {
+                self.chart.title.get_or_insert_default().push_str(&text);
+            }
+            return;
+        }
+        if !self.in_series() || !self.at(&[b"v"]) || self.point >= MAX_POINTS {
+            return;
+        }
+        let point = self.point;
+        let (name, category, value) =
+            (self.inside(b"tx"), self.inside(b"cat"), self.inside(b"val"));
+        let (Some(series), Some(categories)) =
+            (self.chart.series.last_mut(), self.categories.last_mut())
+        else {
+            return;
+        };
+        if name {
+            series.name = Some(text);
+        } else if category {
+            if categories.len() <= point {
+                categories.resize(point + 1, String::new());
+            }
+            categories[point] = text;
+        } else if value {
+            if series.values.len() <= point {
+                series.values.resize(point + 1, None);
+            }
+            series.values[point] = text.trim().parse().ok();
+        }
+    }
+}
+
+pub fn parse_color(hex: &str) -> Option<[u8; 3]> {
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Some([r, g, b])
+}
+
+/// Reads a chart part, e.g. `word/charts/chart1.xml`, None when its plot area
+/// has no bar, line, area or pie chart. Only the title, the categories and the
+/// values cached in the part are kept, axes, labels and styles are left to
+/// the drawing
+pub fn parse_chart(
+    xml: &str,
+    part: &str,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<Chart>> {
+    let mut reader = Reader::from_str(xml);
+    let mut state = ChartState::default();
+    let mut errors = XmlErrors::new(part, strict);
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => state.start(&e, false),
+            Ok(Event::Empty(e)) => state.start(&e, true),
+            Ok(Event::End(_)) => state.end(),
+            Ok(Event::Text(e)) => {
+                let text = errors.unescape(&e, reader.buffer_position())?;
+                state.text(text);
+            }
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+    }
+    errors.finish(warnings);
+
+    if !state.done {
+        return Ok(None);
+    }
+    let mut chart = state.chart;
+    chart.categories = state
+        .categories
+        .into_iter()
+        .find(|categories| !categories.is_empty())
+        .unwrap_or_default();
+    Ok(Some(chart))
+}
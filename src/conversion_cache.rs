@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use sha2::{Digest, Sha256};
+use tracing::{debug, trace};
+
+use super::font_metrics::{PdfFont, TrueTypeFont};
+use super::font_provider::{FontProvider, FontRequest, FontSource};
+use super::image_preprocessor::ImageSettings;
+
+/// Images re-encoded by earlier conversions are kept up to this many bytes of JPEG
+pub const DEFAULT_MAX_IMAGE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Resolved fonts and re-encoded images shared between conversions
+///
+/// A server converting many documents sets the same cache on the options of
+/// every conversion, from as many threads as it likes, so fonts are loaded and
+/// parsed once and recurring images (logos, letterheads) are decoded once.
+/// Fonts are keyed on the request and images on their bytes and settings, so a
+/// cache should only be shared between options with the same font provider
+/// and image decoders.
+#[derive(Debug)]
+pub struct ConversionCache {
+    // None when the body font is used
+    fonts: RwLock<HashMap<FontKey, Option<(PdfFont, bool)>>>,
+    images: RwLock<HashMap<ImageKey, Arc<EncodedImage>>>,
+    image_bytes: AtomicUsize,
+    max_image_bytes: usize,
+}
+
+/// A JPEG ready for a DCTDecode stream
+#[derive(Debug)]
+pub(crate) struct EncodedImage {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub source_width: u32, // before downsampling
+    pub source_height: u32,
+    pub components: u8,
+}
+
+impl Default for ConversionCache {
+    fn default() -> Self {
+        Self::with_max_image_bytes(DEFAULT_MAX_IMAGE_BYTES)
+    }
+}
+
+impl ConversionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Once the cached images add up to `max` bytes no more are added,
+    /// 0 turns image caching off
+    pub fn with_max_image_bytes(max: usize) -> Self {
+        Self {
+            fonts: RwLock::new(HashMap::new()),
+            images: RwLock::new(HashMap::new()),
+            image_bytes: AtomicUsize::new(0),
+            max_image_bytes: max,
+        }
+    }
+
+    /// Distinct font requests resolved so far
+    pub fn font_count(&self) -> usize {
+        self.fonts.read().expect("font cache poisoned").len()
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.images.read().expect("image cache poisoned").len()
+    }
+
+    /// Drops everything, e.g. after the fonts on disk changed
+    pub fn clear(&self) {
+        self.fonts.write().expect("font cache poisoned").clear();
+        self.images.write().expect("image cache poisoned").clear();
+        self.image_bytes.store(0, Ordering::Relaxed);
+    }
+
+    /// The cached result for `request` or the provider's, stored for next time
+    pub(crate) fn font(
+        &self,
+        provider: &dyn FontProvider,
+        request: &FontRequest,
+    ) -> Option<(PdfFont, bool)> {
+        let key = FontKey {
+            family: request.family.to_string(),
+            bold: request.bold,
+            italic: request.italic,
+        };
+        if let Some(resolved) = self.fonts.read().expect("font cache poisoned").get(&key) {
+            trace!(family = request.family, "font cache hit");
+            return resolved.clone();
+        }
+        // two threads may resolve the same font at once, the first one stored wins
+        // so every conversion ends up with the same Arc
+        let resolved = resolve_font_uncached(provider, request);
+        self.fonts
+            .write()
+            .expect("font cache poisoned")
+            .entry(key)
+            .or_insert(resolved)
+            .clone()
+    }
+
+    pub(crate) fn image(
+        &self,
+        data: &[u8],
+        settings: &ImageSettings,
+    ) -> (ImageKey, Option<Arc<EncodedImage>>) {
+        let key = ImageKey {
+            digest: Sha256::digest(data).into(),
+            quality: settings.quality,
+            downsample: settings.max_dpi.map(|dpi| {
+                let setup = &settings.setup;
+                (
+                    dpi,
+                    setup.content_width().to_bits(),
+                    setup.content_height().to_bits(),
+                )
+            }),
+        };
+        let image = self
+            .images
+            .read()
+            .expect("image cache poisoned")
+            .get(&key)
+            .cloned();
+        (key, image)
+    }
+
+    pub(crate) fn insert_image(&self, key: ImageKey, image: Arc<EncodedImage>) {
+        let len = image.data.len();
+        let total = self.image_bytes.fetch_add(len, Ordering::Relaxed) + len;
+        if total > self.max_image_bytes {
+            self.image_bytes.fetch_sub(len, Ordering::Relaxed);
+            return;
+        }
+        let mut images = self.images.write().expect("image cache poisoned");
+        if images.insert(key, image).is_some() {
+            // another conversion encoded the same image in the meantime
+            self.image_bytes.fetch_sub(len, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct FontKey {
+    family: String,
+    bold: bool,
+    italic: bool,
+}
+
+/// Identifies an image in the cache, from `ConversionCache::image`
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ImageKey {
+    digest: [u8; 32], // SHA-256 of the media
+    quality: u8,
+    // the dpi limit and the content area (f32 bits) it is applied to
+    downsample: Option<(u32, u32, u32)>,
+}
+
+/// Asks `provider` for the font, None when it has nothing usable
+pub(crate) fn resolve_font_uncached(
+    provider: &dyn FontProvider,
+    request: &FontRequest,
+) -> Option<(PdfFont, bool)> {
+    match provider.resolve(request)? {
+        FontSource::Standard(font) => {
+            let exact = font.base_font().eq_ignore_ascii_case(request.family);
+            Some((PdfFont::Standard(font), exact))
+        }
+        FontSource::TrueType(data) => match TrueTypeFont::parse(data) {
+            Ok(font) => Some((PdfFont::TrueType(Arc::new(font)), true)),
+            Err(e) => {
+                debug!(family = request.family, "unusable font: {:#}", e);
+                None
+            }
+        },
+    }
+}
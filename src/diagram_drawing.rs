@@ -0,0 +1,192 @@
+use std::fmt::Write as _;
+
+use super::chart_drawing::{Canvas, PALETTE};
+use super::document_model::{Diagram, DiagramNode};
+use super::font_metrics::StandardFont;
+use super::pdf_document::num;
+
+const TEXT_SIZE: f32 = 10.0;
+const CHILD_SIZE: f32 = 8.0;
+const PADDING: f32 = 5.0;
+
+/// Content stream operators drawing `diagram` into `rect` (x, y of the
+/// bottom left corner, width, height): a box per top level node in rows of
+/// up to four, its children listed inside it, arrows between the boxes of
+/// process and cycle layouts
+pub fn draw_diagram(diagram: &Diagram, rect: (f32, f32, f32, f32), font: StandardFont) -> String {
+    let (x, y, w, h) = rect;
+    let nodes: Vec<&DiagramNode> = diagram
+        .nodes
+        .iter()
+        .filter(|node| !node.text.is_empty() || !node.children.is_empty())
+        .collect();
+    let mut canvas = Canvas::new(font);
+    if nodes.is_empty() {
+        return canvas.content;
+    }
+    let columns = nodes.len().min(if diagram.connected { 5 } else { 4 });
+    let rows = nodes.len().div_ceil(columns);
+    let gap = if diagram.connected { 16.0 } else { 8.0 };
+    let box_w = (w - gap * (columns - 1) as f32) / columns as f32;
+    let box_h = (h - gap * (rows - 1) as f32) / rows as f32;
+    if box_w <= 2.0 * PADDING || box_h <= 2.0 * PADDING {
+        return canvas.content;
+    }
+
+    canvas.push("q");
+    for (i, node) in nodes.iter().enumerate() {
+        let (row, column) = (i / columns, i % columns);
+        let left = x + column as f32 * (box_w + gap);
+        let bottom = y + h - (row + 1) as f32 * box_h - row as f32 * gap;
+        canvas.fill_color(PALETTE[0]);
+        rounded_rect(&mut canvas, left, bottom, box_w, box_h, 4.0);
+        draw_text(&mut canvas, node, left, bottom, box_w, box_h);
+
+        let last_in_row = column + 1 == columns || i + 1 == nodes.len();
+        if diagram.connected && !last_in_row {
+            canvas.fill_color([0xA5, 0xA5, 0xA5]);
+            let (tip, cy) = (left + box_w + gap - 3.0, bottom + box_h / 2.0);
+            let _ = writeln!(
+                canvas.content,
+                "{} {} m {} {} l {} {} l h f",
+                num(left + box_w + 3.0),
+                num(cy + 5.0),
+                num(tip),
+                num(cy),
+                num(left + box_w + 3.0),
+                num(cy - 5.0)
+            );
+        }
+    }
+    canvas.push("Q");
+    canvas.content
+}
+
+/// The node's text centered at the top of its box in white, the text of its
+/// children below as a list, whatever doesn't fit is left out
+fn draw_text(canvas: &mut Canvas, node: &DiagramNode, x: f32, y: f32, w: f32, h: f32) {
+    canvas.text_gray = 1.0;
+    let width = w - 2.0 * PADDING;
+    let mut baseline = y + h - PADDING - TEXT_SIZE;
+    let mut lines = Vec::new();
+    if !node.text.is_empty() {
+        lines.extend(
+            wrap(canvas, &node.text, TEXT_SIZE, width)
+                .into_iter()
+                .map(|line| (line, TEXT_SIZE, true)),
+        );
+    }
+    let mut items = Vec::new();
+    list_items(&node.children, 0, &mut items);
+    for (depth, text) in items {
+        let indent = depth as f32 * CHILD_SIZE;
+        let bulleted = format!("\u{2022} {}", text);
+        for line in wrap(canvas, &bulleted, CHILD_SIZE, width - indent) {
+            lines.push((
+                format!("{}{}", " ".repeat(depth * 2), line),
+                CHILD_SIZE,
+                false,
+            ));
+        }
+    }
+    // a box with only its own text has it in the middle
+    if node.children.is_empty() {
+        let height = lines.len() as f32 * TEXT_SIZE * 1.2;
+        baseline = y + (h + height) / 2.0 - TEXT_SIZE;
+    }
+    for (line, size, centered) in lines {
+        if baseline < y + PADDING - 1.0 {
+            break;
+        }
+        if centered {
+            canvas.text_centered(x + w / 2.0, baseline, size, &line, width);
+        } else {
+            canvas.text(x + PADDING, baseline, size, &line);
+        }
+        baseline -= size * 1.2;
+    }
+    canvas.text_gray = 0.0;
+}
+
+/// The text of `nodes` and their descendants in order, with their depth
+fn list_items<'a>(nodes: &'a [DiagramNode], depth: usize, items: &mut Vec<(usize, &'a str)>) {
+    for node in nodes {
+        if !node.text.is_empty() {
+            items.push((depth, &node.text));
+        }
+        list_items(&node.children, depth + 1, items);
+    }
+}
+
+/// Breaks `text` at spaces into lines no wider than `width`, a word too long
+/// for a line is cut short
+fn wrap(canvas: &Canvas, text: &str, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if canvas.width(&candidate, size) <= width || line.is_empty() {
+            line = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+        .into_iter()
+        .map(|line| canvas.fit(&line, size, width))
+        .collect()
+}
+
+/// A filled rectangle with corners rounded by cubic Béziers
+fn rounded_rect(canvas: &mut Canvas, x: f32, y: f32, w: f32, h: f32, radius: f32) {
+    let r = radius.min(w / 2.0).min(h / 2.0);
+    // control points 0.5523 of the radius along from the ends approximate a
+    // quarter circle, so 0.4477 of it from the corner
+    let k = r * 0.4477;
+    let _ = writeln!(
+        canvas.content,
+        "{} {} m {} {} l {} {} {} {} {} {} c {} {} l {} {} {} {} {} {} c \
+         {} {} l {} {} {} {} {} {} c {} {} l {} {} {} {} {} {} c h f",
+        num(x + r),
+        num(y),
+        num(x + w - r),
+        num(y),
+        num(x + w - k),
+        num(y),
+        num(x + w),
+        num(y + k),
+        num(x + w),
+        num(y + r),
+        num(x + w),
+        num(y + h - r),
+        num(x + w),
+        num(y + h - k),
+        num(x + w - k),
+        num(y + h),
+        num(x + w - r),
+        num(y + h),
+        num(x + r),
+        num(y + h),
+        num(x + k),
+        num(y + h),
+        num(x),
+        num(y + h - k),
+        num(x),
+        num(y + h - r),
+        num(x),
+        num(y + r),
+        num(x),
+        num(y + k),
+        num(x + k),
+        num(y),
+        num(x + r),
+        num(y)
+    );
+}
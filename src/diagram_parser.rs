@@ -0,0 +1,185 @@
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+
+use super::document_model::DiagramNode;
+use super::report::Warning;
+use super::xml_errors::XmlErrors;
+
+/// Nesting past this depth is flattened, SmartArt itself stops far earlier
+const MAX_DEPTH: usize = 16;
+
+/// Words in the `uniqueId` of the layouts that connect their boxes, e.g.
+/// `urn:microsoft.com/office/officeart/2005/8/layout/process1`
+const CONNECTED_LAYOUTS: [&str; 4] = ["process", "cycle", "arrow", "chevron"];
+
+fn attribute(e: &BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.as_ref() == key)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// A `dgm:pt` that holds content, the document root or one of its nodes
+struct Point {
+    id: String,
+    root: bool,
+    text: String,
+}
+
+/// A `dgm:cxn` of the parent-of kind
+struct Connection {
+    parent: String,
+    child: String,
+    order: u32,
+}
+
+/// Reads the data model part of a SmartArt diagram into its node tree,
+/// presentation points and transitions are left out
+pub fn parse_data_model(
+    xml: &str,
+    part: &str,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<DiagramNode>> {
+    let mut reader = Reader::from_str(xml);
+    let mut errors = XmlErrors::new(part, strict);
+    let mut points = Vec::new();
+    let mut connections = Vec::new();
+    // the point being read, None inside those without content
+    let mut point: Option<Point> = None;
+    let mut paragraph_started = false;
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"pt" => {
+                    let kind = attribute(&e, b"type").unwrap_or_else(|| "node".to_string());
+                    // a point without text and settings ends here
+                    points.extend(point.take());
+                    point = matches!(kind.as_str(), "doc" | "node" | "asst").then(|| Point {
+                        id: attribute(&e, b"modelId").unwrap_or_default(),
+                        root: kind == "doc",
+                        text: String::new(),
+                    });
+                    paragraph_started = false;
+                }
+                b"cxn" => {
+                    let kind = attribute(&e, b"type").unwrap_or_else(|| "parOf".to_string());
+                    if let (Some(parent), Some(child), "parOf") = (
+                        attribute(&e, b"srcId"),
+                        attribute(&e, b"destId"),
+                        kind.as_str(),
+                    ) {
+                        connections.push(Connection {
+                            parent,
+                            child,
+                            order: attribute(&e, b"srcOrd")
+                                .and_then(|order| order.parse().ok())
+                                .unwrap_or(0),
+                        });
+                    }
+                }
+                // the paragraphs of a node's text are run together
+                b"p" if point.is_some() => {
+                    if paragraph_started {
+                        point.as_mut().expect("checked").text.push(' ');
+                    }
+                    paragraph_started = true;
+                }
+                b"t" if point.is_some() => in_text = true,
+                _ => {}
+            },
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"pt" => points.extend(point.take()),
+                b"t" => in_text = false,
+                _ => {}
+            },
+            Ok(Event::Text(e)) if in_text => {
+                let text = errors.unescape(&e, reader.buffer_position())?;
+                if let Some(point) = point.as_mut() {
+                    point.text.push_str(&text);
+                }
+            }
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+    }
+    errors.finish(warnings);
+    points.extend(point);
+
+    let Some(root) = points.iter().find(|point| point.root) else {
+        return Ok(Vec::new());
+    };
+    let texts: HashMap<&str, &str> = points
+        .iter()
+        .map(|point| (point.id.as_str(), point.text.trim()))
+        .collect();
+    let mut children: HashMap<&str, Vec<(u32, &str)>> = HashMap::new();
+    for connection in &connections {
+        if texts.contains_key(connection.child.as_str()) {
+            children
+                .entry(connection.parent.as_str())
+                .or_default()
+                .push((connection.order, connection.child.as_str()));
+        }
+    }
+    for siblings in children.values_mut() {
+        siblings.sort_by_key(|&(order, _)| order);
+    }
+    let mut visited = HashSet::from([root.id.as_str()]);
+    Ok(subtree(&root.id, &texts, &children, &mut visited, 0))
+}
+
+/// The nodes under `parent`, each at most once however the connections loop
+fn subtree<'a>(
+    parent: &str,
+    texts: &HashMap<&str, &str>,
+    children: &HashMap<&str, Vec<(u32, &'a str)>>,
+    visited: &mut HashSet<&'a str>,
+    depth: usize,
+) -> Vec<DiagramNode> {
+    let Some(siblings) = children.get(parent) else {
+        return Vec::new();
+    };
+    let mut nodes = Vec::new();
+    for &(_, id) in siblings {
+        if !visited.insert(id) {
+            continue;
+        }
+        let grandchildren = if depth < MAX_DEPTH {
+            subtree(id, texts, children, visited, depth + 1)
+        } else {
+            Vec::new()
+        };
+        nodes.push(DiagramNode {
+            text: texts[id].to_string(),
+            children: grandchildren,
+        });
+    }
+    nodes
+}
+
+/// Whether the layout part describes one whose boxes follow each other
+pub fn layout_is_connected(xml: &str) -> bool {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e))
+                if e.local_name().as_ref() == b"layoutDef" =>
+            {
+                let id = attribute(&e, b"uniqueId")
+                    .unwrap_or_default()
+                    .to_lowercase();
+                let name = id.rsplit('/').next().unwrap_or_default();
+                return CONNECTED_LAYOUTS.iter().any(|word| name.contains(word));
+            }
+            Ok(Event::Eof) | Err(_) => return false,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::Result;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{Read, Seek};
+use zip::ZipArchive;
+
+use super::limits::ReadBudget;
+use super::report::Warning;
+use super::xml_errors::XmlErrors;
+
+/// Document properties pulled from `docProps/core.xml` and `docProps/app.xml`
+#[derive(Clone, Default, Debug)]
+pub struct DocProps {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub description: Option<String>,
+    pub application: Option<String>,
+    pub created: Option<String>,  // W3CDTF, e.g. 2024-03-05T10:20:30Z
+    pub modified: Option<String>, // W3CDTF
+}
+
+impl DocProps {
+    /// Reads both property parts, missing parts simply leave fields empty
+    pub fn read<R: Read + Seek>(
+        archive: &mut ZipArchive<R>,
+        budget: &ReadBudget,
+        strict: bool,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Self> {
+        let mut props = DocProps::default();
+
+        if let Some(xml) = budget.read_part(archive, "docProps/core.xml")? {
+            let mut errors = XmlErrors::new("docProps/core.xml", strict);
+            parse_props(&xml, &mut errors, |name, value| {
+                let slot = match name {
+                    b"title" => &mut props.title,
+                    b"creator" => &mut props.author,
+                    b"subject" => &mut props.subject,
+                    b"keywords" => &mut props.keywords,
+                    b"description" => &mut props.description,
+                    b"created" => &mut props.created,
+                    b"modified" => &mut props.modified,
+                    _ => return,
+                };
+                *slot = Some(value);
+            })?;
+            errors.finish(warnings);
+        }
+
+        if let Some(xml) = budget.read_part(archive, "docProps/app.xml")? {
+            let mut errors = XmlErrors::new("docProps/app.xml", strict);
+            parse_props(&xml, &mut errors, |name, value| {
+                if name == b"Application" {
+                    props.application = Some(value);
+                }
+            })?;
+            errors.finish(warnings);
+        }
+
+        Ok(props)
+    }
+}
+
+/// Calls `on_value` with the local name and trimmed text of every leaf element
+fn parse_props<F>(xml: &str, errors: &mut XmlErrors, mut on_value: F) -> Result<()>
+where
+    F: FnMut(&[u8], String),
+{
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => current = Some(e.local_name().as_ref().to_vec()),
+            Ok(Event::Text(e)) => {
+                if let Some(name) = &current {
+                    let value = errors.unescape(&e, reader.buffer_position())?;
+                    let value = value.trim();
+                    if !value.is_empty() {
+                        on_value(name, value.to_string());
+                    }
+                }
+            }
+            Ok(Event::End(_)) => current = None,
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+/// Converts a W3CDTF timestamp (`2024-03-05T10:20:30Z`, `2024-03-05T10:20:30+02:00`)
+/// into a PDF date string (`D:20240305102030Z`, `D:20240305102030+02'00'`); the
+/// date is YYYY, YYYY-MM or YYYY-MM-DD, a time needs the whole date. None
+/// when it is anything else or a field is out of range
+pub fn pdf_date(w3c: &str) -> Option<String> {
+    let (date, rest) = match w3c.split_once('T') {
+        Some((date, rest)) => (date, Some(rest)),
+        None => (w3c, None),
+    };
+
+    let mut out = String::from("D:");
+    let date_parts: Vec<&str> = date.split('-').collect();
+    let year = number(date_parts[0], 4, 0..=9999)?;
+    out.push_str(date_parts[0]);
+    if let Some(month) = date_parts.get(1) {
+        let month = number(month, 2, 1..=12)?;
+        out.push_str(&format!("{:02}", month));
+        if let Some(day) = date_parts.get(2) {
+            number(day, 2, 1..=days_in_month(year, month))?;
+            out.push_str(day);
+        }
+    }
+    if date_parts.len() > 3 {
+        return None;
+    }
+    let Some(rest) = rest else {
+        return Some(out);
+    };
+    if date_parts.len() < 3 {
+        return None;
+    }
+
+    // split the time from its zone designator
+    let zone_at = rest.find(['Z', '+', '-']).unwrap_or(rest.len());
+    let (time, zone) = rest.split_at(zone_at);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    if fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let time_parts: Vec<&str> = time.split(':').collect();
+    if !(2..=3).contains(&time_parts.len()) {
+        return None;
+    }
+    for (part, max) in time_parts.iter().zip([23, 59, 59]) {
+        number(part, 2, 0..=max)?;
+        out.push_str(part);
+    }
+
+    match zone {
+        "" => {}
+        "Z" => out.push('Z'),
+        _ => {
+            let (hh, mm) = zone[1..].split_once(':')?;
+            number(hh, 2, 0..=23)?;
+            number(mm, 2, 0..=59)?;
+            out.push_str(&format!("{}{}'{}'", &zone[..1], hh, mm));
+        }
+    }
+    Some(out)
+}
+
+/// `field` as a number of exactly `width` digits within `range`
+fn number(field: &str, width: usize, range: std::ops::RangeInclusive<u32>) -> Option<u32> {
+    if field.len() != width || !field.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    field.parse().ok().filter(|value| range.contains(value))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pdf_date;
+
+    #[test]
+    fn accepts_w3cdtf_dates() {
+        let cases = [
+            ("2024", "D:2024"),
+            ("2024-03", "D:202403"),
+            ("2024-03-05", "D:20240305"),
+            ("2024-02-29", "D:20240229"),
+            ("2024-03-05T10:20", "D:202403051020"),
+            ("2024-03-05T10:20:30Z", "D:20240305102030Z"),
+            ("2024-03-05T10:20:30.123Z", "D:20240305102030Z"),
+            ("2024-03-05T10:20:30+02:00", "D:20240305102030+02'00'"),
+            ("2024-03-05T23:59:59-05:30", "D:20240305235959-05'30'"),
+        ];
+        for (w3c, pdf) in cases {
+            assert_eq!(pdf_date(w3c).as_deref(), Some(pdf), "{}", w3c);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        let cases = [
+            "",
+            "12345",
+            "24",
+            "2024-3-5",
+            "2024-03-5",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-13-45",
+            "2024-04-31",
+            "2023-02-29",
+            "2024-03-05-01",
+            "2024-03-05T",
+            "2024-03T10:20",
+            "2024-03-05T24:00",
+            "2024-03-05T10:60",
+            "2024-03-05T10:20:61Z",
+            "2024-03-05T10",
+            "2024-03-05T10:20:30.Z",
+            "2024-03-05T10:20:30+0200",
+            "2024-03-05T10:20:30+25:00",
+            "2024-03-05T10:20:30Q",
+            "２０２４",
+        ];
+        for w3c in cases {
+            assert_eq!(pdf_date(w3c), None, "{}", w3c);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::limits::ReadBudget;
+use crate::pdf_encryption::{
+    ALL_PERMISSIONS, PERMIT_ANNOTATE, PERMIT_ASSEMBLE, PERMIT_COPY, PERMIT_FILL_FORMS,
+    PERMIT_MODIFY, PERMIT_PRINT, PERMIT_PRINT_HIGH_QUALITY,
+};
+use crate::report::Warning;
+use crate::xml_errors::XmlErrors;
+use anyhow::Result;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{Read, Seek};
+use zip::ZipArchive;
+
+/// Editing restriction from an enforced `w:documentProtection` in the settings part,
+/// `word/settings.xml` in what Word writes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditRestriction {
+    ReadOnly,
+    Comments,
+    TrackedChanges,
+    Forms,
+}
+
+impl EditRestriction {
+    /// Returns the enforced restriction in `part`, if any
+    pub fn read<R: Read + Seek>(
+        archive: &mut ZipArchive<R>,
+        budget: &ReadBudget,
+        part: &str,
+        strict: bool,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Option<Self>> {
+        let Some(xml) = budget.read_part(archive, part)? else {
+            return Ok(None);
+        };
+
+        let mut reader = Reader::from_str(&xml);
+        let mut buf = Vec::new();
+        // the protection is still found after damage earlier in the part
+        let mut errors = XmlErrors::new(part, strict);
+        let restriction = loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Eof) => break None,
+                Ok(Event::Empty(e)) | Ok(Event::Start(e))
+                    if e.local_name().as_ref() == b"documentProtection" =>
+                {
+                    let mut edit = None;
+                    let mut enforced = false;
+                    for attr in e.attributes().with_checks(false).flatten() {
+                        match attr.key.local_name().as_ref() {
+                            b"edit" => edit = Self::from_attr(&attr.value),
+                            b"enforcement" => {
+                                enforced = matches!(attr.value.as_ref(), b"1" | b"true" | b"on")
+                            }
+                            _ => {}
+                        }
+                    }
+                    break edit.filter(|_| enforced);
+                }
+                Err(e) => errors.recover(e, reader.error_position())?,
+                _ => {}
+            }
+            buf.clear();
+        };
+        errors.finish(warnings);
+        Ok(restriction)
+    }
+
+    fn from_attr(value: &[u8]) -> Option<Self> {
+        match value {
+            b"readOnly" => Some(Self::ReadOnly),
+            b"comments" => Some(Self::Comments),
+            b"trackedChanges" => Some(Self::TrackedChanges),
+            b"forms" => Some(Self::Forms),
+            _ => None,
+        }
+    }
+
+    /// PDF /P value for this restriction, `no_copy`/`no_print` additionally
+    /// deny extraction and printing
+    pub fn permissions(self, no_copy: bool, no_print: bool) -> i32 {
+        let denied = match self {
+            Self::ReadOnly => PERMIT_MODIFY | PERMIT_ANNOTATE | PERMIT_FILL_FORMS | PERMIT_ASSEMBLE,
+            Self::Comments | Self::TrackedChanges => {
+                PERMIT_MODIFY | PERMIT_FILL_FORMS | PERMIT_ASSEMBLE
+            }
+            Self::Forms => PERMIT_MODIFY | PERMIT_ANNOTATE | PERMIT_ASSEMBLE,
+        };
+        let mut permissions = ALL_PERMISSIONS & !denied;
+        if no_copy {
+            permissions &= !PERMIT_COPY;
+        }
+        if no_print {
+            permissions &= !(PERMIT_PRINT | PERMIT_PRINT_HIGH_QUALITY);
+        }
+        permissions
+    }
+}
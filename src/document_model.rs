@@ -0,0 +1,701 @@
+use std::collections::BTreeMap;
+
+use super::pdf_document::PageLabelStyle;
+
+/// The body of a DOCX as the renderer sees it, read from `word/document.xml`
+///
+/// Building the whole model before anything is written lets callers inspect
+/// or change the content first, and keeps layout decisions out of the parser.
+#[derive(Clone, Debug, Default)]
+pub struct Document {
+    pub sections: Vec<Section>,
+    /// elements the model has no place for, by description, with how often they occurred
+    pub unsupported: BTreeMap<String, usize>,
+    /// the text of each bookmark by name, what a REF field to it shows
+    pub bookmarks: BTreeMap<String, String>,
+    /// content controls in the order they start, their content is in the
+    /// blocks like any other
+    pub content_controls: Vec<ContentControl>,
+}
+
+/// Blocks up to a `w:sectPr` and the page settings it carries
+#[derive(Clone, Debug, Default)]
+pub struct Section {
+    pub blocks: Vec<Block>,
+    /// `w:pgSz` wider than tall or `w:orient="landscape"`
+    pub landscape: bool,
+    /// `w:pgNumType w:fmt`, None keeps the numbering style of the PDF default
+    pub number_format: Option<PageLabelStyle>,
+    /// `w:pgNumType w:start`, None continues from the previous section
+    pub number_start: Option<u32>,
+}
+
+impl Document {
+    /// The text without any layout: a line per paragraph, the cells of a
+    /// table row separated by tabs, list labels in front of their items and
+    /// fields showing what they would be laid out with, merge fields their
+    /// placeholder
+    pub fn plain_text(&self) -> String {
+        let mut text = String::new();
+        for block in self.sections.iter().flat_map(|section| &section.blocks) {
+            self.push_block(block, &mut text);
+        }
+        text
+    }
+
+    fn push_block(&self, block: &Block, text: &mut String) {
+        match block {
+            Block::Paragraph(paragraph) => {
+                text.push_str(&self.paragraph_line(paragraph));
+                text.push('\n');
+            }
+            Block::Table(table) => {
+                for row in &table.rows {
+                    let cells: Vec<String> = row
+                        .cells
+                        .iter()
+                        .map(|cell| {
+                            // a cell stays in its column, whatever it holds
+                            let mut content = String::new();
+                            for block in &cell.blocks {
+                                self.push_block(block, &mut content);
+                            }
+                            content.split_whitespace().collect::<Vec<_>>().join(" ")
+                        })
+                        .collect();
+                    text.push_str(&cells.join("\t"));
+                    text.push('\n');
+                }
+            }
+            Block::Omitted(_) => {}
+        }
+    }
+
+    fn paragraph_line(&self, paragraph: &Paragraph) -> String {
+        let mut line = String::new();
+        if let Some(label) = &paragraph.label {
+            line.push_str(&label.text);
+            line.push_str(match label.suffix {
+                LabelSuffix::Tab => "\t",
+                LabelSuffix::Space => " ",
+                LabelSuffix::Nothing => "",
+            });
+        }
+        self.push_inlines(&paragraph.content, &mut line);
+        line.trim_end().to_string()
+    }
+
+    fn push_inlines(&self, content: &[Inline], line: &mut String) {
+        for inline in content {
+            let text = match inline {
+                Inline::Run(run) => run.text.clone(),
+                Inline::LineBreak | Inline::PageBreak => {
+                    line.push('\n');
+                    continue;
+                }
+                Inline::Reference(reference) => match self.bookmarks.get(&reference.bookmark) {
+                    Some(text) if !reference.page => text.clone(),
+                    _ => reference.cached.clone(),
+                },
+                Inline::MergeField(field) => format!("\u{ab}{}\u{bb}", field.name),
+                Inline::FormField(field) => match field.kind {
+                    FormFieldKind::CheckBox if field.checked => "\u{2612}".to_string(),
+                    FormFieldKind::CheckBox => "\u{2610}".to_string(),
+                    _ => field.value.clone(),
+                },
+                Inline::ControlField(control) => {
+                    self.push_inlines(&control.content, line);
+                    continue;
+                }
+                _ => continue,
+            };
+            // runs are words of their own, as they are laid out
+            if text.is_empty() {
+                continue;
+            }
+            if !line.is_empty()
+                && !line.ends_with(char::is_whitespace)
+                && !text.starts_with(char::is_whitespace)
+            {
+                line.push(' ');
+            }
+            line.push_str(&text);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Block {
+    Paragraph(Paragraph),
+    Table(Table),
+    /// content between paragraphs that was left out, an altChunk
+    Omitted(Omitted),
+}
+
+impl Block {
+    /// Its paragraphs and those in the cells of its tables, in document order
+    pub fn paragraphs(&self) -> Vec<&Paragraph> {
+        let mut paragraphs = Vec::new();
+        self.collect_paragraphs(&mut paragraphs);
+        paragraphs
+    }
+
+    fn collect_paragraphs<'a>(&'a self, paragraphs: &mut Vec<&'a Paragraph>) {
+        match self {
+            Block::Paragraph(paragraph) => paragraphs.push(paragraph),
+            Block::Table(table) => {
+                for cell in table.rows.iter().flat_map(|row| &row.cells) {
+                    for block in &cell.blocks {
+                        block.collect_paragraphs(paragraphs);
+                    }
+                }
+            }
+            Block::Omitted(_) => {}
+        }
+    }
+
+    /// `paragraphs` to change them
+    pub fn paragraphs_mut(&mut self) -> Vec<&mut Paragraph> {
+        let mut paragraphs = Vec::new();
+        self.collect_paragraphs_mut(&mut paragraphs);
+        paragraphs
+    }
+
+    fn collect_paragraphs_mut<'a>(&'a mut self, paragraphs: &mut Vec<&'a mut Paragraph>) {
+        match self {
+            Block::Paragraph(paragraph) => paragraphs.push(paragraph),
+            Block::Table(table) => {
+                for cell in table.rows.iter_mut().flat_map(|row| &mut row.cells) {
+                    for block in &mut cell.blocks {
+                        block.collect_paragraphs_mut(paragraphs);
+                    }
+                }
+            }
+            Block::Omitted(_) => {}
+        }
+    }
+}
+
+/// A `w:tbl`, with what its table style gives each cell resolved into the cells
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    pub rows: Vec<TableRow>,
+    /// the `w:gridCol` widths in points, 0 where a column has none
+    pub grid: Vec<f32>,
+    /// `w:tblW`, None for auto
+    pub width: Option<Width>,
+    /// `w:tblLayout w:type="fixed"`, the columns keep their widths whatever
+    /// their content
+    pub fixed: bool,
+}
+
+/// The width a table or a cell asks for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Width {
+    Points(f32),
+    /// of the width there is for the table
+    Percent(f32),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TableRow {
+    pub cells: Vec<TableCell>,
+    /// `w:trHeight`, None to fit the content
+    pub height: Option<RowHeight>,
+    /// `w:tblHeader`, repeated at the top of every page the table goes on to
+    /// along with the header rows before it
+    pub header: bool,
+    /// `w:cantSplit`, kept on one page unless it is taller than a page
+    pub cant_split: bool,
+}
+
+/// The height a table row asks for, in points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RowHeight {
+    /// grows to fit its content
+    AtLeast(f32),
+    /// what doesn't fit is cut off
+    Exact(f32),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TableCell {
+    /// paragraphs and nested tables
+    pub blocks: Vec<Block>,
+    /// the background, from the cell's `w:shd` or the table style
+    pub shading: Option<[u8; 3]>,
+    /// from the cell's `w:tcBorders`, the table's `w:tblBorders` or the table style
+    pub borders: CellBorders,
+    /// from the cell's `w:tcMar` or the table's `w:tblCellMar`
+    pub margins: CellMargins,
+    /// `w:vAlign`
+    pub vertical_align: VerticalAlign,
+    /// `w:textDirection`
+    pub text_direction: TextDirection,
+    /// the grid columns it covers, `w:gridSpan`; 0 is taken as 1
+    pub span: usize,
+    /// `w:tcW`, None for auto
+    pub width: Option<Width>,
+}
+
+/// Space between the edges of a table cell and its content, in points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellMargins {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+impl Default for CellMargins {
+    /// Word's default, 0.08" on either side
+    fn default() -> Self {
+        CellMargins {
+            top: 0.0,
+            bottom: 0.0,
+            left: 5.4,
+            right: 5.4,
+        }
+    }
+}
+
+/// Where the content of a table cell goes when its row is taller
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Which way the lines of a table cell run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextDirection {
+    #[default]
+    Horizontal,
+    /// turned a quarter left, read from the bottom up, `btLr`
+    BottomToTop,
+    /// turned a quarter right, read from the top down, `tbRl`
+    TopToBottom,
+}
+
+/// The lines along the edges of a table cell, None where it has none
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CellBorders {
+    pub top: Option<Border>,
+    pub bottom: Option<Border>,
+    pub left: Option<Border>,
+    pub right: Option<Border>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Paragraph {
+    pub content: Vec<Inline>,
+    /// `w:pBdr`
+    pub borders: ParagraphBorders,
+    /// the background of `w:shd`, its fill with the pattern color mixed in
+    pub shading: Option<[u8; 3]>,
+    /// the number or bullet of a list item, `w:numPr`
+    pub label: Option<ListLabel>,
+    /// `w:ind`
+    pub indent: Indent,
+    /// `w:jc`
+    pub alignment: Alignment,
+    /// the custom tab stops of `w:tabs`, its own and its style's, left to right
+    pub tabs: Vec<TabStop>,
+}
+
+/// A custom tab stop
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TabStop {
+    /// from the left edge of the column, in points
+    pub position: f32,
+    pub alignment: TabAlignment,
+    /// `w:leader`, what fills the room the tab takes
+    pub leader: TabLeader,
+}
+
+/// How the text after a tab sits at its stop
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TabAlignment {
+    /// starts at it
+    #[default]
+    Left,
+    Center,
+    /// ends at it
+    Right,
+    /// has its decimal point at it
+    Decimal,
+}
+
+/// What a tab is filled with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TabLeader {
+    #[default]
+    None,
+    Dot,
+    Hyphen,
+    /// also for `heavy`, a thick underline
+    Underscore,
+    MiddleDot,
+}
+
+impl TabLeader {
+    /// The character repeated to fill a tab
+    pub fn glyph(self) -> Option<char> {
+        match self {
+            TabLeader::None => None,
+            TabLeader::Dot => Some('.'),
+            TabLeader::Hyphen => Some('-'),
+            TabLeader::Underscore => Some('_'),
+            TabLeader::MiddleDot => Some('\u{b7}'),
+        }
+    }
+}
+
+/// How the lines of a paragraph sit between its indents
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// flush with both indents, all but the last line and lines ending in a
+    /// break
+    Justify,
+}
+
+/// How far a paragraph is indented from the sides of the column, in points
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Indent {
+    pub left: f32,
+    pub right: f32,
+    /// of the first line from `left`, negative for a hanging indent
+    pub first_line: f32,
+}
+
+/// The label of a list item, counted in document order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ListLabel {
+    /// the level text, `w:lvlText`, with the numbers written in
+    pub text: String,
+    /// `w:suff`
+    pub suffix: LabelSuffix,
+}
+
+/// What goes between a list label and the text of its paragraph
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LabelSuffix {
+    #[default]
+    Tab,
+    Space,
+    Nothing,
+}
+
+impl Paragraph {
+    /// The text of all runs, each followed by a space
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        push_text(&self.content, &mut text);
+        text
+    }
+}
+
+fn push_text(content: &[Inline], text: &mut String) {
+    for inline in content {
+        match inline {
+            Inline::Run(run) => {
+                text.push_str(&run.text);
+                text.push(' ');
+            }
+            Inline::ControlField(control) => push_text(&control.content, text),
+            _ => {}
+        }
+    }
+}
+
+/// The lines `w:pBdr` draws around a paragraph, None where it has none
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParagraphBorders {
+    pub top: Option<Border>,
+    pub bottom: Option<Border>,
+    pub left: Option<Border>,
+    pub right: Option<Border>,
+    /// drawn instead of the bottom and top borders between paragraphs that
+    /// have the same borders, which are boxed together
+    pub between: Option<Border>,
+}
+
+impl ParagraphBorders {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Border {
+    pub style: BorderStyle,
+    /// of one line in points, `w:sz` is in eighths of a point
+    pub width: f32,
+    /// `w:space`, the distance from the text in points
+    pub space: f32,
+    /// None for `auto`, black
+    pub color: Option<[u8; 3]>,
+}
+
+/// How a border line is drawn, styles with no counterpart here are drawn single
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BorderStyle {
+    #[default]
+    Single,
+    Double,
+    Dotted,
+    Dashed,
+}
+
+/// What a paragraph is made of, in document order
+#[derive(Clone, Debug)]
+pub enum Inline {
+    Run(Run),
+    Image(Image),
+    Chart(Chart),
+    Diagram(Diagram),
+    Object(EmbeddedObject),
+    /// `w:cr`, the text goes on on the next line
+    LineBreak,
+    /// `w:bookmarkStart`, where the bookmark with this name begins
+    Bookmark(String),
+    /// a REF or PAGEREF field
+    Reference(Reference),
+    /// a MERGEFIELD
+    MergeField(MergeField),
+    /// a FORMTEXT or FORMCHECKBOX field
+    FormField(FormField),
+    /// a check box or drop-down content control
+    ControlField(ControlField),
+    PageBreak,
+    /// content left out where it was, e.g. an equation
+    Omitted(Omitted),
+}
+
+/// What stands in for content that isn't rendered, only drawn on request
+#[derive(Clone, Debug)]
+pub struct Omitted {
+    /// the name the report counts it by, e.g. `equation`
+    pub what: String,
+    /// `wp:extent` of its drawing in points, None when it has none
+    pub size: Option<(f32, f32)>,
+}
+
+impl Omitted {
+    pub fn new(what: &str, size: Option<(f32, f32)>) -> Self {
+        Self {
+            what: what.to_string(),
+            size,
+        }
+    }
+}
+
+/// Text of a single `w:t` and the font its run asks for
+#[derive(Clone, Debug, Default)]
+pub struct Run {
+    pub text: String,
+    /// `w:rFonts w:ascii`, None for the document default
+    pub font: Option<String>,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// An inline picture, the relationship id points at the media in the archive
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub rid: String,
+}
+
+/// A DrawingML chart with the values cached in its part, the embedded
+/// workbook they come from is never read
+#[derive(Clone, Debug, Default)]
+pub struct Chart {
+    /// points to the chart part, e.g. `word/charts/chart1.xml`
+    pub rid: String,
+    pub kind: ChartKind,
+    pub title: Option<String>,
+    pub categories: Vec<String>,
+    pub series: Vec<ChartSeries>,
+    /// `wp:extent` of the drawing in points, None when it has none
+    pub size: Option<(f32, f32)>,
+}
+
+/// How the series are drawn; area charts are drawn as lines and doughnuts as pies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChartKind {
+    /// vertical bars, `c:barDir val="col"`
+    #[default]
+    Column,
+    /// horizontal bars
+    Bar,
+    Line,
+    Pie,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ChartSeries {
+    pub name: Option<String>,
+    /// by category, None for points the cache leaves out
+    pub values: Vec<Option<f64>>,
+    /// `a:srgbClr` of the series, None for the default palette
+    pub color: Option<[u8; 3]>,
+}
+
+/// A SmartArt diagram, the text of its data model drawn as boxes; the shapes
+/// of its layout aren't reproduced
+#[derive(Clone, Debug, Default)]
+pub struct Diagram {
+    /// points to the data model part, e.g. `word/diagrams/data1.xml`
+    pub rid: String,
+    /// points to the layout part, None when the drawing has none
+    pub layout_rid: Option<String>,
+    /// the nodes under the root of the data model, in order
+    pub nodes: Vec<DiagramNode>,
+    /// the layout is a process or cycle, the boxes are drawn with arrows between them
+    pub connected: bool,
+    /// `wp:extent` of the drawing in points, None when it has none
+    pub size: Option<(f32, f32)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DiagramNode {
+    pub text: String,
+    pub children: Vec<DiagramNode>,
+}
+
+/// An OLE object such as an Excel sheet or a Visio drawing, shown by the
+/// preview Word stores with it; the object's own data is never read
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddedObject {
+    /// `o:OLEObject ProgID`, e.g. `Excel.Sheet.12`
+    pub prog_id: Option<String>,
+    /// points to the preview picture, usually an EMF or WMF
+    pub preview_rid: Option<String>,
+    /// from the style of its `v:shape` in points, None when it has none
+    pub size: Option<(f32, f32)>,
+}
+
+/// A mail merge field, showing the value given for its name when laid out or
+/// else its `«name»` placeholder
+#[derive(Clone, Debug, Default)]
+pub struct MergeField {
+    /// the column of the data source it takes its value from
+    pub name: String,
+    /// `\b`, text put before the value unless it is empty
+    pub before: String,
+    /// `\f`, text put after it
+    pub after: String,
+}
+
+/// A legacy form field with the settings of its `w:ffData`, turned into a
+/// fillable field where it stands in the text
+#[derive(Clone, Debug, Default)]
+pub struct FormField {
+    pub kind: FormFieldKind,
+    /// `w:name`, None for a field without one
+    pub name: Option<String>,
+    /// the result Word stored with a text field, what was typed into it
+    pub value: String,
+    /// `w:textInput w:default`
+    pub default: String,
+    /// `w:maxLength` of a text field, None for no limit
+    pub max_length: Option<u32>,
+    /// `w:checked` of a check box, or its default when it has none
+    pub checked: bool,
+    /// `w:checkBox w:default`
+    pub default_checked: bool,
+    /// `w:checkBox w:size` in points, None to size it like the text
+    pub size: Option<f32>,
+    /// entries of a drop-down as (display text, value)
+    pub items: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FormFieldKind {
+    /// FORMTEXT
+    #[default]
+    Text,
+    /// FORMCHECKBOX
+    CheckBox,
+    /// a list to pick one entry from
+    DropDown,
+    /// a list whose entries can be typed over
+    ComboBox,
+}
+
+/// A check box or drop-down content control, its content is shown as it is
+/// unless interactive forms are asked for, then the field takes its place
+#[derive(Clone, Debug, Default)]
+pub struct ControlField {
+    /// the state of a check box, the selected text of a drop-down as `value`
+    pub field: FormField,
+    pub content: Vec<Inline>,
+}
+
+/// A content control (`w:sdt`), what Word knows about a part of the document
+/// a template or a program fills in
+#[derive(Clone, Debug, Default)]
+pub struct ContentControl {
+    pub kind: ContentControlKind,
+    /// `w:tag`, set by whoever made the control to find it again
+    pub tag: Option<String>,
+    /// `w:alias`, the title Word shows on the control
+    pub alias: Option<String>,
+    /// `w:id`
+    pub id: Option<i64>,
+    /// `w:showingPlcHdr`, the content is the placeholder text
+    pub showing_placeholder: bool,
+    /// `w:docPartGallery` of a building block, e.g. `Cover Pages`
+    pub gallery: Option<String>,
+    /// the first block of the content as (section, block) indexes, a control
+    /// inside a paragraph starts and ends with that paragraph
+    pub start: (usize, usize),
+    /// the block after the content, in the section of `start` unless the
+    /// control spans a section break
+    pub end: (usize, usize),
+}
+
+/// What a content control holds, by the element in its `w:sdtPr`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContentControlKind {
+    /// `w:richText`, or no element at all
+    #[default]
+    RichText,
+    /// `w:text`
+    PlainText,
+    /// `w:date`
+    Date,
+    /// `w:dropDownList`
+    DropDownList,
+    /// `w:comboBox`
+    ComboBox,
+    /// `w:picture`
+    Picture,
+    /// `w14:checkbox`
+    CheckBox,
+    /// `w15:repeatingSection`
+    RepeatingSection,
+    /// `w15:repeatingSectionItem`, one copy of the content of a repeating section
+    RepeatingSectionItem,
+    /// `w:docPartObj` or `w:docPartList`, e.g. a cover page or a table of contents
+    BuildingBlock,
+    /// `w:group`
+    Group,
+}
+
+/// A field showing the text of a bookmark or the page it is on, filled in
+/// while the document is laid out
+#[derive(Clone, Debug, Default)]
+pub struct Reference {
+    pub bookmark: String,
+    /// PAGEREF rather than REF
+    pub page: bool,
+    /// the result Word stored with the field, shown when the bookmark is missing
+    pub cached: String,
+}
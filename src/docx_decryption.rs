@@ -0,0 +1,633 @@
+use aes::cipher::{block_padding::NoPadding, BlockDecryptMut, KeyIvInit};
+use aes::{Aes128, Aes192, Aes256};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use tracing::{debug, info_span};
+
+use super::error::ConvertError;
+use super::invalid;
+use super::limits::Limits;
+
+/// Start of an OLE compound file, what password protected OOXML is stored in
+const CFB_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// Chain markers in the sector allocation tables, anything above the largest
+/// sector number ends a chain
+const MAX_SECTOR: u32 = 0xFFFF_FFFA;
+
+/// The package is encrypted in segments of this many bytes, each with its own IV
+const SEGMENT: usize = 4096;
+
+/// Streams smaller than this are in the mini stream, MS-CFB allows no other value
+const MINI_STREAM_CUTOFF: u64 = 4096;
+
+/// The most password hashing rounds MS-OFFCRYPTO allows, a file asking for
+/// more would keep the conversion busy for hours
+const MAX_SPIN_COUNT: u32 = 10_000_000;
+
+// block keys of MS-OFFCRYPTO agile encryption, mixed into the password hash
+const VERIFIER_INPUT_BLOCK: [u8; 8] = [0xfe, 0xa7, 0xd2, 0x76, 0x3b, 0x4b, 0x9e, 0x79];
+const VERIFIER_VALUE_BLOCK: [u8; 8] = [0xd7, 0xaa, 0x0f, 0x6d, 0x30, 0x61, 0x34, 0x4e];
+const KEY_VALUE_BLOCK: [u8; 8] = [0x14, 0x6e, 0x0b, 0xe7, 0xab, 0xac, 0xd0, 0xd6];
+
+/// The DOCX as the ZIP reader sees it: the input itself, or the package
+/// decrypted out of a password protected one
+pub(crate) enum DocxInput<R> {
+    Plain(R),
+    Decrypted(Cursor<Vec<u8>>),
+}
+
+impl<R: Read + Seek> DocxInput<R> {
+    /// Decrypts `input` with `password` when it is an encrypted OOXML file,
+    /// from the current position on; the compound file and the package in it
+    /// count against `limits.max_total_bytes`
+    pub fn open(
+        mut input: R,
+        password: Option<&str>,
+        limits: &Limits,
+    ) -> Result<Self, ConvertError> {
+        let start = input.stream_position()?;
+        let mut signature = [0; 8];
+        let is_cfb = match input.read_exact(&mut signature) {
+            Ok(()) => signature == CFB_SIGNATURE,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => false,
+            Err(e) => return Err(e.into()),
+        };
+        input.seek(SeekFrom::Start(start))?;
+        if !is_cfb {
+            return Ok(Self::Plain(input));
+        }
+
+        let _span = info_span!("decrypt_docx").entered();
+        let mut data = Vec::new();
+        (&mut input)
+            .take(limits.max_total_bytes.saturating_add(1))
+            .read_to_end(&mut data)?;
+        if data.len() as u64 > limits.max_total_bytes {
+            return Err(too_large("the encrypted file", limits));
+        }
+        let file = CompoundFile::parse(data).map_err(invalid("compound file"))?;
+        let Some(info) = file.stream("EncryptionInfo") else {
+            // e.g. a Word 97-2003 .doc, which is a compound file too
+            return Err(ConvertError::invalid_docx(
+                "compound file",
+                "an OLE compound file without an encrypted package, not a DOCX",
+            ));
+        };
+        let info = info
+            .and_then(|info| EncryptionInfo::parse(&info))
+            .map_err(invalid("EncryptionInfo"))?;
+        let Some(password) = password else {
+            return Err(encrypted("a password is needed to open it"));
+        };
+        let key = info.secret_key(password)?;
+        let stream = file
+            .stream("EncryptedPackage")
+            .ok_or_else(|| anyhow!("no EncryptedPackage stream"))
+            .and_then(|package| package)
+            .map_err(invalid("EncryptedPackage"))?;
+        // the size the package says it has, before anything is decrypted
+        let size = stream
+            .get(..8)
+            .map(|size| u64::from_le_bytes(size.try_into().unwrap()));
+        if size.is_some_and(|size| size > limits.max_total_bytes) {
+            return Err(too_large("the decrypted package", limits));
+        }
+        let package = info
+            .decrypt_package(&key, &stream)
+            .map_err(invalid("EncryptedPackage"))?;
+        debug!(bytes = package.len(), "decrypted the package");
+        Ok(Self::Decrypted(Cursor::new(package)))
+    }
+}
+
+impl<R: Read> Read for DocxInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(input) => input.read(buf),
+            Self::Decrypted(package) => package.read(buf),
+        }
+    }
+}
+
+impl<R: Seek> Seek for DocxInput<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Plain(input) => input.seek(pos),
+            Self::Decrypted(package) => package.seek(pos),
+        }
+    }
+}
+
+fn too_large(what: &str, limits: &Limits) -> ConvertError {
+    ConvertError::LimitExceeded(format!("{} is over {} bytes", what, limits.max_total_bytes))
+}
+
+fn encrypted(reason: &str) -> ConvertError {
+    ConvertError::EncryptedDocument {
+        reason: reason.to_string(),
+    }
+}
+
+/// The streams of an OLE compound file (MS-CFB), only as much of the format
+/// as reading the two streams of an encrypted package takes
+struct CompoundFile {
+    data: Vec<u8>,
+    sector_shift: u32,
+    mini_sector_shift: u32,
+    mini_stream_cutoff: u64,
+    fat: Vec<u32>,
+    mini_fat: Vec<u32>,
+    mini_stream: Vec<u8>,
+    entries: Vec<DirEntry>,
+}
+
+struct DirEntry {
+    name: String,
+    is_stream: bool,
+    start: u32,
+    size: u64,
+}
+
+impl CompoundFile {
+    fn parse(data: Vec<u8>) -> Result<Self> {
+        ensure!(data.len() >= 512, "truncated header");
+        let u16_at = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        let sector_shift = u16_at(0x1E) as u32;
+        let mini_sector_shift = u16_at(0x20) as u32;
+        ensure!(
+            matches!(sector_shift, 9 | 12) && mini_sector_shift == 6,
+            "unsupported sector size"
+        );
+        let mini_stream_cutoff = u32_at(0x38) as u64;
+        ensure!(
+            mini_stream_cutoff == MINI_STREAM_CUTOFF,
+            "mini stream cutoff {} instead of {}",
+            mini_stream_cutoff,
+            MINI_STREAM_CUTOFF
+        );
+        let mut file = Self {
+            sector_shift,
+            mini_sector_shift,
+            mini_stream_cutoff,
+            fat: Vec::new(),
+            mini_fat: Vec::new(),
+            mini_stream: Vec::new(),
+            entries: Vec::new(),
+            data: Vec::new(),
+        };
+
+        // the FAT sectors are listed in the header, then in a chain of DIFAT sectors
+        let mut fat_sectors: Vec<u32> = (0..109).map(|i| u32_at(0x4C + i * 4)).collect();
+        let mut difat = u32_at(0x44);
+        let per_sector = (1usize << sector_shift) / 4;
+        let first_dir = u32_at(0x30);
+        let first_mini_fat = u32_at(0x3C);
+        file.data = data;
+        let mut guard = 0;
+        while difat <= MAX_SECTOR {
+            let sector = file.sector(difat)?;
+            let ids: Vec<u32> = sector
+                .chunks_exact(4)
+                .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+                .collect();
+            fat_sectors.extend(&ids[..per_sector - 1]);
+            difat = ids[per_sector - 1];
+            guard += 1;
+            ensure!(guard <= file.sector_count(), "DIFAT chain loops");
+        }
+        let mut fat = Vec::new();
+        for id in fat_sectors.into_iter().filter(|&id| id <= MAX_SECTOR) {
+            fat.extend(
+                file.sector(id)?
+                    .chunks_exact(4)
+                    .map(|id| u32::from_le_bytes(id.try_into().unwrap())),
+            );
+        }
+        file.fat = fat;
+
+        let directory = file.read_chain(first_dir)?;
+        file.entries = directory
+            .chunks_exact(128)
+            .map(|entry| {
+                let name_len = (u16::from_le_bytes([entry[64], entry[65]]) as usize).min(64);
+                let name: Vec<u16> = entry[..name_len.saturating_sub(2)]
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                let size = u64::from_le_bytes(entry[120..128].try_into().unwrap());
+                DirEntry {
+                    name: String::from_utf16_lossy(&name),
+                    is_stream: entry[66] == 2,
+                    start: u32::from_le_bytes(entry[116..120].try_into().unwrap()),
+                    // the high half isn't kept by files with 512 byte sectors
+                    size: if sector_shift == 9 {
+                        size & 0xFFFF_FFFF
+                    } else {
+                        size
+                    },
+                }
+            })
+            .collect();
+        let root = file
+            .entries
+            .first()
+            .ok_or_else(|| anyhow!("no root entry"))?;
+        let (mini_start, mini_size) = (root.start, root.size);
+
+        file.mini_fat = file
+            .read_chain(first_mini_fat)?
+            .chunks_exact(4)
+            .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+            .collect();
+        let mut mini_stream = file.read_chain(mini_start)?;
+        mini_stream.truncate(mini_size as usize);
+        file.mini_stream = mini_stream;
+        Ok(file)
+    }
+
+    fn sector_count(&self) -> usize {
+        (self.data.len() >> self.sector_shift).saturating_sub(1)
+    }
+
+    fn sector(&self, id: u32) -> Result<&[u8]> {
+        let size = 1usize << self.sector_shift;
+        let start = (id as usize + 1) * size;
+        self.data
+            .get(start..start + size)
+            .ok_or_else(|| anyhow!("sector {} past the end of the file", id))
+    }
+
+    /// The sectors linked from `start` on, concatenated
+    fn read_chain(&self, start: u32) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut id = start;
+        while id <= MAX_SECTOR {
+            data.extend_from_slice(self.sector(id)?);
+            ensure!(data.len() <= self.data.len(), "sector chain loops");
+            id = *self
+                .fat
+                .get(id as usize)
+                .ok_or_else(|| anyhow!("sector {} not in the FAT", id))?;
+        }
+        Ok(data)
+    }
+
+    /// Small streams live in 64 byte sectors inside the mini stream
+    fn read_mini_chain(&self, start: u32, size: u64) -> Result<Vec<u8>> {
+        let sector_size = 1usize << self.mini_sector_shift;
+        let mut data = Vec::new();
+        let mut id = start;
+        while id <= MAX_SECTOR && (data.len() as u64) < size {
+            let at = id as usize * sector_size;
+            let sector = self
+                .mini_stream
+                .get(at..at + sector_size)
+                .ok_or_else(|| anyhow!("mini sector {} past the mini stream", id))?;
+            data.extend_from_slice(sector);
+            id = *self
+                .mini_fat
+                .get(id as usize)
+                .ok_or_else(|| anyhow!("mini sector {} not in the mini FAT", id))?;
+        }
+        Ok(data)
+    }
+
+    /// The stream called `name`, None when there is none
+    fn stream(&self, name: &str) -> Option<Result<Vec<u8>>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.is_stream && entry.name == name)?;
+        let data = if entry.size < self.mini_stream_cutoff {
+            self.read_mini_chain(entry.start, entry.size)
+        } else {
+            self.read_chain(entry.start)
+        };
+        Some(data.and_then(|mut data| {
+            ensure!(data.len() as u64 >= entry.size, "{} is truncated", name);
+            data.truncate(entry.size as usize);
+            Ok(data)
+        }))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum HashAlgorithm {
+    /// what Office 2010 encrypts with
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    fn parse(name: &[u8]) -> Result<Self> {
+        Ok(match name {
+            b"SHA1" => Self::Sha1,
+            b"SHA256" => Self::Sha256,
+            b"SHA384" => Self::Sha384,
+            b"SHA512" => Self::Sha512,
+            name => bail!(
+                "unsupported hash algorithm {}",
+                String::from_utf8_lossy(name)
+            ),
+        })
+    }
+
+    fn hash(self, parts: &[&[u8]]) -> Vec<u8> {
+        fn hash<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+            let mut digest = D::new();
+            for part in parts {
+                digest.update(part);
+            }
+            digest.finalize().to_vec()
+        }
+        match self {
+            Self::Sha1 => hash::<Sha1>(parts),
+            Self::Sha256 => hash::<Sha256>(parts),
+            Self::Sha384 => hash::<Sha384>(parts),
+            Self::Sha512 => hash::<Sha512>(parts),
+        }
+    }
+}
+
+/// Salt, key size and hash of one `keyData` or `encryptedKey` element
+struct KeyParams {
+    salt: Vec<u8>,
+    key_bytes: usize,
+    block_size: usize,
+    hash: HashAlgorithm,
+}
+
+/// The agile encryption descriptor (MS-OFFCRYPTO 2.3.4.10) of the
+/// EncryptionInfo stream, with the password key encryptor
+struct EncryptionInfo {
+    key_data: KeyParams,
+    password: KeyParams,
+    spin_count: u32,
+    encrypted_verifier_input: Vec<u8>,
+    encrypted_verifier_value: Vec<u8>,
+    encrypted_key: Vec<u8>,
+}
+
+impl EncryptionInfo {
+    fn parse(stream: &[u8]) -> Result<Self> {
+        ensure!(stream.len() > 8, "truncated EncryptionInfo");
+        let version = (
+            u16::from_le_bytes([stream[0], stream[1]]),
+            u16::from_le_bytes([stream[2], stream[3]]),
+        );
+        if version != (4, 4) {
+            return Err(encrypted(
+                "only agile encryption, as written by Office 2010 and later, can be decrypted",
+            )
+            .into());
+        }
+
+        let mut reader = Reader::from_reader(&stream[8..]);
+        let mut buf = Vec::new();
+        let mut key_data = None;
+        let mut password = None;
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Eof => break,
+                Event::Empty(e) | Event::Start(e) => {
+                    let mut attrs = Attrs::default();
+                    for attr in e.attributes().with_checks(false).flatten() {
+                        attrs.set(attr.key.local_name().as_ref(), &attr.value);
+                    }
+                    match e.local_name().as_ref() {
+                        b"keyData" => key_data = Some(attrs),
+                        // the certificate key encryptor has no spin count, only
+                        // the password one is of use here
+                        b"encryptedKey" if attrs.spin_count.is_some() => password = Some(attrs),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+            buf.clear();
+        }
+        let key_data = key_data.ok_or_else(|| anyhow!("no keyData"))?;
+        let password = password.ok_or_else(|| anyhow!("no password key encryptor"))?;
+        let spin_count = password.spin_count.unwrap_or_default();
+        ensure!(
+            spin_count <= MAX_SPIN_COUNT,
+            "spin count {} over the {} allowed",
+            spin_count,
+            MAX_SPIN_COUNT
+        );
+        Ok(Self {
+            spin_count,
+            encrypted_verifier_input: password.field(&password.verifier_input, "verifier input")?,
+            encrypted_verifier_value: password.field(&password.verifier_value, "verifier value")?,
+            encrypted_key: password.field(&password.key_value, "key value")?,
+            key_data: key_data.params()?,
+            password: password.params()?,
+        })
+    }
+
+    /// The key the package is encrypted with, from `password`
+    fn secret_key(&self, password: &str) -> Result<Vec<u8>, ConvertError> {
+        let params = &self.password;
+        let utf16: Vec<u8> = password.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let mut hash = params.hash.hash(&[&params.salt, &utf16]);
+        for i in 0..self.spin_count {
+            hash = params.hash.hash(&[&i.to_le_bytes(), &hash]);
+        }
+        let decrypt = |block: &[u8], data: &[u8]| {
+            let mut key = params.hash.hash(&[&hash, block]);
+            key.resize(params.key_bytes, 0x36);
+            aes_cbc_decrypt(&key, &params.salt[..params.block_size], data)
+                .map_err(invalid("EncryptionInfo"))
+        };
+
+        let verifier_input = decrypt(&VERIFIER_INPUT_BLOCK, &self.encrypted_verifier_input)?;
+        let verifier_value = decrypt(&VERIFIER_VALUE_BLOCK, &self.encrypted_verifier_value)?;
+        let expected = params
+            .hash
+            .hash(&[&verifier_input[..params.salt.len().min(verifier_input.len())]]);
+        if verifier_value.get(..expected.len()) != Some(&expected[..]) {
+            return Err(encrypted("wrong password"));
+        }
+        let mut key = decrypt(&KEY_VALUE_BLOCK, &self.encrypted_key)?;
+        key.truncate(self.key_data.key_bytes);
+        Ok(key)
+    }
+
+    /// The ZIP inside the EncryptedPackage stream
+    fn decrypt_package(&self, key: &[u8], stream: &[u8]) -> Result<Vec<u8>> {
+        ensure!(stream.len() >= 8, "truncated EncryptedPackage");
+        let size = u64::from_le_bytes(stream[..8].try_into().unwrap());
+        let params = &self.key_data;
+        let mut package = Vec::with_capacity(stream.len() - 8);
+        for (i, segment) in stream[8..].chunks(SEGMENT).enumerate() {
+            let iv = params.hash.hash(&[&params.salt, &(i as u32).to_le_bytes()]);
+            // the last segment is padded to the block size, trailing bytes past it are junk
+            let whole = segment.len() / params.block_size * params.block_size;
+            package.extend(aes_cbc_decrypt(
+                key,
+                &iv[..params.block_size],
+                &segment[..whole],
+            )?);
+        }
+        ensure!(
+            package.len() as u64 >= size,
+            "EncryptedPackage is truncated"
+        );
+        package.truncate(size as usize);
+        Ok(package)
+    }
+}
+
+/// The attributes of `keyData` and `encryptedKey` that matter, as written
+#[derive(Default)]
+struct Attrs {
+    salt: Option<Vec<u8>>,
+    key_bits: Option<usize>,
+    block_size: Option<usize>,
+    hash: Option<Vec<u8>>,
+    cipher: Option<Vec<u8>>,
+    chaining: Option<Vec<u8>>,
+    spin_count: Option<u32>,
+    verifier_input: Option<Vec<u8>>,
+    verifier_value: Option<Vec<u8>>,
+    key_value: Option<Vec<u8>>,
+}
+
+impl Attrs {
+    fn set(&mut self, name: &[u8], value: &[u8]) {
+        fn number<T: std::str::FromStr>(value: &[u8]) -> Option<T> {
+            std::str::from_utf8(value).ok()?.parse().ok()
+        }
+        match name {
+            b"saltValue" => self.salt = base64_decode(value),
+            b"keyBits" => self.key_bits = number(value),
+            b"blockSize" => self.block_size = number(value),
+            b"hashAlgorithm" => self.hash = Some(value.to_vec()),
+            b"cipherAlgorithm" => self.cipher = Some(value.to_vec()),
+            b"cipherChaining" => self.chaining = Some(value.to_vec()),
+            b"spinCount" => self.spin_count = number(value),
+            b"encryptedVerifierHashInput" => self.verifier_input = base64_decode(value),
+            b"encryptedVerifierHashValue" => self.verifier_value = base64_decode(value),
+            b"encryptedKeyValue" => self.key_value = base64_decode(value),
+            _ => {}
+        }
+    }
+
+    fn field(&self, value: &Option<Vec<u8>>, name: &str) -> Result<Vec<u8>> {
+        value
+            .clone()
+            .ok_or_else(|| anyhow!("no encrypted {}", name))
+    }
+
+    fn params(&self) -> Result<KeyParams> {
+        ensure!(
+            self.cipher.as_deref() == Some(b"AES")
+                && self.chaining.as_deref() == Some(b"ChainingModeCBC"),
+            "only AES in CBC mode is supported"
+        );
+        let key_bits = self.key_bits.unwrap_or_default();
+        let block_size = self.block_size.unwrap_or_default();
+        let salt = self.salt.clone().unwrap_or_default();
+        ensure!(
+            matches!(key_bits, 128 | 192 | 256) && block_size == 16 && salt.len() >= block_size,
+            "unsupported key size {} or block size {}",
+            key_bits,
+            block_size
+        );
+        Ok(KeyParams {
+            salt,
+            key_bytes: key_bits / 8,
+            block_size,
+            hash: HashAlgorithm::parse(self.hash.as_deref().unwrap_or_default())?,
+        })
+    }
+}
+
+fn aes_cbc_decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = data.to_vec();
+    let len = match key.len() {
+        16 => cbc::Decryptor::<Aes128>::new_from_slices(key, iv)?
+            .decrypt_padded_mut::<NoPadding>(&mut buf)
+            .map(|plain| plain.len()),
+        24 => cbc::Decryptor::<Aes192>::new_from_slices(key, iv)?
+            .decrypt_padded_mut::<NoPadding>(&mut buf)
+            .map(|plain| plain.len()),
+        32 => cbc::Decryptor::<Aes256>::new_from_slices(key, iv)?
+            .decrypt_padded_mut::<NoPadding>(&mut buf)
+            .map(|plain| plain.len()),
+        len => bail!("unsupported AES key length {}", len),
+    }
+    .map_err(|_| anyhow!("encrypted data isn't a whole number of blocks"))
+    .context("decrypting")?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// Standard base64 as the salts and keys are written, None when malformed
+pub(crate) fn base64_decode(text: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for &c in text.iter().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConvertError, DocxInput};
+    use crate::limits::Limits;
+    use std::io::Cursor;
+
+    /// 5000 bytes encrypted the way Office 2010 does by default, with AES-128
+    /// and SHA-1, but 1000 spins instead of 100000
+    const SHA1_FIXTURE: &[u8] = include_bytes!("testdata/sha1.docx");
+
+    fn open(password: &str) -> Result<DocxInput<Cursor<&'static [u8]>>, ConvertError> {
+        DocxInput::open(
+            Cursor::new(SHA1_FIXTURE),
+            Some(password),
+            &Limits::default(),
+        )
+    }
+
+    #[test]
+    fn decrypts_sha1_agile_encryption() {
+        let Ok(DocxInput::Decrypted(package)) = open("Password1") else {
+            panic!("not decrypted");
+        };
+        let expected: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(package.into_inner(), expected);
+    }
+
+    #[test]
+    fn rejects_a_wrong_password() {
+        match open("password1") {
+            Err(ConvertError::EncryptedDocument { reason }) => assert_eq!(reason, "wrong password"),
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("decrypted with the wrong password"),
+        }
+    }
+}
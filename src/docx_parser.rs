@@ -0,0 +1,1672 @@
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Seek};
+use tracing::{debug, info_span};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::alt_chunk::{self, ChunkFormat};
+use super::cancellation::CancelToken;
+use super::chart_parser::{parse_chart, parse_color};
+use super::diagram_parser::{layout_is_connected, parse_data_model};
+use super::document_model::{
+    Alignment, Block, Border, BorderStyle, CellBorders, CellMargins, Chart, ContentControl,
+    ContentControlKind, ControlField, Diagram, Document, EmbeddedObject, FormField, FormFieldKind,
+    Image, Inline, MergeField, Omitted, Paragraph, ParagraphBorders, Reference, RowHeight, Run,
+    Section, Table, TableCell, TableRow, TextDirection, VerticalAlign, Width,
+};
+use super::error::ConvertError;
+use super::limits::ReadBudget;
+use super::numbering::{read_numbering, Numbering};
+use super::package::Relationship;
+use super::pdf_document::PageLabelStyle;
+use super::report::Warning;
+use super::styles::{
+    read_styles, CellPosition, EdgeBorders, IndentProperties, RunProperties, Styles, TabProperties,
+    TableLook, TableStyle,
+};
+use super::symbol_fonts::symbol_char;
+use super::xml_errors::XmlErrors;
+
+/// Read buffer for the main document part, which can run to hundreds of MB
+const XML_BUFFER: usize = 64 * 1024;
+
+/// Bookmarked text past this length is left out of what REF fields show
+const MAX_BOOKMARK_TEXT: usize = 2000;
+
+/// Row heights are cut down to this, in points, Word's largest page height
+const MAX_ROW_HEIGHT: f32 = 1584.0;
+
+/// English Metric Units, what DrawingML sizes are given in, per point
+const EMU_PER_POINT: f32 = 12700.0;
+
+/// Where the parser is inside the main document part
+#[derive(Default)]
+struct ParserState {
+    document: Document,
+    section: Section,
+    content: Vec<Inline>, // of the paragraph being read
+    styles: Styles,
+    numbering: Numbering,
+    paragraph_style: Option<String>, // `w:pStyle` of the paragraph being read
+    list: Option<String>,            // `w:numId` of the paragraph being read
+    list_level: Option<usize>,       // its `w:ilvl`
+    indent: IndentProperties,        // of the paragraph being read
+    alignment: Option<Alignment>,    // its own `w:jc`
+    tabs: TabProperties,             // its own `w:tabs`
+    in_tabs: bool,
+    run_style: Option<String>, // `w:rStyle` of the run being read
+    direct: RunProperties,     // the run's own formatting
+    paragraph_depth: usize,
+    in_text: bool,
+    in_drawing: bool,
+    extent: Option<(f32, f32)>, // `wp:extent` of the drawing being read, in points
+    // the w:sectPr of all but the last section sits in the pPr of its final paragraph
+    section_break: bool,
+    alt_chunks: Vec<AltChunk>,
+    object: Option<EmbeddedObject>, // the `w:object` being read
+    pict: Option<bool>,             // inside a `w:pict`, whether it had a picture
+    // the text before ended in a hyphen element, what follows joins it
+    // without the space written between runs
+    glue: bool,
+    fields: Vec<Field>, // open fields, innermost last
+    in_instr: bool,
+    in_ff_data: bool,
+    bookmarks: Vec<OpenBookmark>, // started and not yet ended
+    borders: ParagraphBorders,    // of the paragraph being read
+    shading: Option<[u8; 3]>,     // of the paragraph being read
+    in_paragraph_props: bool,     // in the `w:pPr` of a paragraph outside text boxes
+    in_props_change: bool,        // in a `w:pPrChange`, the properties before a tracked change
+    in_borders: bool,
+    controls: Vec<OpenControl>, // innermost last
+    in_sdt_pr: bool,
+    tables: Vec<OpenTable>, // innermost last
+    in_table_props: bool,   // in the `w:tblPr` of the innermost table
+    in_cell_props: bool,    // in a `w:tcPr`
+    in_table_borders: bool, // in a `w:tblBorders` or `w:tcBorders`
+    in_cell_margins: bool,  // in a `w:tblCellMar` or `w:tcMar`
+    in_row_props: bool,     // in a `w:trPr`
+}
+
+/// A `w:tbl` whose end hasn't been read yet
+#[derive(Default)]
+struct OpenTable {
+    style: Option<String>, // `w:tblStyle`
+    look: TableLook,
+    borders: EdgeBorders,       // `w:tblBorders`
+    margins: CellMargins,       // `w:tblCellMar`
+    grid: Vec<f32>,             // `w:gridCol` widths
+    width: Option<Width>,       // `w:tblW`
+    fixed: bool,                // `w:tblLayout`
+    format: Option<TableStyle>, // once the first row starts
+    rows: Vec<OpenRow>,
+}
+
+/// A row of an open table
+#[derive(Default)]
+struct OpenRow {
+    cells: Vec<OpenCell>,
+    height: Option<RowHeight>, // `w:trHeight`
+    header: bool,              // `w:tblHeader`
+    cant_split: bool,          // `w:cantSplit`
+}
+
+/// A cell of an open table, with what it sets itself
+#[derive(Default)]
+struct OpenCell {
+    blocks: Vec<Block>,
+    shading: Option<Option<[u8; 3]>>,
+    borders: EdgeBorders,
+    margins: CellMargins,
+    vertical_align: VerticalAlign,
+    text_direction: TextDirection,
+    span: usize,
+    width: Option<Width>,
+    // what the table style gives runs in the cell, before the count of rows is known
+    run: RunProperties,
+}
+
+/// A content control whose end hasn't been read yet
+struct OpenControl {
+    index: usize, // in `Document::content_controls`
+    // a check box or drop-down, its content is wrapped in an
+    // `Inline::ControlField` unless a control around it already is
+    field: Option<FormField>,
+    content_start: Option<usize>, // in the paragraph content, for one inside a paragraph
+}
+
+/// A field from its `w:fldChar` begin or `w:fldSimple` start to its end
+#[derive(Default)]
+struct Field {
+    instr: String,
+    separated: bool, // past the instructions, in the result
+    reference: Option<Reference>,
+    merge: Option<MergeField>,
+    form: Option<FormField>,    // from the `w:ffData` of its begin
+    form_checked: Option<bool>, // `w:checked`, the default applies without it
+}
+
+/// A bookmark whose text is still being read
+struct OpenBookmark {
+    id: String,
+    name: String,
+    text: String,
+}
+
+/// A `w:altChunk`, the part it imports can only be read once the main part is
+struct AltChunk {
+    rid: String,
+    section: usize, // index in `Document::sections`
+    block: usize,   // index in the blocks of that section
+}
+
+/// Where the next block will be, as (section, block) indexes
+fn block_position(state: &ParserState) -> (usize, usize) {
+    (state.document.sections.len(), state.section.blocks.len())
+}
+
+/// Reads an element of a `w:sdtPr` into the innermost open content control
+fn read_control_property(state: &mut ParserState, e: &BytesStart, name: &[u8]) {
+    let Some(open) = state.controls.last_mut() else {
+        return;
+    };
+    let Some(control) = state.document.content_controls.get_mut(open.index) else {
+        return;
+    };
+    let value = || attribute_value(e, b"w:val");
+    let field = |kind| FormField {
+        kind,
+        ..FormField::default()
+    };
+    match name {
+        b"w14:checkbox" => open.field = Some(field(FormFieldKind::CheckBox)),
+        b"w:dropDownList" => open.field = Some(field(FormFieldKind::DropDown)),
+        b"w:comboBox" => open.field = Some(field(FormFieldKind::ComboBox)),
+        _ => {}
+    }
+    if let Some(field) = open.field.as_mut() {
+        match name {
+            b"w14:checked" => {
+                field.checked = attribute_value(e, b"w14:val")
+                    .is_some_and(|checked| matches!(checked.trim(), "1" | "true"));
+                field.default_checked = field.checked;
+            }
+            b"w:listItem" => {
+                let value = attribute_value(e, b"w:value").unwrap_or_default();
+                let display = attribute_value(e, b"w:displayText").unwrap_or_else(|| value.clone());
+                field.items.push((display, value));
+            }
+            _ => {}
+        }
+    }
+    match name {
+        b"w:tag" => control.tag = value(),
+        b"w:alias" => control.alias = value(),
+        b"w:id" => control.id = value().and_then(|id| id.trim().parse().ok()),
+        b"w:showingPlcHdr" => control.showing_placeholder = toggle_on(e),
+        b"w:docPartGallery" => control.gallery = value(),
+        b"w:richText" => control.kind = ContentControlKind::RichText,
+        b"w:text" => control.kind = ContentControlKind::PlainText,
+        b"w:date" => control.kind = ContentControlKind::Date,
+        b"w:dropDownList" => control.kind = ContentControlKind::DropDownList,
+        b"w:comboBox" => control.kind = ContentControlKind::ComboBox,
+        b"w:picture" => control.kind = ContentControlKind::Picture,
+        b"w14:checkbox" => control.kind = ContentControlKind::CheckBox,
+        b"w15:repeatingSection" => control.kind = ContentControlKind::RepeatingSection,
+        b"w15:repeatingSectionItem" => control.kind = ContentControlKind::RepeatingSectionItem,
+        b"w:docPartObj" | b"w:docPartList" => control.kind = ContentControlKind::BuildingBlock,
+        b"w:group" => control.kind = ContentControlKind::Group,
+        _ => {}
+    }
+}
+
+/// Reads `<w:jc w:val="both"/>` of a paragraph, None for values with no
+/// counterpart here
+pub fn read_alignment(e: &BytesStart) -> Option<Alignment> {
+    match attribute_value(e, b"w:val")?.as_str() {
+        "left" | "start" => Some(Alignment::Left),
+        "center" => Some(Alignment::Center),
+        "right" | "end" => Some(Alignment::Right),
+        "both" | "distribute" | "lowKashida" | "mediumKashida" | "highKashida"
+        | "thaiDistribute" => Some(Alignment::Justify),
+        _ => None,
+    }
+}
+
+/// Reads `<w:top w:val="single" w:sz="6" w:space="1" w:color="auto"/>` and
+/// the other sides of a `w:pBdr`, None for `nil` and `none`
+pub fn read_border(e: &BytesStart) -> Option<Border> {
+    let style = match attribute_value(e, b"w:val")?.as_str() {
+        "nil" | "none" => return None,
+        "double" | "triple" => BorderStyle::Double,
+        "dotted" => BorderStyle::Dotted,
+        "dashed" | "dashSmallGap" | "dotDash" | "dotDotDash" => BorderStyle::Dashed,
+        _ => BorderStyle::Single,
+    };
+    let number =
+        |key: &[u8]| attribute_value(e, key).and_then(|value| value.trim().parse::<f32>().ok());
+    Some(Border {
+        style,
+        width: (number(b"w:sz").unwrap_or(4.0) / 8.0).clamp(0.25, 6.0),
+        space: number(b"w:space").unwrap_or(0.0).clamp(0.0, 31.0),
+        color: attribute_value(e, b"w:color").and_then(|color| parse_color(&color)),
+    })
+}
+
+/// The background `<w:shd w:val="pct25" w:color="auto" w:fill="FFFF00"/>`
+/// gives: the fill, the color for `solid` and the two mixed for the
+/// percentage patterns. None when nothing is filled
+pub fn read_shading(e: &BytesStart) -> Option<[u8; 3]> {
+    let color = |key: &[u8]| attribute_value(e, key).and_then(|color| parse_color(&color));
+    let fill = color(b"w:fill");
+    let pattern = color(b"w:color").unwrap_or([0, 0, 0]);
+    let value = attribute_value(e, b"w:val").unwrap_or_default();
+    let percent = match value.as_str() {
+        "nil" => return None,
+        "solid" => 100,
+        _ => match value
+            .strip_prefix("pct")
+            .and_then(|n| n.parse::<u16>().ok())
+        {
+            Some(percent) => percent.min(100),
+            // `clear` and the stripe and cross patterns, shown by their fill
+            None => return fill,
+        },
+    };
+    let under = fill.unwrap_or([255, 255, 255]);
+    let mix = |over: u8, under: u8| {
+        ((over as u16 * percent + under as u16 * (100 - percent)) / 100) as u8
+    };
+    Some([
+        mix(pattern[0], under[0]),
+        mix(pattern[1], under[1]),
+        mix(pattern[2], under[2]),
+    ])
+}
+
+fn label_style(fmt: &[u8]) -> PageLabelStyle {
+    match fmt {
+        b"upperRoman" => PageLabelStyle::UpperRoman,
+        b"lowerRoman" => PageLabelStyle::LowerRoman,
+        b"upperLetter" => PageLabelStyle::UpperLetter,
+        b"lowerLetter" => PageLabelStyle::LowerLetter,
+        _ => PageLabelStyle::Decimal,
+    }
+}
+
+/// Reads `<w:pgNumType w:fmt=".." w:start=".."/>` into the current section
+fn read_page_numbering(section: &mut Section, e: &BytesStart) {
+    for a in e.attributes().flatten() {
+        match a.key.as_ref() {
+            b"w:fmt" => section.number_format = Some(label_style(&a.value)),
+            b"w:start" => section.number_start = String::from_utf8_lossy(&a.value).parse().ok(),
+            _ => {}
+        }
+    }
+}
+
+/// Reads the orientation from `<w:pgSz w:w=".." w:h=".." w:orient=".."/>`,
+/// landscape sections are laid out on the page size turned on its side
+fn read_page_size(section: &mut Section, e: &BytesStart) {
+    let (mut w, mut h, mut orient) = (0u32, 0u32, false);
+    for a in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&a.value);
+        match a.key.as_ref() {
+            b"w:w" => w = value.parse().unwrap_or(0),
+            b"w:h" => h = value.parse().unwrap_or(0),
+            b"w:orient" => orient = value == "landscape",
+            _ => {}
+        }
+    }
+    section.landscape = orient || w > h;
+}
+
+/// Elements whose content never reaches the model, with the name used in the report
+fn unsupported_element(name: &[u8]) -> Option<&'static str> {
+    Some(match name {
+        b"m:oMath" => "equation",
+        b"w:footnoteReference" => "footnote",
+        b"w:endnoteReference" => "endnote",
+        b"w:commentReference" => "comment",
+        _ => return None,
+    })
+}
+
+/// `<w:b/>` switches bold on, `<w:b w:val="0"/>` or `"false"` off again
+pub fn toggle_on(e: &BytesStart) -> bool {
+    !e.attributes()
+        .flatten()
+        .any(|a| a.key.as_ref() == b"w:val" && matches!(a.value.as_ref(), b"0" | b"false"))
+}
+
+fn is_drawing(name: &[u8]) -> bool {
+    name == b"w:drawing" || name == b"wp:inline" || name == b"wp:extent"
+}
+
+/// Reads `<wp:extent cx=".." cy=".."/>`, the size of a drawing in EMU
+fn read_extent(e: &BytesStart) -> Option<(f32, f32)> {
+    let (mut cx, mut cy) = (None, None);
+    for a in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&a.value).parse::<f32>().ok();
+        match a.key.as_ref() {
+            b"cx" => cx = value,
+            b"cy" => cy = value,
+            _ => {}
+        }
+    }
+    Some((cx? / EMU_PER_POINT, cy? / EMU_PER_POINT))
+}
+
+/// The size in the `style` of a `v:shape`, e.g. `width:414pt;height:123.75pt`
+fn read_vml_size(e: &BytesStart) -> Option<(f32, f32)> {
+    let style = e
+        .attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.as_ref() == b"style")?;
+    let style = String::from_utf8_lossy(&style.value);
+    let length = |value: &str| {
+        let value = value.trim();
+        let split = value.find(|c: char| c.is_ascii_alphabetic())?;
+        let number: f32 = value[..split].trim().parse().ok()?;
+        let scale = match &value[split..] {
+            "pt" => 1.0,
+            "in" => 72.0,
+            "cm" => 72.0 / 2.54,
+            "mm" => 72.0 / 25.4,
+            "px" => 0.75,
+            _ => return None,
+        };
+        Some(number * scale)
+    };
+    let (mut width, mut height) = (None, None);
+    for declaration in style.split(';') {
+        match declaration.split_once(':') {
+            Some((key, value)) if key.trim() == "width" => width = length(value),
+            Some((key, value)) if key.trim() == "height" => height = length(value),
+            _ => {}
+        }
+    }
+    Some((width?, height?))
+}
+
+pub fn attribute_value(e: &BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.as_ref() == key)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// Adds the text of a `w:t`, or of a character element when `glued`, which
+/// joins the runs before and after it without the space written between runs
+fn add_text(state: &mut ParserState, text: &str, glued: bool) {
+    // the result of a REF, PAGEREF or MERGEFIELD is replaced when laid out,
+    // the one of a form field becomes its value
+    if let Some(field) = state.fields.iter_mut().rev().find(|field| {
+        field.separated
+            && (field.reference.is_some() || field.merge.is_some() || field.form.is_some())
+    }) {
+        match (&mut field.reference, &mut field.form) {
+            (Some(reference), _) => reference.cached.push_str(text),
+            (_, Some(form)) => form.value.push_str(text),
+            _ => {}
+        }
+        return;
+    }
+    let joined = glued || state.glue;
+    for bookmark in &mut state.bookmarks {
+        if bookmark.text.len() < MAX_BOOKMARK_TEXT {
+            if !joined && !bookmark.text.is_empty() {
+                bookmark.text.push(' ');
+            }
+            bookmark.text.push_str(text);
+        }
+    }
+    match state.content.last_mut() {
+        Some(Inline::Run(run)) if joined => run.text.push_str(text),
+        _ => {
+            let no_table = RunProperties::default();
+            let table = open_cell(&mut state.tables).map_or(&no_table, |cell| &cell.run);
+            let format = state.styles.resolve(
+                state.paragraph_style.as_deref(),
+                state.run_style.as_deref(),
+                table,
+                &state.direct,
+            );
+            state.content.push(Inline::Run(Run {
+                text: text.to_string(),
+                font: format.font,
+                bold: format.bold.unwrap_or(false),
+                italic: format.italic.unwrap_or(false),
+            }))
+        }
+    }
+    state.glue = glued;
+}
+
+/// The bookmark a REF or PAGEREF field instruction points at, e.g.
+/// ` PAGEREF _Ref123 \h `; other fields keep the result Word stored
+fn parse_reference(instr: &str) -> Option<Reference> {
+    let mut words = instr.split_whitespace();
+    let page = match words.next()?.to_ascii_uppercase().as_str() {
+        "REF" => false,
+        "PAGEREF" => true,
+        _ => return None,
+    };
+    let bookmark = words.next()?.trim_matches('"');
+    (!bookmark.starts_with('\\')).then(|| Reference {
+        bookmark: bookmark.to_string(),
+        page,
+        cached: String::new(),
+    })
+}
+
+/// The words of field instructions, quoted ones with the quotes taken off
+fn field_arguments(instr: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut chars = instr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            arguments.push(chars.by_ref().take_while(|&c| c != '"').collect());
+        } else {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+            arguments.push(word);
+        }
+    }
+    arguments
+}
+
+/// The field a MERGEFIELD instruction takes its value from, e.g.
+/// ` MERGEFIELD "First Name" \b "Dear " \* MERGEFORMAT `
+fn parse_merge_field(instr: &str) -> Option<MergeField> {
+    let arguments = field_arguments(instr);
+    let (keyword, arguments) = arguments.split_first()?;
+    if !keyword.eq_ignore_ascii_case("MERGEFIELD") {
+        return None;
+    }
+    let (name, switches) = arguments.split_first()?;
+    let mut field = MergeField {
+        name: name.clone(),
+        ..MergeField::default()
+    };
+    for pair in switches.windows(2) {
+        match pair[0].to_ascii_lowercase().as_str() {
+            "\\b" => field.before = pair[1].clone(),
+            "\\f" => field.after = pair[1].clone(),
+            _ => {}
+        }
+    }
+    Some(field)
+}
+
+/// FORMTEXT and FORMCHECKBOX, drop-down fields keep the result Word stored
+fn form_kind(instr: &str) -> Option<FormFieldKind> {
+    match instr
+        .split_whitespace()
+        .next()?
+        .to_ascii_uppercase()
+        .as_str()
+    {
+        "FORMTEXT" => Some(FormFieldKind::Text),
+        "FORMCHECKBOX" => Some(FormFieldKind::CheckBox),
+        _ => None,
+    }
+}
+
+/// Once the instructions are read, finds what the field refers to and keeps
+/// the `w:ffData` of a form field, forgetting the one of other fields
+fn read_instructions(field: &mut Field) {
+    field.reference = parse_reference(&field.instr);
+    field.merge = parse_merge_field(&field.instr);
+    match (form_kind(&field.instr), field.form.as_mut()) {
+        (Some(kind), Some(form)) => form.kind = kind,
+        (Some(kind), None) => {
+            field.form = Some(FormField {
+                kind,
+                ..FormField::default()
+            })
+        }
+        (None, _) => field.form = None,
+    }
+}
+
+/// Reads an element of a `w:ffData` into the form field being started
+fn read_form_data(state: &mut ParserState, e: &BytesStart, name: &[u8]) {
+    let Some(field) = state.fields.last_mut() else {
+        return;
+    };
+    let form = field.form.get_or_insert_with(FormField::default);
+    let value = || attribute_value(e, b"w:val");
+    match name {
+        b"w:name" => form.name = value().filter(|name| !name.is_empty()),
+        b"w:checkBox" => form.kind = FormFieldKind::CheckBox,
+        b"w:default" if form.kind == FormFieldKind::CheckBox => form.default_checked = toggle_on(e),
+        b"w:default" => form.default = value().unwrap_or_default(),
+        b"w:checked" => field.form_checked = Some(toggle_on(e)),
+        b"w:maxLength" => form.max_length = value().and_then(|n| n.trim().parse().ok()),
+        // in half-points
+        b"w:size" => {
+            form.size = value()
+                .and_then(|size| size.trim().parse::<f32>().ok())
+                .map(|size| size / 2.0)
+                .filter(|size| *size > 0.0)
+        }
+        _ => {}
+    }
+}
+
+fn start_field(state: &mut ParserState, instr: Option<String>) {
+    let mut field = Field::default();
+    if let Some(instr) = instr {
+        field.separated = true;
+        field.instr = instr;
+        read_instructions(&mut field);
+    }
+    state.fields.push(field);
+}
+
+fn end_field(state: &mut ParserState) {
+    let Some(mut field) = state.fields.pop() else {
+        return;
+    };
+    // a field inside the instructions of another one is never shown
+    let shown = state.fields.iter().all(|outer| outer.separated);
+    // check boxes and fields Word never updated have no result, they end
+    // right after their instructions
+    if !field.separated {
+        read_instructions(&mut field);
+    }
+    if let Some(mut form) = field.form
+        && shown
+    {
+        form.value = form.value.trim().to_string();
+        if form.kind == FormFieldKind::CheckBox {
+            form.checked = field.form_checked.unwrap_or(form.default_checked);
+        }
+        state.content.push(Inline::FormField(form));
+        state.glue = false;
+        return;
+    }
+    let inline = match (field.reference, field.merge) {
+        (Some(reference), _) => Inline::Reference(reference),
+        (_, Some(merge)) => Inline::MergeField(merge),
+        _ => return,
+    };
+    if shown {
+        state.content.push(inline);
+        state.glue = false;
+    }
+}
+
+fn count_unsupported(state: &mut ParserState, element: &str) {
+    *state
+        .document
+        .unsupported
+        .entry(element.to_string())
+        .or_default() += 1;
+}
+
+/// Counts `element` and leaves a placeholder for it where it was
+fn omit(state: &mut ParserState, element: &str) {
+    count_unsupported(state, element);
+    state
+        .content
+        .push(Inline::Omitted(Omitted::new(element, None)));
+}
+
+/// Handles a start tag, `empty` for self-closing ones
+fn start_element(state: &mut ParserState, e: &BytesStart, empty: bool) {
+    let name = e.name();
+    let name = name.as_ref();
+    // footnote and comment marks are too small to stand in for what they point at
+    match unsupported_element(name) {
+        Some(element @ "equation") => omit(state, element),
+        Some(element) => count_unsupported(state, element),
+        None => {}
+    }
+
+    if state.in_sdt_pr {
+        read_control_property(state, e, name);
+    }
+    if state.in_ff_data {
+        read_form_data(state, e, name);
+    }
+    if state.in_table_borders
+        && let Some(table) = state.tables.last_mut()
+    {
+        match table.rows.last_mut().and_then(|row| row.cells.last_mut()) {
+            Some(cell) if state.in_cell_props => cell.borders.read(e, name),
+            _ => table.borders.read(e, name),
+        }
+    }
+    if state.in_cell_margins
+        && let Some(table) = state.tables.last_mut()
+    {
+        let margins = match table.rows.last_mut().and_then(|row| row.cells.last_mut()) {
+            Some(cell) if state.in_cell_props => &mut cell.margins,
+            _ => &mut table.margins,
+        };
+        read_margin(margins, e, name);
+    }
+    if state.in_borders {
+        let borders = &mut state.borders;
+        match name {
+            b"w:top" => borders.top = read_border(e),
+            b"w:bottom" => borders.bottom = read_border(e),
+            b"w:left" | b"w:start" => borders.left = read_border(e),
+            b"w:right" | b"w:end" => borders.right = read_border(e),
+            b"w:between" => borders.between = read_border(e),
+            _ => {}
+        }
+    }
+
+    match name {
+        b"w:p" if !empty => state.paragraph_depth += 1,
+        b"w:r" => {
+            state.direct = RunProperties::default();
+            state.run_style = None;
+        }
+        b"w:b" | b"w:i" | b"w:rFonts" => state.direct.read(e, name),
+        b"w:rStyle" => state.run_style = attribute_value(e, b"w:val"),
+        b"w:pStyle" if state.in_paragraph_props => {
+            state.paragraph_style = attribute_value(e, b"w:val")
+        }
+        b"w:numId" if state.in_paragraph_props => state.list = attribute_value(e, b"w:val"),
+        b"w:ind" if state.in_paragraph_props => state.indent.read(e),
+        b"w:jc" if state.in_paragraph_props => state.alignment = read_alignment(e),
+        b"w:tabs" if !empty => state.in_tabs = true,
+        b"w:tab" if state.in_tabs && state.in_paragraph_props => state.tabs.read(e),
+        // tab stops elsewhere, of a revision or in a text box, are left out
+        b"w:tab" if state.in_tabs => {}
+        // a tab character in a run
+        b"w:tab" => add_text(state, "\t", true),
+        b"w:ilvl" if state.in_paragraph_props => {
+            state.list_level = attribute_value(e, b"w:val").and_then(|v| v.trim().parse().ok())
+        }
+        b"w:t" if !empty => state.in_text = true,
+        // manual page break: <w:br w:type="page"/>
+        b"w:br"
+            if e.attributes()
+                .flatten()
+                .any(|a| a.key.as_ref() == b"w:type" && a.value.as_ref() == b"page") =>
+        {
+            state.content.push(Inline::PageBreak);
+        }
+        // Word's hyphens are elements, not characters in the text
+        b"w:noBreakHyphen" => add_text(state, "-", true),
+        b"w:softHyphen" => add_text(state, "\u{ad}", true),
+        b"w:cr" => state.content.push(Inline::LineBreak),
+        b"w:sym" => {
+            let font = attribute_value(e, b"w:font").unwrap_or_default();
+            let code = attribute_value(e, b"w:char")
+                .and_then(|code| u32::from_str_radix(code.trim(), 16).ok());
+            match code.and_then(|code| symbol_char(&font, code)) {
+                Some(c) => add_text(state, c.encode_utf8(&mut [0; 4]), true),
+                None => count_unsupported(state, "symbol character"),
+            }
+        }
+        // complex fields run from begin over their instructions and the
+        // result Word stored to end, possibly across paragraphs
+        b"w:fldChar" => match attribute_value(e, b"w:fldCharType").as_deref() {
+            Some("begin") => start_field(state, None),
+            Some("separate") => {
+                if let Some(field) = state.fields.last_mut() {
+                    field.separated = true;
+                    read_instructions(field);
+                }
+            }
+            Some("end") => end_field(state),
+            _ => {}
+        },
+        b"w:instrText" if !empty => state.in_instr = true,
+        // legacy form field settings, inside the w:fldChar that begins it
+        b"w:ffData" if !empty => state.in_ff_data = true,
+        b"w:fldSimple" => {
+            start_field(
+                state,
+                Some(attribute_value(e, b"w:instr").unwrap_or_default()),
+            );
+            if empty {
+                end_field(state);
+            }
+        }
+        b"w:bookmarkStart" => {
+            if let (Some(id), Some(name)) =
+                (attribute_value(e, b"w:id"), attribute_value(e, b"w:name"))
+            {
+                state.content.push(Inline::Bookmark(name.clone()));
+                state.bookmarks.push(OpenBookmark {
+                    id,
+                    name,
+                    text: String::new(),
+                });
+            }
+        }
+        b"w:bookmarkEnd" => {
+            let id = attribute_value(e, b"w:id");
+            if let Some(i) = state
+                .bookmarks
+                .iter()
+                .position(|b| Some(&b.id) == id.as_ref())
+            {
+                let bookmark = state.bookmarks.remove(i);
+                state
+                    .document
+                    .bookmarks
+                    .entry(bookmark.name)
+                    .or_insert(bookmark.text);
+            }
+        }
+        // content controls are transparent, their content is read like the
+        // body around it and only their properties are kept aside
+        b"w:sdt" if !empty => {
+            state.controls.push(OpenControl {
+                index: state.document.content_controls.len(),
+                field: None,
+                content_start: None,
+            });
+            let start = block_position(state);
+            state.document.content_controls.push(ContentControl {
+                start,
+                end: start,
+                ..ContentControl::default()
+            });
+        }
+        b"w:sdtPr" if !empty => state.in_sdt_pr = true,
+        // the borders and shading of paragraphs in text boxes would go
+        // around the outer one
+        b"w:pPr" if !empty && state.paragraph_depth <= 1 && !state.in_props_change => {
+            state.in_paragraph_props = true
+        }
+        b"w:pPrChange" if !empty => {
+            state.in_props_change = true;
+            state.in_paragraph_props = false;
+        }
+        // the paragraph mark's run properties come after the paragraph's own
+        b"w:rPr" => state.in_paragraph_props = false,
+        b"w:pBdr" if !empty && state.in_paragraph_props => state.in_borders = true,
+        b"w:shd" if state.in_paragraph_props => state.shading = read_shading(e),
+        // tables in text boxes are read as part of the paragraph around them
+        b"w:tbl" if !empty && state.paragraph_depth == 0 => state.tables.push(OpenTable::default()),
+        b"w:tblPr" if !empty && !state.in_props_change => state.in_table_props = true,
+        b"w:tcPr" if !empty && !state.in_props_change => state.in_cell_props = true,
+        b"w:tblPrChange" | b"w:tcPrChange" if !empty => state.in_props_change = true,
+        b"w:tblBorders" | b"w:tcBorders"
+            if !empty && (state.in_table_props || state.in_cell_props) =>
+        {
+            state.in_table_borders = true
+        }
+        b"w:tblCellMar" | b"w:tcMar" if !empty && (state.in_table_props || state.in_cell_props) => {
+            state.in_cell_margins = true
+        }
+        b"w:vAlign" if state.in_cell_props => {
+            if let Some(cell) = open_cell(&mut state.tables) {
+                cell.vertical_align = match attribute_value(e, b"w:val").as_deref() {
+                    Some("center") => VerticalAlign::Center,
+                    Some("bottom") => VerticalAlign::Bottom,
+                    _ => VerticalAlign::Top,
+                };
+            }
+        }
+        b"w:textDirection" if state.in_cell_props => {
+            if let Some(cell) = open_cell(&mut state.tables) {
+                cell.text_direction = match attribute_value(e, b"w:val").as_deref() {
+                    Some("btLr") => TextDirection::BottomToTop,
+                    Some("tbRl" | "tbRlV" | "tbLrV") => TextDirection::TopToBottom,
+                    _ => TextDirection::Horizontal,
+                };
+            }
+        }
+        b"w:shd" if state.in_cell_props => {
+            if let Some(cell) = open_cell(&mut state.tables) {
+                cell.shading = Some(read_shading(e));
+            }
+        }
+        b"w:tblStyle" | b"w:tblLook" | b"w:tblW" | b"w:tblLayout" | b"w:gridCol"
+            if state.paragraph_depth == 0 =>
+        {
+            if let Some(table) = state.tables.last_mut() {
+                match name {
+                    b"w:tblStyle" if state.in_table_props => {
+                        table.style = attribute_value(e, b"w:val")
+                    }
+                    b"w:tblLook" if state.in_table_props => table.look = TableLook::read(e),
+                    b"w:tblW" if state.in_table_props => table.width = read_width(e),
+                    b"w:tblLayout" if state.in_table_props => {
+                        table.fixed = attribute_value(e, b"w:type").as_deref() == Some("fixed")
+                    }
+                    b"w:gridCol" => {
+                        let width = attribute_value(e, b"w:w")
+                            .and_then(|w| w.trim().parse::<f32>().ok())
+                            .filter(|w| w.is_finite() && *w > 0.0);
+                        table.grid.push(width.map_or(0.0, |w| w / 20.0));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        b"w:tcW" | b"w:gridSpan" if state.in_cell_props => {
+            if let Some(cell) = open_cell(&mut state.tables) {
+                match name {
+                    b"w:tcW" => cell.width = read_width(e),
+                    _ => {
+                        cell.span = attribute_value(e, b"w:val")
+                            .and_then(|span| span.trim().parse().ok())
+                            .unwrap_or(1)
+                            .clamp(1, 64)
+                    }
+                }
+            }
+        }
+        b"w:tr" if state.paragraph_depth == 0 => {
+            if let Some(table) = state.tables.last_mut() {
+                table.rows.push(OpenRow::default());
+            }
+        }
+        b"w:tc" if state.paragraph_depth == 0 => start_cell(state),
+        b"w:trPr" if !empty && !state.in_props_change => state.in_row_props = true,
+        b"w:trPrChange" if !empty => state.in_props_change = true,
+        b"w:trHeight" | b"w:tblHeader" | b"w:cantSplit" if state.in_row_props => {
+            if let Some(row) = state.tables.last_mut().and_then(|t| t.rows.last_mut()) {
+                match name {
+                    b"w:trHeight" => row.height = read_row_height(e),
+                    b"w:tblHeader" => row.header = toggle_on(e),
+                    _ => row.cant_split = toggle_on(e),
+                }
+            }
+        }
+        b"w:sdtContent" if !empty => {
+            let start = state.content.len();
+            if let Some((open, outer)) = state.controls.split_last_mut() {
+                if outer.iter().any(|outer| outer.field.is_some()) {
+                    open.field = None;
+                } else if open.field.is_some() && state.paragraph_depth > 0 {
+                    open.content_start = Some(start);
+                }
+            }
+        }
+        b"w:pgSz" => read_page_size(&mut state.section, e),
+        b"w:pgNumType" => read_page_numbering(&mut state.section, e),
+        b"w:sectPr" if empty => end_section(state),
+        // inside w:drawing, which already set `in_drawing`
+        b"wp:extent" => state.extent = read_extent(e),
+        // chart reference in the graphic data of a drawing, filled in once
+        // the main part is read
+        b"c:chart" => {
+            if let Some(rid) = e
+                .attributes()
+                .with_checks(false)
+                .flatten()
+                .find(|a| a.key.as_ref() == b"r:id")
+            {
+                state.content.push(Inline::Chart(Chart {
+                    rid: String::from_utf8_lossy(&rid.value).into_owned(),
+                    size: state.extent,
+                    ..Chart::default()
+                }));
+            }
+        }
+        // SmartArt, the data model holds the text and the layout how it is arranged
+        b"dgm:relIds" => {
+            let (mut data, mut layout) = (None, None);
+            for a in e.attributes().with_checks(false).flatten() {
+                let value = || String::from_utf8_lossy(&a.value).into_owned();
+                match a.key.as_ref() {
+                    b"r:dm" => data = Some(value()),
+                    b"r:lo" => layout = Some(value()),
+                    _ => {}
+                }
+            }
+            if let Some(rid) = data {
+                state.content.push(Inline::Diagram(Diagram {
+                    rid,
+                    layout_rid: layout,
+                    size: state.extent,
+                    ..Diagram::default()
+                }));
+            }
+        }
+        // an OLE object, the VML shape inside carries its preview
+        b"w:object" if !empty => state.object = Some(EmbeddedObject::default()),
+        b"v:shape" => {
+            if let Some(object) = state.object.as_mut() {
+                object.size = read_vml_size(e);
+            }
+        }
+        // legacy VML picture, `o:relid` where it was pasted from other Office applications
+        b"v:imagedata" => {
+            let rid = attribute_value(e, b"r:id").or_else(|| attribute_value(e, b"o:relid"));
+            if let Some(object) = state.object.as_mut() {
+                object.preview_rid = rid;
+            } else if let Some(rid) = rid {
+                state.content.push(Inline::Image(Image { rid }));
+                if let Some(pict) = state.pict.as_mut() {
+                    *pict = true;
+                }
+            }
+        }
+        b"w:pict" if empty => omit(state, "VML drawing"),
+        b"w:pict" => state.pict = Some(false),
+        b"o:OLEObject" => {
+            if let Some(object) = state.object.as_mut() {
+                object.prog_id = attribute_value(e, b"ProgID");
+            }
+        }
+        b"w:altChunk" => {
+            if let Some(rid) = e
+                .attributes()
+                .with_checks(false)
+                .flatten()
+                .find(|a| a.key.as_ref() == b"r:id")
+            {
+                state.alt_chunks.push(AltChunk {
+                    rid: String::from_utf8_lossy(&rid.value).into_owned(),
+                    section: state.document.sections.len(),
+                    block: state.section.blocks.len(),
+                });
+            }
+        }
+        _ if is_drawing(name) && !empty => state.in_drawing = true,
+        // inline image reference
+        _ if name.ends_with(b"blip") => {
+            if let Some(attr) = e
+                .attributes()
+                .with_checks(false)
+                .filter_map(|a| a.ok())
+                .find(|a| a.key.as_ref().ends_with(b"embed"))
+            {
+                let rid = String::from_utf8_lossy(&attr.value).to_string();
+                state.content.push(Inline::Image(Image { rid }));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn end_element(state: &mut ParserState, name: &[u8]) {
+    match name {
+        b"w:t" => state.in_text = false,
+        b"w:instrText" => state.in_instr = false,
+        b"w:ffData" => state.in_ff_data = false,
+        b"w:fldSimple" => end_field(state),
+        b"w:p" => {
+            state.paragraph_depth = state.paragraph_depth.saturating_sub(1);
+            // paragraphs nested in text boxes stay part of the outer one
+            if state.paragraph_depth == 0 {
+                end_paragraph(state);
+                if state.section_break {
+                    finish_section(state);
+                }
+            }
+        }
+        b"w:sectPr" => end_section(state),
+        b"w:sdtPr" => state.in_sdt_pr = false,
+        b"w:pBdr" => state.in_borders = false,
+        b"w:pPr" => state.in_paragraph_props = false,
+        b"w:tabs" => state.in_tabs = false,
+        b"w:pPrChange" | b"w:tblPrChange" | b"w:tcPrChange" | b"w:trPrChange" => {
+            state.in_props_change = false
+        }
+        b"w:trPr" => state.in_row_props = false,
+        b"w:tblPr" => state.in_table_props = false,
+        b"w:tcPr" => state.in_cell_props = false,
+        b"w:tblBorders" | b"w:tcBorders" => state.in_table_borders = false,
+        b"w:tblCellMar" | b"w:tcMar" => state.in_cell_margins = false,
+        b"w:tbl" if state.paragraph_depth == 0 => end_table(state),
+        b"w:sdt" => end_control(state),
+        // shapes and text boxes aren't drawn, only the pictures
+        // the guard also resets `pict` for the next one
+        b"w:pict" if state.pict.take() == Some(false) => omit(state, "VML drawing"),
+        b"w:object" => {
+            if let Some(object) = state.object.take() {
+                state.content.push(Inline::Object(object));
+            }
+        }
+        _ if is_drawing(name) => state.in_drawing = false,
+        _ => {}
+    }
+}
+
+fn end_control(state: &mut ParserState) {
+    let Some(open) = state.controls.pop() else {
+        return;
+    };
+    let (section, block) = block_position(state);
+    // a control inside a paragraph ends with it
+    let end = (section, block + usize::from(state.paragraph_depth > 0));
+    let Some(control) = state.document.content_controls.get_mut(open.index) else {
+        return;
+    };
+    control.end = end;
+    let Some(mut field) = open.field else {
+        return;
+    };
+    let placeholder = control.showing_placeholder;
+    let start = control.start;
+    let content = match open.content_start {
+        Some(at) if state.paragraph_depth > 0 && at <= state.content.len() => {
+            state.content.drain(at..).collect()
+        }
+        // a control around one whole paragraph
+        None if state.paragraph_depth == 0 && start == (section, block.wrapping_sub(1)) => {
+            match state.section.blocks.last_mut() {
+                Some(Block::Paragraph(paragraph)) => std::mem::take(&mut paragraph.content),
+                Some(Block::Table(_) | Block::Omitted(_)) | None => return,
+            }
+        }
+        _ => return,
+    };
+    if !placeholder && field.kind != FormFieldKind::CheckBox {
+        let text: String = content
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Run(run) => Some(run.text.as_str()),
+                _ => None,
+            })
+            .collect();
+        field.value = text.trim().to_string();
+    }
+    let inline = Inline::ControlField(ControlField { field, content });
+    match state.section.blocks.last_mut() {
+        Some(Block::Paragraph(paragraph)) if state.paragraph_depth == 0 => {
+            paragraph.content.push(inline)
+        }
+        _ => state.content.push(inline),
+    }
+    state.glue = false;
+}
+
+fn end_paragraph(state: &mut ParserState) {
+    state.glue = false;
+    let content = std::mem::take(&mut state.content);
+    let borders = std::mem::take(&mut state.borders);
+    let shading = state.shading.take();
+    // a `w:numPr` of the paragraph's own wins over the one of its style,
+    // `w:numId` 0 takes it out of the list
+    let style = state.paragraph_style.take();
+    let (list, level) = state.styles.numbering(style.as_deref());
+    let level = state.list_level.take().or(level);
+    let direct_list = state.list.is_some();
+    let numbered = state
+        .list
+        .take()
+        .or(list)
+        .and_then(|list| state.numbering.next_label(&list, level, style.as_deref()));
+    // the indents of a list level win over those of the paragraph style
+    // when the paragraph is put in the list itself, not by its style
+    let mut indent = std::mem::take(&mut state.indent);
+    let style_indent = state.styles.indent(style.as_deref());
+    let (label, list_indent) = numbered.unzip();
+    let list_indent = list_indent.unwrap_or_default();
+    let (nearer, further) = match direct_list {
+        true => (list_indent, style_indent),
+        false => (style_indent, list_indent),
+    };
+    indent.inherit(&nearer);
+    indent.inherit(&further);
+    let alignment = state.alignment.take();
+    let alignment = alignment.or_else(|| state.styles.alignment(style.as_deref()));
+    let mut tabs = state.styles.tabs(style.as_deref());
+    std::mem::take(&mut state.tabs).apply(&mut tabs);
+    push_block(
+        state,
+        Block::Paragraph(Paragraph {
+            content,
+            borders,
+            shading,
+            label,
+            indent: indent.indent(),
+            alignment: alignment.unwrap_or_default(),
+            tabs,
+        }),
+    );
+}
+
+/// Ends the innermost table, formatting each cell as the table style says
+/// for where it turned out to be; tables without cells are left out
+fn end_table(state: &mut ParserState) {
+    let Some(open) = state.tables.pop() else {
+        return;
+    };
+    let style = open
+        .format
+        .unwrap_or_else(|| state.styles.table_style(open.style.as_deref()));
+    let rows = open.rows.len();
+    let spans = |row: &OpenRow| row.cells.iter().map(|cell| cell.span.max(1)).sum::<usize>();
+    let columns = match open.grid.len() {
+        0 => open.rows.iter().map(spans).max().unwrap_or(0),
+        columns => columns,
+    };
+    let mut table = Table {
+        grid: open.grid,
+        width: open.width,
+        fixed: open.fixed,
+        ..Table::default()
+    };
+    for (r, row) in open.rows.into_iter().enumerate() {
+        let mut cells = Vec::new();
+        let mut column = 0;
+        for cell in row.cells {
+            let at = CellPosition {
+                row: (r, rows),
+                column: (column, columns),
+            };
+            column += cell.span.max(1);
+            let format = style.cell(&open.look, &open.borders, at);
+            let mut blocks = cell.blocks;
+            // the last row and column were not known when its runs were read
+            if format.run != cell.run {
+                restyle(&mut blocks, &cell.run, &format.run);
+            }
+            let edge = |direct: Option<Option<Border>>, styled| direct.or(styled).flatten();
+            let (direct, styled) = (cell.borders, format.borders);
+            cells.push(TableCell {
+                blocks,
+                shading: cell.shading.unwrap_or(format.shading.flatten()),
+                borders: CellBorders {
+                    top: edge(direct.top, styled.top),
+                    bottom: edge(direct.bottom, styled.bottom),
+                    left: edge(direct.left, styled.left),
+                    right: edge(direct.right, styled.right),
+                },
+                margins: cell.margins,
+                vertical_align: cell.vertical_align,
+                text_direction: cell.text_direction,
+                span: cell.span.max(1),
+                width: cell.width,
+            });
+        }
+        table.rows.push(TableRow {
+            cells,
+            height: row.height,
+            header: row.header,
+            cant_split: row.cant_split,
+        });
+    }
+    if table.rows.iter().any(|row| !row.cells.is_empty()) {
+        push_block(state, Block::Table(table));
+    }
+}
+
+/// Gives the runs in `blocks` what `format` says where it differs from
+/// `was`, the table style formatting they were read with
+fn restyle(blocks: &mut [Block], was: &RunProperties, format: &RunProperties) {
+    fn restyle_content(content: &mut [Inline], was: &RunProperties, format: &RunProperties) {
+        for inline in content {
+            match inline {
+                Inline::Run(run) => {
+                    if format.font.is_some() && format.font != was.font {
+                        run.font.clone_from(&format.font);
+                    }
+                    if let Some(bold) = format.bold.filter(|_| format.bold != was.bold) {
+                        run.bold = bold;
+                    }
+                    if let Some(italic) = format.italic.filter(|_| format.italic != was.italic) {
+                        run.italic = italic;
+                    }
+                }
+                Inline::ControlField(control) => restyle_content(&mut control.content, was, format),
+                _ => {}
+            }
+        }
+    }
+    for paragraph in blocks.iter_mut().flat_map(Block::paragraphs_mut) {
+        restyle_content(&mut paragraph.content, was, format);
+    }
+}
+
+/// Reads a preferred width, `<w:tcW w:w="2880" w:type="dxa"/>` in
+/// twentieths of a point or `w:type="pct"` in fiftieths of a percent (or
+/// as "50%"), None for auto
+fn read_width(e: &BytesStart) -> Option<Width> {
+    let value = attribute_value(e, b"w:w")?;
+    let value = value.trim();
+    let number = |value: &str| {
+        value
+            .parse::<f32>()
+            .ok()
+            .filter(|n| n.is_finite() && *n > 0.0)
+    };
+    match attribute_value(e, b"w:type").as_deref() {
+        Some("pct") => match value.strip_suffix('%') {
+            Some(percent) => number(percent),
+            None => number(value).map(|n| n / 50.0),
+        }
+        .map(Width::Percent),
+        None | Some("dxa") => number(value).map(|n| Width::Points(n / 20.0)),
+        _ => None,
+    }
+}
+
+/// Reads `<w:trHeight w:val="400" w:hRule="exact"/>`, in twentieths of a
+/// point; without a rule the height is a minimum
+fn read_row_height(e: &BytesStart) -> Option<RowHeight> {
+    let height = attribute_value(e, b"w:val")
+        .and_then(|height| height.trim().parse::<f32>().ok())
+        .filter(|height| height.is_finite() && *height > 0.0)?
+        .min(MAX_ROW_HEIGHT * 20.0)
+        / 20.0;
+    match attribute_value(e, b"w:hRule").as_deref() {
+        Some("exact") => Some(RowHeight::Exact(height)),
+        Some("auto") => None,
+        _ => Some(RowHeight::AtLeast(height)),
+    }
+}
+
+/// Reads the margin element `name` of a `w:tblCellMar` or `w:tcMar`,
+/// e.g. `<w:left w:w="108" w:type="dxa"/>` in twentieths of a point;
+/// percentages of the table width are left out
+fn read_margin(margins: &mut CellMargins, e: &BytesStart, name: &[u8]) {
+    let edge = match name {
+        b"w:top" => &mut margins.top,
+        b"w:bottom" => &mut margins.bottom,
+        b"w:left" | b"w:start" => &mut margins.left,
+        b"w:right" | b"w:end" => &mut margins.right,
+        _ => return,
+    };
+    let width = match attribute_value(e, b"w:type").as_deref() {
+        None | Some("dxa") => attribute_value(e, b"w:w").and_then(|w| w.trim().parse::<f32>().ok()),
+        Some("nil") => Some(0.0),
+        _ => None,
+    };
+    if let Some(width) = width.filter(|w| w.is_finite()) {
+        *edge = (width / 20.0).clamp(0.0, 72.0);
+    }
+}
+
+/// Adds `block` to the innermost open table cell, or the section outside tables
+fn push_block(state: &mut ParserState, block: Block) {
+    match open_cell(&mut state.tables) {
+        Some(cell) => cell.blocks.push(block),
+        None => state.section.blocks.push(block),
+    }
+}
+
+/// The last cell of the innermost open table, the one being read
+fn open_cell(tables: &mut [OpenTable]) -> Option<&mut OpenCell> {
+    tables.last_mut()?.rows.last_mut()?.cells.last_mut()
+}
+
+/// Starts a cell, with the run formatting the table style gives it as far
+/// as it can tell before the table ends
+fn start_cell(state: &mut ParserState) {
+    let Some(table) = state.tables.last_mut() else {
+        return;
+    };
+    let format = table
+        .format
+        .get_or_insert_with(|| state.styles.table_style(table.style.as_deref()));
+    let columns = match table.grid.len() {
+        0 => usize::MAX,
+        columns => columns,
+    };
+    let Some(row_index) = table.rows.len().checked_sub(1) else {
+        return;
+    };
+    let row = &mut table.rows[row_index].cells;
+    let at = CellPosition {
+        row: (row_index, usize::MAX),
+        column: (row.iter().map(|cell| cell.span.max(1)).sum(), columns),
+    };
+    let run = format.cell(&table.look, &table.borders, at).run;
+    row.push(OpenCell {
+        run,
+        margins: table.margins,
+        ..OpenCell::default()
+    });
+}
+
+fn end_section(state: &mut ParserState) {
+    if state.paragraph_depth > 0 {
+        state.section_break = true;
+    } else {
+        finish_section(state);
+    }
+}
+
+fn finish_section(state: &mut ParserState) {
+    let section = std::mem::take(&mut state.section);
+    state.document.sections.push(section);
+    state.section_break = false;
+}
+
+/// Reads the parts the charts and SmartArt diagrams of `document` point at,
+/// those whose part is missing or has nothing that can be drawn are taken
+/// out again
+fn read_graphics<R: Read + Seek>(
+    document: &mut Document,
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    rel_map: &HashMap<String, Relationship>,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<()> {
+    let unsupported = &mut document.unsupported;
+    for section in &mut document.sections {
+        for paragraph in section.blocks.iter_mut().flat_map(Block::paragraphs_mut) {
+            let mut result = Ok(());
+            paragraph.content.retain_mut(|inline| {
+                let (read, element, rid, empty, size) = match inline {
+                    Inline::Chart(_) | Inline::Diagram(_) if result.is_err() => return false,
+                    Inline::Chart(chart) => (
+                        read_chart(chart, archive, budget, rel_map, strict, warnings),
+                        "chart",
+                        &chart.rid,
+                        chart.series.is_empty(),
+                        chart.size,
+                    ),
+                    Inline::Diagram(diagram) => (
+                        read_diagram(diagram, archive, budget, rel_map, strict, warnings),
+                        "SmartArt diagram",
+                        &diagram.rid,
+                        diagram.nodes.is_empty(),
+                        diagram.size,
+                    ),
+                    _ => return true,
+                };
+                let what = format!("{} {}", element, rid);
+                let omitted = Inline::Omitted(Omitted::new(element, size));
+                match read {
+                    Ok(Some(reason)) => {
+                        warnings.push(Warning::DroppedContent { what, reason });
+                        *inline = omitted;
+                        true
+                    }
+                    Ok(None) if empty => {
+                        *unsupported.entry(element.to_string()).or_default() += 1;
+                        *inline = omitted;
+                        true
+                    }
+                    Ok(None) => true,
+                    Err(e) => {
+                        result = Err(e);
+                        false
+                    }
+                }
+            });
+            result?;
+        }
+    }
+    Ok(())
+}
+
+/// Fills in `chart` from its part, Some with the reason when it can't be read
+fn read_chart<R: Read + Seek>(
+    chart: &mut Chart,
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    rel_map: &HashMap<String, Relationship>,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<String>> {
+    let Some(rel) = rel_map.get(&chart.rid) else {
+        return Ok(Some("no such relationship".to_string()));
+    };
+    let Some(xml) = budget.read_part(archive, &rel.target)? else {
+        return Ok(Some(format!("{} is missing", rel.target)));
+    };
+    let parsed = parse_chart(&xml, &rel.target, strict, warnings)
+        .map_err(|e| ConvertError::invalid_docx(&rel.target, e))?;
+    if let Some(parsed) = parsed {
+        debug!(part = %rel.target, kind = ?parsed.kind, series = parsed.series.len(), "read a chart");
+        *chart = Chart {
+            rid: std::mem::take(&mut chart.rid),
+            size: chart.size,
+            ..parsed
+        };
+    }
+    Ok(None)
+}
+
+/// The styles part the main part points at, no styles without one
+fn read_style_part<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    rel_map: &HashMap<String, Relationship>,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Styles> {
+    let Some(rel) = rel_map.values().find(|rel| rel.kind == "styles") else {
+        return Ok(Styles::default());
+    };
+    let Some(xml) = budget.read_part(archive, &rel.target)? else {
+        return Ok(Styles::default());
+    };
+    let mut errors = XmlErrors::new(&rel.target, strict);
+    let styles =
+        read_styles(&xml, &mut errors).map_err(|e| ConvertError::invalid_docx(&rel.target, e))?;
+    errors.finish(warnings);
+    Ok(styles)
+}
+
+/// The numbering part the main part points at, no lists without one
+fn read_numbering_part<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    rel_map: &HashMap<String, Relationship>,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Numbering> {
+    let Some(rel) = rel_map.values().find(|rel| rel.kind == "numbering") else {
+        return Ok(Numbering::default());
+    };
+    let Some(xml) = budget.read_part(archive, &rel.target)? else {
+        return Ok(Numbering::default());
+    };
+    let mut errors = XmlErrors::new(&rel.target, strict);
+    let numbering = read_numbering(&xml, &mut errors)
+        .map_err(|e| ConvertError::invalid_docx(&rel.target, e))?;
+    errors.finish(warnings);
+    Ok(numbering)
+}
+
+/// Fills in `diagram` from its data model part, and from its layout part
+/// whether the boxes are connected, Some with the reason when it can't be read
+fn read_diagram<R: Read + Seek>(
+    diagram: &mut Diagram,
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    rel_map: &HashMap<String, Relationship>,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<String>> {
+    let Some(rel) = rel_map.get(&diagram.rid) else {
+        return Ok(Some("no such relationship".to_string()));
+    };
+    let Some(xml) = budget.read_part(archive, &rel.target)? else {
+        return Ok(Some(format!("{} is missing", rel.target)));
+    };
+    diagram.nodes = parse_data_model(&xml, &rel.target, strict, warnings)
+        .map_err(|e| ConvertError::invalid_docx(&rel.target, e))?;
+    // without its layout the boxes are drawn side by side
+    if let Some(layout) = diagram.layout_rid.as_ref().and_then(|rid| rel_map.get(rid))
+        && let Some(xml) = budget.read_part(archive, &layout.target)?
+    {
+        diagram.connected = layout_is_connected(&xml);
+    }
+    debug!(part = %rel.target, nodes = diagram.nodes.len(), connected = diagram.connected, "read a SmartArt diagram");
+    Ok(None)
+}
+
+/// Puts the text of the parts `chunks` import where they stand in the body,
+/// HTML and the HTML in MHTML are read, other formats only counted and left
+/// as a placeholder
+fn insert_alt_chunks<R: Read + Seek>(
+    document: &mut Document,
+    chunks: Vec<AltChunk>,
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    rel_map: &HashMap<String, Relationship>,
+    warnings: &mut Vec<Warning>,
+) -> Result<()> {
+    // from the back so the indices of the chunks before stay right
+    for chunk in chunks.into_iter().rev() {
+        let html = read_alt_chunk(document, &chunk, archive, budget, rel_map, warnings)?;
+        let blocks: Vec<Block> = match html {
+            Some(html) => alt_chunk::html_paragraphs(&html)
+                .into_iter()
+                .map(Block::Paragraph)
+                .collect(),
+            None => vec![Block::Omitted(Omitted::new("altChunk", None))],
+        };
+        let Some(section) = document.sections.get_mut(chunk.section) else {
+            continue;
+        };
+        let at = chunk.block.min(section.blocks.len());
+        let inserted = blocks.len();
+        section.blocks.splice(at..at, blocks);
+        // controls around the chunk grow, the ones after it move
+        for control in &mut document.content_controls {
+            if control.start.0 == chunk.section && control.start.1 > at {
+                control.start.1 += inserted;
+            }
+            if control.end.0 == chunk.section && control.end.1 >= at {
+                control.end.1 += inserted;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The HTML of the part `chunk` imports, None once what keeps it from being
+/// read is reported
+fn read_alt_chunk<R: Read + Seek>(
+    document: &mut Document,
+    chunk: &AltChunk,
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    rel_map: &HashMap<String, Relationship>,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<String>> {
+    let dropped = |reason: &str| Warning::DroppedContent {
+        what: format!("altChunk {}", chunk.rid),
+        reason: reason.to_string(),
+    };
+    let Some(rel) = rel_map.get(&chunk.rid) else {
+        warnings.push(dropped("no such relationship"));
+        return Ok(None);
+    };
+    let mut data = Vec::new();
+    match budget.open(archive, &rel.target) {
+        Ok(mut entry) => {
+            data.reserve(entry.size_hint());
+            entry.read_to_end(&mut data)?;
+        }
+        Err(ZipError::FileNotFound) => {
+            warnings.push(dropped(&format!("{} is missing", rel.target)));
+            return Ok(None);
+        }
+        Err(e) => return Err(ConvertError::invalid_docx(&rel.target, e).into()),
+    }
+    let text = alt_chunk::decode_text(&data);
+    let html = match ChunkFormat::sniff(&text) {
+        ChunkFormat::Html => Ok(text),
+        ChunkFormat::Mhtml => alt_chunk::mhtml_html(&text).ok_or("MHTML altChunk without HTML"),
+        ChunkFormat::Rtf => Err("RTF altChunk"),
+    };
+    match html {
+        Ok(html) => {
+            debug!(part = %rel.target, "read an altChunk");
+            Ok(Some(html))
+        }
+        Err(element) => {
+            *document.unsupported.entry(element.to_string()).or_default() += 1;
+            Ok(None)
+        }
+    }
+}
+
+/// Reads the main document part, `word/document.xml` in what Word writes, into
+/// the document model, nothing is laid out yet, malformed XML is skipped past
+/// with a warning unless `strict`. Content imported with `w:altChunk` is
+/// looked up in `rel_map`, the relationships of the part
+pub fn parse_document<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    part: &str,
+    rel_map: &HashMap<String, Relationship>,
+    cancel: &CancelToken,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Document> {
+    let _span = info_span!("parse_document").entered();
+    // runs take their formatting from the styles as they are read
+    let styles = read_style_part(archive, budget, rel_map, strict, warnings)?;
+    // and paragraphs their list labels, counted in document order
+    let numbering = read_numbering_part(archive, budget, rel_map, strict, warnings)?;
+
+    // inflated as it is parsed, only XML_BUFFER bytes and the current event are held
+    let doc_xml = budget
+        .open(archive, part)
+        .map_err(|e| ConvertError::invalid_docx(part, e))?;
+    let mut reader = Reader::from_reader(BufReader::with_capacity(XML_BUFFER, doc_xml));
+    let mut buf = Vec::new();
+    let mut state = ParserState {
+        styles,
+        numbering,
+        ..ParserState::default()
+    };
+    let mut errors = XmlErrors::new(part, strict);
+
+    loop {
+        // one atomic load per event is cheap next to the XML parsing
+        cancel.check()?;
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Text(e)) if state.in_instr => {
+                let text = errors.unescape(&e, reader.buffer_position())?;
+                if let Some(field) = state.fields.last_mut() {
+                    field.instr.push_str(&text);
+                }
+            }
+            Ok(Event::Text(e)) if state.in_text && !state.in_drawing => {
+                let text = errors.unescape(&e, reader.buffer_position())?;
+                add_text(&mut state, &text, false);
+            }
+            Ok(Event::Start(e)) => start_element(&mut state, &e, false),
+            Ok(Event::Empty(e)) => start_element(&mut state, &e, true),
+            Ok(Event::End(e)) => end_element(&mut state, e.name().as_ref()),
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+    errors.finish(warnings);
+    drop(reader);
+
+    // content after the last w:sectPr, or a body without any
+    if !state.content.is_empty() {
+        end_paragraph(&mut state);
+    }
+    if !state.section.blocks.is_empty() || state.document.sections.is_empty() {
+        finish_section(&mut state);
+    }
+
+    read_graphics(
+        &mut state.document,
+        archive,
+        budget,
+        rel_map,
+        strict,
+        warnings,
+    )?;
+    let chunks = std::mem::take(&mut state.alt_chunks);
+    insert_alt_chunks(
+        &mut state.document,
+        chunks,
+        archive,
+        budget,
+        rel_map,
+        warnings,
+    )?;
+    Ok(state.document)
+}
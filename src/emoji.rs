@@ -0,0 +1,127 @@
+use ttf_parser::{Face, OutlineBuilder};
+
+use super::font_metrics::TrueTypeFont;
+use super::pdf_document::num;
+use super::pdf_string::win_ansi_bytes;
+
+/// Fonts asked for the glyphs of emoji, in order. Color fonts made of
+/// bitmaps alone, such as Noto Color Emoji, have no outlines to draw
+pub const EMOJI_FAMILIES: [&str; 6] = [
+    "Segoe UI Emoji",
+    "Noto Emoji",
+    "Twemoji Mozilla",
+    "Segoe UI Symbol",
+    "Symbola",
+    "DejaVu Sans",
+];
+
+/// A pictograph WinAnsiEncoding has neither the character nor a look-alike
+/// for, drawn one em wide from an emoji font
+pub fn is_emoji(c: char) -> bool {
+    let pictograph = matches!(
+        c as u32,
+        0x1F000..=0x1F3FA
+            | 0x1F400..=0x1FAFF
+            | 0x2600..=0x27BF
+            | 0x2B00..=0x2BFF
+            | 0x231A..=0x231B
+            | 0x2328
+            | 0x23CF
+            | 0x23E9..=0x23FA
+            | 0x25AA..=0x25FE
+            | 0x3030
+            | 0x303D
+            | 0x3297
+            | 0x3299
+    );
+    pictograph && win_ansi_bytes(c.encode_utf8(&mut [0; 4])) == b"?"
+}
+
+/// The outline of the glyph for `c` in `font` as path operators drawing it
+/// `size` points high from the origin, shrunk to one em where it is wider,
+/// and its advance in points; None when the face has no outline for it
+pub fn glyph_outline(font: &TrueTypeFont, c: char, size: f32) -> Option<(String, f32)> {
+    let face = Face::parse(&font.data, 0).ok()?;
+    let glyph = face.glyph_index(c)?;
+    let units = face.units_per_em() as f32;
+    let advance = face.glyph_hor_advance(glyph).unwrap_or(0) as f32;
+    let scale = size / units.max(advance);
+    let mut path = PathOperators::new(scale);
+    face.outline_glyph(glyph, &mut path)?;
+    Some((path.content, advance * scale))
+}
+
+/// Collects outlines as PDF path construction operators
+#[derive(Default)]
+pub struct PathOperators {
+    content: String,
+    current: (f32, f32), // in font units
+    origin: (f32, f32),  // where the glyph being outlined goes, in font units
+    scale: f32,          // points per font unit
+}
+
+impl PathOperators {
+    pub fn new(scale: f32) -> Self {
+        Self {
+            scale,
+            ..Self::default()
+        }
+    }
+
+    /// places the glyphs outlined from here on at `origin`, in font units
+    pub fn set_origin(&mut self, origin: (f32, f32)) {
+        self.origin = origin;
+    }
+
+    pub fn into_content(self) -> String {
+        self.content
+    }
+
+    /// `(x, y)` in font units relative to the origin, in points
+    fn point(&self, x: f32, y: f32) -> String {
+        let (ox, oy) = self.origin;
+        format!(
+            "{} {}",
+            num((ox + x) * self.scale),
+            num((oy + y) * self.scale)
+        )
+    }
+}
+
+impl OutlineBuilder for PathOperators {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let point = self.point(x, y);
+        self.content.push_str(&format!("{} m ", point));
+        self.current = (x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let point = self.point(x, y);
+        self.content.push_str(&format!("{} l ", point));
+        self.current = (x, y);
+    }
+
+    /// PDF has cubic curves only, a quadratic one is raised to one
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x0, y0) = self.current;
+        let third = |from: f32, control: f32| from + (control - from) * 2.0 / 3.0;
+        self.curve_to(
+            third(x0, x1),
+            third(y0, y1),
+            third(x, x1),
+            third(y, y1),
+            x,
+            y,
+        );
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let points = [self.point(x1, y1), self.point(x2, y2), self.point(x, y)];
+        self.content.push_str(&format!("{} c ", points.join(" ")));
+        self.current = (x, y);
+    }
+
+    fn close(&mut self) {
+        self.content.push_str("h ");
+    }
+}
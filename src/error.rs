@@ -0,0 +1,80 @@
+use std::error::Error as StdError;
+use std::io;
+
+type Source = Box<dyn StdError + Send + Sync>;
+
+/// Why a conversion failed, or why a piece of content was skipped
+#[derive(Debug, thiserror::Error)]
+pub enum ConvertError {
+    /// the input isn't a readable DOCX, `entry` names the zip part involved
+    #[error("invalid DOCX ({entry})")]
+    InvalidDocx { entry: String, source: Source },
+
+    /// a media entry could not be decoded or re-encoded
+    #[error("unsupported image {entry}")]
+    UnsupportedImage { entry: String, source: Source },
+
+    /// a drawing references an `r:embed` id with no usable image behind it
+    #[error("no image found for {rid}")]
+    MissingImage { rid: String },
+
+    #[error("I/O error")]
+    Io(#[from] io::Error),
+
+    /// producing a PDF object failed, `context` says which one
+    #[error("could not write {context}")]
+    PdfWrite { context: String, source: Source },
+
+    /// the DOCX is password protected and was not decrypted, `reason` says why
+    #[error("encrypted DOCX: {reason}")]
+    EncryptedDocument { reason: String },
+
+    /// the input goes past one of the `Limits` set in the options
+    #[error("resource limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    #[error("invalid options: {0}")]
+    InvalidOptions(String),
+
+    #[error("conversion cancelled")]
+    Cancelled,
+}
+
+impl ConvertError {
+    pub(crate) fn invalid_docx(entry: &str, source: impl Into<Source>) -> Self {
+        ConvertError::InvalidDocx {
+            entry: entry.to_string(),
+            source: source.into(),
+        }
+    }
+
+    pub(crate) fn pdf_write(context: &str, source: impl Into<Source>) -> Self {
+        ConvertError::PdfWrite {
+            context: context.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Recovers the category of an error that went through `anyhow`,
+    /// using `fallback` for anything that wasn't already categorised
+    pub(crate) fn classify(err: anyhow::Error, fallback: impl FnOnce(Source) -> Self) -> Self {
+        let err = match err.downcast::<ConvertError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        match err.downcast::<io::Error>() {
+            // a limit hit while reading, see `LimitedEntry`
+            Ok(err)
+                if err
+                    .get_ref()
+                    .is_some_and(|inner| inner.is::<ConvertError>()) =>
+            {
+                *err.into_inner()
+                    .and_then(|inner| inner.downcast().ok())
+                    .expect("checked above")
+            }
+            Ok(err) => ConvertError::Io(err),
+            Err(err) => fallback(err.into()),
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use ttf_parser::gpos::{PairAdjustment, PositioningSubtable};
+use ttf_parser::{name_id, Face, GlyphId, Permissions, Tag};
+
+use super::emoji::is_emoji;
+use super::pdf_string::{win_ansi_bytes, win_ansi_char};
+
+/// Helvetica advance widths for WinAnsi codes 32..=255, in 1/1000 em (from
+/// the Adobe AFM); the codes WinAnsiEncoding leaves undefined get the width
+/// of a space
+const HELVETICA_WIDTHS: [u16; 224] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278,
+    278, // space../
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // 0..?
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // @..O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // P.._
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // `..o
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, 278, // p..DEL
+    556, 278, 222, 556, 333, 1000, 556, 556, 333, 1000, 667, 333, 1000, 278, 611,
+    278, // Euro..
+    278, 222, 222, 333, 333, 350, 556, 1000, 333, 1000, 500, 333, 944, 278, 500,
+    667, // ..Ydieresis
+    278, 333, 556, 556, 556, 556, 260, 556, 333, 737, 370, 556, 584, 333, 737,
+    333, // nbsp..macron
+    400, 584, 333, 333, 333, 556, 537, 278, 333, 333, 365, 556, 834, 834, 834,
+    611, // degree..questiondown
+    667, 667, 667, 667, 667, 667, 1000, 722, 667, 667, 667, 667, 278, 278, 278,
+    278, // Agrave..Idieresis
+    722, 722, 778, 778, 778, 778, 778, 584, 778, 722, 722, 722, 722, 667, 667,
+    611, // Eth..germandbls
+    556, 556, 556, 556, 556, 556, 889, 500, 556, 556, 556, 556, 278, 278, 278,
+    278, // agrave..idieresis
+    556, 556, 556, 556, 556, 556, 556, 584, 611, 556, 556, 556, 556, 500, 556,
+    500, // eth..ydieresis
+];
+
+/// The standard ligatures set in place of their letters where the font has
+/// them: the letters, the ligature's character and glyph name, and the code
+/// it is given, one of those WinAnsiEncoding leaves undefined. Longest first
+pub const LIGATURES: [(&str, char, &str, u8); 5] = [
+    ("ffi", '\u{fb03}', "ffi", 143),
+    ("ffl", '\u{fb04}', "ffl", 144),
+    ("ff", '\u{fb00}', "ff", 157),
+    ("fi", '\u{fb01}', "fi", 129),
+    ("fl", '\u{fb02}', "fl", 141),
+];
+
+/// Every Courier glyph has the same advance
+const COURIER_WIDTH: u16 = 600;
+
+/// Standard 14 fonts the text can be set in, none of them need embedding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StandardFont {
+    #[default]
+    Helvetica,
+    Courier,
+}
+
+impl StandardFont {
+    pub fn base_font(self) -> &'static str {
+        match self {
+            StandardFont::Helvetica => "Helvetica",
+            StandardFont::Courier => "Courier",
+        }
+    }
+
+    /// Width in points of WinAnsi encoded `bytes` at `font_size`
+    pub fn text_width(self, bytes: &[u8], font_size: f32) -> f32 {
+        let units: u32 = bytes
+            .iter()
+            .map(|&b| match self {
+                StandardFont::Courier => COURIER_WIDTH,
+                StandardFont::Helvetica => HELVETICA_WIDTHS[b.saturating_sub(32) as usize],
+            } as u32)
+            .sum();
+        units as f32 * font_size / 1000.0
+    }
+
+    /// Width in points of `text` at `font_size`, as it is written
+    pub fn measure(self, text: &str, font_size: f32) -> f32 {
+        self.text_width(&win_ansi_bytes(text), font_size)
+    }
+}
+
+/// A TrueType font embedded as a whole and set with WinAnsiEncoding
+#[derive(Debug)]
+pub struct TrueTypeFont {
+    pub data: Vec<u8>,
+    /// /BaseFont, the PostScript name limited to characters a PDF name can hold as-is
+    pub name: String,
+    /// advance widths of WinAnsi codes 32..=255, in 1/1000 em
+    pub widths: Vec<u16>,
+    /// added to the advance of the first code of a pair, in 1/1000 em
+    pub kerning: HashMap<(u8, u8), i16>,
+    /// the entries of LIGATURES the font has a glyph for
+    pub ligatures: Vec<usize>,
+    pub ascent: i32,
+    pub descent: i32,
+    pub cap_height: i32,
+    pub bbox: [i32; 4],
+    pub italic_angle: f32,
+    pub monospaced: bool,
+    pub italic: bool,
+}
+
+impl TrueTypeFont {
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        let face = Face::parse(&data, 0)?;
+        if face.permissions() == Some(Permissions::Restricted) {
+            bail!("the font's license does not allow embedding");
+        }
+        let scale = 1000.0 / face.units_per_em() as f32;
+        let units = |value: i16| (value as f32 * scale).round() as i32;
+
+        let ligatures: Vec<usize> = (0..LIGATURES.len())
+            .filter(|&i| face.glyph_index(LIGATURES[i].1).is_some())
+            .collect();
+        let glyph = |code: u8| {
+            let ligature = ligatures.iter().find(|&&i| LIGATURES[i].3 == code);
+            match ligature {
+                Some(&i) => face.glyph_index(LIGATURES[i].1),
+                None => win_ansi_char(code).and_then(|c| face.glyph_index(c)),
+            }
+        };
+        let notdef = face.glyph_hor_advance(GlyphId(0)).unwrap_or(0);
+        let widths = (32..=255u8)
+            .map(|code| {
+                let advance = glyph(code)
+                    .and_then(|glyph| face.glyph_hor_advance(glyph))
+                    .unwrap_or(notdef);
+                (advance as f32 * scale).round() as u16
+            })
+            .collect();
+        // pairs with a space are left out, so that words measure the same
+        // on their own as in a line
+        let glyphs: Vec<(u8, GlyphId)> = (33..=255u8)
+            .filter_map(|code| Some((code, glyph(code)?)))
+            .collect();
+        let kerning = read_kerning(&face, &glyphs)
+            .into_iter()
+            .map(|(pair, value)| (pair, (value as f32 * scale).round() as i16))
+            .filter(|(_, value)| *value != 0)
+            .collect();
+
+        let name = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .unwrap_or_else(|| "EmbeddedFont".to_string())
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+
+        let bbox = face.global_bounding_box();
+        Ok(Self {
+            name,
+            widths,
+            kerning,
+            ligatures,
+            ascent: units(face.ascender()),
+            descent: units(face.descender()),
+            cap_height: units(face.capital_height().unwrap_or(face.ascender())),
+            bbox: [
+                units(bbox.x_min),
+                units(bbox.y_min),
+                units(bbox.x_max),
+                units(bbox.y_max),
+            ],
+            italic_angle: face.italic_angle(),
+            monospaced: face.is_monospaced(),
+            italic: face.is_italic(),
+            data,
+        })
+    }
+}
+
+/// The kerning between `glyphs`, by their codes, in font units: from the
+/// pair adjustments of the GPOS `kern` feature, or from the `kern` table of
+/// fonts without one. The first subtable of a lookup to cover a pair sets it
+fn read_kerning(face: &Face, glyphs: &[(u8, GlyphId)]) -> HashMap<(u8, u8), i16> {
+    let count = glyphs.len();
+    // by the positions of the glyphs in `glyphs`, left * count + right
+    let mut kerning = vec![0i16; count * count];
+    let tables = face.tables();
+    if let Some(gpos) = tables.gpos {
+        let tag = Tag::from_bytes(b"kern");
+        let mut lookups: Vec<u16> = gpos
+            .features
+            .into_iter()
+            .filter(|feature| feature.tag == tag)
+            .flat_map(|feature| feature.lookup_indices)
+            .collect();
+        lookups.sort_unstable();
+        lookups.dedup();
+        for lookup in lookups.into_iter().filter_map(|i| gpos.lookups.get(i)) {
+            let mut covered = vec![false; count * count];
+            for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+                let PositioningSubtable::Pair(pair) = subtable else {
+                    continue;
+                };
+                let right_classes: Vec<u16> = match pair {
+                    PairAdjustment::Format2 { classes, .. } => glyphs
+                        .iter()
+                        .map(|(_, glyph)| classes.1.get(*glyph))
+                        .collect(),
+                    PairAdjustment::Format1 { .. } => Vec::new(),
+                };
+                for (left, &(_, left_glyph)) in glyphs.iter().enumerate() {
+                    let Some(index) = pair.coverage().get(left_glyph) else {
+                        continue;
+                    };
+                    let set = match pair {
+                        PairAdjustment::Format1 { sets, .. } => sets.get(index),
+                        PairAdjustment::Format2 { .. } => None,
+                    };
+                    for (right, &(_, right_glyph)) in glyphs.iter().enumerate() {
+                        let at = left * count + right;
+                        if covered[at] {
+                            continue;
+                        }
+                        let values = match pair {
+                            PairAdjustment::Format1 { .. } => {
+                                set.and_then(|set| set.get(right_glyph))
+                            }
+                            PairAdjustment::Format2 {
+                                classes, matrix, ..
+                            } => matrix.get((classes.0.get(left_glyph), right_classes[right])),
+                        };
+                        if let Some((first, _)) = values {
+                            covered[at] = true;
+                            kerning[at] = kerning[at].saturating_add(first.x_advance);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if kerning.iter().all(|value| *value == 0)
+        && let Some(kern) = tables.kern
+    {
+        for subtable in kern.subtables {
+            if !subtable.horizontal || subtable.variable || subtable.has_cross_stream {
+                continue;
+            }
+            for (left, &(_, left_glyph)) in glyphs.iter().enumerate() {
+                for (right, &(_, right_glyph)) in glyphs.iter().enumerate() {
+                    if let Some(value) = subtable.glyphs_kerning(left_glyph, right_glyph) {
+                        let at = left * count + right;
+                        kerning[at] = kerning[at].saturating_add(value);
+                    }
+                }
+            }
+        }
+    }
+    kerning
+        .into_iter()
+        .enumerate()
+        .filter(|(_, value)| *value != 0)
+        .map(|(at, value)| ((glyphs[at / count].0, glyphs[at % count].0), value))
+        .collect()
+}
+
+/// A font text is set in, everything but the standard fonts gets embedded
+#[derive(Clone, Debug)]
+pub enum PdfFont {
+    Standard(StandardFont),
+    TrueType(Arc<TrueTypeFont>),
+}
+
+impl PdfFont {
+    pub fn base_font(&self) -> &str {
+        match self {
+            PdfFont::Standard(font) => font.base_font(),
+            PdfFont::TrueType(font) => &font.name,
+        }
+    }
+
+    /// `text` as it is written: in WinAnsiEncoding, with look-alikes for
+    /// what it lacks and the ligatures of an embedded font, each code with
+    /// the kerning added to its advance, in 1/1000 em
+    pub fn shape(&self, text: &str) -> Vec<(u8, i16)> {
+        let bytes = win_ansi_bytes(text);
+        let PdfFont::TrueType(font) = self else {
+            return bytes.into_iter().map(|b| (b, 0)).collect();
+        };
+        let mut codes = Vec::with_capacity(bytes.len());
+        let mut rest = bytes.as_slice();
+        while let Some(&first) = rest.first() {
+            let ligature = font
+                .ligatures
+                .iter()
+                .map(|&i| LIGATURES[i])
+                .find(|(letters, ..)| rest.starts_with(letters.as_bytes()));
+            match ligature {
+                Some((letters, _, _, code)) => {
+                    codes.push(code);
+                    rest = &rest[letters.len()..];
+                }
+                None => {
+                    codes.push(first);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        (0..codes.len())
+            .map(|i| {
+                let next = codes.get(i + 1);
+                let kerning = next.and_then(|&next| font.kerning.get(&(codes[i], next)));
+                (codes[i], kerning.copied().unwrap_or(0))
+            })
+            .collect()
+    }
+
+    /// Width in points of `text` at `font_size`, as `shape` writes it; emoji
+    /// are drawn apart, one em wide
+    pub fn measure(&self, text: &str, font_size: f32) -> f32 {
+        let emoji = text.chars().filter(|&c| is_emoji(c)).count();
+        if emoji > 0 {
+            let parts = text
+                .split(is_emoji)
+                .map(|part| self.measure(part, font_size));
+            return parts.sum::<f32>() + emoji as f32 * font_size;
+        }
+        match self {
+            PdfFont::Standard(font) => font.measure(text, font_size),
+            PdfFont::TrueType(font) => {
+                let units: i32 = self
+                    .shape(text)
+                    .into_iter()
+                    .map(|(b, kerning)| {
+                        font.widths[b.saturating_sub(32) as usize] as i32 + kerning as i32
+                    })
+                    .sum();
+                units as f32 * font_size / 1000.0
+            }
+        }
+    }
+
+    /// Advance of WinAnsi code `code`, in 1/1000 em
+    pub fn advance(&self, code: u8) -> u16 {
+        match self {
+            PdfFont::Standard(StandardFont::Courier) => COURIER_WIDTH,
+            PdfFont::Standard(StandardFont::Helvetica) => {
+                HELVETICA_WIDTHS[code.saturating_sub(32) as usize]
+            }
+            PdfFont::TrueType(font) => font.widths[code.saturating_sub(32) as usize],
+        }
+    }
+
+    /// the same standard font or the same embedded font data
+    pub fn same_as(&self, other: &PdfFont) -> bool {
+        match (self, other) {
+            (PdfFont::Standard(a), PdfFont::Standard(b)) => a == b,
+            (PdfFont::TrueType(a), PdfFont::TrueType(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
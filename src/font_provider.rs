@@ -0,0 +1,126 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ttf_parser::{name_id, Face, Width};
+
+use super::font_metrics::StandardFont;
+
+/// The font a run asks for, from `w:rFonts`, `w:b` and `w:i`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FontRequest<'a> {
+    pub family: &'a str,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// Where the glyphs for a requested font come from
+#[derive(Clone, Debug)]
+pub enum FontSource {
+    Standard(StandardFont),
+    /// a TrueType font, embedded whole
+    TrueType(Vec<u8>),
+}
+
+/// Resolves the fonts a document names to something the PDF can use
+///
+/// Returning None sets the text in the body font of the page setup. Each
+/// distinct request is resolved once per conversion.
+pub trait FontProvider: fmt::Debug + Send + Sync {
+    fn resolve(&self, request: &FontRequest) -> Option<FontSource>;
+}
+
+/// Standard fonts only: monospaced families become Courier, common sans-serif
+/// ones Helvetica and everything else the body font
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StandardFonts;
+
+impl FontProvider for StandardFonts {
+    fn resolve(&self, request: &FontRequest) -> Option<FontSource> {
+        let family = request.family.to_ascii_lowercase();
+        let font = match family.as_str() {
+            "courier" | "courier new" | "consolas" | "lucida console" | "menlo" | "monaco"
+            | "source code pro" => StandardFont::Courier,
+            "helvetica" | "arial" | "liberation sans" | "nimbus sans" => StandardFont::Helvetica,
+            _ => return None,
+        };
+        Some(FontSource::Standard(font))
+    }
+}
+
+/// TrueType fonts from a directory, matched on family name, weight and style
+///
+/// Only the names are read up front, the font files are loaded when resolved.
+#[derive(Clone, Debug, Default)]
+pub struct FontDirectory {
+    faces: Vec<FontFace>,
+}
+
+#[derive(Clone, Debug)]
+struct FontFace {
+    family: String,
+    weight: u16, // 400 regular, 700 bold
+    italic: bool,
+    condensed_or_expanded: bool,
+    path: PathBuf,
+}
+
+impl FontDirectory {
+    /// Scans `dir` for `.ttf` files, files that don't parse are left out
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut faces = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_ttf = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("ttf"));
+            if !is_ttf {
+                continue;
+            }
+            let data = fs::read(&path)?;
+            let Ok(face) = Face::parse(&data, 0) else {
+                continue;
+            };
+            let family = [name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY]
+                .iter()
+                .find_map(|&id| {
+                    face.names()
+                        .into_iter()
+                        .filter(|name| name.name_id == id)
+                        .find_map(|name| name.to_string())
+                });
+            if let Some(family) = family {
+                faces.push(FontFace {
+                    family,
+                    weight: face.weight().to_number(),
+                    italic: face.is_italic(),
+                    condensed_or_expanded: face.width() != Width::Normal,
+                    path,
+                });
+            }
+        }
+        // read_dir order is up to the file system
+        faces.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { faces })
+    }
+}
+
+impl FontProvider for FontDirectory {
+    fn resolve(&self, request: &FontRequest) -> Option<FontSource> {
+        let weight = if request.bold { 700 } else { 400 };
+        // the right slant first, then normal width, then the closest weight
+        let face = self
+            .faces
+            .iter()
+            .filter(|face| face.family.eq_ignore_ascii_case(request.family))
+            .min_by_key(|face| {
+                (
+                    face.italic != request.italic,
+                    face.condensed_or_expanded,
+                    face.weight.abs_diff(weight),
+                )
+            })?;
+        fs::read(&face.path).ok().map(FontSource::TrueType)
+    }
+}
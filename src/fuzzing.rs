@@ -0,0 +1,76 @@
+//! The parsers behind the public API, fed straight from the fuzz targets in
+//! `fuzz/`. Nothing here is stable, the functions only exist so malformed
+//! input reaches each parser without being stopped by the ones before it.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::cancellation::CancelToken;
+use super::docx_parser;
+use super::image_preprocessor;
+use super::limits::{Limits, ReadBudget};
+use super::numbering;
+use super::package::{self, DEFAULT_MAIN_PART};
+use super::styles;
+use super::xml_errors::XmlErrors;
+
+/// An archive holding `data` as its only entry, stored so the bytes reach
+/// the parser as they are
+fn archive_with(name: &str, data: &[u8]) -> ZipArchive<Cursor<Vec<u8>>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file(name, options).expect("in memory");
+    zip.write_all(data).expect("in memory");
+    let archive = zip.finish().expect("in memory");
+    ZipArchive::new(archive).expect("just written")
+}
+
+/// `xml` as word/document.xml into the document model, the number of sections on success
+pub fn parse_document_xml(xml: &[u8]) -> Option<usize> {
+    let mut archive = archive_with(DEFAULT_MAIN_PART, xml);
+    docx_parser::parse_document(
+        &mut archive,
+        &ReadBudget::new(Limits::default()),
+        DEFAULT_MAIN_PART,
+        &HashMap::new(),
+        &CancelToken::default(),
+        false,
+        &mut Vec::new(),
+    )
+    .ok()
+    .map(|document| document.sections.len())
+}
+
+/// `xml` as word/styles.xml, whether it was read
+pub fn read_styles(xml: &[u8]) -> bool {
+    let xml = String::from_utf8_lossy(xml);
+    styles::read_styles(&xml, &mut XmlErrors::new("word/styles.xml", false)).is_ok()
+}
+
+/// `xml` as word/numbering.xml, whether it was read
+pub fn read_numbering(xml: &[u8]) -> bool {
+    let xml = String::from_utf8_lossy(xml);
+    numbering::read_numbering(&xml, &mut XmlErrors::new("word/numbering.xml", false)).is_ok()
+}
+
+/// `xml` as word/_rels/document.xml.rels, the number of relationships on success
+pub fn build_rel_map(xml: &[u8]) -> Option<usize> {
+    let mut archive = archive_with("word/_rels/document.xml.rels", xml);
+    package::build_rel_map(
+        &mut archive,
+        &ReadBudget::new(Limits::default()),
+        DEFAULT_MAIN_PART,
+        false,
+        &mut Vec::new(),
+    )
+    .ok()
+    .map(|map| map.len())
+}
+
+/// Width, height and components of a JPEG that can be passed through
+pub fn parse_jpeg_size(data: &[u8]) -> Option<(u32, u32, u8)> {
+    image_preprocessor::parse_jpeg_size(data).map(|info| (info.width, info.height, info.components))
+}
@@ -0,0 +1,106 @@
+use super::bates::STAMP_INSET;
+use super::page_hooks::{PageCanvas, PageHooks};
+
+/// A line of text centered at the top and one at the bottom of every page
+/// written, whether or not the document has headers and footers of its own
+///
+/// `{page}` in the text is the page of the PDF, 1 based, `{pages}` the
+/// `total_pages`, and `{date}` and `{filename}` the fields of the same name.
+/// `{pages}` stays as it is without a total, the conversion writes pages
+/// before it knows how many there are; `check` gives the count.
+#[derive(Clone, Debug)]
+pub struct HeaderFooter {
+    pub header: Option<String>,
+    pub footer: Option<String>,
+    pub total_pages: Option<usize>,
+    pub date: String,
+    pub file_name: String,
+    pub font_size: f32,
+}
+
+impl Default for HeaderFooter {
+    fn default() -> Self {
+        Self {
+            header: None,
+            footer: None,
+            total_pages: None,
+            date: String::new(),
+            file_name: String::new(),
+            font_size: 9.0,
+        }
+    }
+}
+
+impl HeaderFooter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn header(mut self, text: impl Into<String>) -> Self {
+        self.header = Some(text.into());
+        self
+    }
+
+    pub fn footer(mut self, text: impl Into<String>) -> Self {
+        self.footer = Some(text.into());
+        self
+    }
+
+    pub fn total_pages(mut self, pages: usize) -> Self {
+        self.total_pages = Some(pages);
+        self
+    }
+
+    pub fn date(mut self, date: impl Into<String>) -> Self {
+        self.date = date.into();
+        self
+    }
+
+    pub fn file_name(mut self, name: impl Into<String>) -> Self {
+        self.file_name = name.into();
+        self
+    }
+
+    pub fn font_size(mut self, size: f32) -> Self {
+        self.font_size = size;
+        self
+    }
+
+    /// `text` with the placeholders filled in for the `page`th page of the PDF
+    pub fn expand(&self, text: &str, page: usize) -> String {
+        let mut expanded = text
+            .replace("{page}", &page.to_string())
+            .replace("{date}", &self.date)
+            .replace("{filename}", &self.file_name);
+        if let Some(pages) = self.total_pages {
+            expanded = expanded.replace("{pages}", &pages.to_string());
+        }
+        expanded
+    }
+
+    /// Whether the text uses `{pages}`, which needs `total_pages`
+    pub fn needs_total(&self) -> bool {
+        [&self.header, &self.footer]
+            .into_iter()
+            .flatten()
+            .any(|text| text.contains("{pages}"))
+    }
+}
+
+impl PageHooks for HeaderFooter {
+    fn on_page_end(&self, page: &mut PageCanvas) {
+        let lines = [
+            (&self.header, page.height() - STAMP_INSET - self.font_size),
+            (&self.footer, STAMP_INSET),
+        ];
+        page.set_fill_color(0.0, 0.0, 0.0);
+        for (text, y) in lines {
+            let Some(text) = text else {
+                continue;
+            };
+            let text = self.expand(text, page.output_page_number());
+            let width = page.text_width(&text, self.font_size);
+            page.text((page.width() - width) / 2.0, y, self.font_size, &text);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+/// What an `ImageDecoder` hands back for a media entry
+#[derive(Clone, Debug)]
+pub enum DecodedImage {
+    /// bytes in a format the built-in decoders read, e.g. the PNG an external
+    /// converter produced, baseline JPEGs are embedded as they are
+    Encoded(Vec<u8>),
+    /// 8 bit RGB pixels, row by row without padding, encoded as JPEG
+    Rgb {
+        pixels: Vec<u8>,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// Decodes media the crate can't, like EMF/WMF or TIFF variants
+///
+/// Decoders are asked in the order they were added, only for entries
+/// outside the built-in PNG, JPEG, GIF and BMP support.
+pub trait ImageDecoder: fmt::Debug + Send + Sync {
+    /// `entry` is the archive path, e.g. `word/media/image3.emf`,
+    /// Ok(None) leaves the entry to the next decoder
+    fn decode(
+        &self,
+        entry: &str,
+        data: &[u8],
+    ) -> Result<Option<DecodedImage>, Box<dyn StdError + Send + Sync>>;
+}
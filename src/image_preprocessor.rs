@@ -1,128 +1,477 @@
-use anyhow::Result;
-use image::GenericImageView;
+use anyhow::{bail, Result};
+use image::imageops::{self, FilterType};
+use image::{ImageReader, RgbImage};
+#[cfg(feature = "rayon")]
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Cursor, Read, Seek};
+#[cfg(feature = "tempfile")]
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "tempfile")]
 use tempfile::TempDir;
-use turbojpeg::{Compressor, Image, PixelFormat};
+use tracing::{debug, info_span, trace, Span};
 use zip::ZipArchive;
 
+use super::cancellation::CancelToken;
+use super::conversion_cache::{ConversionCache, EncodedImage};
+use super::error::ConvertError;
+use super::image_decoder::{DecodedImage, ImageDecoder};
+use super::limits::{Limits, ReadBudget};
+use super::page_layout::PageSetup;
+use super::progress::ProgressSink;
+use super::Options;
+
 #[derive(Clone)]
 pub struct ImageParams {
     pub w: u32,
     pub h: u32,
+    pub source_w: u32, // pixel size of the media, the image is laid out at
+    pub source_h: u32,
     pub len: usize,
-    pub path: PathBuf,
+    pub components: u8, // 1 for grayscale, 3 for RGB
+    pub data: ImageData,
+}
+
+/// Where a processed JPEG waits until the renderer embeds it
+#[derive(Clone)]
+pub enum ImageData {
+    /// in the preprocessor's temp dir, so image heavy documents don't have to fit in memory
+    #[cfg(feature = "tempfile")]
+    File(PathBuf),
+    /// without the `tempfile` feature, e.g. on wasm32 where there is no file system
+    #[cfg(not(feature = "tempfile"))]
+    Memory(Arc<EncodedImage>),
+}
+
+impl ImageParams {
+    /// the JPEG bytes, `len` of them
+    pub fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+        match &self.data {
+            #[cfg(feature = "tempfile")]
+            ImageData::File(path) => Ok(Box::new(std::fs::File::open(path)?)),
+            #[cfg(not(feature = "tempfile"))]
+            ImageData::Memory(image) => Ok(Box::new(&image.data[..])),
+        }
+    }
+}
+
+/// How media is turned into the JPEGs that get embedded
+#[derive(Clone, Copy, Debug)]
+pub struct ImageSettings {
+    pub quality: u8, // 1-100, for the images that are re-encoded
+    pub max_dpi: Option<u32>,
+    pub recompress: bool, // JPEGs over max_dpi are downsampled rather than passed through
+    pub drop: bool,
+    pub setup: PageSetup, // decides the size images are drawn at
+    pub limits: Limits,
+}
+
+impl ImageSettings {
+    pub fn new(opts: &Options) -> Self {
+        Self {
+            quality: opts.image_quality,
+            max_dpi: opts.image_dpi,
+            recompress: opts.recompress_images,
+            drop: opts.drop_images,
+            setup: opts.page,
+            limits: opts.limits,
+        }
+    }
+
+    /// Pixel size to embed a `width` x `height` image at, None when it is
+    /// drawn at no more than `max_dpi` as it is
+    fn downsampled_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let dpi = self.max_dpi? as f32;
+        let (draw_w, draw_h) = self.setup.image_size(width, height);
+        let target_w = (draw_w / 72.0 * dpi).ceil() as u32;
+        let target_h = (draw_h / 72.0 * dpi).ceil() as u32;
+        (target_w < width || target_h < height)
+            .then(|| (target_w.clamp(1, width), target_h.clamp(1, height)))
+    }
+
+    /// Whether a baseline JPEG goes into the DCTDecode stream untouched
+    fn passes_through(&self, info: &JpegInfo) -> bool {
+        !self.recompress || self.downsampled_size(info.width, info.height).is_none()
+    }
+}
+
+/// Frame header of a JPEG the PDF can embed as-is
+pub(crate) struct JpegInfo {
+    pub width: u32,
+    pub height: u32,
+    pub components: u8,
 }
 
 pub struct ImagePreprocessor {
-    pub temp_dir: TempDir,
-    pub image_map: HashMap<String, ImageParams>,
+    // holds the JPEGs `image_map` refers to until the conversion is done
+    _store: ImageStore,
+    pub image_map: BTreeMap<String, ImageParams>, // sorted so iteration never depends on scheduling
+    /// media entries no decoder could handle
+    pub unsupported: BTreeSet<String>,
+}
+
+/// Formats the `image` crate decodes or JPEGs passed through as they are
+fn is_builtin_format(name: &str) -> bool {
+    [".png", ".bmp", ".gif", ".jpeg", ".jpg"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
 }
 
 impl ImagePreprocessor {
-    pub fn preprocess_images(docx_path: &str) -> Result<Self> {
-        let temp_dir = TempDir::new()?;
-
-        // Step 1: Collect image paths from the archive
-        let image_entries = {
-            let file = File::open(docx_path)?;
-            let mut archive = ZipArchive::new(BufReader::new(file))?;
-
-            let mut entries = Vec::new();
-            for i in 0..archive.len() {
-                let file = archive.by_index(i)?;
-                let name = file.name().to_string();
-
-                if name.starts_with("word/media/")
-                    && (name.ends_with(".png")
-                        || name.ends_with(".bmp")
-                        || name.ends_with(".gif")
-                        || name.ends_with(".jpeg")
-                        || name.ends_with(".jpg"))
-                {
-                    entries.push((i, name));
+    /// `settings` decide what is re-encoded and how, `decoders` are asked for
+    /// media in any other format, re-encoded images are taken from and added
+    /// to `cache`
+    ///
+    /// Media entries, those under `media_dir`, are read one after the other
+    /// from the shared archive, only the decoding and re-encoding runs in parallel.
+    #[allow(clippy::too_many_arguments)]
+    pub fn preprocess_images<R: Read + Seek>(
+        archive: &mut ZipArchive<R>,
+        budget: &ReadBudget,
+        media_dir: &str,
+        settings: &ImageSettings,
+        decoders: &[Arc<dyn ImageDecoder>],
+        cache: Option<&ConversionCache>,
+        progress: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<Self> {
+        let _span = info_span!("preprocess_images").entered();
+        let store = ImageStore::new()?;
+
+        // Step 1: Read the image entries out of the archive
+        let mut image_entries = Vec::new();
+        let mut unsupported = BTreeSet::new();
+        // picked from the central directory, the local headers of everything
+        // else are never read; nothing to read when the images are left out
+        let media: BTreeSet<String> = if settings.drop {
+            BTreeSet::new()
+        } else {
+            archive
+                .file_names()
+                .filter(|name| name.starts_with(media_dir))
+                .map(str::to_string)
+                .collect()
+        };
+        for name in media {
+            if is_builtin_format(&name) || !decoders.is_empty() {
+                cancel.check()?;
+                let mut file = budget.open(archive, &name)?;
+                let mut data = Vec::with_capacity(file.size_hint());
+                file.read_to_end(&mut data)?;
+                image_entries.push((name, data));
+            } else {
+                unsupported.insert(name);
+            }
+        }
+
+        debug!(count = image_entries.len(), "found images to process");
+
+        // Step 2: Process images in parallel
+        let total = image_entries.len();
+        let done = AtomicUsize::new(0);
+        let span = Span::current();
+        // decoded one after the other without the `rayon` feature
+        #[cfg(feature = "rayon")]
+        let image_entries = image_entries.into_par_iter();
+        #[cfg(not(feature = "rayon"))]
+        let image_entries = image_entries.into_iter();
+        let processed: Vec<(String, Option<ImageParams>)> = image_entries
+            .map(|(path, data)| {
+                // rayon workers don't inherit the span on their own
+                let _span = info_span!(parent: &span, "process_image", entry = %path).entered();
+                // the remaining tasks bail out early once cancelled
+                cancel.check()?;
+                let image_params =
+                    Self::decode_and_process(data, &path, &store, settings, decoders, cache)
+                        .map_err(|e| {
+                            ConvertError::classify(e, |source| ConvertError::UnsupportedImage {
+                                entry: path.clone(),
+                                source,
+                            })
+                        })?;
+                progress.image_processed(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+                Ok((path, image_params))
+            })
+            .collect::<Result<_>>()?;
+
+        let mut image_map = BTreeMap::new();
+        for (path, image_params) in processed {
+            match image_params {
+                Some(image_params) => {
+                    image_map.insert(path, image_params);
+                }
+                None => {
+                    unsupported.insert(path);
                 }
             }
-            entries
+        }
+
+        Ok(Self {
+            _store: store,
+            image_map,
+            unsupported,
+        })
+    }
+
+    /// None when the format is neither built in nor handled by a decoder
+    fn decode_and_process(
+        data: Vec<u8>,
+        original_path: &str,
+        store: &ImageStore,
+        settings: &ImageSettings,
+        decoders: &[Arc<dyn ImageDecoder>],
+        cache: Option<&ConversionCache>,
+    ) -> Result<Option<ImageParams>> {
+        // there is nothing to gain from caching JPEGs that are passed through
+        let passthrough = is_builtin_format(original_path)
+            && parse_jpeg_size(&data).is_some_and(|info| settings.passes_through(&info));
+        let Some(cache) = cache.filter(|_| !passthrough) else {
+            return Self::encode(data, original_path, settings, decoders)?
+                .map(|image| store.add(Arc::new(image), original_path))
+                .transpose();
         };
+        let (key, cached) = cache.image(&data, settings);
+        if let Some(image) = cached {
+            trace!("image cache hit");
+            return store.add(image, original_path).map(Some);
+        }
+        match Self::encode(data, original_path, settings, decoders)? {
+            Some(image) => {
+                let image = Arc::new(image);
+                let image_params = store.add(image.clone(), original_path)?;
+                cache.insert_image(key, image);
+                Ok(Some(image_params))
+            }
+            None => Ok(None),
+        }
+    }
 
-        println!("Found {} images to process", image_entries.len());
+    /// The JPEG to embed for a media entry, through the decoders for formats
+    /// that aren't built in
+    fn encode(
+        data: Vec<u8>,
+        original_path: &str,
+        settings: &ImageSettings,
+        decoders: &[Arc<dyn ImageDecoder>],
+    ) -> Result<Option<EncodedImage>> {
+        if is_builtin_format(original_path) {
+            return Self::encode_builtin(data, settings).map(Some);
+        }
+        for decoder in decoders {
+            let decoded = decoder.decode(original_path, &data).map_err(|source| {
+                ConvertError::UnsupportedImage {
+                    entry: original_path.to_string(),
+                    source,
+                }
+            })?;
+            match decoded {
+                Some(DecodedImage::Encoded(bytes)) => {
+                    return Self::encode_builtin(bytes, settings).map(Some);
+                }
+                Some(DecodedImage::Rgb {
+                    pixels,
+                    width,
+                    height,
+                }) => {
+                    let len = pixels.len();
+                    let Some(rgb) = RgbImage::from_raw(width, height, pixels)
+                        .filter(|_| len == width as usize * height as usize * 3)
+                    else {
+                        bail!(
+                            "{} bytes of pixels for a {}x{} RGB image",
+                            len,
+                            width,
+                            height
+                        );
+                    };
+                    return encode_downsampled(rgb, settings).map(Some);
+                }
+                None => {}
+            }
+        }
+        Ok(None)
+    }
 
-        // Step 2: Process images in parallel, each with its own archive handle
-        let image_map: HashMap<String, ImageParams> = image_entries
-            .into_par_iter()
-            .map(|(index, path)| {
-                println!("{:?}", path);
+    fn encode_builtin(data: Vec<u8>, settings: &ImageSettings) -> Result<EncodedImage> {
+        if let Some(info) = parse_jpeg_size(&data)
+            && settings.passes_through(&info)
+        {
+            return Ok(EncodedImage {
+                data,
+                width: info.width,
+                height: info.height,
+                source_width: info.width,
+                source_height: info.height,
+                components: info.components,
+            });
+        }
 
-                let file = File::open(docx_path)?;
-                let mut archive = ZipArchive::new(BufReader::new(file))?;
+        // the header alone says how much decoding would take
+        let (width, height) = ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()?
+            .into_dimensions()?;
+        settings.limits.check_pixels(width, height)?;
 
-                // Process this specific image
-                let mut zip_file = archive.by_index(index)?;
-                let image_params = Self::process_single_image(&mut zip_file, &path, &temp_dir)?;
+        // Decode it using the `image` crate into a raw RGB8 pixel buffer
+        let img = image::load_from_memory(&data)?;
+        encode_downsampled(img.to_rgb8(), settings)
+    }
+}
 
-                Ok((path, image_params))
-            })
-            .collect::<Result<HashMap<_, _>>>()?;
+/// JPEG encodes `rgb`, scaled down first if it would be drawn at more than
+/// the dpi limit
+fn encode_downsampled(rgb: RgbImage, settings: &ImageSettings) -> Result<EncodedImage> {
+    let (source_width, source_height) = rgb.dimensions();
+    let rgb = match settings.downsampled_size(source_width, source_height) {
+        Some((width, height)) => {
+            debug!(
+                source_width,
+                source_height, width, height, "downsampling image"
+            );
+            imageops::resize(&rgb, width, height, FilterType::Triangle)
+        }
+        None => rgb,
+    };
+    let (width, height) = rgb.dimensions();
+    Ok(EncodedImage {
+        data: encode_rgb(rgb.as_raw(), width, height, settings.quality)?,
+        width,
+        height,
+        source_width,
+        source_height,
+        components: 3,
+    })
+}
+
+/// Keeps the processed JPEGs of one conversion, as files in a temp dir that
+/// is removed on drop or in memory without the `tempfile` feature
+pub struct ImageStore {
+    #[cfg(feature = "tempfile")]
+    temp_dir: TempDir,
+}
 
+impl ImageStore {
+    fn new() -> Result<Self> {
         Ok(Self {
-            temp_dir,
-            image_map,
+            #[cfg(feature = "tempfile")]
+            temp_dir: TempDir::new()?,
         })
     }
 
-    fn process_single_image<R: Read>(
-        zip_file: &mut R,
-        original_path: &str,
-        temp_dir: &TempDir,
-    ) -> Result<ImageParams> {
+    fn add(&self, image: Arc<EncodedImage>, original_path: &str) -> Result<ImageParams> {
+        // the PDF stream /Length must match the re-encoded bytes, not the source media
+        Ok(ImageParams {
+            w: image.width,
+            h: image.height,
+            source_w: image.source_width,
+            source_h: image.source_height,
+            len: image.data.len(),
+            components: image.components,
+            data: self.keep(image, original_path)?,
+        })
+    }
+
+    #[cfg(feature = "tempfile")]
+    fn keep(&self, image: Arc<EncodedImage>, original_path: &str) -> Result<ImageData> {
         // Generate consistent temp filename
-        let filename = original_path
-            .strip_prefix("word/media/")
-            .unwrap_or(original_path);
+        let filename = original_path.rsplit('/').next().unwrap_or(original_path);
 
         let path = std::path::Path::new(filename);
         let filename_with_jpg = path.with_extension("jpg");
-        println!("path: {:?}", filename_with_jpg);
-        let temp_path = temp_dir.path().join(filename_with_jpg);
+        trace!(temp_file = ?filename_with_jpg);
+        let temp_path = self.temp_dir.path().join(filename_with_jpg);
+        std::fs::write(&temp_path, &image.data)?;
+        Ok(ImageData::File(temp_path))
+    }
 
-        let mut data = Vec::new();
-        zip_file.read_to_end(&mut data)?;
-        let len = data.len();
+    #[cfg(not(feature = "tempfile"))]
+    fn keep(&self, image: Arc<EncodedImage>, _original_path: &str) -> Result<ImageData> {
+        Ok(ImageData::Memory(image))
+    }
+}
 
-        // Decode it using the `image` crate
-        let img = image::load_from_memory(&data)?;
+/// JPEG encodes 8 bit RGB `pixels` with TurboJPEG
+#[cfg(feature = "turbojpeg")]
+fn encode_rgb(pixels: &[u8], width: u32, height: u32, quality: u8) -> Result<Vec<u8>> {
+    use turbojpeg::{Compressor, Image, PixelFormat};
 
-        let (width, height) = img.dimensions();
+    // Initialize TurboJPEG compressor
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(quality as i32)?;
+    compressor.set_subsamp(turbojpeg::Subsamp::Sub2x1)?; // choose 4:2:2 subsampling
 
-        // Convert dynamic image to raw RGB8 pixel buffer
-        let rgb = img.to_rgb8();
-        let pixels: &[u8] = rgb.as_raw(); // this is what TurboJPEG needs
+    // Prepare image descriptor for TurboJPEG
+    let image = Image {
+        pixels,
+        width: width as usize,
+        height: height as usize,
+        pitch: (width as usize) * 3, // 3 bytes per pixel for RGB
+        format: PixelFormat::RGB,
+    };
+    Ok(compressor.compress_to_vec(image)?)
+}
 
-        // Initialize TurboJPEG compressor
-        let mut compressor = Compressor::new()?;
-        compressor.set_quality(50)?;
-        compressor.set_subsamp(turbojpeg::Subsamp::Sub2x1)?; // choose 4:2:2 subsampling
+/// JPEG encodes 8 bit RGB `pixels` with the `image` crate's encoder, slower
+/// than TurboJPEG but pure Rust
+#[cfg(not(feature = "turbojpeg"))]
+fn encode_rgb(pixels: &[u8], width: u32, height: u32, quality: u8) -> Result<Vec<u8>> {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::ExtendedColorType;
 
-        // Prepare image descriptor for TurboJPEG
-        let image = Image {
-            pixels,
-            width: width as usize,
-            height: height as usize,
-            pitch: (width as usize) * 3, // 3 bytes per pixel for RGB
-            format: PixelFormat::RGB,
-        };
-        let jpeg_data = compressor.compress_to_vec(image)?;
-        std::fs::write(&temp_path, jpeg_data)?;
+    let mut jpeg_data = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg_data, quality).encode(
+        pixels,
+        width,
+        height,
+        ExtendedColorType::Rgb8,
+    )?;
+    Ok(jpeg_data)
+}
 
-        Ok(ImageParams {
-            path: temp_path,
-            w: width,
-            h: height,
-            len,
-        })
+/// Reads the SOF segment of a baseline (or extended sequential) 8 bit JPEG
+/// with gray or RGB/YCbCr samples, None for anything that has to be re-encoded
+pub(crate) fn parse_jpeg_size(data: &[u8]) -> Option<JpegInfo> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    loop {
+        // markers may be preceded by any number of 0xFF fill bytes
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        while *data.get(pos)? == 0xFF {
+            pos += 1;
+        }
+        let marker = data[pos];
+        pos += 1;
+        // standalone markers carry no length
+        if matches!(marker, 0x01 | 0xD0..=0xD7) {
+            continue;
+        }
+        let len = u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        let segment = data.get(pos + 2..pos + len)?;
+        match marker {
+            0xC0 | 0xC1 => {
+                let precision = *segment.first()?;
+                let height = u16::from_be_bytes([*segment.get(1)?, *segment.get(2)?]) as u32;
+                let width = u16::from_be_bytes([*segment.get(3)?, *segment.get(4)?]) as u32;
+                let components = *segment.get(5)?;
+                // a height of 0 is only given later in a DNL segment
+                return (precision == 8 && width > 0 && height > 0 && matches!(components, 1 | 3))
+                    .then_some(JpegInfo {
+                        width,
+                        height,
+                        components,
+                    });
+            }
+            // progressive, lossless, hierarchical and arithmetic coded frames
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+            // image data started without a frame header
+            0xDA | 0xD9 => return None,
+            _ => pos += len,
+        }
     }
 }
@@ -0,0 +1,134 @@
+use rustybuzz::ttf_parser::GlyphId;
+use rustybuzz::{script, Direction, Face, Script, UnicodeBuffer};
+
+use super::emoji::PathOperators;
+use super::font_metrics::TrueTypeFont;
+
+/// A script whose characters are reordered and joined into conjuncts as they
+/// are set, which WinAnsiEncoding has none of
+pub struct IndicScript {
+    block: (char, char),
+    /// the OpenType script its shaper is chosen by
+    pub tag: Script,
+    /// fonts asked for its glyphs in order, when the run's own has none
+    pub families: &'static [&'static str],
+}
+
+const SCRIPTS: [IndicScript; 10] = [
+    IndicScript {
+        block: ('\u{0900}', '\u{097F}'),
+        tag: script::DEVANAGARI,
+        families: &[
+            "Nirmala UI",
+            "Mangal",
+            "Noto Sans Devanagari",
+            "Lohit Devanagari",
+        ],
+    },
+    IndicScript {
+        block: ('\u{0980}', '\u{09FF}'),
+        tag: script::BENGALI,
+        families: &["Nirmala UI", "Vrinda", "Noto Sans Bengali", "Lohit Bengali"],
+    },
+    IndicScript {
+        block: ('\u{0A00}', '\u{0A7F}'),
+        tag: script::GURMUKHI,
+        families: &[
+            "Nirmala UI",
+            "Raavi",
+            "Noto Sans Gurmukhi",
+            "Lohit Gurmukhi",
+        ],
+    },
+    IndicScript {
+        block: ('\u{0A80}', '\u{0AFF}'),
+        tag: script::GUJARATI,
+        families: &[
+            "Nirmala UI",
+            "Shruti",
+            "Noto Sans Gujarati",
+            "Lohit Gujarati",
+        ],
+    },
+    IndicScript {
+        block: ('\u{0B00}', '\u{0B7F}'),
+        tag: script::ORIYA,
+        families: &["Nirmala UI", "Kalinga", "Noto Sans Oriya", "Lohit Odia"],
+    },
+    IndicScript {
+        block: ('\u{0B80}', '\u{0BFF}'),
+        tag: script::TAMIL,
+        families: &["Nirmala UI", "Latha", "Noto Sans Tamil", "Lohit Tamil"],
+    },
+    IndicScript {
+        block: ('\u{0C00}', '\u{0C7F}'),
+        tag: script::TELUGU,
+        families: &["Nirmala UI", "Gautami", "Noto Sans Telugu", "Lohit Telugu"],
+    },
+    IndicScript {
+        block: ('\u{0C80}', '\u{0CFF}'),
+        tag: script::KANNADA,
+        families: &["Nirmala UI", "Tunga", "Noto Sans Kannada", "Lohit Kannada"],
+    },
+    IndicScript {
+        block: ('\u{0D00}', '\u{0D7F}'),
+        tag: script::MALAYALAM,
+        families: &[
+            "Nirmala UI",
+            "Kartika",
+            "Noto Sans Malayalam",
+            "Lohit Malayalam",
+        ],
+    },
+    IndicScript {
+        block: ('\u{0D80}', '\u{0DFF}'),
+        tag: script::SINHALA,
+        families: &["Nirmala UI", "Iskoola Pota", "Noto Sans Sinhala"],
+    },
+];
+
+/// The Indic script `c` belongs to, by its Unicode block
+pub fn indic_script(c: char) -> Option<&'static IndicScript> {
+    SCRIPTS
+        .iter()
+        .find(|script| (script.block.0..=script.block.1).contains(&c))
+}
+
+/// The zero width joiner and non-joiner, which pick the form of the letters
+/// around them and so belong to the run they are in
+pub fn is_joiner(c: char) -> bool {
+    matches!(c, '\u{200C}' | '\u{200D}')
+}
+
+/// `text` in `script` shaped with `font` as path operators drawing it `size`
+/// points high from the origin, and its advance in points; None when the
+/// font lacks a glyph for any of it
+pub fn shaped_outline(
+    font: &TrueTypeFont,
+    text: &str,
+    script: &IndicScript,
+    size: f32,
+) -> Option<(String, f32)> {
+    let face = Face::from_slice(&font.data, 0)?;
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_script(script.tag);
+    buffer.set_direction(Direction::LeftToRight);
+    let glyphs = rustybuzz::shape(&face, &[], buffer);
+
+    let mut path = PathOperators::new(size / face.units_per_em() as f32);
+    let mut x = 0;
+    for (info, position) in glyphs.glyph_infos().iter().zip(glyphs.glyph_positions()) {
+        if info.glyph_id == 0 {
+            return None;
+        }
+        path.set_origin(((x + position.x_offset) as f32, position.y_offset as f32));
+        // marks and spaces may have no outline
+        let _ = face.outline_glyph(GlyphId(info.glyph_id as u16), &mut path);
+        x += position.x_advance;
+    }
+    Some((
+        path.into_content(),
+        x as f32 * size / face.units_per_em() as f32,
+    ))
+}
@@ -0,0 +1,455 @@
+use crate::limits::ReadBudget;
+use crate::package::{build_rel_map, find_main_part, Relationship};
+use crate::report::Warning;
+use crate::xml_errors::XmlErrors;
+use anyhow::Result;
+use image::ImageReader;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{Cursor, Read, Seek};
+use zip::ZipArchive;
+
+/// The package structure of a DOCX, for finding out why it converts badly
+#[derive(Clone, Debug, Serialize)]
+pub struct Inspection {
+    /// The archive entry of the main document part, e.g. `word/document.xml`
+    pub main_part: String,
+    /// docx, docm, dotx or dotm
+    pub kind: &'static str,
+    pub parts: Vec<PartInfo>,
+    /// The internal relationships of the main part, external ones are left out
+    pub relationships: Vec<RelationshipInfo>,
+    pub media: Vec<MediaInfo>,
+    pub sections: Vec<SectionInfo>,
+    /// The styles defined and the ones paragraphs, runs and tables use
+    pub styles: Vec<StyleUsage>,
+    /// malformed XML that was skipped past
+    pub warnings: Vec<Warning>,
+}
+
+/// An archive entry with its sizes as the central directory gives them
+#[derive(Clone, Debug, Serialize)]
+pub struct PartInfo {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RelationshipInfo {
+    pub id: String,
+    /// The last segment of the type URI, e.g. `image`
+    pub kind: String,
+    pub target: String,
+    /// false for a target the archive has no entry for
+    pub present: bool,
+}
+
+/// An image or other media part
+#[derive(Clone, Debug, Serialize)]
+pub struct MediaInfo {
+    pub part: String,
+    /// The format sniffed from the data, the extension for ones the crate
+    /// can't read such as EMF
+    pub format: String,
+    pub bytes: u64,
+    /// Width and height in pixels when the format is one the crate reads
+    pub pixels: Option<(u32, u32)>,
+}
+
+/// The properties of a `w:sectPr`, sizes in points
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SectionInfo {
+    pub page_width: Option<f32>,
+    pub page_height: Option<f32>,
+    pub landscape: bool,
+    /// top, right, bottom and left
+    pub margins: Option<[f32; 4]>,
+    pub columns: u32,
+    /// how the section starts, `nextPage` unless given
+    pub start: String,
+    pub page_number_format: Option<String>,
+    pub page_number_start: Option<u32>,
+    /// `default`, `first` or `even` and the part each refers to
+    pub headers: Vec<(String, String)>,
+    pub footers: Vec<(String, String)>,
+    pub paragraphs: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StyleUsage {
+    pub id: String,
+    pub name: Option<String>,
+    /// paragraph, character, table or numbering
+    pub kind: String,
+    /// How many paragraphs, runs or tables of the main part refer to it
+    pub uses: usize,
+    /// false for a style that is used but the styles part lacks
+    pub defined: bool,
+}
+
+/// Reads the structure of the DOCX in `archive`, XML is skipped past where
+/// malformed as in a conversion
+pub fn inspect_package<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+) -> Result<Inspection> {
+    let mut warnings = Vec::new();
+    let main = find_main_part(archive, budget, false, &mut warnings)?;
+    let rel_map = build_rel_map(archive, budget, &main.name, false, &mut warnings)?;
+
+    let mut parts = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        parts.push(PartInfo {
+            name: entry.name().to_string(),
+            size: entry.size(),
+            compressed_size: entry.compressed_size(),
+        });
+    }
+
+    let mut rels: Vec<&Relationship> = rel_map.values().collect();
+    // rId2 before rId10
+    rels.sort_by(|a, b| (a.id.len(), &a.id).cmp(&(b.id.len(), &b.id)));
+    let relationships = rels
+        .iter()
+        .map(|rel| RelationshipInfo {
+            id: rel.id.clone(),
+            kind: rel.kind.clone(),
+            target: rel.target.clone(),
+            present: parts.iter().any(|part| part.name == rel.target),
+        })
+        .collect();
+
+    let mut media = Vec::new();
+    for part in &parts {
+        let is_media = part.name.contains("/media/")
+            || rel_map
+                .values()
+                .any(|rel| rel.kind == "image" && rel.target == part.name);
+        if is_media {
+            media.push(read_media(archive, budget, part)?);
+        }
+    }
+
+    let mut styles = BTreeMap::new();
+    if let Some(rel) = rel_map.values().find(|rel| rel.kind == "styles") {
+        read_styles(archive, budget, &rel.target, &mut styles, &mut warnings)?;
+    }
+    let sections = read_body(
+        archive,
+        budget,
+        &main.name,
+        &rel_map,
+        &mut styles,
+        &mut warnings,
+    )?;
+
+    let mut styles: Vec<StyleUsage> = styles.into_values().collect();
+    styles.sort_by(|a, b| b.uses.cmp(&a.uses).then_with(|| a.id.cmp(&b.id)));
+    Ok(Inspection {
+        main_part: main.name,
+        kind: main.kind.extension(),
+        parts,
+        relationships,
+        media,
+        sections,
+        styles,
+        warnings,
+    })
+}
+
+fn read_media<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    part: &PartInfo,
+) -> Result<MediaInfo> {
+    let mut data = Vec::new();
+    budget.open(archive, &part.name)?.read_to_end(&mut data)?;
+    let extension = part
+        .name
+        .rsplit_once('.')
+        .map_or(String::new(), |(_, ext)| ext.to_ascii_lowercase());
+    let reader = ImageReader::new(Cursor::new(&data)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .and_then(|format| format.extensions_str().first())
+        .map_or(extension, |ext| ext.to_string());
+    Ok(MediaInfo {
+        part: part.name.clone(),
+        format,
+        bytes: data.len() as u64,
+        pixels: reader.into_dimensions().ok(),
+    })
+}
+
+/// The value of the attribute named `key` whatever its prefix, Strict OOXML
+/// documents may not use `w:`
+fn attr(e: &BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == key)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+fn twips(e: &BytesStart, key: &[u8]) -> Option<f32> {
+    attr(e, key)?.parse::<f32>().ok().map(|twips| twips / 20.0)
+}
+
+fn read_styles<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    part: &str,
+    styles: &mut BTreeMap<String, StyleUsage>,
+    warnings: &mut Vec<Warning>,
+) -> Result<()> {
+    let Some(xml) = budget.read_part(archive, part)? else {
+        return Ok(());
+    };
+    let mut reader = Reader::from_str(&xml);
+    let mut buf = Vec::new();
+    let mut errors = XmlErrors::new(part, false);
+    let mut current: Option<String> = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"style" => {
+                    let id = attr(&e, b"styleId").unwrap_or_default();
+                    styles.insert(
+                        id.clone(),
+                        StyleUsage {
+                            id: id.clone(),
+                            name: None,
+                            kind: attr(&e, b"type").unwrap_or_else(|| "paragraph".into()),
+                            uses: 0,
+                            defined: true,
+                        },
+                    );
+                    current = Some(id);
+                }
+                b"name" => {
+                    if let Some(style) = current.as_ref().and_then(|id| styles.get_mut(id)) {
+                        style.name = attr(&e, b"val");
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"style" => current = None,
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+    errors.finish(warnings);
+    Ok(())
+}
+
+/// The sections of the main part and how often it uses each style
+fn read_body<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    part: &str,
+    rel_map: &HashMap<String, Relationship>,
+    styles: &mut BTreeMap<String, StyleUsage>,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<SectionInfo>> {
+    let Some(xml) = budget.read_part(archive, part)? else {
+        return Ok(Vec::new());
+    };
+    let mut reader = Reader::from_str(&xml);
+    let mut buf = Vec::new();
+    let mut errors = XmlErrors::new(part, false);
+    let mut sections = Vec::new();
+    let mut section = SectionInfo::default();
+    let mut in_sect_pr = false;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"p" => section.paragraphs += 1,
+                name @ (b"pStyle" | b"rStyle" | b"tblStyle") => {
+                    let kind = match name {
+                        b"pStyle" => "paragraph",
+                        b"rStyle" => "character",
+                        _ => "table",
+                    };
+                    if let Some(id) = attr(&e, b"val") {
+                        let style = styles.entry(id.clone()).or_insert_with(|| StyleUsage {
+                            id,
+                            name: None,
+                            kind: kind.into(),
+                            uses: 0,
+                            defined: false,
+                        });
+                        style.uses += 1;
+                    }
+                }
+                b"sectPr" => in_sect_pr = true,
+                _ if !in_sect_pr => {}
+                b"pgSz" => {
+                    section.page_width = twips(&e, b"w");
+                    section.page_height = twips(&e, b"h");
+                    section.landscape = attr(&e, b"orient").as_deref() == Some("landscape");
+                }
+                b"pgMar" => {
+                    section.margins = Some(
+                        ["top", "right", "bottom", "left"]
+                            .map(|side| twips(&e, side.as_bytes()).unwrap_or(0.0)),
+                    )
+                }
+                b"cols" => {
+                    section.columns = attr(&e, b"num")
+                        .and_then(|num| num.parse().ok())
+                        .unwrap_or(1)
+                }
+                b"type" => section.start = attr(&e, b"val").unwrap_or_default(),
+                b"pgNumType" => {
+                    section.page_number_format = attr(&e, b"fmt");
+                    section.page_number_start = attr(&e, b"start").and_then(|n| n.parse().ok());
+                }
+                name @ (b"headerReference" | b"footerReference") => {
+                    let kind = attr(&e, b"type").unwrap_or_else(|| "default".into());
+                    let target = attr(&e, b"id")
+                        .and_then(|id| rel_map.get(&id))
+                        .map_or_else(|| "(missing)".into(), |rel| rel.target.clone());
+                    if name == b"headerReference" {
+                        section.headers.push((kind, target));
+                    } else {
+                        section.footers.push((kind, target));
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"sectPr" => {
+                in_sect_pr = false;
+                if section.start.is_empty() {
+                    section.start = "nextPage".into();
+                }
+                if section.columns == 0 {
+                    section.columns = 1;
+                }
+                sections.push(std::mem::take(&mut section));
+            }
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+    // a body without a final sectPr still has a section with Word's defaults
+    if section.paragraphs > 0 || sections.is_empty() {
+        section.start = "nextPage".into();
+        section.columns = 1;
+        sections.push(section);
+    }
+    errors.finish(warnings);
+    Ok(sections)
+}
+
+/// The inspection as indented text for a terminal
+impl fmt::Display for Inspection {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(out, "main part: {} ({})", self.main_part, self.kind)?;
+
+        writeln!(out, "\nparts ({}):", self.parts.len())?;
+        for part in &self.parts {
+            writeln!(
+                out,
+                "  {:<40} {:>10} bytes {:>10} compressed",
+                part.name, part.size, part.compressed_size
+            )?;
+        }
+
+        writeln!(out, "\nrelationships ({}):", self.relationships.len())?;
+        for rel in &self.relationships {
+            let missing = if rel.present { "" } else { "  (missing)" };
+            writeln!(
+                out,
+                "  {:<8} {:<16} {}{}",
+                rel.id, rel.kind, rel.target, missing
+            )?;
+        }
+
+        writeln!(out, "\nmedia ({}):", self.media.len())?;
+        for media in &self.media {
+            let pixels = media
+                .pixels
+                .map_or(String::new(), |(w, h)| format!(", {}x{} px", w, h));
+            writeln!(
+                out,
+                "  {:<40} {}, {} bytes{}",
+                media.part, media.format, media.bytes, pixels
+            )?;
+        }
+
+        writeln!(out, "\nsections ({}):", self.sections.len())?;
+        for (index, section) in self.sections.iter().enumerate() {
+            let size = match (section.page_width, section.page_height) {
+                (Some(w), Some(h)) => format!("{} x {} pt", w, h),
+                _ => "default size".into(),
+            };
+            let orientation = if section.landscape { " landscape" } else { "" };
+            writeln!(
+                out,
+                "  {}: {}{}, {} paragraphs, starts {}",
+                index + 1,
+                size,
+                orientation,
+                section.paragraphs,
+                section.start
+            )?;
+            if let Some([top, right, bottom, left]) = section.margins {
+                writeln!(
+                    out,
+                    "     margins {} {} {} {} pt (top right bottom left)",
+                    top, right, bottom, left
+                )?;
+            }
+            if section.columns > 1 {
+                writeln!(out, "     {} columns", section.columns)?;
+            }
+            if section.page_number_format.is_some() || section.page_number_start.is_some() {
+                writeln!(
+                    out,
+                    "     page numbers {}{}",
+                    section.page_number_format.as_deref().unwrap_or("decimal"),
+                    section
+                        .page_number_start
+                        .map_or(String::new(), |start| format!(" from {}", start))
+                )?;
+            }
+            for (kind, target) in &section.headers {
+                writeln!(out, "     header {}: {}", kind, target)?;
+            }
+            for (kind, target) in &section.footers {
+                writeln!(out, "     footer {}: {}", kind, target)?;
+            }
+        }
+
+        writeln!(out, "\nstyles ({}):", self.styles.len())?;
+        for style in &self.styles {
+            let name = style.name.as_deref().unwrap_or("");
+            let undefined = if style.defined { "" } else { "  (not defined)" };
+            writeln!(
+                out,
+                "  {:<24} {:<10} {:>5} uses  {}{}",
+                style.id, style.kind, style.uses, name, undefined
+            )?;
+        }
+
+        if !self.warnings.is_empty() {
+            writeln!(out, "\nwarnings ({}):", self.warnings.len())?;
+            for warning in &self.warnings {
+                writeln!(out, "  {}", warning)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use super::error::ConvertError;
+use super::pdf_document::num;
+use super::pdf_encryption::{to_hex, PdfEncryption};
+use anyhow::Result;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Whether the letterhead goes behind the content of a page or over it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LetterheadPlacement {
+    #[default]
+    Underlay,
+    Overlay,
+}
+
+/// The pages of an existing PDF drawn on every page written, e.g. a
+/// company's letterhead stationery
+///
+/// Page N of the letterhead goes on page N of the PDF and its last page on
+/// every page after that, so a two page letterhead gives a first page and a
+/// continuation sheet. Each is imported once as a form XObject, scaled down
+/// to fit a page of another size and centered against its top. Page images
+/// are drawn without it.
+#[derive(Clone, Debug)]
+pub struct Letterhead {
+    pages: Arc<ImportedPages>,
+    pub placement: LetterheadPlacement,
+}
+
+#[derive(Debug)]
+struct ImportedPages {
+    /// the indirect objects the pages refer to, a reference is the index of
+    /// its object here
+    objects: Vec<Object>,
+    pages: Vec<ImportedPage>,
+}
+
+#[derive(Debug)]
+struct ImportedPage {
+    bbox: [f32; 4],
+    resources: Object,
+    group: Option<Object>,
+    content: Vec<u8>,
+}
+
+impl Letterhead {
+    /// Reads the pages of the PDF in `data`, which has to open without a
+    /// password
+    pub fn from_pdf(data: &[u8]) -> Result<Self, ConvertError> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            ConvertError::InvalidOptions(format!("letterhead PDF: {}", e))
+        };
+        let document = Document::load_mem(data).map_err(|e| invalid(&e))?;
+        if document.is_encrypted() {
+            return Err(invalid(&"it needs a password"));
+        }
+        let mut import = Import {
+            document: &document,
+            ids: HashMap::new(),
+            queue: Vec::new(),
+            objects: Vec::new(),
+        };
+        let mut pages = Vec::new();
+        for page_id in document.get_pages().into_values() {
+            let content = document
+                .get_page_content(page_id)
+                .map_err(|e| invalid(&e))?;
+            let bbox = inherited(&document, page_id, b"CropBox")
+                .or_else(|| inherited(&document, page_id, b"MediaBox"))
+                .and_then(|bbox| rect(&document, bbox))
+                .unwrap_or([0.0, 0.0, 612.0, 792.0]);
+            let resources = inherited(&document, page_id, b"Resources")
+                .map_or(Object::Dictionary(Dictionary::new()), |resources| {
+                    import.renumber(resources)
+                });
+            let group = document
+                .get_dictionary(page_id)
+                .ok()
+                .and_then(|page| page.get(b"Group").ok())
+                .map(|group| import.renumber(group));
+            pages.push(ImportedPage {
+                bbox,
+                resources,
+                group,
+                content,
+            });
+        }
+        if pages.is_empty() {
+            return Err(invalid(&"it has no pages"));
+        }
+        import.finish();
+        Ok(Self {
+            pages: Arc::new(ImportedPages {
+                objects: import.objects,
+                pages,
+            }),
+            placement: LetterheadPlacement::default(),
+        })
+    }
+
+    pub fn placement(mut self, placement: LetterheadPlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.pages.len()
+    }
+
+    /// How many indirect objects the pages need besides their forms
+    pub(crate) fn object_count(&self) -> usize {
+        self.pages.objects.len()
+    }
+
+    /// Serializes imported object `index`, its references going to `ids`
+    pub(crate) fn object(
+        &self,
+        index: usize,
+        ids: &[u32],
+        encryption: Option<&PdfEncryption>,
+    ) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        write_object(&self.pages.objects[index], ids, encryption, &mut out)?;
+        Ok(out)
+    }
+
+    /// The dictionary entries and content of the form XObject of `page`,
+    /// 0 based, its references going to `ids`
+    pub(crate) fn form(
+        &self,
+        page: usize,
+        ids: &[u32],
+        encryption: Option<&PdfEncryption>,
+    ) -> Result<(String, &[u8])> {
+        let page = &self.pages.pages[page];
+        let mut dict = b"/Type /XObject /Subtype /Form /BBox ".to_vec();
+        write_object(
+            &Object::Array(page.bbox.iter().map(|&v| Object::Real(v)).collect()),
+            ids,
+            encryption,
+            &mut dict,
+        )?;
+        dict.extend_from_slice(b" /Resources ");
+        write_object(&page.resources, ids, encryption, &mut dict)?;
+        if let Some(group) = &page.group {
+            dict.extend_from_slice(b" /Group ");
+            write_object(group, ids, encryption, &mut dict)?;
+        }
+        Ok((String::from_utf8_lossy(&dict).into_owned(), &page.content))
+    }
+
+    /// Draws the letterhead page for the `output_page`th page of the PDF,
+    /// 1 based, on a page of `width` by `height` points
+    pub(crate) fn operators(&self, output_page: usize, width: f32, height: f32) -> String {
+        let index = output_page.clamp(1, self.page_count()) - 1;
+        let [x0, y0, x1, y1] = self.pages.pages[index].bbox;
+        let (w, h) = ((x1 - x0).max(1.0), (y1 - y0).max(1.0));
+        let scale = (width / w).min(height / h).min(1.0);
+        let x = (width - w * scale) / 2.0 - x0 * scale;
+        let y = height - h * scale - y0 * scale;
+        // the scale needs more than the two decimals of `num`
+        let scale = format!("{:.4}", scale);
+        let scale = scale.trim_end_matches('0').trim_end_matches('.');
+        format!(
+            "q {} 0 0 {} {} {} cm /Lh{} Do Q",
+            scale,
+            scale,
+            num(x),
+            num(y),
+            index + 1
+        )
+    }
+}
+
+/// Collects the objects the imported pages refer to, leaving out other
+/// pages so a link back into the page tree doesn't pull in all of it
+struct Import<'a> {
+    document: &'a Document,
+    ids: HashMap<ObjectId, usize>,
+    queue: Vec<ObjectId>,
+    objects: Vec<Object>,
+}
+
+impl Import<'_> {
+    /// `object` with its references numbered for `objects`
+    fn renumber(&mut self, object: &Object) -> Object {
+        match object {
+            Object::Reference(id) => {
+                if let Some(&index) = self.ids.get(id) {
+                    return Object::Reference((index as u32, 0));
+                }
+                let Ok(target) = self.document.get_object(*id) else {
+                    return Object::Null;
+                };
+                if is_page(target) {
+                    return Object::Null;
+                }
+                let index = self.objects.len();
+                self.objects.push(Object::Null);
+                self.ids.insert(*id, index);
+                self.queue.push(*id);
+                Object::Reference((index as u32, 0))
+            }
+            Object::Array(items) => {
+                Object::Array(items.iter().map(|item| self.renumber(item)).collect())
+            }
+            Object::Dictionary(dict) => Object::Dictionary(self.renumber_dict(dict)),
+            Object::Stream(stream) => {
+                let mut stream = stream.clone();
+                stream.dict = self.renumber_dict(&stream.dict);
+                Object::Stream(stream)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn renumber_dict(&mut self, dict: &Dictionary) -> Dictionary {
+        let mut renumbered = Dictionary::new();
+        for (key, value) in dict.iter() {
+            if key != b"Parent" {
+                renumbered.set(key.clone(), self.renumber(value));
+            }
+        }
+        renumbered
+    }
+
+    /// Copies every object queued by `renumber`, and the ones they refer to
+    fn finish(&mut self) {
+        while let Some(id) = self.queue.pop() {
+            let index = self.ids[&id];
+            if let Ok(object) = self.document.get_object(id) {
+                self.objects[index] = self.renumber(object);
+            }
+        }
+    }
+}
+
+fn is_page(object: &Object) -> bool {
+    let Ok(dict) = object.as_dict() else {
+        return false;
+    };
+    matches!(
+        dict.get(b"Type").and_then(Object::as_name),
+        Ok(b"Page" | b"Pages")
+    )
+}
+
+/// The value of `key` on the page or the closest of its ancestors that has it
+fn inherited<'a>(document: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = document.get_dictionary(page_id).ok()?;
+    // a loop in the page tree ends once it has gone deeper than any real one
+    for _ in 0..64 {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = document.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+fn rect(document: &Document, object: &Object) -> Option<[f32; 4]> {
+    let object = match object {
+        Object::Reference(id) => document.get_object(*id).ok()?,
+        other => other,
+    };
+    let values: Vec<f32> = object
+        .as_array()
+        .ok()?
+        .iter()
+        .filter_map(|value| value.as_float().ok())
+        .collect();
+    let [a, b, c, d] = values[..] else {
+        return None;
+    };
+    Some([a.min(c), b.min(d), a.max(c), b.max(d)])
+}
+
+/// Writes `object` in PDF syntax, reference `N` becoming `ids[N]`; strings
+/// and stream data are encrypted with `encryption`
+fn write_object(
+    object: &Object,
+    ids: &[u32],
+    encryption: Option<&PdfEncryption>,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    match object {
+        Object::Null => out.extend_from_slice(b"null"),
+        Object::Boolean(value) => write!(out, "{}", value)?,
+        Object::Integer(value) => write!(out, "{}", value)?,
+        Object::Real(value) if value.is_finite() => write!(out, "{}", value)?,
+        Object::Real(_) => out.push(b'0'),
+        Object::Name(name) => write_name(name, out),
+        Object::String(bytes, _) => match encryption {
+            Some(encryption) => out.extend_from_slice(encryption.encrypt_string(bytes)?.as_bytes()),
+            None => write!(out, "<{}>", to_hex(bytes))?,
+        },
+        Object::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b' ');
+                }
+                write_object(item, ids, encryption, out)?;
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dict) => write_dict(dict, ids, encryption, out, None)?,
+        Object::Stream(stream) => {
+            let encrypted;
+            let data = match encryption {
+                Some(encryption) => {
+                    encrypted = encryption.encrypt(&stream.content)?;
+                    &encrypted[..]
+                }
+                None => &stream.content[..],
+            };
+            write_dict(&stream.dict, ids, encryption, out, Some(data.len()))?;
+            out.extend_from_slice(b"\nstream\n");
+            out.extend_from_slice(data);
+            out.extend_from_slice(b"\nendstream");
+        }
+        Object::Reference((index, _)) => match ids.get(*index as usize) {
+            Some(id) => write!(out, "{} 0 R", id)?,
+            None => out.extend_from_slice(b"null"),
+        },
+    }
+    Ok(())
+}
+
+/// A dictionary, with `length` as its /Length in place of the one it had
+fn write_dict(
+    dict: &Dictionary,
+    ids: &[u32],
+    encryption: Option<&PdfEncryption>,
+    out: &mut Vec<u8>,
+    length: Option<usize>,
+) -> Result<()> {
+    out.extend_from_slice(b"<<");
+    for (key, value) in dict.iter() {
+        if length.is_some() && key == b"Length" {
+            continue;
+        }
+        out.push(b' ');
+        write_name(key, out);
+        out.push(b' ');
+        write_object(value, ids, encryption, out)?;
+    }
+    if let Some(length) = length {
+        write!(out, " /Length {}", length)?;
+    }
+    out.extend_from_slice(b" >>");
+    Ok(())
+}
+
+/// `/name`, escaping what a name can't hold as `#xx`
+fn write_name(name: &[u8], out: &mut Vec<u8>) {
+    out.push(b'/');
+    for &byte in name {
+        if byte.is_ascii_graphic() && !b"()<>[]{}/%#".contains(&byte) {
+            out.push(byte);
+        } else {
+            let _ = write!(out, "#{:02X}", byte);
+        }
+    }
+}
@@ -0,0 +1,1122 @@
+mod alt_chunk;
+mod bates;
+mod border_drawing;
+mod cancellation;
+mod chart_drawing;
+mod chart_parser;
+mod conversion_cache;
+mod diagram_drawing;
+mod diagram_parser;
+mod doc_props;
+mod doc_protection;
+mod document_model;
+mod docx_decryption;
+mod docx_parser;
+mod emoji;
+mod error;
+mod font_metrics;
+mod font_provider;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+mod header_footer;
+mod image_decoder;
+mod image_preprocessor;
+mod indic;
+mod inspection;
+mod letterhead;
+mod limits;
+mod line_breaks;
+mod numbering;
+mod object_placeholder;
+mod package;
+mod page_hooks;
+mod page_layout;
+mod page_range;
+mod pdf_document;
+mod pdf_encryption;
+mod pdf_form;
+mod pdf_linearizer;
+mod pdf_stream_writer;
+mod pdf_string;
+mod pdf_version;
+mod print_profile;
+mod progress;
+mod raster;
+mod renderer;
+mod report;
+mod styles;
+mod symbol_fonts;
+mod tab_stops;
+mod table_layout;
+mod xml_errors;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use tracing::info_span;
+use zip::ZipArchive;
+
+use doc_props::{pdf_date, DocProps};
+use doc_protection::EditRestriction;
+use docx_decryption::DocxInput;
+use docx_parser::parse_document;
+use image_preprocessor::{ImageParams, ImagePreprocessor, ImageSettings};
+use limits::ReadBudget;
+use package::{build_rel_map, find_main_part, part_dir, rels_part, MainPart, Relationship};
+use pdf_document::{PageLabel, PdfDocument};
+use pdf_encryption::{to_hex, Entropy, PdfEncryption, ALL_PERMISSIONS};
+use pdf_version::Feature;
+use renderer::render_document;
+use sha2::{Digest, Sha256};
+
+pub use bates::{BatesNumbering, StampPosition};
+pub use cancellation::CancelToken;
+pub use conversion_cache::{ConversionCache, DEFAULT_MAX_IMAGE_BYTES};
+pub use document_model::{
+    Alignment, Block, Border, BorderStyle, CellBorders, CellMargins, ContentControl,
+    ContentControlKind, Document, Image, Indent, Inline, LabelSuffix, ListLabel, Paragraph,
+    ParagraphBorders, RowHeight, Run, Section, Table, TableCell, TableRow, TextDirection,
+    VerticalAlign, Width,
+};
+pub use error::ConvertError;
+pub use font_metrics::StandardFont;
+pub use font_provider::{FontDirectory, FontProvider, FontRequest, FontSource, StandardFonts};
+pub use header_footer::HeaderFooter;
+pub use image_decoder::{DecodedImage, ImageDecoder};
+pub use inspection::{Inspection, MediaInfo, PartInfo, RelationshipInfo, SectionInfo, StyleUsage};
+pub use letterhead::{Letterhead, LetterheadPlacement};
+pub use limits::Limits;
+pub use page_hooks::{PageCanvas, PageHooks};
+pub use page_layout::{PageSetup, WrappedLine};
+pub use page_range::PageRanges;
+pub use pdf_document::{PageLabelStyle, ViewerOptions, Zoom};
+pub use pdf_version::PdfVersion;
+pub use print_profile::PrintProfile;
+pub use progress::{NoProgress, Phase, ProgressSink};
+pub use raster::{PageImageSink, PageImages, RasterFormat, MAX_DPI};
+pub use report::{Report, Warning};
+
+/// JPEG quality of re-encoded images unless set otherwise
+const DEFAULT_IMAGE_QUALITY: u8 = 50;
+
+/// Conversion settings, the defaults give a plain unencrypted A4 PDF 1.7
+///
+/// Fields can be set directly or through the chained setters:
+/// `Options::new().page_size(612.0, 792.0).image_quality(80).compress(false)`
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// encrypts the output, an empty string opens without prompting
+    pub user_password: Option<String>,
+    /// decrypts a password protected DOCX
+    pub input_password: Option<String>,
+    pub owner_password: Option<String>,
+    pub protect_no_copy: bool,
+    pub protect_no_print: bool,
+    /// skips mapping w:documentProtection to PDF permissions
+    pub ignore_protection: bool,
+    pub linearize: bool,
+    /// embeds the input under this file name, merged inputs under their titles
+    pub attach_source: Option<String>,
+    /// adds an unsigned signature field with this name
+    pub signature_field: Option<String>,
+    /// values of MERGEFIELD fields by field name, fields without one show
+    /// their `«name»` placeholder
+    pub merge_values: BTreeMap<String, String>,
+    /// turns check box and drop-down content controls into fillable fields,
+    /// otherwise they show their content like the text around them
+    pub interactive_forms: bool,
+    pub pdf_version: PdfVersion,
+    pub viewer: ViewerOptions,
+    /// gives every merged input an outline entry
+    pub outline: bool,
+    /// clockwise page rotation in degrees, a multiple of 90
+    pub rotate: u16,
+    /// page size and margins in points, body font and size
+    pub page: PageSetup,
+    /// resolves the fonts runs ask for, whatever it can't goes in the body font
+    pub fonts: Arc<dyn FontProvider>,
+    /// asked in order for media the built-in decoders can't read
+    pub image_decoders: Vec<Arc<dyn ImageDecoder>>,
+    /// fonts and images shared with other conversions using the same cache
+    pub cache: Option<Arc<ConversionCache>>,
+    /// writes only these pages of the laid out document
+    pub pages: Option<PageRanges>,
+    /// called in order for every page written, e.g. for headers and watermarks
+    pub page_hooks: Vec<Arc<dyn PageHooks>>,
+    /// pages of another PDF drawn behind or over every page written
+    pub letterhead: Option<Letterhead>,
+    /// JPEG quality (1-100) for images that can't be passed through
+    pub image_quality: u8,
+    /// images drawn at a higher resolution are downsampled to this many dots per inch
+    pub image_dpi: Option<u32>,
+    /// JPEGs over `image_dpi` are downsampled too, otherwise they are always
+    /// embedded as they are
+    pub recompress_images: bool,
+    /// leaves all images out, the text flows as if they weren't there
+    pub drop_images: bool,
+    /// draws a labeled box where content that isn't rendered was left out,
+    /// e.g. "equation omitted", instead of nothing
+    pub mark_unsupported: bool,
+    /// writes PDF/X-4 for print, at most PDF 1.6 whatever `pdf_version` says
+    pub print: Option<PrintProfile>,
+    /// renders every page written to an image as well
+    pub page_images: Option<PageImages>,
+    /// Flate compresses content streams and attachments, on by default
+    pub compress: bool,
+    /// writes identical streams once, e.g. an image placed on several pages
+    /// or the same header on every page
+    pub deduplicate: bool,
+    /// the same input and options always give the same bytes: the trailer /ID
+    /// is derived from a hash of the input, any encryption keys and IVs from
+    /// that and the passwords, dates only ever come from the DOCX properties
+    pub deterministic: bool,
+    /// fails on the first malformed XML in the DOCX instead of skipping past
+    /// it with a warning
+    pub strict_xml: bool,
+    /// how much the DOCX may inflate to, how many entries and how large images
+    /// it may have
+    pub limits: Limits,
+    /// replace the document properties read from docProps
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    /// replaces the creation date, W3CDTF as in docProps: 2024-03-05 or
+    /// 2024-03-05T10:20:30Z
+    pub creation_date: Option<String>,
+    /// stops the conversion with an error once cancelled, whatever was written
+    /// to the output so far is incomplete and temporary files are removed
+    pub cancel: CancelToken,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            user_password: None,
+            input_password: None,
+            owner_password: None,
+            protect_no_copy: false,
+            protect_no_print: false,
+            ignore_protection: false,
+            linearize: false,
+            attach_source: None,
+            signature_field: None,
+            merge_values: BTreeMap::new(),
+            interactive_forms: false,
+            pdf_version: PdfVersion::default(),
+            viewer: ViewerOptions::default(),
+            outline: false,
+            rotate: 0,
+            page: PageSetup::default(),
+            fonts: Arc::new(StandardFonts),
+            image_decoders: Vec::new(),
+            pages: None,
+            page_hooks: Vec::new(),
+            letterhead: None,
+            cache: None,
+            image_quality: DEFAULT_IMAGE_QUALITY,
+            image_dpi: None,
+            recompress_images: true,
+            drop_images: false,
+            mark_unsupported: false,
+            print: None,
+            page_images: None,
+            compress: true,
+            deduplicate: false,
+            deterministic: false,
+            strict_xml: false,
+            limits: Limits::default(),
+            title: None,
+            author: None,
+            subject: None,
+            keywords: None,
+            creation_date: None,
+            cancel: CancelToken::default(),
+        }
+    }
+}
+
+// one Options is shared by conversions running on any number of threads
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    let _ = assert_send_sync::<Options>;
+};
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn page_size(mut self, width: f32, height: f32) -> Self {
+        self.page.width = width;
+        self.page.height = height;
+        self
+    }
+
+    pub fn margin(mut self, margin: f32) -> Self {
+        self.page.margin = margin;
+        self
+    }
+
+    pub fn font(mut self, font: StandardFont, size: f32) -> Self {
+        self.page.font = font;
+        self.page.font_size = size;
+        self
+    }
+
+    pub fn font_provider(mut self, provider: impl FontProvider + 'static) -> Self {
+        self.fonts = Arc::new(provider);
+        self
+    }
+
+    pub fn image_decoder(mut self, decoder: impl ImageDecoder + 'static) -> Self {
+        self.image_decoders.push(Arc::new(decoder));
+        self
+    }
+
+    pub fn cache(mut self, cache: Arc<ConversionCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn pages(mut self, pages: PageRanges) -> Self {
+        self.pages = Some(pages);
+        self
+    }
+
+    pub fn page_hook(mut self, hooks: impl PageHooks + 'static) -> Self {
+        self.page_hooks.push(Arc::new(hooks));
+        self
+    }
+
+    pub fn letterhead(mut self, letterhead: Letterhead) -> Self {
+        self.letterhead = Some(letterhead);
+        self
+    }
+
+    pub fn image_quality(mut self, quality: u8) -> Self {
+        self.image_quality = quality.clamp(1, 100);
+        self
+    }
+
+    pub fn image_dpi(mut self, dpi: u32) -> Self {
+        self.image_dpi = Some(dpi.max(1));
+        self
+    }
+
+    pub fn recompress_images(mut self, recompress: bool) -> Self {
+        self.recompress_images = recompress;
+        self
+    }
+
+    pub fn drop_images(mut self, drop: bool) -> Self {
+        self.drop_images = drop;
+        self
+    }
+
+    pub fn mark_unsupported(mut self, mark: bool) -> Self {
+        self.mark_unsupported = mark;
+        self
+    }
+
+    pub fn print_profile(mut self, print: PrintProfile) -> Self {
+        self.print = Some(print);
+        self
+    }
+
+    pub fn page_images(mut self, images: PageImages) -> Self {
+        self.page_images = Some(images);
+        self
+    }
+
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn strict_xml(mut self, strict: bool) -> Self {
+        self.strict_xml = strict;
+        self
+    }
+
+    pub fn merge_value(mut self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.merge_values.insert(field.into(), value.into());
+        self
+    }
+
+    pub fn interactive_forms(mut self, interactive: bool) -> Self {
+        self.interactive_forms = interactive;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn pdf_version(mut self, version: PdfVersion) -> Self {
+        self.pdf_version = version;
+        self
+    }
+
+    pub fn encrypt(mut self, user_password: &str, owner_password: Option<&str>) -> Self {
+        self.user_password = Some(user_password.to_string());
+        self.owner_password = owner_password.map(str::to_string);
+        self
+    }
+
+    pub fn input_password(mut self, password: &str) -> Self {
+        self.input_password = Some(password.to_string());
+        self
+    }
+
+    pub fn linearize(mut self, linearize: bool) -> Self {
+        self.linearize = linearize;
+        self
+    }
+
+    pub fn outline(mut self, outline: bool) -> Self {
+        self.outline = outline;
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.subject = Some(subject.to_string());
+        self
+    }
+
+    pub fn keywords(mut self, keywords: &str) -> Self {
+        self.keywords = Some(keywords.to_string());
+        self
+    }
+
+    pub fn creation_date(mut self, date: &str) -> Self {
+        self.creation_date = Some(date.to_string());
+        self
+    }
+
+    pub fn cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// docProps with the overrides applied
+    fn doc_props(&self, mut props: DocProps) -> DocProps {
+        let overrides = [
+            (&self.title, &mut props.title),
+            (&self.author, &mut props.author),
+            (&self.subject, &mut props.subject),
+            (&self.keywords, &mut props.keywords),
+            (&self.creation_date, &mut props.created),
+        ];
+        for (value, slot) in overrides {
+            if value.is_some() {
+                slot.clone_from(value);
+            }
+        }
+        props
+    }
+}
+
+/// Converts the DOCX read from `input` into a PDF written to `output`,
+/// any `Write + Seek` sink works, a file, a `Cursor<Vec<u8>>` or `&mut` either
+pub fn convert<R, W>(input: R, output: W, opts: &Options) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    convert_with_progress(input, output, opts, &NoProgress)
+}
+
+/// `convert`, reporting phases, images and pages to `progress` along the way
+pub fn convert_with_progress<R, W>(
+    input: R,
+    output: W,
+    opts: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    run_conversion(input, None, output, opts, progress)
+}
+
+/// Reads the body of the DOCX into the document model without writing anything,
+/// it can be inspected or changed and then rendered with `convert_document`
+pub fn read_document<R: Read + Seek>(input: R) -> Result<Document, ConvertError> {
+    // there is no password to give, encrypted documents are an error
+    let limits = Limits::default();
+    let input = DocxInput::open(input, None, &limits)?;
+    let mut archive = ZipArchive::new(BufReader::new(input))
+        .map_err(|e| ConvertError::invalid_docx("zip archive", e))?;
+    let budget = ReadBudget::new(limits);
+    budget.check_entries(&archive)?;
+    // malformed XML is skipped past as in a conversion, without the warnings
+    let mut warnings = Vec::new();
+    let main_part = find_main_part(&mut archive, &budget, false, &mut warnings)
+        .map_err(invalid("package relationships"))?;
+    let rel_map = build_rel_map(&mut archive, &budget, &main_part.name, false, &mut warnings)
+        .map_err(invalid(&rels_part(&main_part.name)))?;
+    parse_document(
+        &mut archive,
+        &budget,
+        &main_part.name,
+        &rel_map,
+        &CancelToken::default(),
+        false,
+        &mut warnings,
+    )
+    .map_err(invalid(&main_part.name))
+}
+
+/// The text of the DOCX read from `input`, a line per paragraph, without
+/// laying it out; see `Document::plain_text`
+pub fn extract_text<R: Read + Seek>(input: R) -> Result<String, ConvertError> {
+    Ok(read_document(input)?.plain_text())
+}
+
+/// The package structure of the DOCX read from `input`: its parts, the
+/// relationships of the main part, media, sections and style usage
+pub fn inspect<R: Read + Seek>(input: R) -> Result<Inspection, ConvertError> {
+    let limits = Limits::default();
+    let input = DocxInput::open(input, None, &limits)?;
+    let mut archive = ZipArchive::new(BufReader::new(input))
+        .map_err(|e| ConvertError::invalid_docx("zip archive", e))?;
+    let budget = ReadBudget::new(limits);
+    budget.check_entries(&archive)?;
+    inspection::inspect_package(&mut archive, &budget).map_err(invalid("package"))
+}
+
+/// `convert` with `document` rendered in place of the body of `input`,
+/// media, properties and protection are still taken from `input`
+pub fn convert_document<R, W>(
+    document: &Document,
+    input: R,
+    output: W,
+    opts: &Options,
+) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    run_conversion(input, Some(document), output, opts, &NoProgress)
+}
+
+/// The conversion pipeline, parsing the body of `input` unless `document` is given
+fn run_conversion<R, W>(
+    input: R,
+    document: Option<&Document>,
+    output: W,
+    opts: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    check_options(opts)?;
+    let mut input = Input::open(input, opts, progress)?;
+    let part = Part {
+        outline: None,
+        attachment: opts.attach_source.clone(),
+        document,
+        input: &mut input,
+    };
+    write_output(vec![part], output, opts, progress)
+}
+
+/// One of the DOCX files `merge` puts into a single PDF
+pub struct MergeInput<R> {
+    /// the outline entry of this input, with `attach_source` it is also
+    /// embedded under this name with `.docx` appended
+    pub title: String,
+    pub input: R,
+}
+
+/// Converts `inputs` into a single PDF, the pages of each one after those of
+/// the one before, with an outline entry per input when `opts.outline` is set
+///
+/// The document properties are taken from the first input, editing
+/// restrictions from the first input that has any.
+pub fn merge<R, W>(
+    inputs: Vec<MergeInput<R>>,
+    output: W,
+    opts: &Options,
+) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    merge_with_progress(inputs, output, opts, &NoProgress)
+}
+
+/// `merge`, reporting to `progress` for every input in turn
+pub fn merge_with_progress<R, W>(
+    inputs: Vec<MergeInput<R>>,
+    output: W,
+    opts: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    check_options(opts)?;
+    if inputs.is_empty() {
+        return Err(ConvertError::InvalidOptions("nothing to merge".to_string()));
+    }
+    if opts.pages.is_some() {
+        return Err(ConvertError::InvalidOptions(
+            "a page selection cannot be combined with merging".to_string(),
+        ));
+    }
+    // the bodies are parsed one at a time as they are written, what is read
+    // here up front is what encrypting and attaching needs
+    let mut opened = Vec::with_capacity(inputs.len());
+    for merge_input in inputs {
+        let _span = info_span!("merge_input", title = %merge_input.title).entered();
+        let input = Input::open(merge_input.input, opts, progress)?;
+        opened.push((merge_input.title, input));
+    }
+    let parts = opened
+        .iter_mut()
+        .map(|(title, input)| Part {
+            outline: opts.outline.then_some(title.as_str()),
+            attachment: opts
+                .attach_source
+                .as_ref()
+                .map(|_| format!("{}.{}", title, input.main_part.kind.extension())),
+            document: None,
+            input,
+        })
+        .collect();
+    write_output(parts, output, opts, progress)
+}
+
+/// Rejects option combinations no input can satisfy
+fn check_options(opts: &Options) -> Result<(), ConvertError> {
+    if opts.linearize && opts.signature_field.is_some() {
+        // renumbering would move the bytes the /ByteRange points at
+        return Err(ConvertError::InvalidOptions(
+            "linearization cannot be combined with a signature field".to_string(),
+        ));
+    }
+    if !opts.rotate.is_multiple_of(90) {
+        return Err(ConvertError::InvalidOptions(format!(
+            "pages are rotated by a multiple of 90 degrees, not {}",
+            opts.rotate
+        )));
+    }
+    if let Some(print) = &opts.print {
+        if !print.bleed.is_finite() || print.bleed < 0.0 {
+            return Err(ConvertError::InvalidOptions(
+                "the bleed must be zero or more points".to_string(),
+            ));
+        }
+        print
+            .profile()
+            .map_err(|e| ConvertError::InvalidOptions(format!("output intent: {:#}", e)))?;
+    }
+    if let Some(images) = &opts.page_images
+        && !(1..=MAX_DPI).contains(&images.dpi)
+    {
+        return Err(ConvertError::InvalidOptions(format!(
+            "page images are rendered at 1 to {} dpi",
+            MAX_DPI
+        )));
+    }
+    if let Some(date) = &opts.creation_date
+        && pdf_date(date).is_none()
+    {
+        return Err(ConvertError::InvalidOptions(format!(
+            "creation date {:?} is not like 2024-03-05 or 2024-03-05T10:20:30Z",
+            date
+        )));
+    }
+    Ok(())
+}
+
+/// A DOCX opened for conversion, with everything but its body read
+struct Input<R> {
+    archive: ZipArchive<BufReader<DocxInput<R>>>,
+    source_bytes: Option<Vec<u8>>,
+    budget: ReadBudget,  // what may still be inflated out of the archive
+    main_part: MainPart, // word/document.xml unless the package says otherwise
+    rel_map: HashMap<String, Relationship>,
+    doc_props: DocProps,
+    restriction: Option<EditRestriction>,
+    images: ImagePreprocessor,
+    warnings: Vec<Warning>, // malformed XML skipped while reading it
+}
+
+impl<R: Read + Seek> Input<R> {
+    fn open(
+        mut input: R,
+        opts: &Options,
+        progress: &dyn ProgressSink,
+    ) -> Result<Self, ConvertError> {
+        // attachments and the deterministic seed are the only things needing the raw bytes
+        let source_bytes = if opts.attach_source.is_some() || opts.deterministic {
+            let mut bytes = Vec::new();
+            input.rewind()?;
+            input.read_to_end(&mut bytes)?;
+            Some(bytes)
+        } else {
+            None
+        };
+        input.rewind()?;
+        let input = DocxInput::open(input, opts.input_password.as_deref(), &opts.limits)?;
+        let mut archive = ZipArchive::new(BufReader::new(input))
+            .map_err(|e| ConvertError::invalid_docx("zip archive", e))?;
+
+        let budget = ReadBudget::new(opts.limits);
+        budget.check_entries(&archive)?;
+
+        opts.cancel.check()?;
+        progress.phase(Phase::RelMap);
+        let strict = opts.strict_xml;
+        let mut warnings = Vec::new();
+        let main_part = find_main_part(&mut archive, &budget, strict, &mut warnings)
+            .map_err(invalid("package relationships"))?;
+        let rel_map = build_rel_map(
+            &mut archive,
+            &budget,
+            &main_part.name,
+            strict,
+            &mut warnings,
+        )
+        .map_err(invalid(&rels_part(&main_part.name)))?;
+        let doc_props = DocProps::read(&mut archive, &budget, strict, &mut warnings)
+            .map_err(invalid("docProps"))?;
+        let doc_props = opts.doc_props(doc_props);
+        let restriction = if opts.ignore_protection {
+            None
+        } else {
+            // Word always relates the settings, hand-made packages may not
+            let settings = rel_map
+                .values()
+                .find(|rel| rel.kind == "settings")
+                .map(|rel| rel.target.clone())
+                .unwrap_or_else(|| format!("{}settings.xml", part_dir(&main_part.name)));
+            EditRestriction::read(&mut archive, &budget, &settings, strict, &mut warnings)
+                .map_err(invalid(&settings))?
+        };
+
+        progress.phase(Phase::ImagePreprocessing);
+        let images = ImagePreprocessor::preprocess_images(
+            &mut archive,
+            &budget,
+            &format!("{}media/", part_dir(&main_part.name)),
+            &ImageSettings::new(opts),
+            &opts.image_decoders,
+            opts.cache.as_deref(),
+            progress,
+            &opts.cancel,
+        )
+        .map_err(|e| {
+            ConvertError::classify(e, |source| ConvertError::pdf_write("images", source))
+        })?;
+
+        Ok(Self {
+            archive,
+            source_bytes,
+            budget,
+            main_part,
+            rel_map,
+            doc_props,
+            restriction,
+            images,
+            warnings,
+        })
+    }
+
+    fn parse(
+        &mut self,
+        opts: &Options,
+        progress: &dyn ProgressSink,
+    ) -> Result<Document, ConvertError> {
+        opts.cancel.check()?;
+        progress.phase(Phase::Parsing);
+        parse_document(
+            &mut self.archive,
+            &self.budget,
+            &self.main_part.name,
+            &self.rel_map,
+            &opts.cancel,
+            opts.strict_xml,
+            &mut self.warnings,
+        )
+        .map_err(invalid(&self.main_part.name))
+    }
+}
+
+impl<R> Input<R> {
+    /// The media entry behind relationship `rid` and the JPEG made of it
+    fn media(&self, rid: &str) -> Result<(String, ImageParams), ConvertError> {
+        let missing = || ConvertError::MissingImage {
+            rid: rid.to_string(),
+        };
+        let zip_path = self.rel_map.get(rid).ok_or_else(missing)?.target.clone();
+
+        if let Some(image_params) = self.images.image_map.get(&zip_path) {
+            Ok((zip_path, image_params.clone()))
+        } else if self.images.unsupported.contains(&zip_path) {
+            Err(ConvertError::UnsupportedImage {
+                entry: zip_path,
+                source: "no decoder for this format".into(),
+            })
+        } else {
+            Err(missing())
+        }
+    }
+}
+
+/// Maps an error reading `entry` to `ConvertError::InvalidDocx` unless it is
+/// an I/O error or a cancellation
+fn invalid(entry: &str) -> impl Fn(anyhow::Error) -> ConvertError + '_ {
+    move |e| ConvertError::classify(e, |source| ConvertError::invalid_docx(entry, source))
+}
+
+/// Encrypts as the options and the inputs' editing restrictions ask and
+/// writes the PDF of `parts`, linearized if asked to
+fn write_output<R, W>(
+    parts: Vec<Part<R>>,
+    output: W,
+    opts: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let seed: Option<[u8; 32]> = opts.deterministic.then(|| {
+        let mut hasher = Sha256::new();
+        for part in &parts {
+            hasher.update(part.input.source_bytes.as_deref().unwrap_or_default());
+        }
+        hasher.finalize().into()
+    });
+    let restriction = parts.iter().find_map(|part| part.input.restriction);
+    let encrypt =
+        opts.user_password.is_some() || opts.owner_password.is_some() || restriction.is_some();
+    let encryption = if encrypt {
+        let entropy = match seed {
+            Some(seed) => Entropy::seeded(encryption_seed(seed, opts, restriction)),
+            None => Entropy::os(),
+        };
+        Some(Arc::new(
+            encryption(opts, restriction, entropy)
+                .map_err(|e| ConvertError::pdf_write("encryption", e))?,
+        ))
+    } else {
+        None
+    };
+    let images = parts
+        .iter()
+        .map(|part| part.input.images.image_map.len())
+        .sum();
+    let source = Source {
+        doc_props: parts[0].input.doc_props.clone(),
+        parts,
+        seed,
+        encryption: encryption.clone(),
+    };
+
+    let writing = |e| ConvertError::classify(e, |source| ConvertError::pdf_write("PDF", source));
+    let mut report = if opts.linearize {
+        // linearization is a post-pass over the finished file
+        #[cfg(feature = "tempfile")]
+        let mut temp = tempfile::tempfile()?;
+        #[cfg(not(feature = "tempfile"))]
+        let mut temp = Cursor::new(Vec::new());
+        let report = write_pdf(&mut temp, source, opts, progress).map_err(writing)?;
+        temp.rewind()?;
+        opts.cancel.check()?;
+        let _span = info_span!("linearize").entered();
+        // the hint stream is encrypted with the same file key as the rest
+        pdf_linearizer::linearize(BufReader::new(temp), output, encryption.as_deref())
+            .map_err(|e| ConvertError::pdf_write("linearized PDF", e))?;
+        report
+    } else {
+        write_pdf(output, source, opts, progress).map_err(writing)?
+    };
+
+    report.images = images;
+    Ok(report)
+}
+
+/// Converts the DOCX read from `input` and returns the finished PDF bytes,
+/// for callers that answer with the PDF directly instead of writing a file
+pub fn convert_to_vec<R>(input: R, opts: &Options) -> Result<(Vec<u8>, Report), ConvertError>
+where
+    R: Read + Seek,
+{
+    let mut output = Cursor::new(Vec::new());
+    let report = convert(input, &mut output, opts)?;
+    Ok((output.into_inner(), report))
+}
+
+/// Runs the whole conversion of `input` without keeping the PDF, to find out
+/// whether it converts and what the report would say
+pub fn check<R>(input: R, opts: &Options) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+{
+    check_with_progress(input, opts, &NoProgress)
+}
+
+/// `check`, reporting to `progress` along the way
+pub fn check_with_progress<R>(
+    input: R,
+    opts: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Report, ConvertError>
+where
+    R: Read + Seek,
+{
+    run_conversion(input, None, NullSink::default(), opts, progress)
+}
+
+/// Discards what is written but keeps track of the position, which the
+/// writer needs for the xref offsets
+#[derive(Default)]
+struct NullSink {
+    pos: u64,
+    len: u64,
+}
+
+impl Write for NullSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pos += buf.len() as u64;
+        self.len = self.len.max(self.pos);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for NullSink {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the output",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// Everything read from the inputs before the PDF is written
+struct Source<'a, R> {
+    parts: Vec<Part<'a, R>>,
+    seed: Option<[u8; 32]>,
+    doc_props: DocProps,
+    encryption: Option<Arc<PdfEncryption>>,
+}
+
+/// One input of the PDF being written
+struct Part<'a, R> {
+    outline: Option<&'a str>,       // title of its outline entry
+    attachment: Option<String>,     // embeds the DOCX under this file name
+    document: Option<&'a Document>, // parsed from the input when it is written
+    input: &'a mut Input<R>,
+}
+
+/// Writes the whole PDF into `sink`, reporting pages and warnings
+fn write_pdf<S, R>(
+    sink: S,
+    source: Source<R>,
+    opts: &Options,
+    progress: &dyn ProgressSink,
+) -> anyhow::Result<Report>
+where
+    S: Write + Seek,
+    R: Read + Seek,
+{
+    let mut warnings = Vec::new();
+    let mut version = match opts.print {
+        // PDF/X-4 is based on PDF 1.6
+        Some(_) => opts.pdf_version.min(PdfVersion::V1_6),
+        None => opts.pdf_version,
+    };
+    // dropping the encryption would expose the content, so the header goes up
+    if source.encryption.is_some() && !version.supports(Feature::Aes256) {
+        let raised = Feature::Aes256.min_version();
+        warnings.push(Warning::RaisedVersion {
+            feature: Feature::Aes256.to_string(),
+            target: version.to_string(),
+            written: raised.to_string(),
+        });
+        version = raised;
+    }
+    let mut pdf_document = PdfDocument::new(sink, version)?;
+    pdf_document.set_page_setup(opts.page);
+    if let Some(print) = &opts.print {
+        pdf_document.set_print_profile(print.clone());
+    }
+    pdf_document.set_compression(opts.compress);
+    pdf_document.set_deduplication(opts.deduplicate);
+    pdf_document.set_metadata(source.doc_props);
+    pdf_document.set_viewer_options(opts.viewer.clone());
+    pdf_document.set_rotation(opts.rotate);
+    if let Some(seed) = source.seed {
+        // the encryption entropy hashes the same seed with a counter
+        let mut file_id = [0u8; 16];
+        file_id.copy_from_slice(&seed[..16]);
+        pdf_document.set_file_id(file_id);
+    }
+    if let Some(encryption) = source.encryption {
+        pdf_document.set_encryption(encryption);
+    }
+    if let Some(name) = &opts.signature_field {
+        pdf_document.add_signature_field(name)?;
+    }
+    for part in &source.parts {
+        if let (Some(name), Some(bytes)) = (&part.attachment, &part.input.source_bytes) {
+            pdf_document.attach_file(name, part.input.main_part.kind.mime_type(), bytes)?;
+        }
+    }
+    if let Some(letterhead) = &opts.letterhead {
+        pdf_document.add_letterhead(letterhead)?;
+    }
+    let mut labels = Vec::new();
+    for part in source.parts {
+        let first_page = pdf_document.page_count();
+        // only the model of the input being written is held
+        let parsed;
+        let document = match part.document {
+            Some(document) => document,
+            None => {
+                parsed = part.input.parse(opts, progress)?;
+                &parsed
+            }
+        };
+        warnings.extend(part.input.warnings.iter().cloned());
+        warnings.extend(render_document(
+            document,
+            &mut pdf_document,
+            |rid| part.input.media(rid),
+            opts,
+            progress,
+        )?);
+        if let Some(title) = part.outline
+            && pdf_document.page_count() > first_page
+        {
+            pdf_document.add_outline_entry(title, first_page);
+        }
+        labels.push((first_page, pdf_document.take_page_labels()));
+    }
+    progress.phase(Phase::Writing);
+    // merged inputs keep their own numbering, as soon as one has labels the
+    // others are numbered from 1 again
+    if labels.iter().any(|(_, labels)| !labels.is_empty()) {
+        let mut merged = Vec::new();
+        for (first_page, labels) in labels {
+            if labels.is_empty() {
+                merged.push(PageLabel {
+                    first_page,
+                    style: PageLabelStyle::Decimal,
+                    start: 1,
+                });
+            }
+            merged.extend(labels.into_iter().map(|label| PageLabel {
+                first_page: first_page + label.first_page,
+                ..label
+            }));
+        }
+        pdf_document.set_page_labels(merged);
+    }
+    warnings.extend(
+        pdf_document
+            .print_problems()?
+            .into_iter()
+            .map(|what| Warning::NotPrintReady { what }),
+    );
+    opts.cancel.check()?;
+    let pages = pdf_document.page_count();
+    let _span = info_span!("finish_document", pages).entered();
+    pdf_document.finish_document()?;
+    Ok(Report {
+        pages,
+        images: 0,
+        warnings,
+    })
+}
+
+/// Mixes the passwords and permissions into the deterministic `seed`, so the
+/// file key can't be derived from the input alone
+fn encryption_seed(
+    seed: [u8; 32],
+    opts: &Options,
+    restriction: Option<EditRestriction>,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(seed);
+    for password in [&opts.user_password, &opts.owner_password] {
+        let password = password.as_deref().map(str::as_bytes);
+        // length prefixed so no two pairs of passwords hash alike
+        hasher.update([password.is_some() as u8]);
+        hasher.update((password.unwrap_or_default().len() as u64).to_be_bytes());
+        hasher.update(password.unwrap_or_default());
+    }
+    let permissions = restriction
+        .map(|restriction| restriction.permissions(opts.protect_no_copy, opts.protect_no_print));
+    hasher.update(permissions.unwrap_or(ALL_PERMISSIONS).to_be_bytes());
+    hasher.finalize().into()
+}
+
+fn encryption(
+    opts: &Options,
+    restriction: Option<EditRestriction>,
+    entropy: Entropy,
+) -> anyhow::Result<PdfEncryption> {
+    let user_password = opts.user_password.clone().unwrap_or_default();
+    let (owner_password, permissions) = match restriction {
+        // a protected document gets an unguessable owner password unless one is given,
+        // otherwise the permissions could be lifted with the user password
+        Some(restriction) => (
+            opts.owner_password
+                .clone()
+                .unwrap_or(to_hex(&entropy.bytes::<16>()?)),
+            restriction.permissions(opts.protect_no_copy, opts.protect_no_print),
+        ),
+        // without an owner password the user password unlocks everything
+        None => (
+            opts.owner_password
+                .clone()
+                .unwrap_or_else(|| user_password.clone()),
+            ALL_PERMISSIONS,
+        ),
+    };
+    PdfEncryption::new(&user_password, &owner_password, permissions, entropy)
+}
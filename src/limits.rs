@@ -0,0 +1,153 @@
+use std::cell::Cell;
+use std::io::{self, Read, Seek};
+use zip::read::ZipFile;
+use zip::result::{ZipError, ZipResult};
+use zip::ZipArchive;
+
+use super::error::ConvertError;
+
+/// Ceilings on the resources reading one DOCX may take
+///
+/// A crafted DOCX can inflate a few MB of deflate data to many GB or claim
+/// an image of billions of pixels, when it goes past any of these the
+/// conversion fails with `ConvertError::LimitExceeded` instead of exhausting
+/// memory or disk. The defaults are far above what real documents need.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// bytes any one archive entry may inflate to
+    pub max_entry_bytes: u64,
+    /// bytes all entries read may inflate to together
+    pub max_total_bytes: u64,
+    /// entries in the archive, read or not
+    pub max_entries: usize,
+    /// width times height of an image that has to be decoded
+    pub max_image_pixels: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_entry_bytes: 1 << 30,
+            max_total_bytes: 4 << 30,
+            max_entries: 10_000,
+            max_image_pixels: 200_000_000,
+        }
+    }
+}
+
+impl Limits {
+    /// Fails for an image too large to decode
+    pub(crate) fn check_pixels(&self, width: u32, height: u32) -> Result<(), ConvertError> {
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_image_pixels {
+            return Err(ConvertError::LimitExceeded(format!(
+                "a {}x{} image has more than {} pixels",
+                width, height, self.max_image_pixels
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// What is left of the limits while one DOCX is read, entries are read one
+/// after the other so a plain counter does
+pub(crate) struct ReadBudget {
+    limits: Limits,
+    total: Cell<u64>,
+}
+
+impl ReadBudget {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            total: Cell::new(0),
+        }
+    }
+
+    /// Fails for an archive with more entries than allowed, before any is read
+    pub fn check_entries<R: Read + Seek>(
+        &self,
+        archive: &ZipArchive<R>,
+    ) -> Result<(), ConvertError> {
+        if archive.len() > self.limits.max_entries {
+            return Err(ConvertError::LimitExceeded(format!(
+                "{} archive entries, more than {}",
+                archive.len(),
+                self.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    /// Entry `name` of `archive`, reading it fails once it or everything read
+    /// so far inflates past the limits
+    pub fn open<'a, R: Read + Seek>(
+        &'a self,
+        archive: &'a mut ZipArchive<R>,
+        name: &str,
+    ) -> ZipResult<LimitedEntry<'a>> {
+        Ok(LimitedEntry {
+            entry: archive.by_name(name)?,
+            read: 0,
+            budget: self,
+        })
+    }
+
+    /// All of entry `name` as text, None when the archive has no such entry
+    pub fn read_part<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+        name: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let mut part = match self.open(archive, name) {
+            Ok(part) => part,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut text = String::new();
+        part.read_to_string(&mut text)?;
+        Ok(Some(text))
+    }
+}
+
+/// An archive entry that stops inflating at the limits, the sizes in the
+/// central directory are whatever the producer wrote so they aren't trusted
+pub(crate) struct LimitedEntry<'a> {
+    entry: ZipFile<'a>,
+    read: u64,
+    budget: &'a ReadBudget,
+}
+
+impl LimitedEntry<'_> {
+    /// The size the archive gives, capped at what may be read, for buffers
+    pub fn size_hint(&self) -> usize {
+        self.entry.size().min(self.budget.limits.max_entry_bytes) as usize
+    }
+}
+
+impl Read for LimitedEntry<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.entry.read(buf)?;
+        self.read += n as u64;
+        let total = self.budget.total.get() + n as u64;
+        self.budget.total.set(total);
+        let limits = &self.budget.limits;
+        let exceeded = if self.read > limits.max_entry_bytes {
+            format!(
+                "{} inflates to more than {} bytes",
+                self.entry.name(),
+                limits.max_entry_bytes
+            )
+        } else if total > limits.max_total_bytes {
+            format!(
+                "the archive inflates to more than {} bytes",
+                limits.max_total_bytes
+            )
+        } else {
+            return Ok(n);
+        };
+        // carried through the readers as an I/O error, `ConvertError::classify`
+        // takes it out again
+        Err(io::Error::other(ConvertError::LimitExceeded(exceeded)))
+    }
+}
@@ -0,0 +1,28 @@
+use icu_segmenter::options::LineBreakOptions;
+use icu_segmenter::LineSegmenter;
+
+/// Scripts written without spaces between words, which UAX #14 leaves to a
+/// dictionary to break: Thai, Lao, Myanmar and Khmer
+fn needs_dictionary(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0E00}'..='\u{0EFF}'
+            | '\u{1000}'..='\u{109F}'
+            | '\u{1780}'..='\u{17FF}'
+            | '\u{19E0}'..='\u{19FF}'
+            | '\u{A9E0}'..='\u{A9FF}'
+            | '\u{AA60}'..='\u{AA7F}'
+    )
+}
+
+/// Byte offsets inside `word` where a line may break without a hyphen: the
+/// word boundaries of the scripts above, nothing for words without them
+pub fn word_breaks(word: &str) -> Vec<usize> {
+    if !word.chars().any(needs_dictionary) {
+        return Vec::new();
+    }
+    LineSegmenter::new_dictionary(LineBreakOptions::default())
+        .segment_str(word)
+        .filter(|&i| i > 0 && i < word.len())
+        .collect()
+}
@@ -1,298 +1,1865 @@
-mod image_preprocessor;
-mod pdf_document;
-mod pdf_stream_writer;
-
-use anyhow::Result;
-use quick_xml::events::Event;
-use quick_xml::Reader;
-use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Seek, Write};
-use std::time::Instant;
-use zip::ZipArchive;
-
-use image_preprocessor::{ImageParams, ImagePreprocessor};
-use pdf_document::PdfDocument;
-
-struct ParserEnv<'a, W: Write + Seek> {
-    pdf_document: &'a mut PdfDocument<W>,
-    current_text: String,
-    seen_rid: HashSet<String>,
-    current_page_objs: Vec<u32>,
-    current_page_img_objs: Vec<(u32, u32)>,
-}
-
-fn parse_document_xml<R, W, F>(
-    mut archive: ZipArchive<R>,
-    mut pdf_document: &mut PdfDocument<W>,
-    media_lookup: F,
-) -> Result<()>
-where
-    R: BufRead + Seek,
-    W: Write + Seek,
-    F: Fn(&str) -> Option<(String, ImageParams)>,
-{
-    // Load entire document.xml into memory
-    let doc_xml = {
-        let mut buf = Vec::new();
-        let mut doc_xml = archive.by_name("word/document.xml")?;
-        doc_xml.read_to_end(&mut buf)?;
-        buf
-    };
-    let mut buf_doc_xml = BufReader::new(&doc_xml[..]);
-    let mut reader = Reader::from_reader(&mut buf_doc_xml);
-
-    let mut buf = Vec::new();
-    let mut in_drawing = false;
-
-    let mut env = ParserEnv {
-        pdf_document: &mut pdf_document,
-        current_text: String::new(),
-        seen_rid: HashSet::new(),
-        current_page_objs: Vec::new(),
-        current_page_img_objs: Vec::new(),
-    };
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, TryLockError};
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, info, warn};
 
-    let mut _create_image_obj = |env: &mut ParserEnv<W>, rid: String| {
-        if env.seen_rid.contains(&rid) {
-            return;
-        }
-        if let Some((path, params)) = media_lookup(&rid) {
-            println!("image file path: {}", path);
-            if let Ok(f) = File::open(&path) {
-                if let Ok(image_id) = env
-                    .pdf_document
-                    .new_img_obj(f, params.w, params.h, params.len)
-                {
-                    env.current_page_img_objs.push(image_id);
-                } else {
-                    eprintln!("Image object could not be created, skipping");
-                }
-            } else {
-                eprintln!("Image file could not be opened, skipping");
+use docx2pdf::{
+    check_with_progress, convert_to_vec, convert_with_progress, extract_text, inspect,
+    merge_with_progress, BatesNumbering, ConversionCache, ConvertError, FontDirectory,
+    HeaderFooter, Letterhead, LetterheadPlacement, MergeInput, NoProgress, Options, PageImageSink,
+    PageImages, PageRanges, PdfVersion, Phase, PrintProfile, ProgressSink, RasterFormat, Report,
+    StampPosition, ViewerOptions, Zoom,
+};
+
+/// Converts a DOCX file to a minimal PDF
+#[derive(Parser)]
+#[command(version, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// the DOCX files to convert, glob patterns like `docs/*.docx` are expanded
+    #[arg(required_unless_present = "watch")]
+    inputs: Vec<PathBuf>,
+
+    /// where to write the PDF, for a single input
+    #[arg(short, long, conflicts_with = "out_dir")]
+    output: Option<PathBuf>,
+
+    /// writes each PDF here, named after its input
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+
+    /// number of files converted at the same time, all cores by default
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// writes all inputs one after the other into the single PDF given with -o
+    #[arg(long, requires = "output", conflicts_with_all = ["out_dir", "jobs", "pages"])]
+    merge: bool,
+
+    /// adds an outline entry for every merged input
+    #[arg(long, requires = "merge")]
+    outline: bool,
+
+    /// converts without writing anything, to see whether the inputs convert and
+    /// what would be left out
+    #[arg(long, conflicts_with_all = ["output", "out_dir", "merge", "watch"])]
+    check: bool,
+
+    /// converts new and changed .docx files dropped in DIR into --out-dir until interrupted
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "out_dir",
+        conflicts_with_all = ["inputs", "output", "merge", "report", "progress"]
+    )]
+    watch: Option<PathBuf>,
+
+    /// how long a watched file has to stay unchanged before it is converted, 1000 by default
+    #[arg(long, value_name = "MS", requires = "watch")]
+    debounce: Option<u64>,
+
+    /// opens password protected DOCX files with this password
+    #[arg(long, value_name = "PASSWORD")]
+    input_password: Option<String>,
+
+    /// encrypts the PDF with this user password, an empty one opens without prompting
+    #[arg(long, value_name = "USER_PASSWORD")]
+    encrypt: Option<String>,
+
+    /// owner password for the permissions, random when left out
+    #[arg(long, value_name = "PASSWORD")]
+    owner_password: Option<String>,
+
+    /// forbids copying text out of the PDF
+    #[arg(long)]
+    protect_no_copy: bool,
+
+    /// forbids printing the PDF
+    #[arg(long)]
+    protect_no_print: bool,
+
+    /// doesn't turn the DOCX's editing restrictions into PDF permissions
+    #[arg(long)]
+    ignore_protection: bool,
+
+    /// writes a linearized PDF for fast web view
+    #[arg(long)]
+    linearize: bool,
+
+    /// embeds the DOCX in the PDF
+    #[arg(long)]
+    attach_source: bool,
+
+    /// adds an unsigned signature field with this name
+    #[arg(long, value_name = "NAME")]
+    signature_field: Option<String>,
+
+    /// turns check box and drop-down content controls into fillable fields
+    #[arg(long)]
+    interactive_forms: bool,
+
+    /// fills MERGEFIELD fields from this JSON object of field names and values
+    #[arg(long, value_name = "PATH")]
+    merge_data: Option<PathBuf>,
+
+    /// PDF version of the output, 1.4 to 2.0, 1.7 by default
+    #[arg(long, value_name = "VERSION", value_parser = parse_pdf_version)]
+    pdf_version: Option<PdfVersion>,
+
+    /// writes PDF/X-4 for print, warning about what keeps it from complying
+    #[arg(long)]
+    pdf_x: bool,
+
+    /// how far the pages extend past their trimmed size on every side
+    #[arg(long, value_name = "POINTS", requires = "pdf_x")]
+    bleed: Option<f32>,
+
+    /// ICC profile of the printing condition, sRGB by default
+    #[arg(long, value_name = "PATH", requires_all = ["pdf_x", "output_condition"])]
+    output_intent: Option<PathBuf>,
+
+    /// identifier of the printing condition, e.g. FOGRA39
+    #[arg(long, value_name = "NAME", requires = "output_intent")]
+    output_condition: Option<String>,
+
+    /// what to write: pdf, png or jpeg, e.g. pdf,png for both; the page images
+    /// go next to the output as NAME-1.png, NAME-2.png ..
+    #[arg(
+        long,
+        value_name = "FORMATS",
+        value_delimiter = ',',
+        default_value = "pdf",
+        conflicts_with = "check"
+    )]
+    format: Vec<OutputFormat>,
+
+    /// resolution of the page images, 150 by default
+    #[arg(long, value_name = "DPI", value_parser = clap::value_parser!(u32).range(1..=1200))]
+    dpi: Option<u32>,
+
+    /// writes only these pages, e.g. 3-10,15 or 20- for page 20 to the end
+    #[arg(long, value_name = "RANGES", value_parser = parse_pages)]
+    pages: Option<PageRanges>,
+
+    /// leaves content streams and attachments uncompressed, to read them in a text editor
+    #[arg(long)]
+    no_compress: bool,
+
+    /// writes identical streams, such as repeated images and headers, once
+    #[arg(long)]
+    deduplicate: bool,
+
+    /// JPEG quality of the images that are re-encoded, 50 by default
+    #[arg(long, value_name = "1-100", value_parser = clap::value_parser!(u8).range(1..=100))]
+    image_quality: Option<u8>,
+
+    /// downsamples images drawn at a higher resolution than this
+    #[arg(long, value_name = "DPI", value_parser = clap::value_parser!(u32).range(1..))]
+    image_dpi: Option<u32>,
+
+    /// embeds JPEGs as they are, even above --image-dpi
+    #[arg(long)]
+    no_recompress: bool,
+
+    /// leaves all images out
+    #[arg(long, conflicts_with_all = ["image_quality", "image_dpi", "no_recompress"])]
+    drop_images: bool,
+
+    /// draws a labeled box where an equation, chart or other content that
+    /// isn't rendered was left out
+    #[arg(long)]
+    mark_unsupported: bool,
+
+    /// embeds TrueType fonts from this directory for the fonts runs ask for
+    #[arg(long, value_name = "DIR")]
+    font_dir: Option<PathBuf>,
+
+    /// replaces the title from the DOCX properties
+    #[arg(long)]
+    title: Option<String>,
+
+    /// replaces the author from the DOCX properties
+    #[arg(long)]
+    author: Option<String>,
+
+    /// replaces the subject from the DOCX properties
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// replaces the keywords from the DOCX properties
+    #[arg(long)]
+    keywords: Option<String>,
+
+    /// replaces the creation date from the DOCX properties, e.g. 2024-03-05
+    /// or 2024-03-05T10:20:30Z
+    #[arg(long, value_name = "DATE")]
+    creation_date: Option<String>,
+
+    /// viewer sizes its window to the first page
+    #[arg(long)]
+    fit_window: bool,
+
+    /// viewer hides its toolbar
+    #[arg(long)]
+    hide_toolbar: bool,
+
+    /// viewer shows two pages side by side
+    #[arg(long)]
+    two_page: bool,
+
+    /// page the viewer opens at, 1 based
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    open_page: Option<u32>,
+
+    /// zoom the viewer opens at: fit, width or a percentage
+    #[arg(long, value_parser = parse_zoom)]
+    zoom: Option<Zoom>,
+
+    /// rotates every page clockwise
+    #[arg(long, value_name = "DEGREES", default_value_t = 0, value_parser = parse_rotation)]
+    rotate: u16,
+
+    /// stamps every page with a Bates number starting with this prefix, which may be empty
+    #[arg(long, value_name = "PREFIX")]
+    bates: Option<String>,
+
+    /// first Bates number
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "bates")]
+    bates_start: u64,
+
+    /// digits the Bates numbers are padded to with zeros
+    #[arg(long, value_name = "N", default_value_t = 6, requires = "bates")]
+    bates_digits: usize,
+
+    /// where the Bates number goes: top-left, top-center, top-right,
+    /// bottom-left, bottom-center or bottom-right
+    #[arg(long, value_name = "POSITION", value_parser = parse_stamp_position, requires = "bates")]
+    bates_position: Option<StampPosition>,
+
+    /// writes this line centered at the top of every page, with {page},
+    /// {pages}, {date} and {filename} filled in
+    #[arg(long, value_name = "TEXT")]
+    header_text: Option<String>,
+
+    /// writes this line centered at the bottom of every page, with the same
+    /// placeholders as --header-text
+    #[arg(long, value_name = "TEXT")]
+    footer_text: Option<String>,
+
+    /// draws the pages of this PDF behind every page, its last page on the
+    /// pages past its end
+    #[arg(long, value_name = "PDF")]
+    letterhead: Option<PathBuf>,
+
+    /// draws the letterhead over the content instead of behind it
+    #[arg(long, requires = "letterhead")]
+    letterhead_overlay: bool,
+
+    /// the same input and options always give the same bytes
+    #[arg(long)]
+    deterministic: bool,
+
+    /// fails on malformed XML in the DOCX instead of skipping past it with a warning
+    #[arg(long)]
+    strict_xml: bool,
+
+    /// fails on a DOCX entry inflating to more than this, 1024 by default
+    #[arg(long, value_name = "MIB")]
+    max_entry_size: Option<u64>,
+
+    /// fails on a DOCX inflating to more than this in all, 4096 by default
+    #[arg(long, value_name = "MIB")]
+    max_total_size: Option<u64>,
+
+    /// fails on a DOCX with more entries than this, 10000 by default
+    #[arg(long, value_name = "N")]
+    max_entries: Option<usize>,
+
+    /// fails on an image to decode with more pixels than this, 200 by default
+    #[arg(long, value_name = "MEGAPIXELS")]
+    max_megapixels: Option<u64>,
+
+    /// doesn't list the content that was left out
+    #[arg(long)]
+    no_warnings: bool,
+
+    /// only prints errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// logs more, -v for timings, -vv for debug and -vvv for trace output
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// shows a progress bar on stderr
+    #[arg(long)]
+    progress: bool,
+
+    /// prints a machine readable report of every conversion to stdout
+    #[arg(long, value_name = "FORMAT")]
+    report: Option<ReportFormat>,
+
+    /// writes the report to this file instead of stdout
+    #[arg(long, value_name = "PATH", requires = "report")]
+    report_file: Option<PathBuf>,
+
+    /// reads defaults from this file instead of ./docx2pdf.toml, flags given here win
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+}
+
+/// Defaults from docx2pdf.toml, every key is optional
+///
+/// ```toml
+/// image_quality = 80
+/// font_dir = "fonts"      # relative to the file
+/// [page]
+/// width = 595.3           # points
+/// height = 841.9
+/// [metadata]
+/// author = "ACME Corp"
+/// [limits]
+/// max_total_size = 512    # MiB
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    image_quality: Option<u8>,
+    image_dpi: Option<u32>,
+    recompress_images: Option<bool>,
+    drop_images: bool,
+    mark_unsupported: bool,
+    font_dir: Option<PathBuf>,
+    pdf_version: Option<String>,
+    compress: Option<bool>,
+    deduplicate: bool,
+    linearize: bool,
+    deterministic: bool,
+    strict_xml: bool,
+    page: PageConfig,
+    metadata: MetadataConfig,
+    limits: LimitsConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PageConfig {
+    width: Option<f32>,
+    height: Option<f32>,
+    margin: Option<f32>,
+    font_size: Option<f32>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MetadataConfig {
+    title: Option<String>,
+    author: Option<String>,
+    subject: Option<String>,
+    keywords: Option<String>,
+    creation_date: Option<String>,
+}
+
+/// Same names and units as the flags
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LimitsConfig {
+    max_entry_size: Option<u64>,
+    max_total_size: Option<u64>,
+    max_entries: Option<usize>,
+    max_megapixels: Option<u64>,
+}
+
+const CONFIG_FILE: &str = "docx2pdf.toml";
+
+impl Config {
+    /// The file given with --config, else docx2pdf.toml in the working directory if there is one
+    fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(CONFIG_FILE).is_file() => Path::new(CONFIG_FILE),
+            None => return Ok(Self::default()),
+        };
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut config: Self =
+            toml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))?;
+        if let Some(quality) = config.image_quality
+            && !(1..=100).contains(&quality)
+        {
+            bail!(
+                "invalid config {}: image_quality must be 1 to 100",
+                path.display()
+            );
+        }
+        if config.image_dpi == Some(0) {
+            bail!(
+                "invalid config {}: image_dpi must be at least 1",
+                path.display()
+            );
+        }
+        if let Some(dir) = &mut config.font_dir
+            && dir.is_relative()
+            && let Some(parent) = path.parent()
+        {
+            *dir = parent.join(&*dir);
+        }
+        debug!("using config {}", path.display());
+        Ok(config)
+    }
+}
+
+/// Reads `{"FirstName": "Ada", "Orders": 3}`, numbers and booleans are
+/// written as they are and null as nothing
+fn read_merge_data(path: &Path) -> Result<BTreeMap<String, String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let data: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&text)
+        .with_context(|| format!("invalid merge data {}", path.display()))?;
+    let mut values = BTreeMap::new();
+    for (name, value) in data {
+        let value = match value {
+            serde_json::Value::String(text) => text,
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::Bool(_) | serde_json::Value::Number(_) => value.to_string(),
+            _ => bail!(
+                "invalid merge data {}: {} is not a string, number or boolean",
+                path.display(),
+                name
+            ),
+        };
+        values.insert(name, value);
+    }
+    Ok(values)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Pdf,
+    Png,
+    Jpeg,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Answers POST /convert with the PDF of the DOCX in the request body,
+    /// converted with the options given before `serve`
+    Serve {
+        #[arg(long, default_value = "127.0.0.1")]
+        host: std::net::IpAddr,
+
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// conversions running at the same time, more requests wait their turn;
+        /// all cores by default
+        #[arg(long, value_name = "N")]
+        max_concurrent: Option<NonZeroUsize>,
+
+        /// largest DOCX accepted, in MiB
+        #[arg(long, value_name = "MIB", default_value_t = 64)]
+        max_body: u64,
+    },
+
+    /// Converts the jobs put in QUEUE with the options given before `daemon`,
+    /// until stopped with Ctrl-C or SIGTERM
+    ///
+    /// QUEUE is either a directory .docx files are moved into, or a named
+    /// pipe the paths of .docx files are written to, one per line. Every job
+    /// gets its PDF and a <name>.status.json in the results directory. Jobs
+    /// taken from a directory end up in its .done or .failed subdirectory.
+    /// On shutdown the conversions already running are finished first.
+    Daemon {
+        queue: PathBuf,
+
+        /// where the PDFs and status files go
+        #[arg(long, value_name = "DIR")]
+        results: PathBuf,
+
+        /// conversions running at the same time, all cores by default
+        #[arg(long, value_name = "N")]
+        workers: Option<NonZeroUsize>,
+    },
+
+    /// Prints the text of a DOCX, a line per paragraph and the cells of a
+    /// table row separated by tabs, without converting it
+    ExtractText {
+        input: PathBuf,
+
+        /// writes the text here instead of to stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Prints the package structure of a DOCX: its parts, relationships,
+    /// media, sections and which styles it uses, for seeing why it converts badly
+    Inspect {
+        input: PathBuf,
+
+        /// prints JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn parse_pdf_version(value: &str) -> Result<PdfVersion, String> {
+    PdfVersion::parse(value).map_err(|e| e.to_string())
+}
+
+fn parse_pages(value: &str) -> Result<PageRanges, String> {
+    PageRanges::parse(value).map_err(|e| format!("{:#}", e))
+}
+
+fn parse_zoom(value: &str) -> Result<Zoom, String> {
+    match value {
+        "fit" => Ok(Zoom::FitPage),
+        "width" => Ok(Zoom::FitWidth),
+        percent => percent
+            .trim_end_matches('%')
+            .parse()
+            .map(Zoom::Percent)
+            .map_err(|_| "expected fit, width or a percentage".to_string()),
+    }
+}
+
+fn parse_stamp_position(value: &str) -> Result<StampPosition, String> {
+    match value {
+        "top-left" => Ok(StampPosition::TopLeft),
+        "top-center" => Ok(StampPosition::TopCenter),
+        "top-right" => Ok(StampPosition::TopRight),
+        "bottom-left" => Ok(StampPosition::BottomLeft),
+        "bottom-center" => Ok(StampPosition::BottomCenter),
+        "bottom-right" => Ok(StampPosition::BottomRight),
+        _ => Err(
+            "expected top-left, top-center, top-right, bottom-left, bottom-center or bottom-right"
+                .to_string(),
+        ),
+    }
+}
+
+fn parse_rotation(value: &str) -> Result<u16, String> {
+    match value.parse() {
+        Ok(degrees @ (0 | 90 | 180 | 270)) => Ok(degrees),
+        _ => Err("expected 0, 90, 180 or 270".to_string()),
+    }
+}
+
+/// One input file and where its PDF goes, nowhere with --check
+struct Job {
+    input: PathBuf,
+    output: Option<PathBuf>,
+}
+
+impl Cli {
+    fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::ERROR,
+            (false, 0) => LevelFilter::WARN,
+            (false, 1) => LevelFilter::INFO,
+            (false, 2) => LevelFilter::DEBUG,
+            (false, _) => LevelFilter::TRACE,
+        }
+    }
+
+    fn warnings(&self) -> bool {
+        !self.no_warnings && !self.quiet
+    }
+
+    /// The inputs with globs expanded
+    fn inputs(&self) -> Result<Vec<PathBuf>> {
+        let mut inputs = Vec::new();
+        for input in &self.inputs {
+            let pattern = input.to_string_lossy();
+            // shells expand globs themselves, except when quoted or on Windows
+            if input.exists() || !pattern.contains(['*', '?', '[']) {
+                inputs.push(input.clone());
+                continue;
             }
-        } else {
-            eprintln!("No data found, skipping");
+            let matched = glob::glob(&pattern)?
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("expanding {}", pattern))?;
+            if matched.is_empty() {
+                bail!("no files match {}", pattern);
+            }
+            inputs.extend(matched);
         }
-        env.seen_rid.insert(rid);
-    };
+        Ok(inputs)
+    }
 
-    let mut _create_text_obj = |env: &mut ParserEnv<W>| {
-        if !env.current_text.trim().is_empty() {
-            println!("Paragraph: {}", env.current_text.trim());
-            if let Ok(obj) = env.pdf_document.new_text_obj(env.current_text.trim()) {
-                env.current_page_objs.push(obj);
-            } else {
-                eprintln!("Text object could not be created, skipping");
+    /// The inputs paired with their outputs
+    fn jobs(&self) -> Result<Vec<Job>> {
+        let mut inputs = self.inputs()?;
+        if self.check {
+            return Ok(inputs
+                .into_iter()
+                .map(|input| Job {
+                    input,
+                    output: None,
+                })
+                .collect());
+        }
+        if let Some(output) = &self.output {
+            if inputs.len() > 1 {
+                bail!(
+                    "-o takes a single input, use --out-dir for {}",
+                    inputs.len()
+                );
             }
+            return Ok(vec![Job {
+                input: inputs.remove(0),
+                output: Some(output.clone()),
+            }]);
         }
-        env.current_text.clear();
-    };
+        let Some(out_dir) = &self.out_dir else {
+            bail!("either -o or --out-dir is required");
+        };
+        std::fs::create_dir_all(out_dir)
+            .with_context(|| format!("creating {}", out_dir.display()))?;
+        let jobs: Vec<Job> = inputs
+            .into_iter()
+            .map(|input| {
+                let output = output_path(out_dir, &input, ".pdf");
+                Job {
+                    input,
+                    output: Some(output),
+                }
+            })
+            .collect();
+        // two inputs with the same name from different directories
+        let mut outputs = HashMap::new();
+        for job in &jobs {
+            if let Some(output) = &job.output
+                && let Some(other) = outputs.insert(output, &job.input)
+            {
+                bail!(
+                    "{} and {} would both be written to {}",
+                    other.display(),
+                    job.input.display(),
+                    output.display()
+                );
+            }
+        }
+        Ok(jobs)
+    }
 
-    let mut _create_page_obj = |env: &mut ParserEnv<W>| {
-        if env
-            .pdf_document
-            .new_page_obj(&env.current_page_objs, &env.current_page_img_objs)
-            .is_err()
-        {
-            eprintln!("Page object could not be created, skipping");
+    /// The image format asked for with --format, if any
+    fn raster_format(&self) -> Result<Option<RasterFormat>> {
+        let formats: Vec<RasterFormat> = self
+            .format
+            .iter()
+            .filter_map(|format| match format {
+                OutputFormat::Pdf => None,
+                OutputFormat::Png => Some(RasterFormat::Png),
+                OutputFormat::Jpeg => Some(RasterFormat::Jpeg),
+            })
+            .collect();
+        match formats[..] {
+            [] => Ok(None),
+            [format] => Ok(Some(format)),
+            _ => bail!("--format takes either png or jpeg, not both"),
         }
-        env.current_page_objs.clear();
-        env.current_page_img_objs.clear();
-    };
+    }
 
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Eof) => break,
-
-            // --- text nodes ---
-            Ok(Event::Text(e)) if !in_drawing => {
-                let t = e.unescape().unwrap_or_default().to_string();
-                env.current_text.push_str(&t);
-                env.current_text.push(' ');
+    fn writes_pdf(&self) -> bool {
+        self.format.contains(&OutputFormat::Pdf)
+    }
+
+    /// `options` stamping --header-text and --footer-text on the pages of the
+    /// PDF converted from `inputs` and named after `file`; `{pages}` takes a
+    /// first pass over the inputs to count them
+    fn with_header_footer(
+        &self,
+        options: &mut Options,
+        inputs: &[PathBuf],
+        file: &Path,
+    ) -> Result<()> {
+        if self.header_text.is_none() && self.footer_text.is_none() {
+            return Ok(());
+        }
+        let mut stamp = HeaderFooter::new().file_name(
+            file.file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+        );
+        stamp.header = self.header_text.clone();
+        stamp.footer = self.footer_text.clone();
+        let uses = |placeholder: &str| {
+            [&stamp.header, &stamp.footer]
+                .into_iter()
+                .flatten()
+                .any(|text| text.contains(placeholder))
+        };
+        if uses("{date}") {
+            stamp.date = self.date()?;
+        }
+        if stamp.needs_total() {
+            let mut pages = 0;
+            for input in inputs {
+                let file =
+                    File::open(input).with_context(|| format!("opening {}", input.display()))?;
+                pages += check_with_progress(BufReader::new(file), options, &NoProgress)?.pages;
             }
+            stamp.total_pages = Some(pages);
+        }
+        options.page_hooks.push(Arc::new(stamp));
+        Ok(())
+    }
 
-            // --- paragraph end ---
-            Ok(Event::End(e)) if e.name().as_ref() == b"w:p" => {
-                _create_text_obj(&mut env);
+    /// Today's date for {date}, with --deterministic the date of
+    /// SOURCE_DATE_EPOCH as in reproducible builds
+    fn date(&self) -> Result<String> {
+        let secs = match std::env::var("SOURCE_DATE_EPOCH") {
+            Ok(epoch) if self.deterministic => epoch
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid SOURCE_DATE_EPOCH {:?}", epoch))?,
+            _ if self.deterministic => {
+                bail!("{{date}} with --deterministic needs SOURCE_DATE_EPOCH to be set")
             }
+            _ => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        };
+        Ok(utc_date(secs))
+    }
 
-            // --- start tags (detect drawings or breaks) ---
-            Ok(Event::Start(e)) => {
-                let name = e.name().into_inner();
+    /// `options` with the page images of the output at `output` written next to it
+    fn with_page_images(&self, options: &mut Options, output: &Path) -> Result<()> {
+        if let Some(format) = self.raster_format()? {
+            let files = ImageFiles {
+                stem: output.with_extension(""),
+                extension: format.extension(),
+            };
+            let mut images = PageImages::new(format, files);
+            if let Some(dpi) = self.dpi {
+                images = images.dpi(dpi);
+            }
+            options.page_images = Some(images);
+        }
+        Ok(())
+    }
 
-                // Detect page breaks: <w:br w:type="page"/>
-                if name == b"w:br" {
-                    for a in e.attributes().flatten() {
-                        if a.key.as_ref() == b"w:type" && a.value.as_ref() == b"page" {
-                            _create_page_obj(&mut env);
-                        }
+    fn options(&self) -> Result<Options> {
+        let config = Config::load(self.config.as_deref())?;
+        // png,jpeg fails before anything is converted
+        self.raster_format()?;
+        let pdf_version = match (self.pdf_version, &config.pdf_version) {
+            (Some(version), _) => version,
+            (None, Some(version)) => PdfVersion::parse(version)
+                .with_context(|| format!("invalid pdf_version {:?} in the config", version))?,
+            (None, None) => PdfVersion::default(),
+        };
+        let mut options = Options {
+            user_password: self.encrypt.clone(),
+            input_password: self.input_password.clone(),
+            owner_password: self.owner_password.clone(),
+            protect_no_copy: self.protect_no_copy,
+            protect_no_print: self.protect_no_print,
+            ignore_protection: self.ignore_protection,
+            linearize: self.linearize || config.linearize,
+            signature_field: self.signature_field.clone(),
+            interactive_forms: self.interactive_forms,
+            pdf_version,
+            viewer: ViewerOptions {
+                fit_window: self.fit_window,
+                hide_toolbar: self.hide_toolbar,
+                two_page: self.two_page,
+                open_page: self.open_page.map(|page| page as usize),
+                zoom: self.zoom,
+            },
+            outline: self.outline,
+            rotate: self.rotate,
+            pages: self.pages.clone(),
+            image_dpi: self.image_dpi.or(config.image_dpi),
+            recompress_images: !self.no_recompress && config.recompress_images.unwrap_or(true),
+            drop_images: self.drop_images || config.drop_images,
+            mark_unsupported: self.mark_unsupported || config.mark_unsupported,
+            compress: !self.no_compress && config.compress.unwrap_or(true),
+            deduplicate: self.deduplicate || config.deduplicate,
+            deterministic: self.deterministic || config.deterministic,
+            strict_xml: self.strict_xml || config.strict_xml,
+            title: self.title.clone().or(config.metadata.title),
+            author: self.author.clone().or(config.metadata.author),
+            subject: self.subject.clone().or(config.metadata.subject),
+            keywords: self.keywords.clone().or(config.metadata.keywords),
+            creation_date: self.creation_date.clone().or(config.metadata.creation_date),
+            ..Options::default()
+        };
+        let page = &mut options.page;
+        page.width = config.page.width.unwrap_or(page.width);
+        page.height = config.page.height.unwrap_or(page.height);
+        page.margin = config.page.margin.unwrap_or(page.margin);
+        page.font_size = config.page.font_size.unwrap_or(page.font_size);
+        if let Some(quality) = self.image_quality.or(config.image_quality) {
+            options = options.image_quality(quality);
+        }
+        if let Some(dir) = self.font_dir.as_ref().or(config.font_dir.as_ref()) {
+            options = options.font_provider(FontDirectory::new(dir)?);
+        }
+        let limits = &mut options.limits;
+        let mib = |size: u64| size.saturating_mul(1024 * 1024);
+        if let Some(size) = self.max_entry_size.or(config.limits.max_entry_size) {
+            limits.max_entry_bytes = mib(size);
+        }
+        if let Some(size) = self.max_total_size.or(config.limits.max_total_size) {
+            limits.max_total_bytes = mib(size);
+        }
+        if let Some(entries) = self.max_entries.or(config.limits.max_entries) {
+            limits.max_entries = entries;
+        }
+        if let Some(megapixels) = self.max_megapixels.or(config.limits.max_megapixels) {
+            limits.max_image_pixels = megapixels.saturating_mul(1_000_000);
+        }
+        if let Some(path) = &self.merge_data {
+            options.merge_values = read_merge_data(path)?;
+        }
+        if self.pdf_x {
+            let mut print = PrintProfile::new().bleed(self.bleed.unwrap_or_default());
+            if let (Some(path), Some(condition)) = (&self.output_intent, &self.output_condition) {
+                let profile = std::fs::read(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                print = print.output_intent(condition, profile);
+            }
+            options = options.print_profile(print);
+        }
+        if let Some(prefix) = &self.bates {
+            let bates = BatesNumbering::new(prefix)
+                .start(self.bates_start)
+                .digits(self.bates_digits)
+                .position(self.bates_position.unwrap_or_default());
+            options = options.page_hook(bates);
+        }
+        if let Some(path) = &self.letterhead {
+            let data = std::fs::read(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let placement = match self.letterhead_overlay {
+                true => LetterheadPlacement::Overlay,
+                false => LetterheadPlacement::Underlay,
+            };
+            options = options.letterhead(Letterhead::from_pdf(&data)?.placement(placement));
+        }
+        Ok(options)
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let jobs = if cli.merge || cli.watch.is_some() || cli.command.is_some() {
+        Vec::new()
+    } else {
+        cli.jobs()?
+    };
+
+    let bar = if !cli.progress || cli.quiet || cli.command.is_some() {
+        ProgressBar::hidden()
+    } else if cli.merge || jobs.len() == 1 {
+        let bar = ProgressBar::new_spinner()
+            .with_style(ProgressStyle::with_template("{spinner} {msg} [{elapsed}]")?);
+        bar.enable_steady_tick(Duration::from_millis(100));
+        bar
+    } else {
+        ProgressBar::new(jobs.len() as u64).with_style(ProgressStyle::with_template(
+            "[{elapsed}] {wide_bar} {pos}/{len} {msg}",
+        )?)
+    };
+
+    // logs go to stderr so stdout stays clean for piping
+    let log_bar = bar.clone();
+    tracing_subscriber::fmt()
+        .with_writer(move || BarWriter(log_bar.clone()))
+        .with_max_level(cli.log_level())
+        .with_target(false)
+        .init();
+
+    let options = cli.options()?;
+    if let Some(Command::Serve {
+        host,
+        port,
+        max_concurrent,
+        max_body,
+    }) = cli.command
+    {
+        let workers = max_concurrent
+            .or_else(|| std::thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get);
+        return serve(
+            &cli,
+            SocketAddr::new(host, port),
+            workers,
+            max_body,
+            &options,
+        );
+    }
+    if let Some(Command::Daemon {
+        queue,
+        results,
+        workers,
+    }) = &cli.command
+    {
+        let workers = workers
+            .or_else(|| std::thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get);
+        return daemon(&cli, queue, results, workers, &options);
+    }
+    if let Some(Command::ExtractText { input, output }) = &cli.command {
+        let file = File::open(input).with_context(|| format!("opening {}", input.display()))?;
+        let text = extract_text(BufReader::new(file))?;
+        return match output {
+            Some(output) => std::fs::write(output, text)
+                .with_context(|| format!("writing {}", output.display())),
+            None => Ok(io::stdout().write_all(text.as_bytes())?),
+        };
+    }
+    if let Some(Command::Inspect { input, json }) = &cli.command {
+        let file = File::open(input).with_context(|| format!("opening {}", input.display()))?;
+        let inspection = inspect(BufReader::new(file))?;
+        let text = match json {
+            true => serde_json::to_string_pretty(&inspection)? + "\n",
+            false => inspection.to_string(),
+        };
+        return Ok(io::stdout().write_all(text.as_bytes())?);
+    }
+    if let (Some(dir), Some(out_dir)) = (&cli.watch, &cli.out_dir) {
+        return watch(&cli, dir, out_dir, &options);
+    }
+    let bar_progress = BarProgress(&bar);
+    if let (true, Some(output)) = (cli.merge, &cli.output) {
+        let timer = StageTimer::new(&bar_progress);
+        let report = merge_files(&cli, output, &options, &timer);
+        bar.finish_and_clear();
+        let file_report = FileReport::new(cli.inputs()?, Some(output), &report, timer.timings());
+        write_report(&cli, vec![file_report])?;
+        print_result(&cli, &report?, None);
+        return Ok(());
+    }
+    if let [job] = &jobs[..] {
+        let timer = StageTimer::new(&bar_progress);
+        let report = convert_file(&cli, job, &options, &timer);
+        bar.finish_and_clear();
+        let file_report = FileReport::new(
+            vec![job.input.clone()],
+            job.output.as_deref(),
+            &report,
+            timer.timings(),
+        );
+        write_report(&cli, vec![file_report])?;
+        print_result(&cli, &report?, None);
+        return Ok(());
+    }
+
+    // the files of a batch share their fonts and recurring images
+    let options = options.cache(Arc::new(ConversionCache::new()));
+    let jobs_count = cli
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+        .min(jobs.len());
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    let file_reports = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs_count {
+            scope.spawn(|| {
+                while let Some(job) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    bar.set_message(job.input.display().to_string());
+                    let timer = StageTimer::new(&NoProgress);
+                    let report = convert_file(&cli, job, &options, &timer);
+                    if cli.report.is_some() {
+                        file_reports.lock().unwrap().push(FileReport::new(
+                            vec![job.input.clone()],
+                            job.output.as_deref(),
+                            &report,
+                            timer.timings(),
+                        ));
+                    }
+                    match report {
+                        Ok(report) => bar.suspend(|| print_result(&cli, &report, Some(job))),
+                        Err(e) => failures.lock().unwrap().push((job, e)),
                     }
+                    bar.inc(1);
                 }
+            });
+        }
+    });
+    bar.finish_and_clear();
 
-                // Detect section breaks: <w:sectPr>
-                if name == b"w:sectPr" {
-                    _create_page_obj(&mut env);
-                }
+    let mut file_reports = file_reports.into_inner().unwrap();
+    file_reports.sort_by(|a, b| a.inputs.cmp(&b.inputs));
+    write_report(&cli, file_reports)?;
 
-                // Handle drawings/images
-                if name == b"w:drawing" || name == b"wp:inline" || name == b"wp:extent" {
-                    in_drawing = true;
-                }
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort_by(|(a, _), (b, _)| a.input.cmp(&b.input));
+    if !cli.quiet {
+        eprintln!(
+            "{} {} of {} files",
+            if cli.check { "checked" } else { "converted" },
+            jobs.len() - failures.len(),
+            jobs.len()
+        );
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+    for (job, e) in &failures {
+        eprintln!("  failed {}: {:#}", job.input.display(), e);
+    }
+    Err(anyhow!("{} of {} files failed", failures.len(), jobs.len()))
+}
+
+fn convert_file(
+    cli: &Cli,
+    job: &Job,
+    options: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Report> {
+    let mut options = options.clone();
+    if cli.attach_source {
+        options.attach_source = Some(attachment_name(&job.input));
+    }
+    let start = Instant::now();
+    let input = BufReader::new(File::open(&job.input)?);
+    let Some(output) = &job.output else {
+        return Ok(check_with_progress(input, &options, progress)?);
+    };
+    cli.with_header_footer(&mut options, std::slice::from_ref(&job.input), &job.input)?;
+    cli.with_page_images(&mut options, output)?;
+    if !cli.writes_pdf() {
+        return Ok(check_with_progress(input, &options, progress)?);
+    }
+    let report = convert_with_progress(input, File::create(output)?, &options, progress)
+        .inspect_err(|_| {
+            // no half written PDFs next to the good ones
+            let _ = std::fs::remove_file(output);
+        })?;
+
+    let duration = Instant::now() - start;
+    info!(
+        "Finished parsing {}, took {}ms",
+        job.input.display(),
+        duration.as_millis()
+    );
+    Ok(report)
+}
+
+/// Writes the page images of an output next to it, as `<stem>-<page>.<extension>`
+#[derive(Debug)]
+struct ImageFiles {
+    stem: PathBuf,
+    extension: &'static str,
+}
+
+impl PageImageSink for ImageFiles {
+    fn page_image(&self, page: usize, image: Vec<u8>) -> io::Result<()> {
+        let mut name = self.stem.clone().into_os_string();
+        name.push(format!("-{}.{}", page, self.extension));
+        std::fs::write(name, image)
+    }
+}
+
+/// Shows the phase of a single conversion next to the spinner
+struct BarProgress<'a>(&'a ProgressBar);
+
+impl ProgressSink for BarProgress<'_> {
+    fn phase(&self, phase: Phase) {
+        let message = match phase {
+            Phase::RelMap => "reading relationships",
+            Phase::ImagePreprocessing => "processing images",
+            Phase::Parsing => "parsing",
+            Phase::Writing => "writing",
+        };
+        self.0.set_message(message);
+    }
+
+    fn image_processed(&self, done: usize, total: usize) {
+        self.0
+            .set_message(format!("processing images {}/{}", done, total));
+    }
+
+    fn page_written(&self, page: usize) {
+        self.0.set_message(format!("writing page {}", page));
+    }
+}
+
+/// Forwards progress while timing every phase for the report
+struct StageTimer<'a> {
+    progress: &'a dyn ProgressSink,
+    start: Instant,
+    phases: Mutex<Vec<(Phase, Instant)>>,
+}
+
+impl<'a> StageTimer<'a> {
+    fn new(progress: &'a dyn ProgressSink) -> Self {
+        Self {
+            progress,
+            start: Instant::now(),
+            phases: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Time spent in each phase so far, summed over the inputs of a merge
+    fn timings(&self) -> Timings {
+        let end = Instant::now();
+        let phases = self.phases.lock().unwrap();
+        let mut timings = Timings {
+            total: millis(end - self.start),
+            ..Timings::default()
+        };
+        for (i, &(phase, start)) in phases.iter().enumerate() {
+            let until = phases.get(i + 1).map_or(end, |&(_, next)| next);
+            let elapsed = millis(until - start);
+            match phase {
+                Phase::RelMap => timings.rel_map += elapsed,
+                Phase::ImagePreprocessing => timings.image_preprocessing += elapsed,
+                Phase::Parsing => timings.parsing += elapsed,
+                Phase::Writing => timings.writing += elapsed,
+            }
+        }
+        timings
+    }
+}
+
+impl ProgressSink for StageTimer<'_> {
+    fn phase(&self, phase: Phase) {
+        self.phases.lock().unwrap().push((phase, Instant::now()));
+        self.progress.phase(phase);
+    }
+
+    fn image_processed(&self, done: usize, total: usize) {
+        self.progress.image_processed(done, total);
+    }
+
+    fn page_written(&self, page: usize) {
+        self.progress.page_written(page);
+    }
+}
 
-                if name.ends_with(b"blip") {
-                    if let Some(attr) = e
-                        .attributes()
-                        .with_checks(false)
-                        .filter_map(|a| a.ok())
-                        .find(|a| a.key.as_ref().ends_with(b"embed"))
-                    {
-                        let rid = String::from_utf8_lossy(&attr.value).to_string();
-                        _create_image_obj(&mut env, rid);
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Milliseconds spent in each phase of a conversion
+#[derive(Default, Serialize)]
+struct Timings {
+    rel_map: f64,
+    image_preprocessing: f64,
+    parsing: f64,
+    writing: f64,
+    total: f64,
+}
+
+/// One conversion in the `--report json` output
+#[derive(Serialize)]
+struct FileReport {
+    inputs: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(flatten)]
+    report: Report,
+    output_bytes: u64,
+    timings_ms: Timings,
+}
+
+impl FileReport {
+    fn new(
+        inputs: Vec<PathBuf>,
+        output: Option<&Path>,
+        result: &Result<Report>,
+        timings: Timings,
+    ) -> Self {
+        let (report, error) = match result {
+            Ok(report) => (report.clone(), None),
+            Err(e) => (Report::default(), Some(format!("{:#}", e))),
+        };
+        // a failed conversion leaves no output behind
+        let output_bytes = output
+            .and_then(|output| std::fs::metadata(output).ok())
+            .map_or(0, |metadata| metadata.len());
+        Self {
+            inputs,
+            output: output.map(Path::to_path_buf),
+            error,
+            report,
+            output_bytes,
+            timings_ms: timings,
+        }
+    }
+}
+
+/// Prints the report of every conversion in the format asked for, if any
+fn write_report(cli: &Cli, files: Vec<FileReport>) -> Result<()> {
+    let Some(ReportFormat::Json) = cli.report else {
+        return Ok(());
+    };
+    #[derive(Serialize)]
+    struct JsonReport {
+        files: Vec<FileReport>,
+    }
+    let json = serde_json::to_string_pretty(&JsonReport { files })?;
+    match &cli.report_file {
+        Some(path) => std::fs::write(path, json + "\n")
+            .with_context(|| format!("writing {}", path.display()))?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Stderr for the logs, clearing the progress bar while a line is written
+struct BarWriter(ProgressBar);
+
+impl Write for BarWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Converts the DOCX in the body of every POST /convert, with `workers`
+/// threads taking the requests in turn, until interrupted
+///
+/// Bodies over `max_body` MiB are refused with a 413. GET /health answers
+/// 200 for load balancers.
+fn serve(
+    cli: &Cli,
+    addr: SocketAddr,
+    workers: usize,
+    max_body: u64,
+    options: &Options,
+) -> Result<()> {
+    let server =
+        tiny_http::Server::http(addr).map_err(|e| anyhow!("listening on {}: {}", addr, e))?;
+    // every request shares the fonts and recurring images of the ones before
+    let options = options.clone().cache(Arc::new(ConversionCache::new()));
+    let max_body = max_body.saturating_mul(1024 * 1024);
+    if !cli.quiet {
+        eprintln!("listening on http://{}", addr);
+    }
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Ok(mut request) = server.recv() {
+                    let start = Instant::now();
+                    let response = respond(&mut request, &options, max_body);
+                    info!(
+                        "{} {} {}, took {}ms",
+                        request.method(),
+                        request.url(),
+                        response.status_code().0,
+                        start.elapsed().as_millis()
+                    );
+                    if let Err(e) = request.respond(response) {
+                        debug!("sending the response: {}", e);
                     }
                 }
+            });
+        }
+    });
+    Ok(())
+}
+
+type HttpResponse = tiny_http::Response<Cursor<Vec<u8>>>;
+
+fn respond(request: &mut tiny_http::Request, options: &Options, max_body: u64) -> HttpResponse {
+    use tiny_http::Method;
+
+    match (request.method(), request.url()) {
+        (Method::Post, "/convert") => {}
+        (Method::Get, "/health") => return text_response(200, "ok"),
+        (_, "/convert") => return text_response(405, "use POST with the DOCX as the body"),
+        _ => return text_response(404, "not found"),
+    }
+    if request
+        .body_length()
+        .is_some_and(|len| len as u64 > max_body)
+    {
+        return text_response(413, "DOCX too large");
+    }
+    // chunked bodies have no length up front
+    let mut body = Vec::new();
+    if let Err(e) = request
+        .as_reader()
+        .take(max_body + 1)
+        .read_to_end(&mut body)
+    {
+        return text_response(400, &format!("reading the body: {}", e));
+    }
+    if body.len() as u64 > max_body {
+        return text_response(413, "DOCX too large");
+    }
+
+    match convert_to_vec(Cursor::new(body), options) {
+        Ok((pdf, report)) => {
+            for warning in &report.warnings {
+                debug!("{}", warning);
             }
+            tiny_http::Response::from_data(pdf)
+                .with_header(header("Content-Type", "application/pdf"))
+                .with_header(header(
+                    "X-Conversion-Warnings",
+                    &report.warnings.len().to_string(),
+                ))
+        }
+        Err(e) => {
+            let status = match e {
+                ConvertError::InvalidDocx { .. }
+                | ConvertError::UnsupportedImage { .. }
+                | ConvertError::MissingImage { .. }
+                | ConvertError::EncryptedDocument { .. }
+                | ConvertError::LimitExceeded(_) => 422,
+                _ => 500,
+            };
+            let message = format!("{:#}", anyhow::Error::from(e));
+            if status == 500 {
+                warn!("conversion failed: {}", message);
+            }
+            text_response(status, &message)
+        }
+    }
+}
+
+fn text_response(status: u16, message: &str) -> HttpResponse {
+    tiny_http::Response::from_string(format!("{}\n", message))
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn header(name: &str, value: &str) -> tiny_http::Header {
+    tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes())
+        .expect("header names and values are ASCII")
+}
+
+/// Runs `workers` threads converting the jobs from `queue` into `results`
+/// until a shutdown signal, see `Command::Daemon`
+fn daemon(
+    cli: &Cli,
+    queue: &Path,
+    results: &Path,
+    workers: usize,
+    options: &Options,
+) -> Result<()> {
+    std::fs::create_dir_all(results).with_context(|| format!("creating {}", results.display()))?;
+    let queue = JobQueue::open(queue)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    let quiet = cli.quiet;
+    ctrlc::set_handler(move || {
+        if handler_stop.swap(true, Ordering::SeqCst) {
+            // asked twice, don't wait any longer
+            std::process::exit(130);
+        }
+        if !quiet {
+            eprintln!("stopping once the running conversions are done");
+        }
+    })?;
 
-            Ok(Event::End(e)) => {
-                let name = e.name().into_inner();
-                if name == b"w:drawing" || name == b"wp:inline" || name == b"wp:extent" {
-                    in_drawing = false;
+    let options = options.clone().cache(Arc::new(ConversionCache::new()));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(job) = queue.next(&stop) {
+                    run_queued(cli, &queue, job, results, &options);
                 }
-            }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Where the daemon takes its jobs from
+enum JobQueue {
+    /// jobs are claimed by moving them into `.processing/<pid>`, so any
+    /// number of daemons can share the directory; each one holds
+    /// `.processing/<pid>.lock` for as long as it runs
+    Dir {
+        dir: PathBuf,
+        claimed: PathBuf,
+        _lock: File,
+    },
+    /// paths read from a named pipe on a thread of their own
+    Pipe(Mutex<mpsc::Receiver<PathBuf>>),
+}
 
-            Ok(Event::Empty(e)) => {
-                let name = e.name().into_inner();
+impl JobQueue {
+    fn open(path: &Path) -> Result<Self> {
+        let metadata =
+            std::fs::metadata(path).with_context(|| format!("opening {}", path.display()))?;
+        if metadata.is_dir() {
+            let processing = path.join(".processing");
+            for sub_dir in [".processing", ".done", ".failed"] {
+                std::fs::create_dir_all(path.join(sub_dir))?;
+            }
+            // locked before the directory exists, so no other daemon starting
+            // up takes it for one left behind
+            let claimed = processing.join(std::process::id().to_string());
+            let lock = File::create(claimed.with_extension("lock"))?;
+            lock.lock()?;
+            recover_jobs(path, &claimed)?;
+            std::fs::create_dir_all(&claimed)?;
+            return Ok(JobQueue::Dir {
+                dir: path.to_path_buf(),
+                claimed,
+                _lock: lock,
+            });
+        }
+        #[cfg(unix)]
+        if std::os::unix::fs::FileTypeExt::is_fifo(&metadata.file_type()) {
+            let (sender, receiver) = mpsc::channel();
+            let pipe = path.to_path_buf();
+            // not scoped, a read blocked on an idle pipe mustn't hold up shutdown
+            std::thread::spawn(move || read_pipe(&pipe, &sender));
+            return Ok(JobQueue::Pipe(Mutex::new(receiver)));
+        }
+        bail!("{} is neither a directory nor a named pipe", path.display())
+    }
 
-                // manual page break (self-closing)
-                if name == b"w:br" {
-                    for a in e.attributes().flatten() {
-                        if a.key.as_ref() == b"w:type" && a.value.as_ref() == b"page" {
-                            _create_page_obj(&mut env);
-                        }
+    /// The path of the next job to convert, None once asked to stop
+    fn next(&self, stop: &AtomicBool) -> Option<PathBuf> {
+        while !stop.load(Ordering::SeqCst) {
+            match self {
+                JobQueue::Dir { dir, claimed, .. } => {
+                    match claim_job(dir, claimed) {
+                        Ok(Some(job)) => return Some(job),
+                        Ok(None) => {}
+                        Err(e) => warn!("reading {}: {}", dir.display(), e),
                     }
+                    std::thread::sleep(POLL_INTERVAL);
                 }
-
-                // inline image reference
-                if name.ends_with(b"blip") {
-                    if let Some(attr) = e
-                        .attributes()
-                        .with_checks(false)
-                        .filter_map(|a| a.ok())
-                        .find(|a| a.key.as_ref().ends_with(b"embed"))
-                    {
-                        let rid = String::from_utf8_lossy(&attr.value).to_string();
-                        _create_image_obj(&mut env, rid);
+                JobQueue::Pipe(receiver) => {
+                    match receiver.lock().unwrap().recv_timeout(POLL_INTERVAL) {
+                        Ok(job) => return Some(job),
+                        Err(mpsc::RecvTimeoutError::Timeout) => {}
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return None,
                     }
                 }
             }
+        }
+        None
+    }
+
+    /// Moves a finished job out of `.processing`
+    fn finish(&self, job: &Path, failed: bool) {
+        if let JobQueue::Dir { dir, .. } = self
+            && let Some(name) = job.file_name()
+        {
+            let to = dir
+                .join(if failed { ".failed" } else { ".done" })
+                .join(name);
+            if let Err(e) = std::fs::rename(job, &to) {
+                warn!("moving {} to {}: {}", job.display(), to.display(), e);
+            }
+        }
+    }
+}
+
+/// Documents, templates and their macro-enabled variants, converted alike
+const DOCX_EXTENSIONS: [&str; 4] = ["docx", "docm", "dotx", "dotm"];
+
+fn is_docx(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        DOCX_EXTENSIONS
+            .iter()
+            .any(|docx| ext.eq_ignore_ascii_case(docx))
+    })
+}
 
+/// Puts the jobs of daemons that stopped before finishing them back into
+/// `dir`, those whose lock nobody holds any more. `own` is the directory of
+/// this daemon, anything in it was left by an earlier one with the same pid
+fn recover_jobs(dir: &Path, own: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir.join(".processing"))? {
+        let owner = entry?.path();
+        if !owner.is_dir() {
+            continue;
+        }
+        let lock = if owner == own {
+            None
+        } else {
+            let lock = File::create(owner.with_extension("lock"))?;
+            match lock.try_lock() {
+                Ok(()) => Some(lock),
+                // still running
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        };
+        // another daemon starting up may have recovered it in the meantime
+        let jobs = match std::fs::read_dir(&owner) {
+            Ok(jobs) => jobs,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for job in jobs {
+            let job = job?;
+            std::fs::rename(job.path(), dir.join(job.file_name()))?;
+        }
+        match std::fs::remove_dir(&owner) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
             _ => {}
         }
-        buf.clear();
+        drop(lock);
     }
+    Ok(())
+}
 
-    // writes any remaining objects
-    if env.current_page_img_objs.len() > 0 || env.current_page_objs.len() > 0 {
-        env.pdf_document
-            .new_page_obj(&env.current_page_objs, &env.current_page_img_objs)?;
+/// Moves the first .docx in `dir` into `claimed`, another daemon may have
+/// been quicker for any of them
+fn claim_job(dir: &Path, claimed: &Path) -> io::Result<Option<PathBuf>> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !is_docx(Path::new(&name)) || name.to_string_lossy().starts_with(['.', '~']) {
+            continue;
+        }
+        let job = claimed.join(&name);
+        match std::fs::rename(entry.path(), &job) {
+            Ok(()) => return Ok(Some(job)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
     }
+    Ok(None)
+}
 
-    Ok(())
+/// Sends every non-empty line written to the pipe, reopening it whenever the
+/// writer closes it
+fn read_pipe(pipe: &Path, sender: &mpsc::Sender<PathBuf>) {
+    loop {
+        // blocks until something opens the pipe for writing
+        let file = match File::open(pipe) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("opening {}: {}", pipe.display(), e);
+                return;
+            }
+        };
+        for line in io::BufRead::lines(BufReader::new(file)) {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    warn!("reading {}: {}", pipe.display(), e);
+                    break;
+                }
+            };
+            let line = line.trim();
+            if !line.is_empty() && sender.send(PathBuf::from(line)).is_err() {
+                return;
+            }
+        }
+    }
 }
 
-fn build_rel_map<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<HashMap<String, String>> {
-    let mut rels_map = HashMap::new();
+/// Where the file for `input` ending in `extension` goes in `dir`; not
+/// with_extension, the stem may have dots of its own
+fn output_path(dir: &Path, input: &Path, extension: &str) -> PathBuf {
+    let mut name = input.file_stem().unwrap_or(input.as_os_str()).to_owned();
+    name.push(extension);
+    dir.join(name)
+}
 
-    // It's always at this path relative to word/document.xml
-    let mut rels_file = archive.by_name("word/_rels/document.xml.rels")?;
-    let mut xml = String::new();
-    std::io::Read::read_to_string(&mut rels_file, &mut xml)?;
+/// Converts one job, with its status file saying so before and after
+fn run_queued(cli: &Cli, queue: &JobQueue, input: PathBuf, results: &Path, options: &Options) {
+    let result = |extension: &str| output_path(results, &input, extension);
+    let status = result(".status.json");
+    let job = Job {
+        output: Some(result(".pdf")),
+        input,
+    };
+    write_status(&status, "processing", None);
+    let timer = StageTimer::new(&NoProgress);
+    let report = convert_file(cli, &job, options, &timer);
+    let file_report = FileReport::new(
+        vec![job.input.clone()],
+        job.output.as_deref(),
+        &report,
+        timer.timings(),
+    );
+    match &report {
+        Ok(report) if !cli.quiet => {
+            eprintln!("converted {}", job.input.display());
+            if cli.warnings() {
+                print_warnings(report, Some(&job));
+            }
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("failed {}: {:#}", job.input.display(), e),
+    }
+    write_status(
+        &status,
+        if report.is_ok() { "done" } else { "failed" },
+        Some(&file_report),
+    );
+    queue.finish(&job.input, report.is_err());
+}
+
+/// Replaces the status file of a job, in one rename so readers never see half of it
+fn write_status(path: &Path, state: &str, report: Option<&FileReport>) {
+    #[derive(Serialize)]
+    struct Status<'a> {
+        state: &'a str,
+        #[serde(flatten)]
+        report: Option<&'a FileReport>,
+    }
+    let written = serde_json::to_string_pretty(&Status { state, report })
+        .map_err(io::Error::from)
+        .and_then(|json| {
+            let temp = path.with_extension("tmp");
+            std::fs::write(&temp, json + "\n")?;
+            std::fs::rename(&temp, path)
+        });
+    if let Err(e) = written {
+        warn!("writing {}: {}", path.display(), e);
+    }
+}
+
+/// How often a watched directory is scanned
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
 
-    let mut reader = Reader::from_str(&xml);
-    let mut buf = Vec::new();
+/// Size and modification time, a file that keeps them for the debounce
+/// period is done being written
+type Stamp = (u64, std::time::SystemTime);
 
+/// Converts every .docx in `dir` whose PDF in `out_dir` is missing or older,
+/// then the ones added or changed later, until interrupted
+///
+/// A file is only converted once it stopped changing for the debounce period,
+/// so files still being copied in aren't picked up half written. Failures are
+/// reported and the file is retried when it changes again.
+fn watch(cli: &Cli, dir: &Path, out_dir: &Path, options: &Options) -> Result<()> {
+    std::fs::create_dir_all(out_dir).with_context(|| format!("creating {}", out_dir.display()))?;
+    let debounce = Duration::from_millis(cli.debounce.unwrap_or(1000));
+    // the files of a drop folder share their fonts and recurring images
+    let options = options.clone().cache(Arc::new(ConversionCache::new()));
+    // last stamp converted, or found up to date, per file
+    let mut done: HashMap<PathBuf, Stamp> = HashMap::new();
+    // files waiting to settle with the stamp they had and since when
+    let mut pending: HashMap<PathBuf, (Stamp, Instant)> = HashMap::new();
+    // files left alone because another one has the same output, reported once
+    let mut clashing: HashSet<PathBuf> = HashSet::new();
+    info!("watching {}", dir.display());
     loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Eof) => break,
-            Ok(Event::Empty(e)) | Ok(Event::Start(e))
-                if e.name().as_ref().ends_with(b"Relationship") =>
-            {
-                // extract Id and Target attributes
-                let mut id = None;
-                let mut target = None;
-
-                for attr in e.attributes().with_checks(false).flatten() {
-                    match attr.key.as_ref() {
-                        b"Id" => id = Some(String::from_utf8_lossy(&attr.value).to_string()),
-                        b"Target" => {
-                            target = Some(String::from_utf8_lossy(&attr.value).to_string())
+        let files = docx_files(dir).with_context(|| format!("reading {}", dir.display()))?;
+        // deleted files are converted again if they come back
+        done.retain(|input, _| files.contains_key(input));
+        pending.retain(|input, _| files.contains_key(input));
+        // e.g. q3.docx and q3.docm, neither is converted while both are there
+        let mut outputs: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::new();
+        for input in files.keys() {
+            outputs
+                .entry(output_path(out_dir, input, ".pdf"))
+                .or_default()
+                .push(input);
+        }
+        let mut clashes = HashSet::new();
+        for (output, inputs) in &outputs {
+            if inputs.len() < 2 {
+                continue;
+            }
+            for &input in inputs {
+                if !clashing.contains(input) {
+                    eprintln!(
+                        "skipped {}: another file would also be written to {}",
+                        input.display(),
+                        output.display()
+                    );
+                }
+                clashes.insert(input.clone());
+            }
+        }
+        clashing = clashes;
+        for (input, stamp) in files {
+            if done.get(&input) == Some(&stamp) || clashing.contains(&input) {
+                continue;
+            }
+            let output = output_path(out_dir, &input, ".pdf");
+            if !done.contains_key(&input) && up_to_date(&output, stamp) {
+                done.insert(input, stamp);
+                continue;
+            }
+            match pending.get(&input) {
+                Some(&(seen, since)) if seen == stamp => {
+                    if since.elapsed() < debounce {
+                        continue;
+                    }
+                    pending.remove(&input);
+                    let job = Job {
+                        input,
+                        output: Some(output),
+                    };
+                    match convert_file(cli, &job, &options, &NoProgress) {
+                        Ok(report) => {
+                            if !cli.quiet {
+                                eprintln!("converted {}", job.input.display());
+                            }
+                            if cli.warnings() {
+                                print_warnings(&report, Some(&job));
+                            }
                         }
-                        _ => {}
+                        Err(e) => eprintln!("failed {}: {:#}", job.input.display(), e),
                     }
+                    done.insert(job.input, stamp);
                 }
-
-                if let (Some(i), Some(t)) = (id, target) {
-                    rels_map.insert(i, t);
+                _ => {
+                    pending.insert(input, (stamp, Instant::now()));
                 }
             }
-            _ => {}
         }
-        buf.clear();
+        std::thread::sleep(POLL_INTERVAL);
     }
-
-    Ok(rels_map)
 }
 
-fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();
-    let (out_path, path) = if args.len() > 3 && args[1] == "-o" {
-        (args[2].clone(), args[3].clone())
-    } else {
-        panic!("Invalid args, should be formatted in './program -o [output_path] [input_path]'");
-    };
+/// The .docx (.docm, .dotx, .dotm) files directly in `dir` with their stamps, Word's `~$` lock files left out
+fn docx_files(dir: &Path) -> io::Result<HashMap<PathBuf, Stamp>> {
+    let mut files = HashMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !is_docx(&path) || entry.file_name().to_string_lossy().starts_with("~$") {
+            continue;
+        }
+        // gone again or not a regular file
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if let (true, Ok(modified)) = (metadata.is_file(), metadata.modified()) {
+            files.insert(path, (metadata.len(), modified));
+        }
+    }
+    Ok(files)
+}
 
-    let file = File::open(&path)?;
-    let mut archive = ZipArchive::new(BufReader::new(file))?;
+/// Whether `output` was written after the input last changed
+fn up_to_date(output: &Path, (_, modified): Stamp) -> bool {
+    std::fs::metadata(output)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|written| written >= modified)
+}
 
+/// Converts all inputs into the single PDF at `output`, in the order given
+fn merge_files(
+    cli: &Cli,
+    output: &Path,
+    options: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Report> {
+    let paths = cli.inputs()?;
+    let mut inputs = Vec::new();
+    for path in &paths {
+        let title = path.file_stem().unwrap_or(path.as_os_str());
+        inputs.push(MergeInput {
+            title: title.to_string_lossy().to_string(),
+            input: BufReader::new(
+                File::open(path).with_context(|| format!("opening {}", path.display()))?,
+            ),
+        });
+    }
+    let mut options = options.clone();
+    if cli.attach_source {
+        // every input is attached under its own name
+        options.attach_source = Some(attachment_name(output));
+    }
+    // a merged PDF is named after its output
+    cli.with_header_footer(&mut options, &paths, output)?;
+    cli.with_page_images(&mut options, output)?;
     let start = Instant::now();
-    let rel_map = build_rel_map(&mut archive)?;
-
-    let image_preprocessor = ImagePreprocessor::preprocess_images(&path)?;
-    let image_map = image_preprocessor.image_map;
-
-    let media_lookup = |rid: &str| {
-        if let Some(target) = rel_map.get(rid) {
-            // Normalise to full ZIP path
-            let zip_path = if let Some(stripped) = target.strip_prefix("../") {
-                format!("word/{}", stripped)
-            } else {
-                format!("word/{}", target)
-            };
+    let count = inputs.len();
+    if !cli.writes_pdf() {
+        // only the page images are kept
+        return Ok(merge_with_progress(
+            inputs,
+            Cursor::new(Vec::new()),
+            &options,
+            progress,
+        )?);
+    }
+    let report = merge_with_progress(inputs, File::create(output)?, &options, progress)
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(output);
+        })?;
 
-            if let Some(image_params) = image_map.get(&zip_path) {
-                let tmp_img_path = image_params.path.clone();
-                let full_path = image_preprocessor
-                    .temp_dir
-                    .path()
-                    .join(tmp_img_path)
-                    .to_string_lossy()
-                    .to_string();
-
-                Some((full_path, image_params.clone()))
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    info!(
+        "Merged {} files into {}, took {}ms",
+        count,
+        output.display(),
+        start.elapsed().as_millis()
+    );
+    Ok(report)
+}
+
+/// `secs` since the Unix epoch as a UTC date, e.g. 2024-03-05
+fn utc_date(secs: u64) -> String {
+    // days to a proleptic Gregorian date, counting eras of 400 years from
+    // March 1st so the leap day ends a year
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
     };
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
 
-    let output_file = File::create(out_path)?;
-    let mut pdf_document = PdfDocument::new(output_file)?;
-    parse_document_xml(archive, &mut pdf_document, &media_lookup)?;
-    pdf_document.finish_document()?;
+fn attachment_name(input: &Path) -> String {
+    input
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "source.docx".to_string())
+}
 
-    let duration = Instant::now() - start;
-    println!("Finished parsing DOCX, took {}ms", duration.as_millis());
-    Ok(())
+/// What is printed once a file converted, the warnings unless turned off
+/// and with --check a line for the files without any
+fn print_result(cli: &Cli, report: &Report, job: Option<&Job>) {
+    if cli.check && report.warnings.is_empty() && !cli.quiet {
+        let file = job.map_or(String::new(), |job| format!("{}: ", job.input.display()));
+        eprintln!("{}{} pages, no problems found", file, report.pages);
+    } else if cli.warnings() {
+        print_warnings(report, job);
+    }
+}
+
+/// Summary of what was lost on the way, on stderr next to the logs
+fn print_warnings(report: &Report, job: Option<&Job>) {
+    if report.warnings.is_empty() {
+        return;
+    }
+    let file = job.map_or(String::new(), |job| format!("{}: ", job.input.display()));
+    // one write, so files converted in parallel don't interleave
+    let mut summary = format!(
+        "{}{} pages written, {} warning(s):\n",
+        file,
+        report.pages,
+        report.warnings.len()
+    );
+    for warning in &report.warnings {
+        summary.push_str(&format!("  - {}\n", warning));
+    }
+    eprint!("{}", summary);
 }
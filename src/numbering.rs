@@ -0,0 +1,477 @@
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+use super::document_model::{LabelSuffix, ListLabel};
+use super::docx_parser::{attribute_value, toggle_on};
+use super::styles::IndentProperties;
+use super::symbol_fonts::{is_symbol_font, symbol_char};
+use super::xml_errors::XmlErrors;
+
+/// A list has levels 0 to 8, `w:ilvl`
+const LEVELS: usize = 9;
+
+/// What a bullet from a symbol font without a Unicode counterpart is shown as
+const BULLET: char = '\u{2022}';
+
+/// How the number of a level is written, `w:numFmt`; the formats not here
+/// are written as decimals
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum NumberFormat {
+    #[default]
+    Decimal,
+    DecimalZero,
+    LowerLetter,
+    UpperLetter,
+    LowerRoman,
+    UpperRoman,
+    Bullet,
+    None,
+}
+
+impl NumberFormat {
+    fn parse(value: &str) -> Self {
+        match value {
+            "decimalZero" => Self::DecimalZero,
+            "lowerLetter" => Self::LowerLetter,
+            "upperLetter" => Self::UpperLetter,
+            "lowerRoman" => Self::LowerRoman,
+            "upperRoman" => Self::UpperRoman,
+            "bullet" => Self::Bullet,
+            "none" => Self::None,
+            _ => Self::Decimal,
+        }
+    }
+
+    /// `number` written in this format
+    fn write(self, number: u32) -> String {
+        match self {
+            Self::Decimal => number.to_string(),
+            Self::DecimalZero => format!("{number:02}"),
+            Self::LowerLetter => letters(number),
+            Self::UpperLetter => letters(number).to_uppercase(),
+            Self::LowerRoman => roman(number),
+            Self::UpperRoman => roman(number).to_uppercase(),
+            Self::Bullet | Self::None => String::new(),
+        }
+    }
+}
+
+/// a to z, then aa to zz and so on, as Word counts in letters
+fn letters(number: u32) -> String {
+    let Some(number) = number.checked_sub(1) else {
+        return String::new();
+    };
+    let letter = char::from(b'a' + (number % 26) as u8);
+    // Word caps the repeats, a huge start value shouldn't make a huge label
+    std::iter::repeat_n(letter, (number / 26 + 1).min(20) as usize).collect()
+}
+
+/// Roman numerals, plain numbers past what they can write
+fn roman(number: u32) -> String {
+    if number == 0 || number >= 4000 {
+        return number.to_string();
+    }
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut rest = number;
+    let mut text = String::new();
+    for (value, numeral) in NUMERALS {
+        while rest >= value {
+            text.push_str(numeral);
+            rest -= value;
+        }
+    }
+    text
+}
+
+/// The character `c` of a level text set in `font`: bullets of the symbol
+/// fonts, and the private use characters Word keeps them as, become the
+/// Unicode character they show, or a plain bullet
+fn level_char(font: Option<&str>, c: char) -> char {
+    let code = c as u32;
+    let font = font.unwrap_or_default();
+    if !(0xF000..=0xF0FF).contains(&code) && !is_symbol_font(font) {
+        return c;
+    }
+    let own = match code {
+        0x20..=0xFF => code + 0xF000,
+        code => code,
+    };
+    symbol_char(font, own).unwrap_or(BULLET)
+}
+
+/// A level of a list definition, `w:lvl`
+#[derive(Clone, Debug, Default)]
+struct Level {
+    /// `w:start`, zero without it
+    start: u32,
+    format: NumberFormat,
+    /// `w:lvlText`, with `%1` to `%9` standing for the numbers of the levels
+    text: String,
+    /// `w:lvlRestart`, the number of the level after which this one starts
+    /// over, 0 for never; None starts over after any level above it
+    restart: Option<usize>,
+    /// `w:isLgl`, the numbers of the levels above are written as decimals
+    legal: bool,
+    suffix: LabelSuffix,
+    /// `w:pStyle`, the paragraph style this level is used for
+    style: Option<String>,
+    /// `w:rFonts` of its `w:rPr`, the font of the label
+    font: Option<String>,
+    /// `w:ind` of its `w:pPr`, the indents of its paragraphs
+    indent: IndentProperties,
+}
+
+/// What a `w:num` changes of a level of its definition, `w:lvlOverride`
+#[derive(Clone, Debug, Default)]
+struct Override {
+    /// `w:startOverride`
+    start: Option<u32>,
+    /// a `w:lvl` of its own replacing the level
+    level: Option<Level>,
+}
+
+/// A list, `w:num`: the definition it numbers by and what it overrides
+#[derive(Clone, Debug, Default)]
+struct List {
+    definition: String,
+    overrides: HashMap<usize, Override>,
+}
+
+/// The numbering part, and the numbers counted so far in the document
+#[derive(Clone, Debug, Default)]
+pub struct Numbering {
+    /// `w:abstractNum` levels by `w:abstractNumId`
+    definitions: HashMap<String, Vec<Option<Level>>>,
+    /// by `w:numId`
+    lists: HashMap<String, List>,
+    /// the last number of each level, by list or by definition for the
+    /// lists that don't override it, which carry on from each other
+    counters: HashMap<String, [Option<u32>; LEVELS]>,
+}
+
+impl Numbering {
+    /// The level `index` of list `list` with its overrides
+    fn level(&self, list: &List, index: usize) -> Level {
+        let over = list.overrides.get(&index);
+        let mut level = over
+            .and_then(|over| over.level.clone())
+            .or_else(|| {
+                let levels = self.definitions.get(&list.definition)?;
+                levels.get(index).cloned().flatten()
+            })
+            .unwrap_or_default();
+        if let Some(start) = over.and_then(|over| over.start) {
+            level.start = start;
+        }
+        level
+    }
+
+    /// Counts the next paragraph of list `id` (`w:numId`) at `level`, or the
+    /// level for its paragraph `style` without one, returning its label and
+    /// the indents of the level. None for `w:numId` 0, which takes a
+    /// paragraph out of its list, and for lists that aren't defined
+    pub fn next_label(
+        &mut self,
+        id: &str,
+        level: Option<usize>,
+        style: Option<&str>,
+    ) -> Option<(ListLabel, IndentProperties)> {
+        let list = self.lists.get(id)?;
+        let levels: Vec<Level> = (0..LEVELS).map(|index| self.level(list, index)).collect();
+        let index = level
+            .or_else(|| {
+                let style = style?;
+                levels
+                    .iter()
+                    .position(|level| level.style.as_deref() == Some(style))
+            })
+            .unwrap_or(0)
+            .min(LEVELS - 1);
+        let key = match list.overrides.is_empty() {
+            true => format!("abstract {}", list.definition),
+            false => format!("num {id}"),
+        };
+        let counters = self.counters.entry(key).or_default();
+        counters[index] =
+            Some(counters[index].map_or(levels[index].start, |n| n.saturating_add(1)));
+        for (deeper, level) in levels.iter().enumerate().skip(index + 1) {
+            let restarts = match level.restart {
+                None => true,
+                Some(after) => index < after,
+            };
+            if restarts {
+                counters[deeper] = None;
+            }
+        }
+        let current = &levels[index];
+        let mut text = String::new();
+        let mut chars = current.text.chars().peekable();
+        while let Some(c) = chars.next() {
+            let Some(digit) = chars
+                .peek()
+                .and_then(|next| next.to_digit(10))
+                .filter(|digit| c == '%' && (1..=LEVELS as u32).contains(digit))
+            else {
+                text.push(level_char(current.font.as_deref(), c));
+                continue;
+            };
+            chars.next();
+            let at = digit as usize - 1;
+            let level = &levels[at];
+            let number = counters[at].unwrap_or(level.start);
+            let format = match current.legal && at < index {
+                true => NumberFormat::Decimal,
+                false => level.format,
+            };
+            text.push_str(&format.write(number));
+        }
+        let label = ListLabel {
+            text,
+            suffix: current.suffix,
+        };
+        Some((label, current.indent))
+    }
+}
+
+/// Where `read_numbering` is in the numbering part
+#[derive(Default)]
+struct NumberingState {
+    numbering: Numbering,
+    definition: Option<(String, Vec<Option<Level>>)>, // the `w:abstractNum` being read
+    list: Option<(String, List)>,                     // the `w:num` being read
+    over: Option<(usize, Override)>,                  // the `w:lvlOverride` being read
+    level: Option<(usize, Level)>,                    // the `w:lvl` being read
+}
+
+fn start_numbering_element(state: &mut NumberingState, e: &BytesStart, empty: bool) {
+    let name = e.name();
+    let name = name.as_ref();
+    let value = || attribute_value(e, b"w:val");
+    let index = |e: &BytesStart| {
+        attribute_value(e, b"w:ilvl")
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .filter(|index| *index < LEVELS)
+    };
+    match name {
+        b"w:abstractNum" if !empty => {
+            if let Some(id) = attribute_value(e, b"w:abstractNumId") {
+                state.definition = Some((id, vec![None; LEVELS]));
+            }
+        }
+        b"w:num" if !empty => {
+            if let Some(id) = attribute_value(e, b"w:numId") {
+                state.list = Some((id, List::default()));
+            }
+        }
+        b"w:abstractNumId" => {
+            if let (Some((_, list)), Some(id)) = (&mut state.list, value()) {
+                list.definition = id;
+            }
+        }
+        b"w:lvlOverride" if !empty => state.over = index(e).map(|i| (i, Override::default())),
+        b"w:startOverride" => {
+            if let Some((_, over)) = &mut state.over {
+                over.start = value().and_then(|value| value.trim().parse().ok());
+            }
+        }
+        b"w:lvl" if !empty => state.level = index(e).map(|i| (i, Level::default())),
+        _ => {
+            let Some((_, level)) = &mut state.level else {
+                return;
+            };
+            match name {
+                b"w:start" => {
+                    level.start = value().and_then(|v| v.trim().parse().ok()).unwrap_or(0)
+                }
+                b"w:numFmt" => level.format = NumberFormat::parse(&value().unwrap_or_default()),
+                b"w:lvlText" => level.text = value().unwrap_or_default(),
+                b"w:lvlRestart" => level.restart = value().and_then(|v| v.trim().parse().ok()),
+                b"w:isLgl" => level.legal = toggle_on(e),
+                b"w:suff" => {
+                    level.suffix = match value().as_deref() {
+                        Some("space") => LabelSuffix::Space,
+                        Some("nothing") => LabelSuffix::Nothing,
+                        _ => LabelSuffix::Tab,
+                    }
+                }
+                b"w:pStyle" => level.style = value(),
+                b"w:ind" => level.indent.read(e),
+                b"w:rFonts" => {
+                    level.font =
+                        attribute_value(e, b"w:ascii").or_else(|| attribute_value(e, b"w:hAnsi"))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn end_numbering_element(state: &mut NumberingState, name: &[u8]) {
+    match name {
+        b"w:lvl" => {
+            let Some((index, level)) = state.level.take() else {
+                return;
+            };
+            if let Some((_, over)) = &mut state.over {
+                over.level = Some(level);
+            } else if let Some((_, levels)) = &mut state.definition {
+                levels[index] = Some(level);
+            }
+        }
+        b"w:lvlOverride" => {
+            if let (Some((index, over)), Some((_, list))) = (state.over.take(), &mut state.list) {
+                list.overrides.insert(index, over);
+            }
+        }
+        b"w:num" => {
+            if let Some((id, list)) = state.list.take() {
+                state.numbering.lists.insert(id, list);
+            }
+        }
+        b"w:abstractNum" => {
+            if let Some((id, levels)) = state.definition.take() {
+                state.numbering.definitions.insert(id, levels);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads the list definitions and lists of `xml`, the numbering part
+pub fn read_numbering(xml: &str, errors: &mut XmlErrors) -> Result<Numbering> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut state = NumberingState::default();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => start_numbering_element(&mut state, &e, false),
+            Ok(Event::Empty(e)) => start_numbering_element(&mut state, &e, true),
+            Ok(Event::End(e)) => end_numbering_element(&mut state, e.name().as_ref()),
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(state.numbering)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_numbering, Numbering};
+    use crate::xml_errors::XmlErrors;
+
+    /// Reads a numbering part with the list definition `levels` as
+    /// `w:abstractNum` 0 and the `w:num` elements `lists`
+    fn numbering(levels: &str, lists: &str) -> Numbering {
+        let xml = format!(
+            r#"<w:numbering><w:abstractNum w:abstractNumId="0">{levels}</w:abstractNum>{lists}</w:numbering>"#
+        );
+        read_numbering(&xml, &mut XmlErrors::new("word/numbering.xml", true)).unwrap()
+    }
+
+    fn level(index: usize, format: &str, text: &str, rest: &str) -> String {
+        format!(
+            r#"<w:lvl w:ilvl="{index}"><w:start w:val="1"/><w:numFmt w:val="{format}"/><w:lvlText w:val="{text}"/>{rest}</w:lvl>"#
+        )
+    }
+
+    const LIST: &str = r#"<w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>"#;
+
+    /// The labels of the next paragraphs of `list` at `levels`
+    fn labels(numbering: &mut Numbering, list: &str, levels: &[usize]) -> Vec<String> {
+        levels
+            .iter()
+            .map(|&level| {
+                numbering
+                    .next_label(list, Some(level), None)
+                    .unwrap()
+                    .0
+                    .text
+            })
+            .collect()
+    }
+
+    #[test]
+    fn writes_the_numbers_of_the_levels_above() {
+        let levels = [
+            level(0, "decimal", "%1.", ""),
+            level(1, "lowerLetter", "%1.%2)", ""),
+            level(2, "lowerRoman", "(%3)", ""),
+            level(3, "upperLetter", "%4", ""),
+            level(4, "upperRoman", "%5", ""),
+        ];
+        let mut numbering = numbering(&levels.concat(), LIST);
+        assert_eq!(
+            labels(&mut numbering, "1", &[0, 1, 1, 2, 2, 2, 2, 0, 1]),
+            ["1.", "1.a)", "1.b)", "(i)", "(ii)", "(iii)", "(iv)", "2.", "2.a)"]
+        );
+        assert_eq!(labels(&mut numbering, "1", &[3; 28])[26..], ["AA", "BB"]);
+        assert_eq!(labels(&mut numbering, "1", &[4; 9])[8], "IX");
+    }
+
+    #[test]
+    fn restarts_after_the_level_asked_for() {
+        let levels = [
+            level(0, "decimal", "%1", ""),
+            level(1, "decimal", "%2", ""),
+            // starts over after level 1 items only, not after level 2 ones
+            level(2, "decimal", "%3", r#"<w:lvlRestart w:val="1"/>"#),
+            // never starts over
+            level(3, "decimal", "%4", r#"<w:lvlRestart w:val="0"/>"#),
+        ];
+        let mut numbering = numbering(&levels.concat(), LIST);
+        assert_eq!(
+            labels(&mut numbering, "1", &[2, 2, 1, 2, 0, 2, 3, 0, 3]),
+            ["1", "2", "1", "3", "1", "1", "1", "2", "2"]
+        );
+    }
+
+    #[test]
+    fn start_overrides_count_on_their_own() {
+        let lists = r#"
+            <w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+            <w:num w:numId="2"><w:abstractNumId w:val="0"/></w:num>
+            <w:num w:numId="3"><w:abstractNumId w:val="0"/>
+                <w:lvlOverride w:ilvl="0"><w:startOverride w:val="5"/></w:lvlOverride>
+            </w:num>"#;
+        let mut numbering = numbering(&level(0, "decimal", "%1.", ""), lists);
+        assert_eq!(labels(&mut numbering, "1", &[0, 0]), ["1.", "2."]);
+        // lists without overrides carry on from each other
+        assert_eq!(labels(&mut numbering, "2", &[0]), ["3."]);
+        assert_eq!(labels(&mut numbering, "3", &[0, 0]), ["5.", "6."]);
+        assert_eq!(labels(&mut numbering, "1", &[0]), ["4."]);
+        assert!(numbering.next_label("0", Some(0), None).is_none());
+    }
+
+    #[test]
+    fn legal_numbering_writes_the_levels_above_as_decimals() {
+        let levels = [
+            level(0, "upperRoman", "%1", ""),
+            level(1, "decimal", "%1.%2", "<w:isLgl/>"),
+            level(2, "lowerLetter", "%1.%2.%3", ""),
+        ];
+        let mut numbering = numbering(&levels.concat(), LIST);
+        assert_eq!(
+            labels(&mut numbering, "1", &[0, 0, 1, 2]),
+            ["I", "II", "2.1", "II.1.a"]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use super::chart_drawing::Canvas;
+use super::font_metrics::StandardFont;
+use super::pdf_document::num;
+
+const TEXT_SIZE: f32 = 10.0;
+const PADDING: f32 = 5.0;
+
+/// What the application behind a ProgID calls its documents, by the start
+/// of the ProgID
+const KINDS: [(&str, &str); 9] = [
+    ("Excel.Chart", "Excel chart"),
+    ("Excel.Sheet", "Excel worksheet"),
+    ("Visio.Drawing", "Visio drawing"),
+    ("Word.Document", "Word document"),
+    ("PowerPoint.Show", "PowerPoint presentation"),
+    ("PowerPoint.Slide", "PowerPoint slide"),
+    ("Equation.", "equation"),
+    ("AcroExch.Document", "PDF document"),
+    ("Package", "package"),
+];
+
+/// The text a placeholder shows for an object of `prog_id`, e.g.
+/// `Embedded Excel worksheet` for `Excel.Sheet.12`
+pub fn label(prog_id: Option<&str>) -> String {
+    let Some(prog_id) = prog_id.map(str::trim).filter(|id| !id.is_empty()) else {
+        return "Embedded object".to_string();
+    };
+    match KINDS.iter().find(|(prefix, _)| prog_id.starts_with(prefix)) {
+        Some((_, kind)) => format!("Embedded {}", kind),
+        None => format!("Embedded object ({})", prog_id),
+    }
+}
+
+/// Content stream operators for a framed gray box with `label` in its middle,
+/// drawn into `rect` (x, y of the bottom left corner, width, height)
+pub fn draw_placeholder(label: &str, rect: (f32, f32, f32, f32), font: StandardFont) -> String {
+    let (x, y, w, h) = rect;
+    let mut canvas = Canvas::new(font);
+    canvas.push("q");
+    canvas.push("0.95 g");
+    canvas.rect(x, y, w, h);
+    canvas.gray_stroke(0.6);
+    canvas.push("0.75 w");
+    canvas.push(&format!("{} {} {} {} re S", num(x), num(y), num(w), num(h)));
+    let baseline = y + (h - TEXT_SIZE * 0.7) / 2.0;
+    canvas.text_centered(x + w / 2.0, baseline, TEXT_SIZE, label, w - 2.0 * PADDING);
+    canvas.push("Q");
+    canvas.content
+}
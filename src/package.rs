@@ -0,0 +1,302 @@
+use anyhow::Result;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::error::ConvertError;
+use super::limits::ReadBudget;
+use super::report::Warning;
+use super::xml_errors::XmlErrors;
+
+/// Where Word puts the main document part, used when the package doesn't say
+pub const DEFAULT_MAIN_PART: &str = "word/document.xml";
+
+/// Content types of the main part of documents, templates and their
+/// macro-enabled variants, Strict OOXML uses the same ones
+const MAIN_CONTENT_TYPES: [(&str, DocumentKind); 4] = [
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml",
+        DocumentKind::Document,
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.template.main+xml",
+        DocumentKind::Template,
+    ),
+    (
+        "application/vnd.ms-word.document.macroEnabled.main+xml",
+        DocumentKind::MacroEnabledDocument,
+    ),
+    (
+        "application/vnd.ms-word.template.macroEnabledTemplate.main+xml",
+        DocumentKind::MacroEnabledTemplate,
+    ),
+];
+
+/// .docx, .docm, .dotx or .dotm, told apart by the content type of the main
+/// part; the markup is the same and macros (`vbaProject.bin`) are never read
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DocumentKind {
+    #[default]
+    Document,
+    MacroEnabledDocument,
+    Template,
+    MacroEnabledTemplate,
+}
+
+impl DocumentKind {
+    /// The MIME type of the file as a whole, for attachments
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Document => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            Self::MacroEnabledDocument => "application/vnd.ms-word.document.macroEnabled.12",
+            Self::Template => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.template"
+            }
+            Self::MacroEnabledTemplate => "application/vnd.ms-word.template.macroEnabled.12",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Document => "docx",
+            Self::MacroEnabledDocument => "docm",
+            Self::Template => "dotx",
+            Self::MacroEnabledTemplate => "dotm",
+        }
+    }
+}
+
+/// The part holding the body and the kind of file it makes the package
+#[derive(Clone, Debug)]
+pub struct MainPart {
+    pub name: String,
+    pub kind: DocumentKind,
+}
+
+/// An internal relationship of a part, external ones (hyperlinks) are left out
+#[derive(Clone, Debug)]
+pub struct Relationship {
+    pub id: String,
+    /// The last segment of the type URI, e.g. `image`; transitional and
+    /// Strict OOXML only differ in what comes before it
+    pub kind: String,
+    /// The archive entry the target resolves to
+    pub target: String,
+}
+
+/// The main document part: the target of the package's officeDocument
+/// relationship, else the part `[Content_Types].xml` gives a main document
+/// content type, else `word/document.xml`
+pub fn find_main_part<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<MainPart> {
+    let relationships =
+        read_relationships(archive, budget, "", strict, warnings)?.unwrap_or_default();
+    let related = relationships
+        .into_iter()
+        .find(|rel| rel.kind == "officeDocument" && has_entry(archive, &rel.target));
+    let overrides = main_part_overrides(archive, budget, strict, warnings)?;
+    if let Some(main) = related {
+        // a main part without its content type is read as a plain document
+        let kind = overrides
+            .iter()
+            .find(|(part, _)| *part == main.target)
+            .map_or(DocumentKind::default(), |&(_, kind)| kind);
+        return Ok(MainPart {
+            name: main.target,
+            kind,
+        });
+    }
+    if let Some((name, kind)) = overrides
+        .into_iter()
+        .find(|(part, _)| has_entry(archive, part))
+    {
+        return Ok(MainPart { name, kind });
+    }
+    Ok(MainPart {
+        name: DEFAULT_MAIN_PART.to_string(),
+        kind: DocumentKind::default(),
+    })
+}
+
+/// The relationships of `part` by Id, a missing relationships part is an error
+pub fn build_rel_map<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    part: &str,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<HashMap<String, Relationship>> {
+    let Some(relationships) = read_relationships(archive, budget, part, strict, warnings)? else {
+        let rels = rels_part(part);
+        return Err(ConvertError::invalid_docx(&rels, ZipError::FileNotFound).into());
+    };
+    let mut rel_map = HashMap::new();
+    for mut rel in relationships {
+        // some producers write media targets as `../media/x` next to the part
+        if !has_entry(archive, &rel.target) {
+            let legacy = rel_target_legacy(part, &rel.target);
+            if let Some(legacy) = legacy.filter(|legacy| has_entry(archive, legacy)) {
+                rel.target = legacy;
+            }
+        }
+        rel_map.insert(rel.id.clone(), rel);
+    }
+    Ok(rel_map)
+}
+
+/// The directory of `part` with its trailing slash, empty at the package root
+pub fn part_dir(part: &str) -> &str {
+    part.rfind('/').map_or("", |slash| &part[..=slash])
+}
+
+/// `_rels/<name>.rels` next to `part`, `_rels/.rels` for the package itself
+pub fn rels_part(part: &str) -> String {
+    let dir = part_dir(part);
+    format!("{}_rels/{}.rels", dir, &part[dir.len()..])
+}
+
+/// The archive entry `target` of a relationship of `source` points at,
+/// relative to the directory of `source` unless it starts with `/`
+fn resolve(source: &str, target: &str) -> String {
+    let (mut segments, target): (Vec<&str>, &str) = match target.strip_prefix('/') {
+        Some(absolute) => (Vec::new(), absolute),
+        None => (part_dir(source).split_terminator('/').collect(), target),
+    };
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+fn rel_target_legacy(source: &str, target: &str) -> Option<String> {
+    let stripped = target.strip_prefix("../")?;
+    Some(format!("{}{}", part_dir(source), stripped))
+}
+
+fn has_entry<R: Read + Seek>(archive: &ZipArchive<R>, name: &str) -> bool {
+    archive.file_names().any(|entry| entry == name)
+}
+
+/// The internal relationships of `part` in document order, "" for those of
+/// the package, None when it has no relationships part
+fn read_relationships<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    part: &str,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<Vec<Relationship>>> {
+    let rels = rels_part(part);
+    let Some(xml) = budget.read_part(archive, &rels)? else {
+        return Ok(None);
+    };
+
+    let mut reader = Reader::from_str(&xml);
+    let mut buf = Vec::new();
+    let mut errors = XmlErrors::new(&rels, strict);
+    let mut relationships = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Empty(e)) | Ok(Event::Start(e))
+                if e.name().as_ref().ends_with(b"Relationship") =>
+            {
+                let mut id = None;
+                let mut kind = None;
+                let mut target = None;
+                let mut external = false;
+
+                for attr in e.attributes().with_checks(false).flatten() {
+                    let value = || String::from_utf8_lossy(&attr.value).to_string();
+                    match attr.key.as_ref() {
+                        b"Id" => id = Some(value()),
+                        b"Type" => kind = value().rsplit('/').next().map(str::to_string),
+                        b"Target" => target = Some(value()),
+                        b"TargetMode" => external = attr.value.as_ref() == b"External",
+                        _ => {}
+                    }
+                }
+
+                if let (Some(id), Some(target), false) = (id, target, external) {
+                    relationships.push(Relationship {
+                        id,
+                        kind: kind.unwrap_or_default(),
+                        target: resolve(part, &target),
+                    });
+                }
+            }
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+    errors.finish(warnings);
+
+    Ok(Some(relationships))
+}
+
+/// The parts `[Content_Types].xml` overrides to a main document content type
+fn main_part_overrides<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &ReadBudget,
+    strict: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<(String, DocumentKind)>> {
+    let Some(xml) = budget.read_part(archive, "[Content_Types].xml")? else {
+        return Ok(Vec::new());
+    };
+
+    let mut reader = Reader::from_str(&xml);
+    let mut buf = Vec::new();
+    let mut errors = XmlErrors::new("[Content_Types].xml", strict);
+    let mut overrides = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.local_name().as_ref() == b"Override" => {
+                let mut part = None;
+                let mut kind = None;
+                for attr in e.attributes().with_checks(false).flatten() {
+                    match attr.key.as_ref() {
+                        b"PartName" => {
+                            part = Some(resolve("", &String::from_utf8_lossy(&attr.value)))
+                        }
+                        b"ContentType" => {
+                            kind = MAIN_CONTENT_TYPES
+                                .iter()
+                                .find(|(main, _)| main.as_bytes() == attr.value.as_ref())
+                                .map(|&(_, kind)| kind)
+                        }
+                        _ => {}
+                    }
+                }
+                if let (Some(part), Some(kind)) = (part, kind) {
+                    overrides.push((part, kind));
+                }
+            }
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+    errors.finish(warnings);
+
+    Ok(overrides)
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::fmt::Write as _;
+
+use super::font_metrics::StandardFont;
+use super::pdf_document::num;
+use super::pdf_string::{string_operand, win_ansi_bytes};
+
+/// Callbacks around every generated page, for headers, footers, watermarks
+/// or barcodes the document itself doesn't have
+///
+/// Both are called once the page is laid out, what `on_page_start` draws ends
+/// up underneath the document content and what `on_page_end` draws on top.
+/// Pages are prepared in batches, the hooks may run for several pages at once
+/// on different threads and not in page order.
+pub trait PageHooks: fmt::Debug + Send + Sync {
+    fn on_page_start(&self, _page: &mut PageCanvas) {}
+    fn on_page_end(&self, _page: &mut PageCanvas) {}
+}
+
+/// Drawing handle for the page being written
+///
+/// Coordinates are in points from the bottom left corner of the page as laid
+/// out, before any /Rotate is applied. Each hook draws in its own graphics
+/// state, colors and line widths don't leak into the document content.
+#[derive(Debug)]
+pub struct PageCanvas {
+    page_number: usize,
+    output_page: usize,
+    width: f32,
+    height: f32,
+    font: StandardFont,
+    font_resource: usize,
+    content: String,
+}
+
+impl PageCanvas {
+    pub(crate) fn new(
+        (page_number, output_page): (usize, usize),
+        width: f32,
+        height: f32,
+        font: StandardFont,
+    ) -> Self {
+        Self {
+            page_number,
+            output_page,
+            width,
+            height,
+            font,
+            font_resource: 1,
+            content: String::new(),
+        }
+    }
+
+    /// 1 based
+    pub fn page_number(&self) -> usize {
+        self.page_number
+    }
+
+    /// Where the page goes in the PDF, 1 based: the pages left out by
+    /// `Options::pages` aren't counted and those of documents merged before
+    /// this one are
+    pub fn output_page_number(&self) -> usize {
+        self.output_page
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// RGB components between 0 and 1
+    pub fn set_fill_color(&mut self, r: f32, g: f32, b: f32) {
+        let _ = writeln!(self.content, "{} {} {} rg", num(r), num(g), num(b));
+    }
+
+    pub fn set_stroke_color(&mut self, r: f32, g: f32, b: f32) {
+        let _ = writeln!(self.content, "{} {} {} RG", num(r), num(g), num(b));
+    }
+
+    pub fn set_line_width(&mut self, width: f32) {
+        let _ = writeln!(self.content, "{} w", num(width));
+    }
+
+    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let _ = writeln!(
+            self.content,
+            "{} {} {} {} re f",
+            num(x),
+            num(y),
+            num(width),
+            num(height)
+        );
+    }
+
+    pub fn stroke_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let _ = writeln!(
+            self.content,
+            "{} {} {} {} re S",
+            num(x),
+            num(y),
+            num(width),
+            num(height)
+        );
+    }
+
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        let _ = writeln!(
+            self.content,
+            "{} {} m {} {} l S",
+            num(x1),
+            num(y1),
+            num(x2),
+            num(y2)
+        );
+    }
+
+    /// A single line of text in the body font with its baseline at `y`
+    pub fn text(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        let _ = writeln!(
+            self.content,
+            "BT /F{} {} Tf {} {} Td {} Tj ET",
+            self.font_resource,
+            num(size),
+            num(x),
+            num(y),
+            string_operand(&win_ansi_bytes(text))
+        );
+    }
+
+    /// Width `text` takes up at `size`, for centering or right aligning it
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        self.font.measure(text, size)
+    }
+
+    /// Content stream operators added as they are, for anything the methods above don't cover
+    pub fn raw(&mut self, operators: &str) {
+        self.content.push_str(operators);
+        self.content.push('\n');
+    }
+
+    /// Everything drawn since the last call, wrapped in q/Q, None if nothing was
+    pub(crate) fn take_content(&mut self) -> Option<String> {
+        if self.content.is_empty() {
+            return None;
+        }
+        let content = std::mem::take(&mut self.content);
+        Some(format!("q\n{}Q\n", content))
+    }
+}
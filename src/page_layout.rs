@@ -0,0 +1,416 @@
+use super::document_model::TabStop;
+use super::font_metrics::{PdfFont, StandardFont};
+use super::line_breaks::word_breaks;
+use super::tab_stops::tab_advance;
+
+/// A4 in points
+pub const PAGE_WIDTH: f32 = 595.0;
+pub const PAGE_HEIGHT: f32 = 842.0;
+pub const MARGIN: f32 = 72.0;
+
+pub const FONT_SIZE: f32 = 12.0;
+
+/// U+00AD, where a word may be broken with a hyphen
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Page size, margins and body text style used for the whole document
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageSetup {
+    pub width: f32,
+    pub height: f32,
+    pub margin: f32,
+    pub font: StandardFont,
+    pub font_size: f32,
+}
+
+impl Default for PageSetup {
+    fn default() -> Self {
+        Self {
+            width: PAGE_WIDTH,
+            height: PAGE_HEIGHT,
+            margin: MARGIN,
+            font: StandardFont::default(),
+            font_size: FONT_SIZE,
+        }
+    }
+}
+
+impl PageSetup {
+    pub fn line_height(&self) -> f32 {
+        self.font_size * 1.2
+    }
+
+    pub fn content_width(&self) -> f32 {
+        self.width - 2.0 * self.margin
+    }
+
+    pub fn content_height(&self) -> f32 {
+        self.height - 2.0 * self.margin
+    }
+
+    /// Size in points of an image `width` x `height` pixels: its natural size
+    /// at 96 dpi, shrunk to fit the content area
+    pub fn image_size(&self, width: u32, height: u32) -> (f32, f32) {
+        self.fit_size(width as f32 * 0.75, height as f32 * 0.75)
+    }
+
+    /// `width` x `height` points shrunk to fit the content area
+    pub fn fit_size(&self, width: f32, height: f32) -> (f32, f32) {
+        self.fit_within(self.content_width(), width, height)
+    }
+
+    /// `width` x `height` points shrunk to fit `max_width` and the content height
+    fn fit_within(&self, max_width: f32, width: f32, height: f32) -> (f32, f32) {
+        let scale = (max_width / width)
+            .min(self.content_height() / height)
+            .min(1.0);
+        (width * scale, height * scale)
+    }
+
+    pub fn left(&self) -> f32 {
+        self.margin
+    }
+
+    pub fn top(&self) -> f32 {
+        self.height - self.margin
+    }
+
+    /// Greedily breaks `text` set in `font` at spaces into lines no wider than
+    /// `max_width`, the first no wider than `first_width`; a single word that
+    /// is too long gets a line of its own. `\n` forces a break, a word that
+    /// doesn't fit is hyphenated at its soft hyphens where it can be, they
+    /// are left out everywhere else. Thai, Lao, Khmer and Myanmar words run
+    /// together without spaces and are broken between them instead. A `\t` goes to the next of `tabs`, the
+    /// first line starting `starts.0` points from the left edge of the
+    /// column and the others `starts.1`, and is kept in the line
+    pub fn wrap_lines(
+        &self,
+        text: &str,
+        font: &PdfFont,
+        first_width: f32,
+        max_width: f32,
+        tabs: &[TabStop],
+        starts: (f32, f32),
+    ) -> Vec<WrappedLine> {
+        let mut lines = Vec::new();
+        let mut broken = Vec::new();
+        for segment in text.split('\n') {
+            let count = lines.len();
+            let widths = (first_width, max_width);
+            self.wrap_segment(segment, font, widths, (tabs, starts), &mut lines);
+            if lines.len() == count {
+                lines.push(String::new());
+            }
+            broken.resize(lines.len(), true);
+            broken[lines.len() - 1] = false;
+        }
+        // the empty line a paragraph ending in a break leaves takes no room
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines
+            .into_iter()
+            .zip(broken)
+            .map(|(text, broken)| WrappedLine { text, broken })
+            .collect()
+    }
+
+    fn wrap_segment(
+        &self,
+        text: &str,
+        font: &PdfFont,
+        (first_width, other_width): (f32, f32),
+        (tabs, starts): (&[TabStop], (f32, f32)),
+        lines: &mut Vec<String>,
+    ) {
+        let width = |text: &str| font.measure(text, self.font_size);
+        let mut line = String::new();
+        let mut line_width = 0.0;
+        for (i, piece) in text.split('\t').enumerate() {
+            if i > 0 {
+                // set against the text after it, as far as the next tab
+                let next = piece.split_whitespace().collect::<Vec<_>>().join(" ");
+                let next = next.replace(SOFT_HYPHEN, "");
+                let (start, max_width) = match lines.is_empty() {
+                    true => (starts.0, first_width),
+                    false => (starts.1, other_width),
+                };
+                let mut advance = tab_advance(tabs, start + line_width, &next, width).0;
+                if line_width + advance > max_width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0.0;
+                    advance = tab_advance(tabs, starts.1, &next, width).0;
+                }
+                line.push('\t');
+                line_width += advance;
+            }
+            self.wrap_words(
+                piece,
+                font,
+                (first_width, other_width),
+                lines,
+                (&mut line, &mut line_width),
+            );
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+
+    /// Adds the words of `text` to `line`, `line_width` wide, pushing it to
+    /// `lines` when full
+    fn wrap_words(
+        &self,
+        text: &str,
+        font: &PdfFont,
+        (first_width, other_width): (f32, f32),
+        lines: &mut Vec<String>,
+        (line, line_width): (&mut String, &mut f32),
+    ) {
+        let width = |text: &str| font.measure(text, self.font_size);
+        let space = width(" ");
+        for word in text.split_whitespace() {
+            let mut rest = word;
+            loop {
+                let shown = rest.replace(SOFT_HYPHEN, "");
+                let word_width = width(&shown);
+                let max_width = match lines.is_empty() {
+                    true => first_width,
+                    false => other_width,
+                };
+                // no space after a tab
+                let gap = match line.is_empty() || line.ends_with('\t') {
+                    true => 0.0,
+                    false => space,
+                };
+                let fits = |piece_width: f32| *line_width + gap + piece_width <= max_width;
+                let word_fits = fits(word_width);
+                // otherwise the longest part up to a soft hyphen or a word
+                // boundary that still fits
+                let head = match word_fits {
+                    true => None,
+                    false => break_points(rest)
+                        .into_iter()
+                        .rev()
+                        .find_map(|(i, hyphen)| {
+                            let head = rest[..i].replace(SOFT_HYPHEN, "");
+                            let head = if hyphen { format!("{}-", head) } else { head };
+                            let tail = rest[i..].strip_prefix(SOFT_HYPHEN).unwrap_or(&rest[i..]);
+                            (fits(width(&head)) && !tail.is_empty()).then_some((head, tail))
+                        }),
+                };
+                if let Some((head, tail)) = head {
+                    if gap > 0.0 {
+                        line.push(' ');
+                    }
+                    line.push_str(&head);
+                    lines.push(std::mem::take(line));
+                    *line_width = 0.0;
+                    rest = tail;
+                } else if !word_fits && !line.is_empty() {
+                    // tried again on a line of its own, where it may be too long
+                    lines.push(std::mem::take(line));
+                    *line_width = 0.0;
+                } else {
+                    if gap > 0.0 {
+                        line.push(' ');
+                    }
+                    line.push_str(&shown);
+                    *line_width += gap + word_width;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Where `word` may be broken, in order, and whether a hyphen is shown there
+fn break_points(word: &str) -> Vec<(usize, bool)> {
+    let mut points: Vec<(usize, bool)> = word
+        .match_indices(SOFT_HYPHEN)
+        .map(|(i, _)| (i, true))
+        .collect();
+    // a soft hyphen is a break opportunity to UAX #14 too, but only before it
+    // does it get its hyphen
+    points.extend(
+        word_breaks(word)
+            .into_iter()
+            .filter(|&i| !word[..i].ends_with(SOFT_HYPHEN))
+            .map(|i| (i, false)),
+    );
+    points.sort_unstable();
+    points
+}
+
+/// A line of wrapped text
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WrappedLine {
+    pub text: String,
+    /// broken where the text no longer fit, rather than ended by a break or
+    /// the end of the text
+    pub broken: bool,
+}
+
+/// Vertical cursor over the content area of the page being written
+///
+/// Blocks are stacked top to bottom, once one no longer fits the caller
+/// flushes the page and starts over, at most a batch of laid out pages is
+/// ever waiting to be written.
+pub struct PageLayout {
+    setup: PageSetup,
+    cursor_y: f32,
+    top: f32,
+    /// left edge and width of the table cell being filled, None for the
+    /// content area of the page
+    column: Option<(f32, f32)>,
+    /// the left and right indents of the paragraph being laid out
+    indent: (f32, f32),
+    /// its tab stops
+    tabs: Vec<TabStop>,
+    /// how far down blocks go before the page is full
+    bottom: f32,
+    /// where the next page starts
+    next_top: f32,
+}
+
+impl PageLayout {
+    pub fn new(setup: PageSetup) -> Self {
+        Self {
+            setup,
+            cursor_y: setup.top(),
+            top: setup.top(),
+            column: None,
+            indent: (0.0, 0.0),
+            tabs: Vec::new(),
+            bottom: setup.margin,
+            next_top: setup.top(),
+        }
+    }
+
+    /// A layout for the content of a table cell, `width` points wide at
+    /// `left` from `top` down. It never runs out of room, the table decides
+    /// where its rows go
+    pub fn column(setup: PageSetup, left: f32, width: f32, top: f32) -> Self {
+        Self::split_column(setup, left, width, top, f32::NEG_INFINITY, top)
+    }
+
+    /// A layout for the content of a table cell whose row may go on to the
+    /// next pages: it runs out of room at `bottom` and carries on from
+    /// `next_top` down
+    pub fn split_column(
+        setup: PageSetup,
+        left: f32,
+        width: f32,
+        top: f32,
+        bottom: f32,
+        next_top: f32,
+    ) -> Self {
+        Self {
+            setup,
+            cursor_y: top,
+            top,
+            column: Some((left, width.max(1.0))),
+            indent: (0.0, 0.0),
+            tabs: Vec::new(),
+            bottom,
+            next_top,
+        }
+    }
+
+    pub fn setup(&self) -> &PageSetup {
+        &self.setup
+    }
+
+    /// Indents what is laid out from here on `left` and `right` points from
+    /// the sides of the column
+    pub fn set_indent(&mut self, left: f32, right: f32) {
+        self.indent = (left, right);
+    }
+
+    /// The tab stops of what is laid out from here on
+    pub fn set_tabs(&mut self, tabs: Vec<TabStop>) {
+        self.tabs = tabs;
+    }
+
+    /// How far a tab `at` points from the left indent reaches, set against
+    /// `next` in `font`, and the stop it goes to
+    pub fn tab(&self, at: f32, next: &str, font: &PdfFont) -> (f32, Option<&TabStop>) {
+        let width = |text: &str| font.measure(text, self.setup.font_size);
+        tab_advance(&self.tabs, self.indent.0 + at, next, width)
+    }
+
+    pub fn left(&self) -> f32 {
+        self.column.map_or(self.setup.left(), |(left, _)| left) + self.indent.0
+    }
+
+    pub fn content_width(&self) -> f32 {
+        let width = self
+            .column
+            .map_or(self.setup.content_width(), |(_, width)| width);
+        (width - self.indent.0 - self.indent.1).max(1.0)
+    }
+
+    /// `text` in `font` broken into lines no wider than the content width,
+    /// the first starting `first_line` points further in
+    pub fn wrap_lines(&self, text: &str, font: &PdfFont, first_line: f32) -> Vec<WrappedLine> {
+        let width = self.content_width();
+        let first = (width - first_line).max(1.0);
+        let starts = (self.indent.0 + first_line, self.indent.0);
+        self.setup
+            .wrap_lines(text, font, first, width, &self.tabs, starts)
+    }
+
+    /// `width` x `height` points shrunk to the content width and page height
+    pub fn fit_size(&self, width: f32, height: f32) -> (f32, f32) {
+        self.setup.fit_within(self.content_width(), width, height)
+    }
+
+    /// Size in points of an image `width` x `height` pixels, its natural size
+    /// at 96 dpi shrunk like `fit_size`
+    pub fn image_size(&self, width: u32, height: u32) -> (f32, f32) {
+        self.fit_size(width as f32 * 0.75, height as f32 * 0.75)
+    }
+
+    /// The y the next block starts at
+    pub fn cursor(&self) -> f32 {
+        self.cursor_y
+    }
+
+    /// How far down blocks go before the page is full
+    pub fn bottom(&self) -> f32 {
+        self.bottom
+    }
+
+    /// The points taken on the current page
+    pub fn used(&self) -> f32 {
+        self.top - self.cursor_y
+    }
+
+    /// nothing has been placed on the current page yet
+    pub fn is_empty(&self) -> bool {
+        self.cursor_y >= self.top
+    }
+
+    /// Reserves `height` points, returning the bottom y of the block,
+    /// or None when it doesn't fit on what is left of the page.
+    /// A block taller than a whole page is always placed on an empty page.
+    pub fn place(&mut self, height: f32) -> Option<f32> {
+        let bottom = self.cursor_y - height;
+        if bottom < self.bottom && !self.is_empty() {
+            return None;
+        }
+        self.cursor_y = bottom;
+        Some(bottom)
+    }
+
+    /// Whether `height` points still fit on the current page, `place` puts
+    /// them there all the same when it is empty
+    pub fn fits(&self, height: f32) -> bool {
+        self.cursor_y - height >= self.bottom
+    }
+
+    pub fn reset(&mut self) {
+        self.top = self.next_top;
+        self.cursor_y = self.top;
+    }
+}
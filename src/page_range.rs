@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+use std::fmt;
+
+/// Pages to write after layout, 1 based, e.g. `3-10,15` or `20-` for page 20 to the end
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageRanges {
+    ranges: Vec<(usize, Option<usize>)>, // first and last page, None for up to the end
+}
+
+impl PageRanges {
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut ranges = Vec::new();
+        for part in value.split(',').map(str::trim) {
+            let page = |number: &str| -> Result<usize> {
+                let page = number
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid page range {:?}", part))?;
+                if page == 0 {
+                    bail!("invalid page range {:?}, pages are numbered from 1", part);
+                }
+                Ok(page)
+            };
+            let range = match part.split_once('-') {
+                Some((first, "")) => (page(first)?, None),
+                Some((first, last)) => (page(first)?, Some(page(last)?)),
+                None => (page(part)?, Some(page(part)?)),
+            };
+            if let (first, Some(last)) = range
+                && first > last
+            {
+                bail!(
+                    "invalid page range {:?}, {} comes after {}",
+                    part,
+                    first,
+                    last
+                );
+            }
+            ranges.push(range);
+        }
+        Ok(Self { ranges })
+    }
+
+    pub fn contains(&self, page: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(first, last)| page >= first && last.is_none_or(|last| page <= last))
+    }
+
+    /// The last selected page, None when a range runs to the end of the document
+    pub fn last(&self) -> Option<usize> {
+        self.ranges
+            .iter()
+            .map(|&(_, last)| last)
+            .try_fold(0, |max, last| last.map(|last| max.max(last)))
+    }
+}
+
+impl fmt::Display for PageRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, &(first, last)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match last {
+                Some(last) if last == first => write!(f, "{}", first)?,
+                Some(last) => write!(f, "{}-{}", first, last)?,
+                None => write!(f, "{}-", first)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,69 +1,573 @@
-use super::pdf_stream_writer::PdfStreamWriter;
+use super::doc_props::{pdf_date, DocProps};
+use super::font_metrics::{PdfFont, TrueTypeFont, LIGATURES};
+use super::letterhead::Letterhead;
+use super::page_layout::PageSetup;
+use super::pdf_encryption::{random_bytes, to_hex, PdfEncryption};
+use super::pdf_form::{self, FormWidget, WidgetKind, FIELD_FONT};
+use super::pdf_stream_writer::{Fixup, PdfStreamWriter};
+use super::pdf_string::{hex_string, literal_string, string_operand, win_ansi_char};
+use super::pdf_version::{Feature, PdfVersion};
+use super::print_profile::PrintProfile;
 use anyhow::Result;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashSet;
 use std::io::{Cursor, Read, Seek, Write};
+use std::sync::Arc;
+
+/// Numbering style of a /PageLabels range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageLabelStyle {
+    Decimal,
+    UpperRoman,
+    LowerRoman,
+    UpperLetter,
+    LowerLetter,
+}
+
+impl PageLabelStyle {
+    /// `number` as a viewer shows it in this style, letters run A to Z, then
+    /// AA to ZZ and so on. Numbers no sensible label exists for, past 3999
+    /// for roman ones, are written in digits
+    pub fn format(self, number: u32) -> String {
+        let letters = |first: u8| {
+            let index = number.checked_sub(1).filter(|&i| i < 26 * 10)?;
+            let letter = (first + (index % 26) as u8) as char;
+            Some(letter.to_string().repeat(index as usize / 26 + 1))
+        };
+        let roman = || (1..4000).contains(&number).then(|| roman(number));
+        let label = match self {
+            PageLabelStyle::Decimal => None,
+            PageLabelStyle::UpperRoman => roman(),
+            PageLabelStyle::LowerRoman => roman().map(|r| r.to_lowercase()),
+            PageLabelStyle::UpperLetter => letters(b'A'),
+            PageLabelStyle::LowerLetter => letters(b'a'),
+        };
+        label.unwrap_or_else(|| number.to_string())
+    }
+}
+
+fn roman(mut number: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+    out
+}
+
+/// Pages from `first_page` (0 based) onwards are numbered `style` starting at `start`
+#[derive(Clone, Debug)]
+pub struct PageLabel {
+    pub first_page: usize,
+    pub style: PageLabelStyle,
+    pub start: u32,
+}
+
+/// Magnification of the /OpenAction destination
+#[derive(Clone, Copy, Debug)]
+pub enum Zoom {
+    FitPage,
+    FitWidth,
+    Percent(f32),
+}
+
+/// How a viewer presents the document when it is opened
+#[derive(Clone, Debug, Default)]
+pub struct ViewerOptions {
+    pub fit_window: bool,
+    pub hide_toolbar: bool,
+    pub two_page: bool,
+    pub open_page: Option<usize>, // 1 based
+    pub zoom: Option<Zoom>,
+}
+
+/// Bytes reserved for the signature's /Contents, enough for a PKCS#7 blob with a certificate chain
+const SIGNATURE_CONTENTS_SIZE: usize = 8192;
+
+struct SignatureField {
+    name: String,
+    field_id: u32, // reserved, the widget is referenced from the first page
+}
 
 pub struct PdfDocument<W>
 where
     W: Write + Seek,
 {
     writer: PdfStreamWriter<W>,
-    pages_id: u32,      // reserved object id for /Pages
-    page_ids: Vec<u32>, // vector of page object ids used to build /Pages
+    version: PdfVersion,
+    pages_id: u32,                // reserved object id for /Pages
+    page_ids: Vec<u32>,           // vector of page object ids used to build /Pages
+    resources_id: u32,            // reserved object id for the /Resources shared by every page
+    xobjects: Vec<(String, u32)>, // image XObjects in the shared /Resources, by name
+    fonts: Vec<PdfFont>,          // /F1, /F2 .. in the shared /Resources, the body font first
+    metadata: Option<DocProps>,
+    encryption: Option<Arc<PdfEncryption>>,
+    file_id: Option<[u8; 16]>, // fixed trailer /ID, random when encrypting otherwise
+    page_labels: Vec<PageLabel>,
+    outline: Vec<(String, usize)>, // title and 0 based page of each top level entry
+    attachments: Vec<(String, u32)>, // file name and /Filespec object id
+    signature: Option<SignatureField>,
+    form_fields: Vec<u32>, // merged field and widget objects, for the /AcroForm
+    field_names: HashSet<String>, // a repeated name would make the widgets one field
+    page_annots: Vec<u32>, // widgets of the next page written
+    field_font_id: Option<u32>, // Helvetica for the fields, written with the first one
+    to_unicode_id: Option<u32>, // WinAnsi to Unicode CMap, written with the first font
+    viewer: ViewerOptions,
+    setup: PageSetup,    // of the current section, `portrait` turned for landscape
+    portrait: PageSetup, // as set with `set_page_setup`
+    compress: bool,      // Flate compress content streams and attachments
+    rotation: u16,       // /Rotate of every page, a multiple of 90
+    print: Option<PrintProfile>, // written as PDF/X-4
 }
 
 impl<W> PdfDocument<W>
 where
     W: Write + Seek,
 {
-    pub fn new(sink: W) -> Result<PdfDocument<W>> {
-        let mut writer = PdfStreamWriter::new(sink)?;
+    pub fn new(sink: W, version: PdfVersion) -> Result<PdfDocument<W>> {
+        let mut writer = PdfStreamWriter::new(sink, version)?;
         let pages_id = writer.reserve_object()?;
+        let resources_id = writer.reserve_object()?;
         Ok(PdfDocument {
             writer,
+            version,
             pages_id,
             page_ids: vec![],
+            resources_id,
+            xobjects: vec![],
+            fonts: vec![PdfFont::Standard(Default::default())],
+            metadata: None,
+            encryption: None,
+            file_id: None,
+            page_labels: vec![],
+            outline: vec![],
+            attachments: vec![],
+            signature: None,
+            form_fields: vec![],
+            field_names: HashSet::new(),
+            page_annots: vec![],
+            field_font_id: None,
+            to_unicode_id: None,
+            viewer: ViewerOptions::default(),
+            setup: PageSetup::default(),
+            portrait: PageSetup::default(),
+            compress: false,
+            rotation: 0,
+            print: None,
         })
     }
 
-    /// writes a new unused text stream object, returning the object_id
-    pub fn new_text_obj(&mut self, text: &str) -> Result<u32> {
-        let stream_content = format!(
-            "BT /F1 12 Tf 0 720 Td ({}) Tj ET",
-            text.replace('(', "\\(").replace(')', "\\)")
+    /// logical page numbering written as the catalog's /PageLabels
+    pub fn set_page_labels(&mut self, labels: Vec<PageLabel>) {
+        self.page_labels = labels;
+    }
+
+    /// the labels set so far, e.g. to renumber them
+    pub fn take_page_labels(&mut self) -> Vec<PageLabel> {
+        std::mem::take(&mut self.page_labels)
+    }
+
+    /// adds a top level outline entry opening `page` (0 based)
+    pub fn add_outline_entry(&mut self, title: &str, page: usize) {
+        self.outline.push((title.to_string(), page));
+    }
+
+    /// number of pages written so far
+    pub fn page_count(&self) -> usize {
+        self.page_ids.len()
+    }
+
+    /// encrypts every string and stream written from here on,
+    /// so it should be set before any content is added
+    pub fn set_encryption(&mut self, encryption: Arc<PdfEncryption>) {
+        self.encryption = Some(encryption);
+    }
+
+    /// a trailer /ID that stays the same for the same input
+    pub fn set_file_id(&mut self, file_id: [u8; 16]) {
+        self.file_id = Some(file_id);
+    }
+
+    /// /ViewerPreferences, /PageLayout and /OpenAction written on finish
+    pub fn set_viewer_options(&mut self, viewer: ViewerOptions) {
+        self.viewer = viewer;
+    }
+
+    /// page size, margins and font, set before any content is added
+    pub fn set_page_setup(&mut self, setup: PageSetup) {
+        self.setup = setup;
+        self.portrait = setup;
+        self.fonts[0] = PdfFont::Standard(setup.font);
+    }
+
+    /// Registers a font for `text_operators`, index 0 is always the body font
+    pub fn add_font(&mut self, font: PdfFont) -> usize {
+        match self.fonts.iter().position(|known| known.same_as(&font)) {
+            Some(index) => index,
+            None => {
+                self.fonts.push(font);
+                self.fonts.len() - 1
+            }
+        }
+    }
+
+    pub fn page_setup(&self) -> &PageSetup {
+        &self.setup
+    }
+
+    /// the fonts `text_operators` refers to, by index
+    pub fn fonts(&self) -> &[PdfFont] {
+        &self.fonts
+    }
+
+    /// writes identical streams from here on once, e.g. an image placed on
+    /// several pages or the same header on every page
+    pub fn set_deduplication(&mut self, deduplicate: bool) {
+        if deduplicate {
+            self.writer.deduplicate();
+        }
+    }
+
+    /// Flate compresses content streams and attachments written from here on
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// rotates every page clockwise by `degrees`, which must be a multiple of 90
+    pub fn set_rotation(&mut self, degrees: u16) {
+        self.rotation = degrees % 360;
+    }
+
+    /// pages written from here on are wider than tall, content is laid out
+    /// across them with `page_setup`
+    pub fn set_landscape(&mut self, landscape: bool) {
+        let portrait = self.portrait;
+        self.setup = if landscape && portrait.width < portrait.height {
+            PageSetup {
+                width: portrait.height,
+                height: portrait.width,
+                ..portrait
+            }
+        } else {
+            portrait
+        };
+    }
+
+    /// /Rotate of the pages written from here on, in degrees clockwise
+    pub fn page_rotation(&self) -> u16 {
+        self.rotation
+    }
+
+    /// writes PDF/X-4 for `print`, set before any page is added
+    pub fn set_print_profile(&mut self, print: PrintProfile) {
+        self.print = Some(print);
+    }
+
+    /// What keeps the document from being PDF/X-4, with a print profile set
+    pub fn print_problems(&self) -> Result<Vec<String>> {
+        let Some(print) = &self.print else {
+            return Ok(Vec::new());
+        };
+        let mut problems = Vec::new();
+        if self.encryption.is_some() {
+            problems.push("it is encrypted".to_string());
+        }
+        for font in &self.fonts {
+            if let PdfFont::Standard(font) = font {
+                problems.push(format!("font {} is not embedded", font.base_font()));
+            }
+        }
+        if !self.attachments.is_empty() {
+            problems.push("it has embedded files".to_string());
+        }
+        if !self.form_fields.is_empty() || self.signature.is_some() {
+            problems.push("it has form fields".to_string());
+        }
+        let props = self.metadata.clone().unwrap_or_default();
+        if props.title.is_none() {
+            problems.push("it has no title".to_string());
+        }
+        let dated = |date: &Option<String>| date.as_deref().and_then(pdf_date).is_some();
+        if !dated(&props.created) || !dated(&props.modified) {
+            problems.push("it has no creation or modification date".to_string());
+        }
+        let (_, components) = print.profile()?;
+        if components != 3 {
+            problems.push(format!(
+                "its colors are RGB, the output intent {} is not",
+                print.output_condition
+            ));
+        }
+        Ok(problems)
+    }
+
+    /// document properties written to /Info and the XMP stream on finish
+    pub fn set_metadata(&mut self, props: DocProps) {
+        self.metadata = Some(props);
+    }
+
+    /// embeds `data` as an attached file listed in the catalog's /EmbeddedFiles name tree
+    pub fn attach_file(&mut self, name: &str, mime_type: &str, data: &[u8]) -> Result<()> {
+        let subtype = mime_type.replace('/', "#2F");
+        let stream_id = self.write_flate_stream(
+            &format!(
+                "/Type /EmbeddedFile /Subtype /{} /Params << /Size {} >>",
+                subtype,
+                data.len()
+            ),
+            data,
+        )?;
+
+        let file_name = self.text_string(name)?;
+        let filespec = format!(
+            "<< /Type /Filespec /F {} /UF {} /EF << /F {} 0 R /UF {} 0 R >>{} >>",
+            file_name,
+            file_name,
+            stream_id,
+            stream_id,
+            if self.version.supports(Feature::AssociatedFiles) {
+                " /AFRelationship /Source"
+            } else {
+                ""
+            }
+        );
+        let filespec_id = self
+            .writer
+            .write_object(Cursor::new(filespec.into_bytes()))?;
+        self.attachments.push((name.to_string(), filespec_id));
+        Ok(())
+    }
+
+    /// adds an unsigned signature field on the first page, must be called before any page is written
+    ///
+    /// Its /V carries a zeroed /Contents and a /ByteRange that is filled in once the
+    /// file is complete, so a signer only has to hash the ranges and write /Contents in place.
+    pub fn add_signature_field(&mut self, name: &str) -> Result<()> {
+        let field_id = self.writer.reserve_object()?;
+        self.signature = Some(SignatureField {
+            name: name.to_string(),
+            field_id,
+        });
+        Ok(())
+    }
+
+    /// adds a fillable field with its appearance streams to the next page written
+    pub fn add_form_field(&mut self, widget: &FormWidget) -> Result<()> {
+        let font_id = match self.field_font_id {
+            Some(id) => id,
+            None => {
+                let dict = format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding \
+                     /ToUnicode {} 0 R >>",
+                    self.write_to_unicode(&[])?
+                );
+                let id = self.writer.write_object(Cursor::new(dict.into_bytes()))?;
+                *self.field_font_id.insert(id)
+            }
+        };
+        let (x, y, w, h) = widget.rect;
+        let form_dict = format!(
+            "/Type /XObject /Subtype /Form /BBox [0 0 {} {}] /Resources << /Font << /{} {} 0 R >> >>",
+            num(w),
+            num(h),
+            FIELD_FONT,
+            font_id
+        );
+        let entries = match &widget.kind {
+            WidgetKind::Text {
+                value,
+                default,
+                max_length,
+            } => {
+                let appearance = pdf_form::text_appearance(value, w, h, widget.font_size);
+                let appearance_id = self.write_flate_stream(&form_dict, appearance.as_bytes())?;
+                let max_length = max_length
+                    .map(|n| format!(" /MaxLen {}", n))
+                    .unwrap_or_default();
+                format!(
+                    "/FT /Tx /V {} /DV {} /DA {}{} /AP << /N {} 0 R >>",
+                    self.text_string(value)?,
+                    self.text_string(default)?,
+                    self.text_string(&pdf_form::default_appearance(widget.font_size))?,
+                    max_length,
+                    appearance_id
+                )
+            }
+            WidgetKind::CheckBox { checked, default } => {
+                let on = pdf_form::check_box_appearance(true, w);
+                let on_id = self.write_flate_stream(&form_dict, on.as_bytes())?;
+                let off = pdf_form::check_box_appearance(false, w);
+                let off_id = self.write_flate_stream(&form_dict, off.as_bytes())?;
+                let state = |on: bool| if on { "/Yes" } else { "/Off" };
+                format!(
+                    "/FT /Btn /V {} /DV {} /AS {} /AP << /N << /Yes {} 0 R /Off {} 0 R >> >>",
+                    state(*checked),
+                    state(*default),
+                    state(*checked),
+                    on_id,
+                    off_id
+                )
+            }
+            WidgetKind::Choice {
+                options,
+                value,
+                shown,
+                editable,
+            } => {
+                let appearance = pdf_form::text_appearance(shown, w, h, widget.font_size);
+                let appearance_id = self.write_flate_stream(&form_dict, appearance.as_bytes())?;
+                let mut opt = String::new();
+                for (display, export) in options {
+                    if display == export {
+                        opt.push_str(&format!("{} ", self.text_string(display)?));
+                    } else {
+                        opt.push_str(&format!(
+                            "[{} {}] ",
+                            self.text_string(export)?,
+                            self.text_string(display)?
+                        ));
+                    }
+                }
+                format!(
+                    "/FT /Ch /Ff {} /Opt [{}] /V {} /DV {} /DA {} /AP << /N {} 0 R >>",
+                    pdf_form::choice_flags(*editable),
+                    opt,
+                    self.text_string(value)?,
+                    self.text_string(value)?,
+                    self.text_string(&pdf_form::default_appearance(widget.font_size))?,
+                    appearance_id
+                )
+            }
+        };
+        // repeated names get a number, fields of the same name share their value
+        let mut name = widget.name.clone();
+        let mut n = 1;
+        while !self.field_names.insert(name.clone()) {
+            n += 1;
+            name = format!("{}_{}", widget.name, n);
+        }
+        // printed with the page
+        let field = format!(
+            "<< /Type /Annot /Subtype /Widget /T {} /Rect [{} {} {} {}] /F 4 {} >>",
+            self.text_string(&name)?,
+            num(x),
+            num(y),
+            num(x + w),
+            num(y + h),
+            entries
         );
-        let content_bytes = stream_content.as_bytes();
-        let length = content_bytes.len();
+        let field_id = self.writer.write_object(Cursor::new(field.into_bytes()))?;
+        self.form_fields.push(field_id);
+        self.page_annots.push(field_id);
+        Ok(())
+    }
 
-        let mut buf = Vec::new();
-        writeln!(buf, "<< /Length {} >>", length)?;
-        writeln!(buf, "stream")?;
-        buf.extend_from_slice(content_bytes);
-        write!(buf, "\nendstream")?;
+    /// serializes and compresses content streams like this document would,
+    /// without touching the writer
+    pub fn content_encoder(&self) -> ContentEncoder {
+        ContentEncoder {
+            setup: self.setup,
+            compress: self.compress,
+        }
+    }
 
-        let reader = Cursor::new(buf);
-        let obj_id = self.writer.write_object(reader)?;
-        Ok(obj_id)
+    /// writes a stream from `content_encoder`, returning the object_id
+    pub fn write_prepared(&mut self, stream: PreparedStream) -> Result<u32> {
+        self.write_stream(stream.dict, &stream.data)
     }
 
-    /// assumes the image data is of JPEG
+    /// assumes the image data is of JPEG, the image goes in the shared
+    /// resources as `name`, which `image_operators` draw
     pub fn new_img_obj<R: Read>(
         &mut self,
         mut image_stream: R,
         w: u32,
         h: u32,
+        components: u8,
         len: usize,
-    ) -> Result<(u32, u32)> {
+        name: &str,
+    ) -> Result<u32> {
+        let color_space = if components == 1 {
+            "DeviceGray"
+        } else {
+            "DeviceRGB"
+        };
+        let image_dict = format!(
+            "/Type /XObject /Subtype /Image \
+           /Width {} /Height {} \
+           /ColorSpace /{} \
+           /BitsPerComponent 8 \
+           /Filter /DCTDecode",
+            w, h, color_space
+        );
+
+        let image_obj_id = if self.encryption.is_some() || self.writer.deduplicates() {
+            // the cipher needs the whole stream, and so does comparing it with
+            // the others; images are small after preprocessing
+            let mut data = Vec::with_capacity(len);
+            image_stream.read_to_end(&mut data)?;
+            self.write_stream(&image_dict, &data)?
+        } else {
+            self.write_image_stream(&image_dict, image_stream, len)?
+        };
+        self.xobjects.push((name.to_string(), image_obj_id));
+        Ok(image_obj_id)
+    }
+
+    /// imports the pages of `letterhead` as the form XObjects /Lh1, /Lh2 ..
+    /// in the shared resources, along with everything they refer to
+    pub fn add_letterhead(&mut self, letterhead: &Letterhead) -> Result<()> {
+        let ids = (0..letterhead.object_count())
+            .map(|_| self.writer.reserve_object())
+            .collect::<Result<Vec<_>>>()?;
+        for (index, &id) in ids.iter().enumerate() {
+            let bytes = letterhead.object(index, &ids, self.encryption.as_deref())?;
+            let mut writer = |obj: &mut W| -> Result<()> { Ok(obj.write_all(&bytes)?) };
+            self.writer.write_object_with_reserved_id(id, &mut writer)?;
+        }
+        for page in 0..letterhead.page_count() {
+            let (dict, content) = letterhead.form(page, &ids, self.encryption.as_deref())?;
+            let id = self.write_flate_stream(&dict, content)?;
+            self.xobjects.push((format!("Lh{}", page + 1), id));
+        }
+        Ok(())
+    }
+
+    /// an empty form XObject in the shared resources as `name`, standing in
+    /// for an image that couldn't be written so the page drawing it stays valid
+    pub fn blank_xobject(&mut self, name: &str) -> Result<u32> {
+        let id = self.write_stream("/Type /XObject /Subtype /Form /BBox [0 0 1 1]", b"")?;
+        self.xobjects.push((name.to_string(), id));
+        Ok(id)
+    }
+
+    /// copies an unencrypted image stream straight from `image_stream` into the output
+    fn write_image_stream<R: Read>(
+        &mut self,
+        image_dict: &str,
+        mut image_stream: R,
+        len: usize,
+    ) -> Result<u32> {
         let mut writer = |obj: &mut W| -> Result<()> {
-            write!(
-                obj,
-                "<< /Type /XObject /Subtype /Image \
-               /Width {} /Height {} \
-               /ColorSpace /DeviceRGB \
-               /BitsPerComponent 8 \
-               /Filter /DCTDecode \
-               /Length {} >>\nstream\n",
-                w, h, len
-            )?;
+            write!(obj, "<< {} /Length {} >>\nstream\n", image_dict, len)?;
 
             let mut buf = [0u8; 8192];
             loop {
@@ -78,72 +582,106 @@ where
             Ok(())
         };
 
-        let image_obj_id = self.writer.write_object_with(&mut writer)?;
-
-        // Create simple content stream
-        let content = format!("q\n500 0 0 500 0 0 cm\n/Im{} Do\nQ\n", image_obj_id);
-        let content_stream = format!(
-            "<< /Length {} >>\nstream\n{}\nendstream",
-            content.len(),
-            content
-        );
-
-        let content_stream_id = self
-            .writer
-            .write_object(Cursor::new(content_stream.into_bytes()))?;
+        self.writer.write_object_with(&mut writer)
+    }
 
-        return Ok((image_obj_id, content_stream_id));
+    /// like `write_stream` but Flate compressed when compression is on,
+    /// the data is compressed before it is encrypted
+    fn write_flate_stream(&mut self, dict: &str, data: &[u8]) -> Result<u32> {
+        if !self.compress {
+            return self.write_stream(dict, data);
+        }
+        let compressed = deflate(data)?;
+        let dict = if dict.is_empty() {
+            "/Filter /FlateDecode".to_string()
+        } else {
+            format!("{} /Filter /FlateDecode", dict)
+        };
+        self.write_stream(&dict, &compressed)
     }
 
-    pub fn new_page_obj(
-        &mut self,
-        current_page_objs: &[u32],            // Text content stream IDs
-        current_page_img_objs: &[(u32, u32)], // Vec of (image_obj_id, content_stream_id) tuples
-    ) -> Result<u32> {
-        // Collect all content stream IDs (text + image drawing commands)
-        let mut all_content_streams = Vec::new();
+    /// writes a stream object, `dict` holds the dictionary entries besides
+    /// /Length; with deduplication on an identical stream is written once
+    fn write_stream(&mut self, dict: &str, data: &[u8]) -> Result<u32> {
+        let encryption = &self.encryption;
+        self.writer.write_shared(&[dict.as_bytes(), data], || {
+            let encrypted;
+            let data = match encryption {
+                Some(encryption) => {
+                    encrypted = encryption.encrypt(data)?;
+                    &encrypted[..]
+                }
+                None => data,
+            };
 
-        // Add text content streams
-        all_content_streams.extend_from_slice(current_page_objs);
+            let mut buf = Vec::new();
+            if dict.is_empty() {
+                writeln!(buf, "<< /Length {} >>", data.len())?;
+            } else {
+                writeln!(buf, "<< {} /Length {} >>", dict, data.len())?;
+            }
+            writeln!(buf, "stream")?;
+            buf.extend_from_slice(data);
+            write!(buf, "\nendstream")?;
+            Ok(buf)
+        })
+    }
 
-        // Add image content streams and build resource dictionary
-        let mut xobject_entries = Vec::new();
-        for (image_obj_id, content_stream_id) in current_page_img_objs {
-            all_content_streams.push(*content_stream_id);
+    /// writes a page drawn by the content stream `content`, None when it is blank
+    pub fn new_page_obj(&mut self, content: Option<u32>) -> Result<u32> {
+        let contents = content.map_or(String::new(), |id| format!("{} 0 R", id));
 
-            // Use image_obj_id as the resource name suffix for uniqueness
-            let resource_name = format!("Im{}", image_obj_id);
-            xobject_entries.push((resource_name, *image_obj_id));
+        let mut annots = std::mem::take(&mut self.page_annots);
+        if let Some(signature) = &self.signature
+            && self.page_ids.is_empty()
+        {
+            annots.insert(0, signature.field_id);
         }
+        let annots = if annots.is_empty() {
+            String::new()
+        } else {
+            let refs = annots
+                .iter()
+                .map(|id| format!("{} 0 R", id))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("/Annots [{}] ", refs)
+        };
 
-        // Build Contents array (all content streams)
-        let contents = all_content_streams
-            .iter()
-            .map(|id| format!("{} 0 R", id))
-            .collect::<Vec<_>>()
-            .join(" ");
+        let rotate = self.page_rotation();
+        let rotate = if rotate == 0 {
+            String::new()
+        } else {
+            format!("/Rotate {} ", rotate)
+        };
 
-        // Build Resources dictionary if we have images
-        let resources = if !xobject_entries.is_empty() {
-            let mut xobject_dict = String::from("<< ");
-            for (resource_name, img_obj_id) in xobject_entries {
-                xobject_dict.push_str(&format!("/{} {} 0 R ", resource_name, img_obj_id));
+        // for print the page is trimmed to its size from a bleed around it
+        let (width, height) = (num(self.setup.width), num(self.setup.height));
+        let boxes = match &self.print {
+            Some(print) => {
+                let bleed = format!(
+                    "[{} {} {} {}]",
+                    num(-print.bleed),
+                    num(-print.bleed),
+                    num(self.setup.width + print.bleed),
+                    num(self.setup.height + print.bleed)
+                );
+                format!(
+                    "/MediaBox {} /BleedBox {} /TrimBox [0 0 {} {}]",
+                    bleed, bleed, width, height
+                )
             }
-            xobject_dict.push_str(">>");
-
-            format!("/Resources << /XObject {} >>", xobject_dict)
-        } else {
-            String::new()
+            None => format!("/MediaBox [0 0 {} {}]", width, height),
         };
 
         // Create the page object
         let page_dict = format!(
             "<< /Type /Page \
            /Parent {} 0 R \
-           /MediaBox [0 0 595 842] \
            {} \
-           /Contents [{}] >>",
-            self.pages_id, resources, contents
+           /Resources {} 0 R \
+           {}{}/Contents [{}] >>",
+            self.pages_id, boxes, self.resources_id, rotate, annots, contents
         );
 
         let reader = Cursor::new(page_dict.into_bytes());
@@ -174,11 +712,756 @@ where
         self.writer
             .write_object_with_reserved_id(self.pages_id, &mut writer)?;
 
-        // 2️⃣ Create the /Catalog object pointing to /Pages
-        let catalog_dict = format!("<< /Type /Catalog /Pages {} 0 R >>", self.pages_id);
+        self.write_resources()?;
+        let outline_id = self.write_outline()?;
+
+        // encryption and PDF/X need an /ID, otherwise it is only written when fixed
+        let file_id = match self.file_id {
+            Some(id) => Some(id),
+            None if self.encryption.is_some() || self.print.is_some() => {
+                Some(random_bytes::<16>()?)
+            }
+            None => None,
+        };
+
+        // 2️⃣ Write /Info and the XMP metadata stream
+        let (info_id, metadata_id) = match self.metadata.take() {
+            Some(props) => (
+                Some(self.write_info(&props)?),
+                Some(self.write_xmp(&props, file_id)?),
+            ),
+            None => (None, None),
+        };
+
+        // 3️⃣ The /Encrypt dictionary itself is never encrypted
+        let mut catalog_extra = String::new();
+        if let Some(encryption) = &self.encryption {
+            let dict = encryption.dictionary();
+            let encrypt_id = self.writer.write_object(Cursor::new(dict.into_bytes()))?;
+            self.writer
+                .add_trailer_entry(format!("/Encrypt {} 0 R", encrypt_id));
+            // AES-256 is an extension to PDF 1.7 and part of 2.0
+            if self.version < PdfVersion::V2_0 {
+                catalog_extra
+                    .push_str(" /Extensions << /ADBE << /BaseVersion /1.7 /ExtensionLevel 8 >> >>");
+            }
+        }
+        if let Some(file_id) = file_id {
+            let file_id = to_hex(&file_id);
+            self.writer
+                .add_trailer_entry(format!("/ID [<{}> <{}>]", file_id, file_id));
+        }
+
+        if !self.page_labels.is_empty() {
+            use std::fmt::Write as _;
+            let mut nums = String::new();
+            for label in &self.page_labels {
+                let style = match label.style {
+                    PageLabelStyle::Decimal => "D",
+                    PageLabelStyle::UpperRoman => "R",
+                    PageLabelStyle::LowerRoman => "r",
+                    PageLabelStyle::UpperLetter => "A",
+                    PageLabelStyle::LowerLetter => "a",
+                };
+                let _ = write!(
+                    nums,
+                    "{} << /S /{} /St {} >> ",
+                    label.first_page, style, label.start
+                );
+            }
+            let _ = write!(catalog_extra, " /PageLabels << /Nums [{}] >>", nums);
+        }
+
+        if !self.attachments.is_empty() {
+            // name tree keys have to be sorted
+            self.attachments.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut names = String::new();
+            for (name, id) in &self.attachments {
+                names.push_str(&format!("{} {} 0 R ", self.text_string(name)?, id));
+            }
+            let ids = self
+                .attachments
+                .iter()
+                .fold(String::new(), |mut acc, (_, id)| {
+                    use std::fmt::Write as _;
+                    let _ = write!(acc, "{} 0 R ", id);
+                    acc
+                });
+            catalog_extra.push_str(&format!(
+                " /Names << /EmbeddedFiles << /Names [{}] >> >>",
+                names
+            ));
+            if self.version.supports(Feature::AssociatedFiles) {
+                catalog_extra.push_str(&format!(" /AF [{}]", ids));
+            }
+        }
+
+        let mut fields = std::mem::take(&mut self.form_fields);
+        let signed = match self.signature.take() {
+            Some(signature) => {
+                fields.insert(0, self.write_signature_field(&signature)?);
+                true
+            }
+            None => false,
+        };
+        if !fields.is_empty() {
+            let fields = fields
+                .iter()
+                .map(|id| format!("{} 0 R", id))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let resources = match self.field_font_id {
+                Some(id) => format!(
+                    " /DA {} /DR << /Font << /{} {} 0 R >> >>",
+                    self.text_string(&pdf_form::default_appearance(0.0))?,
+                    FIELD_FONT,
+                    id
+                ),
+                None => String::new(),
+            };
+            let sig_flags = if signed { " /SigFlags 3" } else { "" };
+            catalog_extra.push_str(&format!(
+                " /AcroForm << /Fields [{}]{}{} >>",
+                fields, resources, sig_flags
+            ));
+        }
+
+        if let Some(print) = self.print.clone() {
+            let (profile, components) = print.profile()?;
+            let profile_id = self.write_flate_stream(&format!("/N {}", components), &profile)?;
+            let condition = self.text_string(&print.output_condition)?;
+            catalog_extra.push_str(&format!(
+                " /OutputIntents [<< /Type /OutputIntent /S /GTS_PDFX \
+                 /OutputConditionIdentifier {} /Info {} /RegistryName {} \
+                 /DestOutputProfile {} 0 R >>]",
+                condition,
+                condition,
+                self.text_string("http://www.color.org")?,
+                profile_id
+            ));
+        }
+
+        if let Some(id) = outline_id {
+            catalog_extra.push_str(&format!(" /Outlines {} 0 R /PageMode /UseOutlines", id));
+        }
+        catalog_extra.push_str(&self.viewer_entries());
+
+        // 4️⃣ Create the /Catalog object pointing to /Pages
+        if let Some(id) = metadata_id {
+            catalog_extra.push_str(&format!(" /Metadata {} 0 R", id));
+        }
+        let catalog_dict = format!(
+            "<< /Type /Catalog /Pages {} 0 R{} >>",
+            self.pages_id, catalog_extra
+        );
         let reader = Cursor::new(catalog_dict.into_bytes());
         let catalog_id = self.writer.write_object(reader)?;
 
-        self.writer.finish(catalog_id)
+        self.writer.finish(catalog_id, info_id)?;
+        Ok(())
+    }
+
+    /// Writes the signature dictionary and its field, returning the field id
+    fn write_signature_field(&mut self, signature: &SignatureField) -> Result<u32> {
+        // /Contents is never encrypted, the signer writes raw PKCS#7 into it
+        let mut byte_range = None;
+        let mut contents = (0, 0);
+        let mut writer = |obj: &mut W| -> Result<()> {
+            write!(
+                obj,
+                "<< /Type /Sig /Filter /Adobe.PPKLite /SubFilter /adbe.pkcs7.detached /ByteRange ["
+            )?;
+            // four ten digit offsets and the spaces between them
+            byte_range = Some(Fixup::write(obj, 43)?);
+            write!(obj, "] /Contents ")?;
+            contents.0 = obj.stream_position()?;
+            write!(obj, "<{}>", "0".repeat(SIGNATURE_CONTENTS_SIZE * 2))?;
+            contents.1 = obj.stream_position()?;
+            write!(obj, " >>")?;
+            Ok(())
+        };
+        let sig_id = self.writer.write_object_with(&mut writer)?;
+
+        // /ByteRange covers everything but the /Contents hex string
+        if let Some(byte_range) = byte_range {
+            let (start, end) = contents;
+            self.writer.fill_on_finish(byte_range, move |file_len| {
+                format!("{:010} {:010} {:010} {:010}", 0, start, end, file_len - end)
+            });
+        }
+
+        // a merged field and widget, invisible but printed with the page
+        let page = self
+            .page_ids
+            .first()
+            .map(|id| format!(" /P {} 0 R", id))
+            .unwrap_or_default();
+        let field = format!(
+            "<< /Type /Annot /Subtype /Widget /FT /Sig /T {} /Rect [0 0 0 0] /F 132{} /V {} 0 R >>",
+            self.text_string(&signature.name)?,
+            page,
+            sig_id
+        );
+        let mut writer = |obj: &mut W| -> Result<()> {
+            obj.write_all(field.as_bytes())?;
+            Ok(())
+        };
+        self.writer
+            .write_object_with_reserved_id(signature.field_id, &mut writer)?;
+
+        Ok(signature.field_id)
+    }
+
+    /// Writes the outline root and its entries, None when there are none
+    fn write_outline(&mut self) -> Result<Option<u32>> {
+        let outline = std::mem::take(&mut self.outline);
+        let Some(last) = outline.len().checked_sub(1) else {
+            return Ok(None);
+        };
+        let root_id = self.writer.reserve_object()?;
+        let ids = outline
+            .iter()
+            .map(|_| self.writer.reserve_object())
+            .collect::<Result<Vec<_>>>()?;
+
+        let root = format!(
+            "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+            ids[0],
+            ids[last],
+            outline.len()
+        );
+        let mut writer = |obj: &mut W| -> Result<()> {
+            obj.write_all(root.as_bytes())?;
+            Ok(())
+        };
+        self.writer
+            .write_object_with_reserved_id(root_id, &mut writer)?;
+
+        for (i, (title, page)) in outline.iter().enumerate() {
+            let mut item = format!(
+                "<< /Title {} /Parent {} 0 R /Dest [{} 0 R /Fit]",
+                self.text_string(title)?,
+                root_id,
+                self.page_ids[*page]
+            );
+            if i > 0 {
+                item.push_str(&format!(" /Prev {} 0 R", ids[i - 1]));
+            }
+            if i < last {
+                item.push_str(&format!(" /Next {} 0 R", ids[i + 1]));
+            }
+            item.push_str(" >>");
+            let mut writer = |obj: &mut W| -> Result<()> {
+                obj.write_all(item.as_bytes())?;
+                Ok(())
+            };
+            self.writer
+                .write_object_with_reserved_id(ids[i], &mut writer)?;
+        }
+        Ok(Some(root_id))
+    }
+
+    /// Catalog entries for the viewer options, empty when none are set
+    fn viewer_entries(&self) -> String {
+        let viewer = &self.viewer;
+        let mut entries = String::new();
+
+        let mut prefs = String::new();
+        if viewer.fit_window {
+            prefs.push_str(" /FitWindow true");
+        }
+        if viewer.hide_toolbar {
+            prefs.push_str(" /HideToolbar true");
+        }
+        if !prefs.is_empty() {
+            entries.push_str(&format!(" /ViewerPreferences <<{} >>", prefs));
+        }
+        if viewer.two_page {
+            entries.push_str(" /PageLayout /TwoColumnLeft");
+        }
+
+        if viewer.open_page.is_some() || viewer.zoom.is_some() {
+            // out of range pages fall back to the last one
+            let index = viewer.open_page.unwrap_or(1).saturating_sub(1);
+            if let Some(page_id) = self.page_ids.get(index).or(self.page_ids.last()) {
+                let view = match viewer.zoom {
+                    Some(Zoom::FitPage) => "/Fit".to_string(),
+                    Some(Zoom::FitWidth) => "/FitH null".to_string(),
+                    Some(Zoom::Percent(percent)) => {
+                        format!("/XYZ null null {}", num(percent / 100.0))
+                    }
+                    None => "/XYZ null null null".to_string(),
+                };
+                entries.push_str(&format!(" /OpenAction [{} 0 R {}]", page_id, view));
+            }
+        }
+        entries
+    }
+
+    /// The /Resources dictionary every page points at, images are named
+    /// after the page and their place on it so the names never clash
+    fn write_resources(&mut self) -> Result<()> {
+        let xobjects = self
+            .xobjects
+            .iter()
+            .fold(String::new(), |mut acc, (name, id)| {
+                use std::fmt::Write as _;
+                let _ = write!(acc, "/{} {} 0 R ", name, id);
+                acc
+            });
+        let mut fonts = String::new();
+        for (i, font) in self.fonts.clone().iter().enumerate() {
+            let font_id = match font {
+                // one of the standard 14 fonts, so nothing needs embedding
+                PdfFont::Standard(font) => {
+                    let dict = format!(
+                        "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding \
+                         /ToUnicode {} 0 R >>",
+                        font.base_font(),
+                        self.write_to_unicode(&[])?
+                    );
+                    self.writer.write_object(Cursor::new(dict.into_bytes()))?
+                }
+                PdfFont::TrueType(font) => self.write_truetype_font(font)?,
+            };
+            fonts.push_str(&format!("/F{} {} 0 R ", i + 1, font_id));
+        }
+        let resources = format!("<< /Font << {}>> /XObject << {}>> >>", fonts, xobjects);
+        let mut writer = |obj: &mut W| -> Result<()> {
+            obj.write_all(resources.as_bytes())?;
+            Ok(())
+        };
+        self.writer
+            .write_object_with_reserved_id(self.resources_id, &mut writer)
+    }
+
+    /// the whole font program, its descriptor and a simple font dictionary over WinAnsi
+    fn write_truetype_font(&mut self, font: &TrueTypeFont) -> Result<u32> {
+        let file_id =
+            self.write_flate_stream(&format!("/Length1 {}", font.data.len()), &font.data)?;
+        // nonsymbolic, plus fixed pitch and italic where they apply
+        let flags = 32 | font.monospaced as u32 | (font.italic as u32) << 6;
+        let [x_min, y_min, x_max, y_max] = font.bbox;
+        let descriptor = format!(
+            "<< /Type /FontDescriptor /FontName /{} /Flags {} /FontBBox [{} {} {} {}] \
+             /ItalicAngle {} /Ascent {} /Descent {} /CapHeight {} /StemV 80 /FontFile2 {} 0 R >>",
+            font.name,
+            flags,
+            x_min,
+            y_min,
+            x_max,
+            y_max,
+            num(font.italic_angle),
+            font.ascent,
+            font.descent,
+            font.cap_height,
+            file_id
+        );
+        let descriptor_id = self
+            .writer
+            .write_object(Cursor::new(descriptor.into_bytes()))?;
+        let widths = font
+            .widths
+            .iter()
+            .map(u16::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        // ligatures take codes WinAnsiEncoding leaves undefined, named so
+        // viewers find their glyphs through the font's Unicode cmap
+        let encoding = match font.ligatures.is_empty() {
+            true => "/WinAnsiEncoding".to_string(),
+            false => {
+                let differences: Vec<String> = font
+                    .ligatures
+                    .iter()
+                    .map(|&i| format!("{} /{}", LIGATURES[i].3, LIGATURES[i].2))
+                    .collect();
+                format!(
+                    "<< /Type /Encoding /BaseEncoding /WinAnsiEncoding /Differences [{}] >>",
+                    differences.join(" ")
+                )
+            }
+        };
+        let dict = format!(
+            "<< /Type /Font /Subtype /TrueType /BaseFont /{} /FirstChar 32 /LastChar 255 \
+             /Widths [{}] /Encoding {} /FontDescriptor {} 0 R /ToUnicode {} 0 R >>",
+            font.name,
+            widths,
+            encoding,
+            descriptor_id,
+            self.write_to_unicode(&font.ligatures)?
+        );
+        self.writer.write_object(Cursor::new(dict.into_bytes()))
+    }
+
+    /// The /ToUnicode CMap of a font set in WinAnsiEncoding with `ligatures`,
+    /// so text can be searched and copied. Fonts without ligatures share one
+    fn write_to_unicode(&mut self, ligatures: &[usize]) -> Result<u32> {
+        if ligatures.is_empty()
+            && let Some(id) = self.to_unicode_id
+        {
+            return Ok(id);
+        }
+        let id = self.write_flate_stream("", to_unicode_cmap(ligatures).as_bytes())?;
+        if ligatures.is_empty() {
+            self.to_unicode_id = Some(id);
+        }
+        Ok(id)
     }
+
+    fn write_info(&mut self, props: &DocProps) -> Result<u32> {
+        let mut info = format!("<< /Producer {}", self.text_string("docx2pdf_rs")?);
+        let entries = [
+            ("Title", &props.title),
+            ("Author", &props.author),
+            ("Subject", &props.subject),
+            ("Keywords", &props.keywords),
+            ("Creator", &props.application),
+        ];
+        for (key, value) in entries {
+            if let Some(value) = value {
+                info.push_str(&format!(" /{} {}", key, self.text_string(value)?));
+            }
+        }
+        let dates = [
+            ("CreationDate", &props.created),
+            ("ModDate", &props.modified),
+        ];
+        for (key, value) in dates {
+            if let Some(date) = value.as_deref().and_then(pdf_date) {
+                info.push_str(&format!(" /{} {}", key, self.text_string(&date)?));
+            }
+        }
+        if self.print.is_some() {
+            info.push_str(&format!(
+                " /Trapped /False /GTS_PDFXVersion {}",
+                self.text_string("PDF/X-4")?
+            ));
+        }
+        info.push_str(" >>");
+
+        self.writer.write_object(Cursor::new(info.into_bytes()))
+    }
+
+    fn write_xmp(&mut self, props: &DocProps, file_id: Option<[u8; 16]>) -> Result<u32> {
+        let mut fields = String::new();
+        if let Some(title) = &props.title {
+            fields.push_str(&format!(
+                "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+                xml_escape(title)
+            ));
+        }
+        if let Some(author) = &props.author {
+            fields.push_str(&format!(
+                "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+                xml_escape(author)
+            ));
+        }
+        if let Some(subject) = &props.subject {
+            fields.push_str(&format!(
+                "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+                xml_escape(subject)
+            ));
+        }
+        if let Some(keywords) = &props.keywords {
+            fields.push_str(&format!(
+                "<pdf:Keywords>{}</pdf:Keywords>\n",
+                xml_escape(keywords)
+            ));
+        }
+        if let Some(application) = &props.application {
+            fields.push_str(&format!(
+                "<xmp:CreatorTool>{}</xmp:CreatorTool>\n",
+                xml_escape(application)
+            ));
+        }
+        if let Some(created) = &props.created {
+            fields.push_str(&format!(
+                "<xmp:CreateDate>{}</xmp:CreateDate>\n",
+                xml_escape(created)
+            ));
+        }
+        if let Some(modified) = &props.modified {
+            fields.push_str(&format!(
+                "<xmp:ModifyDate>{}</xmp:ModifyDate>\n",
+                xml_escape(modified)
+            ));
+        }
+
+        let mut namespaces = "";
+        if self.print.is_some() {
+            namespaces = " xmlns:xmpMM=\"http://ns.adobe.com/xap/1.0/mm/\" \
+                          xmlns:pdfxid=\"http://www.npes.org/pdfx/ns/id/\"";
+            fields.push_str(
+                "<pdf:Trapped>False</pdf:Trapped>\n\
+                 <pdfxid:GTS_PDFXVersion>PDF/X-4</pdfxid:GTS_PDFXVersion>\n",
+            );
+            if let Some(file_id) = file_id {
+                fields.push_str(&format!(
+                    "<xmpMM:DocumentID>uuid:{}</xmpMM:DocumentID>\n",
+                    uuid(&file_id)
+                ));
+            }
+            fields.push_str(
+                "<xmpMM:VersionID>1</xmpMM:VersionID>\n\
+                 <xmpMM:RenditionClass>default</xmpMM:RenditionClass>\n",
+            );
+        }
+
+        let xmp = format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             <rdf:Description rdf:about=\"\" \
+             xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+             xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\" \
+             xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"{}>\n\
+             <pdf:Producer>docx2pdf_rs</pdf:Producer>\n\
+             {}</rdf:Description>\n\
+             </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>",
+            namespaces, fields
+        );
+
+        self.write_stream("/Type /Metadata /Subtype /XML", xmp.as_bytes())
+    }
+
+    /// a text string operand, encrypted when the document is
+    fn text_string(&self, text: &str) -> Result<String> {
+        match &self.encryption {
+            Some(encryption) => encryption.encrypt_string(&text_string_bytes(text)),
+            None => Ok(pdf_text_string(text)),
+        }
+    }
+}
+
+/// A content stream serialized and compressed, waiting for its object id
+pub struct PreparedStream {
+    dict: &'static str,
+    data: Vec<u8>,
+}
+
+/// A line of text to show
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLine {
+    pub text: String,
+    /// how far right of the others' x it starts, in points
+    pub offset: f32,
+    /// added to every space, in points, to justify it
+    pub word_spacing: f32,
+}
+
+impl From<String> for TextLine {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            ..Self::default()
+        }
+    }
+}
+
+/// Operators showing `lines` in `font`, /F`index + 1`, one below the other
+/// starting with the first baseline at (x, y)
+pub fn text_operators(
+    setup: &PageSetup,
+    lines: &[TextLine],
+    (index, font): (usize, &PdfFont),
+    x: f32,
+    y: f32,
+) -> String {
+    let first = lines.first().map_or(0.0, |line| line.offset);
+    let mut content = format!(
+        "BT /F{} {} Tf {} TL {} {} Td",
+        index + 1,
+        num(setup.font_size),
+        num(setup.line_height()),
+        num(x + first),
+        num(y)
+    );
+    let (mut offset, mut word_spacing) = (first, 0.0);
+    for (i, line) in lines.iter().enumerate() {
+        // T* moves down by the leading set with TL, Td from where the line
+        // above started
+        if i > 0 && line.offset == offset {
+            content.push_str(" T*");
+        } else if i > 0 {
+            content.push_str(&format!(
+                " {} {} Td",
+                num(line.offset - offset),
+                num(-setup.line_height())
+            ));
+            offset = line.offset;
+        }
+        // Tw stretches the single byte space of simple fonts
+        if line.word_spacing != word_spacing {
+            word_spacing = line.word_spacing;
+            content.push_str(&format!(" {} Tw", num(word_spacing)));
+        }
+        content.push(' ');
+        content.push_str(&show_operator(&font.shape(&line.text)));
+    }
+    // the word spacing is kept by the graphics state past ET
+    if word_spacing != 0.0 {
+        content.push_str(" 0 Tw");
+    }
+    content.push_str(" ET");
+    content
+}
+
+/// Operators drawing the image named `name` in the resources into `rect`,
+/// as x, y, width and height
+pub fn image_operators(name: &str, (x, y, width, height): (f32, f32, f32, f32)) -> String {
+    format!(
+        "q\n{} 0 0 {} {} {} cm\n/{} Do\nQ",
+        num(width),
+        num(height),
+        num(x),
+        num(y),
+        name
+    )
+}
+
+/// Tj showing `codes`, or TJ when some of them are kerned
+fn show_operator(codes: &[(u8, i16)]) -> String {
+    if codes.iter().all(|(_, kerning)| *kerning == 0) {
+        let bytes: Vec<u8> = codes.iter().map(|(b, _)| *b).collect();
+        return format!("{} Tj", string_operand(&bytes));
+    }
+    let mut content = String::from("[");
+    let mut run = Vec::new();
+    for &(b, kerning) in codes {
+        run.push(b);
+        if kerning != 0 {
+            // TJ numbers move the next glyph left, in 1/1000 em; widened
+            // first as i16::MIN has no positive counterpart
+            content.push_str(&string_operand(&std::mem::take(&mut run)));
+            content.push_str(&format!(" {} ", -i32::from(kerning)));
+        }
+    }
+    if !run.is_empty() {
+        content.push_str(&string_operand(&run));
+    }
+    content.push_str("] TJ");
+    content
+}
+
+/// Builds page content streams apart from the writer, so pages can be
+/// prepared on other threads while object ids are handed out in order
+///
+/// Encryption is left to `PdfDocument::write_prepared`, seeded IVs have to be
+/// drawn in the order the streams are written.
+#[derive(Clone, Copy)]
+pub struct ContentEncoder {
+    setup: PageSetup,
+    compress: bool,
+}
+
+impl ContentEncoder {
+    pub fn page_setup(&self) -> &PageSetup {
+        &self.setup
+    }
+
+    /// a content stream of ready made operators
+    pub fn content(&self, content: &str) -> Result<PreparedStream> {
+        if !self.compress {
+            return Ok(PreparedStream {
+                dict: "",
+                data: content.as_bytes().to_vec(),
+            });
+        }
+        Ok(PreparedStream {
+            dict: "/Filter /FlateDecode",
+            data: deflate(content.as_bytes())?,
+        })
+    }
+}
+
+/// A CMap taking WinAnsi codes to Unicode, the codes of `ligatures` to
+/// their letters
+fn to_unicode_cmap(ligatures: &[usize]) -> String {
+    use std::fmt::Write as _;
+    let utf16 = |text: &str| {
+        text.encode_utf16()
+            .map(|unit| format!("{:04X}", unit))
+            .collect::<String>()
+    };
+    let mut chars: Vec<(u8, String)> = (0x80..=0x9Fu8)
+        .filter_map(|code| Some((code, utf16(&win_ansi_char(code)?.to_string()))))
+        .collect();
+    chars.extend(
+        ligatures
+            .iter()
+            .map(|&i| (LIGATURES[i].3, utf16(LIGATURES[i].0))),
+    );
+    chars.sort();
+
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+         1 begincodespacerange\n<00> <FF>\nendcodespacerange\n\
+         2 beginbfrange\n<20> <7E> <0020>\n<A0> <FF> <00A0>\nendbfrange\n",
+    );
+    let _ = writeln!(cmap, "{} beginbfchar", chars.len());
+    for (code, unicode) in chars {
+        let _ = writeln!(cmap, "<{:02X}> <{}>", code, unicode);
+    }
+    cmap.push_str("endbfchar\nendcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    cmap
+}
+
+/// `id` written as a UUID, 8-4-4-4-12 hex digits
+fn uuid(id: &[u8; 16]) -> String {
+    let hex = to_hex(id).to_lowercase();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Raw bytes of a PDF text string: PDFDocEncoding for ASCII, UTF-16BE with BOM otherwise
+fn text_string_bytes(text: &str) -> Vec<u8> {
+    if text.is_ascii() {
+        text.as_bytes().to_vec()
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+/// Formats a coordinate with at most two decimals and no trailing zeros
+pub(crate) fn num(value: f32) -> String {
+    let s = format!("{:.2}", value);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Encodes a PDF text string, as a literal when plain ASCII and as UTF-16BE hex otherwise
+fn pdf_text_string(text: &str) -> String {
+    let bytes = text_string_bytes(text);
+    if text.is_ascii() {
+        literal_string(&bytes)
+    } else {
+        hex_string(&bytes)
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
@@ -0,0 +1,276 @@
+use aes::cipher::{
+    block_padding::{NoPadding, Pkcs7},
+    BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit,
+};
+use aes::{Aes128, Aes256};
+use anyhow::Result;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+type Aes128CbcEnc = cbc::Encryptor<Aes128>;
+type Aes256CbcEnc = cbc::Encryptor<Aes256>;
+
+/// Every permission granted, bits 1-2 must stay clear
+pub const ALL_PERMISSIONS: i32 = -4;
+
+// individual /P bits, cleared to deny the operation
+pub const PERMIT_PRINT: i32 = 1 << 2;
+pub const PERMIT_MODIFY: i32 = 1 << 3;
+pub const PERMIT_COPY: i32 = 1 << 4;
+pub const PERMIT_ANNOTATE: i32 = 1 << 5;
+pub const PERMIT_FILL_FORMS: i32 = 1 << 8;
+pub const PERMIT_ASSEMBLE: i32 = 1 << 10;
+pub const PERMIT_PRINT_HIGH_QUALITY: i32 = 1 << 11;
+
+/// Standard security handler, revision 6 (AES-256, PDF 2.0)
+///
+/// A single random file key encrypts every string and stream,
+/// the passwords only wrap that key in /U /UE and /O /OE.
+pub struct PdfEncryption {
+    file_key: [u8; 32],
+    o: [u8; 48],
+    u: [u8; 48],
+    oe: [u8; 32],
+    ue: [u8; 32],
+    perms: [u8; 16],
+    permissions: i32,
+    entropy: Entropy,
+}
+
+impl PdfEncryption {
+    pub fn new(
+        user_password: &str,
+        owner_password: &str,
+        permissions: i32,
+        entropy: Entropy,
+    ) -> Result<Self> {
+        let user_password = truncate_password(user_password);
+        let owner_password = truncate_password(owner_password);
+
+        let file_key: [u8; 32] = entropy.bytes()?;
+
+        // /U = hash || validation salt || key salt
+        let user_salts: [u8; 16] = entropy.bytes()?;
+        let mut u = [0u8; 48];
+        u[..32].copy_from_slice(&hash_2b(user_password, &user_salts[..8], &[]));
+        u[32..].copy_from_slice(&user_salts);
+        let ue = wrap_key(&hash_2b(user_password, &user_salts[8..], &[]), &file_key);
+
+        // /O is computed the same way but salted with the full /U string
+        let owner_salts: [u8; 16] = entropy.bytes()?;
+        let mut o = [0u8; 48];
+        o[..32].copy_from_slice(&hash_2b(owner_password, &owner_salts[..8], &u));
+        o[32..].copy_from_slice(&owner_salts);
+        let oe = wrap_key(&hash_2b(owner_password, &owner_salts[8..], &u), &file_key);
+
+        // /Perms = P (little endian) || 0xFFFFFFFF || 'T' || "adb" || random
+        let mut perms = [0u8; 16];
+        perms[..4].copy_from_slice(&permissions.to_le_bytes());
+        perms[4..8].copy_from_slice(&[0xFF; 4]);
+        perms[8..12].copy_from_slice(b"Tadb");
+        perms[12..].copy_from_slice(&entropy.bytes::<4>()?);
+        let cipher = Aes256::new(&file_key.into());
+        cipher.encrypt_block((&mut perms).into());
+
+        Ok(Self {
+            file_key,
+            o,
+            u,
+            oe,
+            ue,
+            perms,
+            permissions,
+            entropy,
+        })
+    }
+
+    /// AES-256-CBC with a random IV prepended, used for both strings and streams
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let iv: [u8; 16] = self.entropy.bytes()?;
+        let cipher = Aes256CbcEnc::new(&self.file_key.into(), &iv.into());
+        let mut out = iv.to_vec();
+        out.extend(cipher.encrypt_padded_vec_mut::<Pkcs7>(data));
+        Ok(out)
+    }
+
+    /// Encrypts and serializes a string operand as a hex string
+    pub fn encrypt_string(&self, data: &[u8]) -> Result<String> {
+        Ok(format!("<{}>", to_hex(&self.encrypt(data)?)))
+    }
+
+    /// The /Encrypt dictionary body, written unencrypted
+    pub fn dictionary(&self) -> String {
+        format!(
+            "<< /Filter /Standard /V 5 /R 6 /Length 256 \
+           /CF << /StdCF << /Type /CryptFilter /CFM /AESV3 /AuthEvent /DocOpen /Length 32 >> >> \
+           /StmF /StdCF /StrF /StdCF \
+           /O <{}> /U <{}> /OE <{}> /UE <{}> /Perms <{}> \
+           /P {} /EncryptMetadata true >>",
+            to_hex(&self.o),
+            to_hex(&self.u),
+            to_hex(&self.oe),
+            to_hex(&self.ue),
+            to_hex(&self.perms),
+            self.permissions
+        )
+    }
+}
+
+/// Where keys, salts and IVs come from
+///
+/// Seeded output is reproducible, so anyone who knows the seed can also
+/// derive the file key, the passwords then only gate well-behaved viewers.
+pub struct Entropy {
+    seed: Option<[u8; 32]>,
+    counter: AtomicU64,
+}
+
+impl Entropy {
+    pub fn os() -> Self {
+        Self {
+            seed: None,
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    pub fn seeded(seed: [u8; 32]) -> Self {
+        Self {
+            seed: Some(seed),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// OS randomness, or SHA-256(seed || counter) blocks when seeded
+    pub fn bytes<const N: usize>(&self) -> Result<[u8; N]> {
+        let Some(seed) = &self.seed else {
+            return random_bytes();
+        };
+        let mut buf = [0u8; N];
+        for chunk in buf.chunks_mut(32) {
+            let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+            let block = Sha256::new()
+                .chain_update(seed)
+                .chain_update(counter.to_be_bytes())
+                .finalize();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        Ok(buf)
+    }
+}
+
+pub fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    getrandom::fill(&mut buf).map_err(|e| anyhow::anyhow!("random source failed: {}", e))?;
+    Ok(buf)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Passwords are UTF-8 and limited to 127 bytes
+fn truncate_password(password: &str) -> &[u8] {
+    let bytes = password.as_bytes();
+    &bytes[..bytes.len().min(127)]
+}
+
+/// AES-256-CBC with a zero IV and no padding, for wrapping the 32 byte file key
+fn wrap_key(key: &[u8; 32], file_key: &[u8; 32]) -> [u8; 32] {
+    let cipher = Aes256CbcEnc::new(key.into(), &[0u8; 16].into());
+    let mut out = *file_key;
+    cipher
+        .encrypt_padded_mut::<NoPadding>(&mut out, 32)
+        .expect("32 bytes is block aligned");
+    out
+}
+
+/// Algorithm 2.B from ISO 32000-2, the iterated hash behind /U and /O
+fn hash_2b(password: &[u8], salt: &[u8], udata: &[u8]) -> [u8; 32] {
+    let mut k: Vec<u8> = Sha256::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(udata)
+        .finalize()
+        .to_vec();
+
+    let mut round = 0u32;
+    loop {
+        let mut k1 = Vec::with_capacity(64 * (password.len() + k.len() + udata.len()));
+        for _ in 0..64 {
+            k1.extend_from_slice(password);
+            k1.extend_from_slice(&k);
+            k1.extend_from_slice(udata);
+        }
+
+        let key: [u8; 16] = k[..16].try_into().expect("hash is at least 32 bytes");
+        let iv: [u8; 16] = k[16..32].try_into().expect("hash is at least 32 bytes");
+        let len = k1.len();
+        let e = Aes128CbcEnc::new(&key.into(), &iv.into())
+            .encrypt_padded_mut::<NoPadding>(&mut k1, len)
+            .expect("k1 is a multiple of 64 bytes")
+            .to_vec();
+
+        let selector: u32 = e[..16].iter().map(|&b| b as u32).sum::<u32>() % 3;
+        k = match selector {
+            0 => Sha256::digest(&e).to_vec(),
+            1 => Sha384::digest(&e).to_vec(),
+            _ => Sha512::digest(&e).to_vec(),
+        };
+
+        round += 1;
+        let last = *e.last().expect("e is never empty") as u32;
+        if round >= 64 && last + 32 <= round {
+            break;
+        }
+    }
+
+    k[..32].try_into().expect("hash is at least 32 bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_2b, to_hex, Entropy, PdfEncryption};
+    use aes::cipher::{BlockDecrypt, KeyInit};
+    use aes::Aes256;
+
+    #[test]
+    fn hashes_like_algorithm_2b() {
+        let salt: Vec<u8> = (1..=8).collect();
+        let udata: Vec<u8> = (0..48).collect();
+        assert_eq!(
+            to_hex(&hash_2b(b"password", &salt, &[])),
+            "22D08D1860CB92EDCADDA1451A4AEBB49C1873722BBFCA2AEF1A7E5F51E69935"
+        );
+        assert_eq!(
+            to_hex(&hash_2b(b"password", &salt, &udata)),
+            "A6353138E2E62BF540145022A4C210DDD44C8CBA248BB6063BC7A4C923540E67"
+        );
+    }
+
+    #[test]
+    fn writes_the_r6_dictionary() {
+        let encryption =
+            PdfEncryption::new("user", "owner", -3904, Entropy::seeded([7; 32])).unwrap();
+        let dictionary = encryption.dictionary();
+        for entry in [
+            "/O <135DDF260FE7E56B1A05AEE94786E2BB6D912AC96C4DFEC046A6A2F3AC353A9A5922329E0C5C73062B0F9D60DA4AE148>",
+            "/U <4E81AEF547308127920F945F1FA02B09C55E89BFDE1AA6A2E15F2ED6AD6704B5F222A20B21927D88E72377031F01EFE0>",
+            "/OE <FD8F1471CD31D443B3D4CDFB14387115182A417ED09D7F2ADAD1A13C5F65EBC7>",
+            "/UE <B683DCBF291B8BAD74E226214A3D70A99E88151EC639E3AD909FDC21D2AA4802>",
+            "/Perms <785C8B1981EFC27C802A90C7337AAC69>",
+            "/P -3904 ",
+        ] {
+            assert!(dictionary.contains(entry), "{} not in {}", entry, dictionary);
+        }
+    }
+
+    #[test]
+    fn perms_decrypt_to_the_permissions() {
+        let encryption = PdfEncryption::new("", "owner", -3904, Entropy::seeded([1; 32])).unwrap();
+        let mut perms = encryption.perms;
+        Aes256::new(&encryption.file_key.into()).decrypt_block((&mut perms).into());
+        assert_eq!(perms[..4], (-3904i32).to_le_bytes());
+        assert_eq!(perms[4..8], [0xFF; 4]);
+        assert_eq!(&perms[8..12], b"Tadb");
+    }
+}
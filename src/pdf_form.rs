@@ -0,0 +1,94 @@
+use super::pdf_document::num;
+use super::pdf_string::{string_operand, win_ansi_bytes};
+
+/// Name of the font in the AcroForm /DR the fields are set in, Helvetica
+pub const FIELD_FONT: &str = "Helv";
+
+/// A fillable field merged with the widget that shows it on its page
+#[derive(Clone, Debug)]
+pub struct FormWidget {
+    pub name: String,
+    pub kind: WidgetKind,
+    /// x, y of the bottom left corner, width, height
+    pub rect: (f32, f32, f32, f32),
+    pub font_size: f32,
+}
+
+#[derive(Clone, Debug)]
+pub enum WidgetKind {
+    Text {
+        value: String,
+        default: String,
+        max_length: Option<u32>,
+    },
+    CheckBox {
+        checked: bool,
+        default: bool,
+    },
+    /// a drop-down, `editable` when other text can be typed in
+    Choice {
+        /// (display text, export value) of each entry
+        options: Vec<(String, String)>,
+        /// the export value selected, or the text typed in
+        value: String,
+        /// what the field shows for `value`
+        shown: String,
+        editable: bool,
+    },
+}
+
+/// /Ff bits of a choice field: a drop-down rather than a list, one with an edit box
+const COMBO: u32 = 1 << 17;
+const EDIT: u32 = 1 << 18;
+
+/// /Ff of a choice field
+pub fn choice_flags(editable: bool) -> u32 {
+    if editable {
+        COMBO | EDIT
+    } else {
+        COMBO
+    }
+}
+
+/// /DA of a field, the font and color a viewer sets typed text in
+pub fn default_appearance(font_size: f32) -> String {
+    format!("/{} {} Tf 0 g", FIELD_FONT, num(font_size))
+}
+
+/// Operators of a text field's normal appearance, `value` on a baseline a
+/// quarter of the font size above the bottom like the text around it
+pub fn text_appearance(value: &str, width: f32, height: f32, font_size: f32) -> String {
+    let mut content = String::from("/Tx BMC");
+    if !value.is_empty() {
+        content.push_str(&format!(
+            " q 1 1 {} {} re W n BT {} 2 {} Td {} Tj ET Q",
+            num(width - 2.0),
+            num(height - 2.0),
+            default_appearance(font_size),
+            num(font_size * 0.25),
+            string_operand(&win_ansi_bytes(value))
+        ));
+    }
+    content.push_str(" EMC");
+    content
+}
+
+/// Operators of a check box's appearance, a frame with a cross in it when
+/// `checked` like Word prints them
+pub fn check_box_appearance(checked: bool, size: f32) -> String {
+    let inset = size * 0.2;
+    let mut content = format!(
+        "q 0 G 0.5 w 0.25 0.25 {} {} re S",
+        num(size - 0.5),
+        num(size - 0.5)
+    );
+    if checked {
+        let (near, far) = (num(inset), num(size - inset));
+        content.push_str(&format!(
+            " 1 w {} {} m {} {} l S {} {} m {} {} l S",
+            near, near, far, far, near, far, far, near
+        ));
+    }
+    content.push_str(" Q");
+    content
+}
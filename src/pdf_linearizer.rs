@@ -0,0 +1,802 @@
+use super::pdf_encryption::PdfEncryption;
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Write};
+
+/// Post-pass that rewrites a finished PDF produced by `PdfStreamWriter` into a
+/// linearized ("fast web view") file, following Annex F of ISO 32000:
+///
+/// ```text
+/// header, linearization dict, first-page xref + trailer,
+/// catalog, primary hint stream, first page section,
+/// remaining pages, shared objects, other objects,
+/// main xref + trailer
+/// ```
+///
+/// Objects are renumbered so the first-page xref section covers everything up
+/// to the end of the first page. Encrypted input needs the `encryption` it
+/// was written with: the AES-256 file key does not depend on object numbers,
+/// so objects are copied as they are and only the hint stream is encrypted.
+pub fn linearize<R: Read, W: Write>(
+    mut input: R,
+    mut output: W,
+    encryption: Option<&PdfEncryption>,
+) -> Result<()> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+
+    let source = SourcePdf::parse(&data)?;
+    if source.trailer_ref(b"/Encrypt").is_some() != encryption.is_some() {
+        bail!("the encryption does not match the /Encrypt entry of the trailer");
+    }
+    let plan = Plan::new(&source)?;
+    let bytes = plan.write(&source, encryption)?;
+    output.write_all(&bytes)?;
+    Ok(())
+}
+
+/// One `N 0 obj ... endobj` from the source file
+struct SourceObject<'a> {
+    /// dictionary / body text, everything before `stream`
+    body: &'a [u8],
+    /// raw stream bytes between `stream\n` and `\nendstream`
+    stream: Option<&'a [u8]>,
+}
+
+struct SourcePdf<'a> {
+    header: &'a [u8],
+    objects: HashMap<u32, SourceObject<'a>>,
+    trailer: &'a [u8],
+}
+
+impl<'a> SourcePdf<'a> {
+    /// Scans objects sequentially instead of trusting the xref table
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let header_end = find(data, b"\n", 0).context("missing PDF header")? + 1;
+        let mut objects = HashMap::new();
+        let mut pos = header_end;
+
+        loop {
+            pos = skip_whitespace(data, pos);
+            if data[pos..].starts_with(b"xref") {
+                break;
+            }
+            let (id, body_start) = parse_object_header(data, pos)
+                .ok_or_else(|| anyhow!("unexpected data at offset {}", pos))?;
+
+            let (body, stream, end) = match find_stream_keyword(data, body_start) {
+                Some(stream_kw) => {
+                    let body = &data[body_start..stream_kw];
+                    let length = dict_int(body, b"/Length")
+                        .ok_or_else(|| anyhow!("stream object {} has no direct /Length", id))?;
+                    let mut start = stream_kw + b"stream".len();
+                    if data[start..].starts_with(b"\r\n") {
+                        start += 2;
+                    } else if data[start] == b'\n' {
+                        start += 1;
+                    }
+                    let end = start + length as usize;
+                    let endobj =
+                        find(data, b"endobj", end).context("unterminated stream object")?;
+                    (body, Some(&data[start..end]), endobj)
+                }
+                None => {
+                    let endobj =
+                        find(data, b"endobj", body_start).context("unterminated object")?;
+                    (&data[body_start..endobj], None, endobj)
+                }
+            };
+
+            objects.insert(
+                id,
+                SourceObject {
+                    body: trim(body),
+                    stream,
+                },
+            );
+            pos = end + b"endobj".len();
+        }
+
+        let trailer_at = rfind(data, b"trailer").context("missing trailer")?;
+        let trailer_end = find(data, b"startxref", trailer_at).context("missing startxref")?;
+
+        Ok(Self {
+            header: &data[..header_end],
+            objects,
+            trailer: &data[trailer_at + b"trailer".len()..trailer_end],
+        })
+    }
+
+    fn trailer_ref(&self, key: &[u8]) -> Option<u32> {
+        dict_ref(self.trailer, key)
+    }
+
+    fn object(&self, id: u32) -> Result<&SourceObject<'a>> {
+        self.objects
+            .get(&id)
+            .ok_or_else(|| anyhow!("object {} is referenced but missing", id))
+    }
+
+    /// Page object ids in document order
+    fn pages(&self, node: u32, out: &mut Vec<u32>) -> Result<()> {
+        let body = self.object(node)?.body;
+        if dict_name(body, b"/Type") == Some(&b"Pages"[..]) {
+            for kid in dict_ref_array(body, b"/Kids") {
+                self.pages(kid, out)?;
+            }
+        } else {
+            out.push(node);
+        }
+        Ok(())
+    }
+}
+
+/// Which part of the linearized file an object belongs to
+struct Plan {
+    catalog: u32,
+    first_page: Vec<u32>,
+    /// objects of pages 2..n, one group per page
+    other_pages: Vec<Vec<u32>>,
+    /// objects referenced by more than one page (but not the first)
+    shared: Vec<u32>,
+    /// shared object references per page (old ids)
+    page_shared_refs: Vec<Vec<u32>>,
+    rest: Vec<u32>,
+}
+
+impl Plan {
+    fn new(source: &SourcePdf) -> Result<Self> {
+        let catalog = source
+            .trailer_ref(b"/Root")
+            .context("trailer has no /Root")?;
+        let pages_root =
+            dict_ref(source.object(catalog)?.body, b"/Pages").context("catalog has no /Pages")?;
+        let mut pages = Vec::new();
+        source.pages(pages_root, &mut pages)?;
+        if pages.is_empty() {
+            bail!("cannot linearize a document without pages");
+        }
+
+        // everything reachable from each page, not following /Parent back up the tree
+        let page_set: BTreeSet<u32> = pages.iter().copied().collect();
+        let mut reachable: Vec<Vec<u32>> = Vec::new();
+        let mut users: HashMap<u32, usize> = HashMap::new();
+        for &page in &pages {
+            let mut seen = BTreeSet::new();
+            let mut order = Vec::new();
+            collect(source, page, &page_set, &mut seen, &mut order)?;
+            for &id in &order {
+                *users.entry(id).or_default() += 1;
+            }
+            reachable.push(order);
+        }
+
+        let mut placed = BTreeSet::new();
+        let first_page = reachable[0].clone();
+        placed.extend(first_page.iter().copied());
+
+        let mut other_pages = Vec::new();
+        let mut shared = Vec::new();
+        let mut page_shared_refs = vec![Vec::new()];
+        for order in &reachable[1..] {
+            let mut own = Vec::new();
+            let mut refs = Vec::new();
+            for &id in order {
+                if users[&id] > 1 {
+                    refs.push(id);
+                    if placed.insert(id) {
+                        shared.push(id);
+                    }
+                } else {
+                    placed.insert(id);
+                    own.push(id);
+                }
+            }
+            other_pages.push(own);
+            page_shared_refs.push(refs);
+        }
+
+        // catalog, page tree nodes, /Info, metadata ...
+        let mut ids: Vec<u32> = source.objects.keys().copied().collect();
+        ids.sort_unstable();
+        let rest = ids
+            .into_iter()
+            .filter(|id| *id != catalog && !placed.contains(id))
+            .collect();
+
+        Ok(Self {
+            catalog,
+            first_page,
+            other_pages,
+            shared,
+            page_shared_refs,
+            rest,
+        })
+    }
+
+    fn write(&self, source: &SourcePdf, encryption: Option<&PdfEncryption>) -> Result<Vec<u8>> {
+        // main xref section: 1..k-1 (remaining pages, shared, rest)
+        // first-page section: k.. (lin dict, catalog, hint stream, first page)
+        let mut renumber: HashMap<u32, u32> = HashMap::new();
+        let mut next = 1;
+        let main_order: Vec<u32> = self
+            .other_pages
+            .iter()
+            .flatten()
+            .chain(self.shared.iter())
+            .chain(self.rest.iter())
+            .copied()
+            .collect();
+        for &id in &main_order {
+            renumber.insert(id, next);
+            next += 1;
+        }
+        let main_count = next; // /Size of the main section, including object 0
+        let lin_id = next;
+        let catalog_id = next + 1;
+        let hint_id = next + 2;
+        next += 3;
+        for &id in &self.first_page {
+            renumber.insert(id, next);
+            next += 1;
+        }
+        renumber.insert(self.catalog, catalog_id);
+        let total = next;
+
+        let serialize = |id: u32| -> Result<Vec<u8>> {
+            let object = source.object(id)?;
+            let mut out = format!("{} 0 obj\n", renumber[&id]).into_bytes();
+            out.extend(rewrite_refs(object.body, &renumber)?);
+            if let Some(stream) = object.stream {
+                out.extend_from_slice(b"\nstream\n");
+                out.extend_from_slice(stream);
+                out.extend_from_slice(b"\nendstream");
+            }
+            out.extend_from_slice(b"\nendobj\n");
+            Ok(out)
+        };
+
+        let catalog_bytes = serialize(self.catalog)?;
+        let first_page_bytes = self
+            .first_page
+            .iter()
+            .map(|&id| serialize(id))
+            .collect::<Result<Vec<_>>>()?;
+        let main_bytes = main_order
+            .iter()
+            .map(|&id| serialize(id))
+            .collect::<Result<Vec<_>>>()?;
+
+        // fixed width placeholders so the layout can be computed before the values are known
+        let lin_dict = |l: usize, h: (usize, usize), e: usize, t: usize| {
+            format!(
+                "{} 0 obj\n<< /Linearized 1 /L {:010} /H [{:010} {:010}] /O {} /E {:010} /N {} /T {:010} >>\nendobj\n",
+                lin_id,
+                l,
+                h.0,
+                h.1,
+                renumber[&self.first_page[0]],
+                e,
+                self.other_pages.len() + 1,
+                t
+            )
+        };
+        let trailer_extra = [b"/Info".as_slice(), b"/Encrypt", b"/ID"]
+            .iter()
+            .filter_map(|key| dict_raw_value(source.trailer, key).map(|v| (key, v)))
+            .map(|(key, value)| {
+                let value = rewrite_refs(value, &renumber).unwrap_or_default();
+                format!(
+                    " {} {}",
+                    String::from_utf8_lossy(key),
+                    String::from_utf8_lossy(&value)
+                )
+            })
+            .collect::<String>();
+        let first_xref = |offsets: &[usize], prev: usize| {
+            let mut out = format!("xref\n{} {}\n", lin_id, total - lin_id);
+            for offset in offsets {
+                out.push_str(&format!("{:010} 00000 n \n", offset));
+            }
+            out.push_str(&format!(
+                "trailer\n<< /Size {} /Root {} 0 R{} /Prev {:010} >>\nstartxref\n0\n%%EOF\n",
+                total, catalog_id, trailer_extra, prev
+            ));
+            out
+        };
+
+        // pass 1: layout without the hint stream, which is what hint offsets refer to
+        let mut header = source.header.to_vec();
+        header.extend_from_slice(b"%\xE2\xE3\xCF\xD3\n");
+        let lin_len = lin_dict(0, (0, 0), 0, 0).len();
+        let first_xref_len = first_xref(&vec![0; (total - lin_id) as usize], 0).len();
+
+        let first_xref_at = header.len() + lin_len;
+        let catalog_at = first_xref_at + first_xref_len;
+        let hint_at = catalog_at + catalog_bytes.len();
+
+        let mut adjusted = Vec::new(); // offsets as if the hint stream was absent
+        let mut pos = hint_at;
+        for bytes in first_page_bytes.iter().chain(main_bytes.iter()) {
+            adjusted.push(pos);
+            pos += bytes.len();
+        }
+        let (hint_data, shared_at) = self.hint_tables(&adjusted, &first_page_bytes, &main_bytes);
+        // /S is an offset into the decrypted data
+        let hint_data = match encryption {
+            Some(encryption) => encryption.encrypt(&hint_data)?,
+            None => hint_data,
+        };
+        let hint_bytes = {
+            let mut out = format!(
+                "{} 0 obj\n<< /S {} /Length {} >>\nstream\n",
+                hint_id,
+                shared_at,
+                hint_data.len()
+            )
+            .into_bytes();
+            out.extend_from_slice(&hint_data);
+            out.extend_from_slice(b"\nendstream\nendobj\n");
+            out
+        };
+
+        // pass 2: real offsets
+        let shift = hint_bytes.len();
+        let real: Vec<usize> = adjusted.iter().map(|offset| offset + shift).collect();
+        let first_count = first_page_bytes.len();
+        let end_of_first_page =
+            hint_at + shift + first_page_bytes.iter().map(Vec::len).sum::<usize>();
+        let main_xref_at = pos + shift;
+        let main_xref_head = format!("xref\n0 {}\n", main_count);
+        let mut main_xref = main_xref_head.clone();
+        main_xref.push_str("0000000000 65535 f \n");
+        for offset in &real[first_count..] {
+            main_xref.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        main_xref.push_str(&format!(
+            "trailer\n<< /Size {} >>\nstartxref\n{}\n%%EOF\n",
+            main_count, first_xref_at
+        ));
+        let file_len = main_xref_at + main_xref.len();
+
+        let mut first_offsets = vec![header.len(), catalog_at, hint_at];
+        first_offsets.extend_from_slice(&real[..first_count]);
+
+        let mut out = header;
+        out.extend(
+            lin_dict(
+                file_len,
+                (hint_at, shift),
+                end_of_first_page,
+                main_xref_at + main_xref_head.len() - 1,
+            )
+            .into_bytes(),
+        );
+        out.extend(first_xref(&first_offsets, main_xref_at).into_bytes());
+        out.extend(catalog_bytes);
+        out.extend(hint_bytes);
+        for bytes in first_page_bytes.into_iter().chain(main_bytes) {
+            out.extend(bytes);
+        }
+        debug_assert_eq!(out.len(), main_xref_at);
+        out.extend(main_xref.into_bytes());
+        Ok(out)
+    }
+
+    /// Page offset and shared object hint tables, returns the stream data and
+    /// the offset of the shared object table within it
+    fn hint_tables(
+        &self,
+        adjusted: &[usize],
+        first_page_bytes: &[Vec<u8>],
+        main_bytes: &[Vec<u8>],
+    ) -> (Vec<u8>, usize) {
+        // (object count, byte length) per page
+        let mut pages = vec![(
+            first_page_bytes.len(),
+            first_page_bytes.iter().map(Vec::len).sum::<usize>(),
+        )];
+        let mut index = 0;
+        for group in &self.other_pages {
+            let len = main_bytes[index..index + group.len()]
+                .iter()
+                .map(Vec::len)
+                .sum();
+            pages.push((group.len(), len));
+            index += group.len();
+        }
+
+        // shared object table entries: first page objects, then the shared section
+        let shared_ids: Vec<u32> = self
+            .first_page
+            .iter()
+            .chain(self.shared.iter())
+            .copied()
+            .collect();
+        let shared_index: HashMap<u32, usize> = shared_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+        let shared_lens: Vec<usize> = first_page_bytes
+            .iter()
+            .map(Vec::len)
+            .chain(
+                main_bytes[index..index + self.shared.len()]
+                    .iter()
+                    .map(Vec::len),
+            )
+            .collect();
+
+        let min_objects = pages.iter().map(|p| p.0).min().unwrap_or(0);
+        let max_objects = pages.iter().map(|p| p.0).max().unwrap_or(0);
+        let min_len = pages.iter().map(|p| p.1).min().unwrap_or(0);
+        let max_len = pages.iter().map(|p| p.1).max().unwrap_or(0);
+        let max_refs = self
+            .page_shared_refs
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0);
+        let objects_bits = bits_needed(max_objects - min_objects);
+        let len_bits = bits_needed(max_len - min_len);
+        let refs_bits = bits_needed(max_refs);
+        let shared_id_bits = bits_needed(shared_ids.len().saturating_sub(1));
+
+        let mut w = BitWriter::default();
+        // page offset hint table header
+        w.write(min_objects as u64, 32);
+        w.write(adjusted[0] as u64, 32); // first page object
+        w.write(objects_bits as u64, 16);
+        w.write(min_len as u64, 32);
+        w.write(len_bits as u64, 16);
+        w.write(0, 32); // content stream offset, unused by viewers
+        w.write(0, 16);
+        w.write(min_len as u64, 32); // content stream length mirrors the page length
+        w.write(len_bits as u64, 16);
+        w.write(refs_bits as u64, 16);
+        w.write(shared_id_bits as u64, 16);
+        w.write(0, 16); // numerator bits
+        w.write(1, 16); // denominator
+
+        // per page items, each item padded to a byte boundary
+        for page in &pages {
+            w.write((page.0 - min_objects) as u64, objects_bits);
+        }
+        w.flush();
+        for page in &pages {
+            w.write((page.1 - min_len) as u64, len_bits);
+        }
+        w.flush();
+        for refs in &self.page_shared_refs {
+            w.write(refs.len() as u64, refs_bits);
+        }
+        w.flush();
+        for refs in &self.page_shared_refs {
+            for id in refs {
+                w.write(shared_index[id] as u64, shared_id_bits);
+            }
+        }
+        w.flush();
+        // item 5 (numerators) has zero bits per entry
+        for page in &pages {
+            w.write((page.1 - min_len) as u64, len_bits);
+        }
+        w.flush();
+
+        // shared object hint table
+        let shared_at = w.bytes.len();
+        let first_shared_at = if self.shared.is_empty() {
+            0
+        } else {
+            adjusted[first_page_bytes.len() + index]
+        };
+        let min_group = shared_lens.iter().copied().min().unwrap_or(0);
+        let max_group = shared_lens.iter().copied().max().unwrap_or(0);
+        let group_bits = bits_needed(max_group - min_group);
+        // the shared section follows the other pages' objects, which are numbered from 1
+        let first_shared_id = if self.shared.is_empty() {
+            0
+        } else {
+            index as u64 + 1
+        };
+        w.write(first_shared_id, 32);
+        w.write(first_shared_at as u64, 32);
+        w.write(first_page_bytes.len() as u64, 32);
+        w.write(shared_ids.len() as u64, 32);
+        w.write(0, 16); // every group is a single object
+        w.write(min_group as u64, 32);
+        w.write(group_bits as u64, 16);
+        for len in &shared_lens {
+            w.write((len - min_group) as u64, group_bits);
+        }
+        w.flush();
+        for _ in &shared_lens {
+            w.write(0, 1); // no signatures
+        }
+        w.flush();
+
+        (w.bytes, shared_at)
+    }
+}
+
+/// Depth first walk of the references of `id`, in first-visit order
+fn collect(
+    source: &SourcePdf,
+    id: u32,
+    pages: &BTreeSet<u32>,
+    seen: &mut BTreeSet<u32>,
+    order: &mut Vec<u32>,
+) -> Result<()> {
+    if !seen.insert(id) {
+        return Ok(());
+    }
+    order.push(id);
+    let body = source.object(id)?.body;
+    let parent = dict_ref(body, b"/Parent");
+    for child in refs(body) {
+        // pages link back to the tree and to nothing else of interest
+        if Some(child) == parent || pages.contains(&child) {
+            continue;
+        }
+        collect(source, child, pages, seen, order)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> i) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.used > 0 {
+            self.bytes.push(self.current << (8 - self.used));
+            self.current = 0;
+            self.used = 0;
+        }
+    }
+}
+
+fn bits_needed(value: usize) -> u32 {
+    usize::BITS - value.leading_zeros()
+}
+
+// Lexical helpers over the small subset of PDF syntax our writer emits
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from)
+}
+
+fn rfind(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).rposition(|w| w == needle)
+}
+
+fn trim(data: &[u8]) -> &[u8] {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    let end = data
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |p| p + 1);
+    &data[start..end]
+}
+
+fn skip_whitespace(data: &[u8], mut pos: usize) -> usize {
+    while pos < data.len() && data[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+/// `N 0 obj`, returning the id and where the body starts
+fn parse_object_header(data: &[u8], pos: usize) -> Option<(u32, usize)> {
+    let line_end = find(data, b"\n", pos)?;
+    let line = std::str::from_utf8(&data[pos..line_end]).ok()?;
+    let mut parts = line.split_whitespace();
+    let id = parts.next()?.parse().ok()?;
+    parts.next()?;
+    (parts.next()? == "obj").then_some((id, line_end + 1))
+}
+
+/// Position of the `stream` keyword of the object starting at `pos`, if it is a stream
+fn find_stream_keyword(data: &[u8], pos: usize) -> Option<usize> {
+    let mut i = pos;
+    while i < data.len() {
+        match data[i] {
+            b'(' => i = skip_literal_string(data, i),
+            b'%' => i = find(data, b"\n", i).unwrap_or(data.len()),
+            b'e' if data[i..].starts_with(b"endobj") => return None,
+            b's' if data[i..].starts_with(b"stream") => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Index just past the literal string starting at `pos`
+fn skip_literal_string(data: &[u8], pos: usize) -> usize {
+    let mut depth = 0;
+    let mut i = pos;
+    while i < data.len() {
+        match data[i] {
+            b'\\' => i += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    data.len()
+}
+
+/// Every `N G R` reference in `body`, ignoring string contents
+fn refs(body: &[u8]) -> Vec<u32> {
+    let mut out = Vec::new();
+    scan_refs(body, |_, _, id| {
+        out.push(id);
+    });
+    out
+}
+
+/// Calls `on_ref(start, end, id)` for every indirect reference outside strings
+fn scan_refs<F: FnMut(usize, usize, u32)>(body: &[u8], mut on_ref: F) {
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            b'(' => {
+                i = skip_literal_string(body, i);
+                continue;
+            }
+            b'<' if body.get(i + 1) == Some(&b'<') => {
+                i += 2;
+                continue;
+            }
+            b'<' => {
+                i = find(body, b">", i).map_or(body.len(), |p| p + 1);
+                continue;
+            }
+            b'0'..=b'9' if i == 0 || !body[i - 1].is_ascii_alphanumeric() => {
+                if let Some((end, id)) = match_ref(body, i) {
+                    on_ref(i, end, id);
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Matches `digits ws digits ws R` at `pos`
+fn match_ref(body: &[u8], pos: usize) -> Option<(usize, u32)> {
+    let digits = |from: usize| {
+        let len = body[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        (len > 0).then_some(from + len)
+    };
+    let id_end = digits(pos)?;
+    let gen_start = skip_whitespace(body, id_end);
+    if gen_start == id_end {
+        return None;
+    }
+    let gen_end = digits(gen_start)?;
+    let r = skip_whitespace(body, gen_end);
+    if r == gen_end || body.get(r) != Some(&b'R') {
+        return None;
+    }
+    if body.get(r + 1).is_some_and(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let id = std::str::from_utf8(&body[pos..id_end]).ok()?.parse().ok()?;
+    Some((r + 1, id))
+}
+
+fn rewrite_refs(body: &[u8], renumber: &HashMap<u32, u32>) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut last = 0;
+    let mut missing = None;
+    scan_refs(body, |start, end, id| {
+        out.extend_from_slice(&body[last..start]);
+        match renumber.get(&id) {
+            Some(new_id) => out.extend_from_slice(format!("{} 0 R", new_id).as_bytes()),
+            None => missing = Some(id),
+        }
+        last = end;
+    });
+    if let Some(id) = missing {
+        bail!("reference to unknown object {}", id);
+    }
+    out.extend_from_slice(&body[last..]);
+    Ok(out)
+}
+
+/// Raw bytes following `key` up to the next key or the end of the dictionary
+fn dict_raw_value<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let mut from = 0;
+    let start = loop {
+        let at = find(dict, key, from)?;
+        let end = at + key.len();
+        // make sure we matched the whole name, not a prefix of a longer one
+        if !dict.get(end).is_some_and(|b| b.is_ascii_alphanumeric()) {
+            break end;
+        }
+        from = end;
+    };
+    let mut depth = 0i32;
+    let mut i = skip_whitespace(dict, start);
+    let value_start = i;
+    while i < dict.len() {
+        match dict[i] {
+            b'(' => {
+                i = skip_literal_string(dict, i);
+                continue;
+            }
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            b'<' if dict.get(i + 1) == Some(&b'<') => {
+                depth += 1;
+                i += 1;
+            }
+            b'>' if dict.get(i + 1) == Some(&b'>') => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+                i += 1;
+            }
+            b'/' if depth == 0 && i > value_start => break,
+            _ => {}
+        }
+        i += 1;
+    }
+    Some(trim(&dict[value_start..i.min(dict.len())]))
+}
+
+fn dict_int(dict: &[u8], key: &[u8]) -> Option<u64> {
+    std::str::from_utf8(dict_raw_value(dict, key)?)
+        .ok()?
+        .parse()
+        .ok()
+}
+
+fn dict_ref(dict: &[u8], key: &[u8]) -> Option<u32> {
+    let value = dict_raw_value(dict, key)?;
+    match_ref(value, 0).map(|(_, id)| id)
+}
+
+fn dict_ref_array(dict: &[u8], key: &[u8]) -> Vec<u32> {
+    dict_raw_value(dict, key).map(refs).unwrap_or_default()
+}
+
+fn dict_name<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    dict_raw_value(dict, key)?.strip_prefix(b"/")
+}
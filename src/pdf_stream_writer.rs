@@ -1,29 +1,116 @@
-use anyhow::Result;
-use std::io::{Read, Seek, Write};
+use super::pdf_version::PdfVersion;
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+/// What a fixup is filled with, given the length of the finished file
+type FixupValue = Box<dyn FnOnce(u64) -> String>;
+
+/// Room left in an object already written for a value that is only known
+/// later, a page count or the /ByteRange of a signature; `finish` writes
+/// the value given with `fill_on_finish` over it
+#[derive(Clone, Copy, Debug)]
+pub struct Fixup {
+    offset: u64,
+    width: usize,
+}
+
+impl Fixup {
+    /// Writes `width` spaces to `obj` at the current position, for an object
+    /// being written with `write_object_with` or the like
+    pub fn write<W: Write + Seek>(obj: &mut W, width: usize) -> Result<Self> {
+        let offset = obj.stream_position()?;
+        obj.write_all(" ".repeat(width).as_bytes())?;
+        Ok(Self { offset, width })
+    }
+}
 
 pub struct PdfStreamWriter<W: Write + Seek> {
     sink: W,
-    offsets: Vec<u64>,
+    // by object id from 1, None until a reserved id is written
+    offsets: Vec<Option<u64>>,
+    trailer_entries: Vec<String>,
     pub next_obj_id: u32,
+    // with deduplication on, the objects written with `write_shared` by a
+    // hash of what they hold
+    shared: Option<HashMap<[u8; 32], u32>>,
+    // written over their placeholders once the file is complete
+    fixups: Vec<(Fixup, FixupValue)>,
 }
 
 impl<W: Write + Seek> PdfStreamWriter<W> {
-    pub fn new(mut sink: W) -> Result<Self> {
-        sink.write_all(b"%PDF-1.7\n")?;
+    pub fn new(mut sink: W, version: PdfVersion) -> Result<Self> {
+        sink.write_all(version.header().as_bytes())?;
         Ok(Self {
             sink,
             offsets: vec![],
+            trailer_entries: vec![],
             next_obj_id: 1,
+            shared: None,
+            fixups: vec![],
         })
     }
 
+    /// Writes identical objects passed to `write_shared` from here on once,
+    /// the later ones are references to the first
+    pub fn deduplicate(&mut self) {
+        self.shared.get_or_insert_with(HashMap::new);
+    }
+
+    pub fn deduplicates(&self) -> bool {
+        self.shared.is_some()
+    }
+
+    /// Writes the object `bytes` builds, like `write_object`, unless one was
+    /// written from the same `content` before with deduplication on: its id
+    /// is returned and `bytes` never called. `content` is what the object
+    /// holds before it is encrypted, which gives different bytes every time
+    pub fn write_shared<F>(&mut self, content: &[&[u8]], bytes: F) -> Result<u32>
+    where
+        F: FnOnce() -> Result<Vec<u8>>,
+    {
+        let Some(shared) = &self.shared else {
+            return self.write_object(Cursor::new(bytes()?));
+        };
+        let mut hasher = Sha256::new();
+        for part in content {
+            // the lengths keep the parts apart
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        let key: [u8; 32] = hasher.finalize().into();
+        if let Some(&id) = shared.get(&key) {
+            return Ok(id);
+        }
+        let id = self.write_object(Cursor::new(bytes()?))?;
+        self.shared.get_or_insert_with(HashMap::new).insert(key, id);
+        Ok(id)
+    }
+
+    /// Has `finish` fill `fixup` with what `value` gives for the length of
+    /// the finished file, padded with spaces to its width. The value goes in
+    /// as it is, never encrypted, so it can't be inside a string or a stream
+    /// of an encrypted file; a fixup never filled stays blank
+    pub fn fill_on_finish<F>(&mut self, fixup: Fixup, value: F)
+    where
+        F: FnOnce(u64) -> String + 'static,
+    {
+        self.fixups.push((fixup, Box::new(value)));
+    }
+
+    /// Extra `/Key value` entry appended to the trailer dictionary
+    pub fn add_trailer_entry(&mut self, entry: String) {
+        self.trailer_entries.push(entry);
+    }
+
     pub fn write_object_with<F>(&mut self, writer: &mut F) -> Result<u32>
     where
         F: FnMut(&mut W) -> Result<()>,
     {
         let id = self._new_object()?;
         writer(&mut self.sink)?;
-        write!(self.sink, "\n")?;
+        writeln!(self.sink)?;
         self._finish_object()?;
         Ok(id)
     }
@@ -51,40 +138,86 @@ impl<W: Write + Seek> PdfStreamWriter<W> {
     }
 
     // Completes a pdf file
-    // writes the xref table and EOF
-    pub fn finish(mut self, root_id: u32) -> Result<()> {
+    // writes the xref table and EOF, `info_id` is the optional /Info dictionary,
+    // then fills in the fixups
+    pub fn finish(mut self, root_id: u32, info_id: Option<u32>) -> Result<W> {
         // write xref table at current position
         let xref_start = self.sink.stream_position()?;
         writeln!(self.sink, "xref")?;
         writeln!(self.sink, "0 {}", self.offsets.len() + 1)?;
-        writeln!(self.sink, "0000000000 65535 f ")?;
-        for &offset in &self.offsets {
-            writeln!(self.sink, "{:010} 00000 n ", offset)?;
+        // ids reserved but never written are free, each entry of the free
+        // list giving the next one and the last object 0
+        let free: Vec<usize> = (1..=self.offsets.len())
+            .filter(|&id| self.offsets[id - 1].is_none())
+            .collect();
+        let next_free = |id: usize| free.iter().find(|&&free| free > id).copied().unwrap_or(0);
+        writeln!(self.sink, "{:010} 65535 f ", next_free(0))?;
+        for (i, offset) in self.offsets.iter().enumerate() {
+            match offset {
+                Some(offset) => writeln!(self.sink, "{:010} 00000 n ", offset)?,
+                None => writeln!(self.sink, "{:010} 00001 f ", next_free(i + 1))?,
+            }
+        }
+        let mut extra = info_id
+            .map(|id| format!(" /Info {} 0 R", id))
+            .unwrap_or_default();
+        for entry in &self.trailer_entries {
+            extra.push(' ');
+            extra.push_str(entry);
         }
         writeln!(
             self.sink,
-            "trailer << /Size {} /Root {} 0 R >>",
+            "trailer << /Size {} /Root {} 0 R{} >>",
             self.offsets.len() + 1,
-            root_id
+            root_id,
+            extra
         )?;
         writeln!(self.sink, "startxref")?;
         writeln!(self.sink, "{}", xref_start)?;
         writeln!(self.sink, "%%EOF")?;
-        Ok(())
+
+        let file_len = self.sink.stream_position()?;
+        for (fixup, value) in std::mem::take(&mut self.fixups) {
+            let value = value(file_len);
+            if value.len() > fixup.width {
+                bail!(
+                    "{:?} does not fit the {} bytes left for it",
+                    value,
+                    fixup.width
+                );
+            }
+            self.sink.seek(SeekFrom::Start(fixup.offset))?;
+            write!(self.sink, "{:<width$}", value, width = fixup.width)?;
+        }
+        self.sink.seek(SeekFrom::Start(file_len))?;
+        Ok(self.sink)
     }
 
+    /// An id for an object written later with `write_object_with_reserved_id`,
+    /// so other objects can refer to it first
     pub fn reserve_object(&mut self) -> Result<u32> {
         let object_id = self.next_obj_id;
         self.next_obj_id += 1;
+        self.offsets.push(None);
         Ok(object_id)
     }
 
+    /// Writes the object of an id from `reserve_object`, once
     pub fn write_object_with_reserved_id<F>(&mut self, id: u32, writer: &mut F) -> Result<()>
     where
         F: FnMut(&mut W) -> Result<()>,
     {
+        let Some(slot) = (id as usize)
+            .checked_sub(1)
+            .and_then(|i| self.offsets.get(i))
+        else {
+            bail!("object {} was never reserved", id);
+        };
+        if slot.is_some() {
+            bail!("object {} is written already", id);
+        }
         let pos = self.sink.stream_position()?;
-        self.offsets.push(pos);
+        self.offsets[id as usize - 1] = Some(pos);
         writeln!(self.sink, "{} 0 obj", id)?;
         writer(&mut self.sink)?;
         writeln!(self.sink, "\nendobj")?;
@@ -95,7 +228,7 @@ impl<W: Write + Seek> PdfStreamWriter<W> {
         let id = self.next_obj_id;
         self.next_obj_id += 1;
         let pos = self.sink.stream_position()?;
-        self.offsets.push(pos);
+        self.offsets.push(Some(pos));
         writeln!(self.sink, "{} 0 obj", id)?;
         Ok(id)
     }
@@ -105,3 +238,43 @@ impl<W: Write + Seek> PdfStreamWriter<W> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PdfStreamWriter;
+    use crate::pdf_version::PdfVersion;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn finish_lists_unwritten_ids_as_free() {
+        let mut writer = PdfStreamWriter::new(Cursor::new(Vec::new()), PdfVersion::V1_7).unwrap();
+        let catalog = writer.reserve_object().unwrap();
+        writer.reserve_object().unwrap();
+        writer.write_object(&b"<< >>"[..]).unwrap();
+        writer.reserve_object().unwrap();
+        let mut body = |obj: &mut Cursor<Vec<u8>>| Ok(obj.write_all(b"<< /Type /Catalog >>")?);
+        writer
+            .write_object_with_reserved_id(catalog, &mut body)
+            .unwrap();
+        let pdf = String::from_utf8(writer.finish(catalog, None).unwrap().into_inner()).unwrap();
+
+        let offset = |id: u32| pdf.find(&format!("{} 0 obj", id)).unwrap();
+        let xref_at = pdf.find("xref\n").unwrap();
+        let expected = format!(
+            "xref\n0 5\n\
+             0000000002 65535 f \n\
+             {:010} 00000 n \n\
+             0000000004 00001 f \n\
+             {:010} 00000 n \n\
+             0000000000 00001 f \n\
+             trailer << /Size 5 /Root 1 0 R >>\n\
+             startxref\n{}\n%%EOF\n",
+            offset(1),
+            offset(3),
+            xref_at
+        );
+        assert_eq!(&pdf[xref_at..], expected);
+        assert!(pdf.starts_with("%PDF-1.7\n"));
+        assert!(pdf[offset(1)..].starts_with("1 0 obj\n<< /Type /Catalog >>\nendobj\n"));
+    }
+}
@@ -0,0 +1,162 @@
+/// Serializes raw bytes as a PDF string operand, a literal `(..)` string
+/// unless escaping would make it longer than the `<..>` hex form
+pub fn string_operand(bytes: &[u8]) -> String {
+    let escaped_len: usize = bytes.iter().map(|&b| escape(b).len()).sum();
+    if escaped_len > 2 * bytes.len() {
+        hex_string(bytes)
+    } else {
+        literal_string(bytes)
+    }
+}
+
+/// `(..)` with backslash escapes, anything outside printable ASCII is written as octal
+/// so the result survives any transport and never ends a line inside the string
+pub fn literal_string(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('(');
+    for &b in bytes {
+        out.push_str(&escape(b));
+    }
+    out.push(')');
+    out
+}
+
+pub fn hex_string(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(2 * bytes.len() + 2);
+    out.push('<');
+    for b in bytes {
+        out.push_str(&format!("{:02X}", b));
+    }
+    out.push('>');
+    out
+}
+
+fn escape(b: u8) -> String {
+    match b {
+        b'\\' => "\\\\".to_string(),
+        b'(' => "\\(".to_string(),
+        b')' => "\\)".to_string(),
+        b'\n' => "\\n".to_string(),
+        b'\r' => "\\r".to_string(),
+        b'\t' => "\\t".to_string(),
+        0x08 => "\\b".to_string(),
+        0x0C => "\\f".to_string(),
+        0x20..=0x7E => (b as char).to_string(),
+        _ => format!("\\{:03o}", b),
+    }
+}
+
+/// Encodes text for the standard fonts' WinAnsiEncoding (cp1252), common
+/// symbols it lacks are written as look-alikes, other characters it can't
+/// represent become '?'. Joiners, variation selectors and the other
+/// characters that only change how those around them look are left out
+pub fn win_ansi_bytes(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c as u32 {
+            0x00..=0x7F | 0xA0..=0xFF => bytes.push(c as u8),
+            // zero width space, non-joiner and joiner, word joiner, byte
+            // order mark, variation selectors, emoji skin tones, the
+            // enclosing keycap and tags
+            0x200B..=0x200D
+            | 0x2060
+            | 0xFEFF
+            | 0xFE00..=0xFE0F
+            | 0x1F3FB..=0x1F3FF
+            | 0x20E3
+            | 0xE0020..=0xE007F => {}
+            _ => match (win_ansi_special(c), look_alike(c)) {
+                (Some(b), _) => bytes.push(b),
+                (None, Some(text)) => bytes.extend(win_ansi_bytes(text)),
+                (None, None) => bytes.push(b'?'),
+            },
+        }
+    }
+    bytes
+}
+
+/// What bullets, check boxes, arrows and math signs from the symbol fonts
+/// are written as, in characters WinAnsiEncoding has
+fn look_alike(c: char) -> Option<&'static str> {
+    Some(match c {
+        '●' | '■' | '▪' | '◆' | '❖' | '⬥' | '⬧' | '⧫' | '◊' | '★' => "\u{2022}",
+        '○' | '❍' => "o",
+        '☐' | '□' | '◻' | '❑' | '❒' => "[ ]",
+        '☑' | '☒' | '✓' | '✔' => "[x]",
+        '✗' | '✘' | '✕' => "\u{d7}",
+        '→' | '➔' | '➢' | '⇨' | '➜' => "->",
+        '←' | '⇦' => "<-",
+        '↔' | '⬄' => "<->",
+        '⇒' => "=>",
+        '⇐' => "<=",
+        '⇔' => "<=>",
+        '↑' | '⇧' => "^",
+        '↓' | '⇩' => "v",
+        '≤' => "<=",
+        '≥' => ">=",
+        '≠' => "!=",
+        '≈' | '∼' | '≅' => "~",
+        '−' => "-",
+        '∗' => "*",
+        '⋅' => "\u{b7}",
+        '′' => "'",
+        '″' => "\"",
+        '∞' => "inf",
+        'μ' => "\u{b5}",
+        _ => return None,
+    })
+}
+
+/// the 0x80-0x9F block, where cp1252 differs from Latin-1, '\0' marks the unused codes
+const WIN_ANSI_HIGH: [char; 32] = [
+    '€', '\0', '‚', 'ƒ', '„', '…', '†', '‡', // 0x80
+    'ˆ', '‰', 'Š', '‹', 'Œ', '\0', 'Ž', '\0', // 0x88
+    '\0', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '•', '–', '—', // 0x90
+    '˜', '™', 'š', '›', 'œ', '\0', 'ž', 'Ÿ', // 0x98
+];
+fn win_ansi_special(c: char) -> Option<u8> {
+    WIN_ANSI_HIGH
+        .iter()
+        .position(|&high| high == c && c != '\0')
+        .map(|i| 0x80 + i as u8)
+}
+
+/// The character a WinAnsiEncoding code stands for, None for unused and control codes
+pub fn win_ansi_char(code: u8) -> Option<char> {
+    match code {
+        0x20..=0x7E | 0xA0..=0xFF => Some(code as char),
+        0x80..=0x9F => Some(WIN_ANSI_HIGH[(code - 0x80) as usize]).filter(|&c| c != '\0'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex_string, literal_string, string_operand};
+
+    #[test]
+    fn escapes_literal_strings() {
+        assert_eq!(literal_string(b"a\\b"), "(a\\\\b)");
+        assert_eq!(literal_string(b"(x) y)"), "(\\(x\\) y\\))");
+        assert_eq!(literal_string(b"1\r\n2\t"), "(1\\r\\n2\\t)");
+        assert_eq!(literal_string(b"\x08\x0C\x00"), "(\\b\\f\\000)");
+        assert_eq!(
+            literal_string(&[b'e', 0x80, 0xE9, 0xFF]),
+            "(e\\200\\351\\377)"
+        );
+        assert_eq!(literal_string(b""), "()");
+    }
+
+    #[test]
+    fn writes_hex_strings() {
+        assert_eq!(hex_string(&[0x00, 0x7F, 0xAB, 0xFF]), "<007FABFF>");
+        assert_eq!(hex_string(b""), "<>");
+    }
+
+    #[test]
+    fn picks_the_shorter_form() {
+        assert_eq!(string_operand(b"Hello (world)"), "(Hello \\(world\\))");
+        // octal escapes take four bytes, hex two
+        assert_eq!(string_operand(&[0xFE, 0xFF, 0x00, 0x41]), "<FEFF0041>");
+    }
+}
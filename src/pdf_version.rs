@@ -0,0 +1,75 @@
+use anyhow::{bail, Result};
+use std::fmt;
+
+/// Target version written to the `%PDF-x.y` header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PdfVersion {
+    V1_4,
+    V1_5,
+    V1_6,
+    #[default]
+    V1_7,
+    V2_0,
+}
+
+impl PdfVersion {
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value {
+            "1.4" => PdfVersion::V1_4,
+            "1.5" => PdfVersion::V1_5,
+            "1.6" => PdfVersion::V1_6,
+            "1.7" => PdfVersion::V1_7,
+            "2.0" => PdfVersion::V2_0,
+            _ => bail!("unsupported PDF version {}, expected 1.4 to 2.0", value),
+        })
+    }
+
+    pub fn header(self) -> String {
+        format!("%PDF-{}\n", self)
+    }
+
+    pub fn supports(self, feature: Feature) -> bool {
+        self >= feature.min_version()
+    }
+}
+
+impl fmt::Display for PdfVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = match self {
+            PdfVersion::V1_4 => "1.4",
+            PdfVersion::V1_5 => "1.5",
+            PdfVersion::V1_6 => "1.6",
+            PdfVersion::V1_7 => "1.7",
+            PdfVersion::V2_0 => "2.0",
+        };
+        f.write_str(version)
+    }
+}
+
+/// Optional output features tied to a minimum PDF version
+#[derive(Clone, Copy, Debug)]
+pub enum Feature {
+    /// AES-256 encryption, an Adobe extension to 1.7 and standard in 2.0
+    Aes256,
+    /// /AF associated files on the catalog
+    AssociatedFiles,
+}
+
+impl Feature {
+    pub fn min_version(self) -> PdfVersion {
+        match self {
+            Feature::Aes256 => PdfVersion::V1_7,
+            Feature::AssociatedFiles => PdfVersion::V2_0,
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Feature::Aes256 => "AES-256 encryption",
+            Feature::AssociatedFiles => "associated files (/AF)",
+        };
+        f.write_str(name)
+    }
+}
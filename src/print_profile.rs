@@ -0,0 +1,150 @@
+use anyhow::{bail, Result};
+
+/// Output condition of the built-in profile, as registered with the ICC
+const SRGB_CONDITION: &str = "sRGB IEC61966-2.1";
+
+/// Writes PDF/X-4 for print: an output intent, a TrimBox on the page size and
+/// a BleedBox `bleed` past it, what can't comply is listed in the report as
+/// `Warning::NotPrintReady`
+#[derive(Clone, Debug)]
+pub struct PrintProfile {
+    /// how far the page extends past its trimmed edge on every side, in points
+    pub bleed: f32,
+    /// the OutputConditionIdentifier of the output intent, e.g. FOGRA39
+    pub output_condition: String,
+    /// the ICC profile of the printing condition, sRGB when not given
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+impl Default for PrintProfile {
+    fn default() -> Self {
+        Self {
+            bleed: 0.0,
+            output_condition: SRGB_CONDITION.to_string(),
+            icc_profile: None,
+        }
+    }
+}
+
+impl PrintProfile {
+    /// sRGB output intent, no bleed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bleed(mut self, bleed: f32) -> Self {
+        self.bleed = bleed.max(0.0);
+        self
+    }
+
+    /// prints to the condition `identifier` characterized by `icc_profile`
+    pub fn output_intent(mut self, identifier: impl Into<String>, icc_profile: Vec<u8>) -> Self {
+        self.output_condition = identifier.into();
+        self.icc_profile = Some(icc_profile);
+        self
+    }
+
+    /// The ICC profile written as /DestOutputProfile and its number of components
+    pub(crate) fn profile(&self) -> Result<(Vec<u8>, u8)> {
+        let profile = match &self.icc_profile {
+            Some(profile) => profile.clone(),
+            None => srgb_profile(),
+        };
+        let components = components(&profile)?;
+        Ok((profile, components))
+    }
+}
+
+/// Components of the data color space named in the header of an ICC profile
+fn components(profile: &[u8]) -> Result<u8> {
+    if profile.len() < 128 || &profile[36..40] != b"acsp" {
+        bail!("not an ICC profile");
+    }
+    Ok(match &profile[16..20] {
+        b"GRAY" => 1,
+        b"RGB " => 3,
+        b"CMYK" => 4,
+        other => bail!(
+            "ICC profiles for {} can't be an output intent",
+            String::from_utf8_lossy(other).trim_end()
+        ),
+    })
+}
+
+/// A version 2 sRGB display profile: the D50 adapted primaries and a 2.2 gamma
+fn srgb_profile() -> Vec<u8> {
+    let xyz = |[x, y, z]: [f32; 3]| {
+        let mut data = b"XYZ \0\0\0\0".to_vec();
+        for value in [x, y, z] {
+            data.extend_from_slice(&s15_fixed16(value));
+        }
+        data
+    };
+    let mut description = b"desc\0\0\0\0".to_vec();
+    description.extend_from_slice(&(SRGB_CONDITION.len() as u32 + 1).to_be_bytes());
+    description.extend_from_slice(SRGB_CONDITION.as_bytes());
+    // the terminating zero, then empty Unicode and ScriptCode descriptions
+    description.extend_from_slice(&[0; 1 + 8 + 3 + 67]);
+    let mut copyright = b"text\0\0\0\0No copyright, use freely".to_vec();
+    copyright.push(0);
+    // count 1 makes it a gamma, as u8Fixed8
+    let gamma = b"curv\0\0\0\0\0\0\0\x01\x02\x33\0\0".to_vec();
+
+    let tags: [(&[u8; 4], Vec<u8>); 8] = [
+        (b"desc", description),
+        (b"cprt", copyright),
+        (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+        (b"rXYZ", xyz([0.4361, 0.2225, 0.0139])),
+        (b"gXYZ", xyz([0.3851, 0.7169, 0.0971])),
+        (b"bXYZ", xyz([0.1431, 0.0606, 0.7141])),
+        (b"rTRC", gamma.clone()),
+        (b"gTRC", gamma),
+    ];
+    // bTRC shares the data of gTRC
+    let count = tags.len() + 1;
+    let mut table = (count as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let mut offset = 128 + 4 + 12 * count;
+    let mut last = (0, 0);
+    for (signature, tag) in &tags {
+        last = (offset, tag.len());
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+        // every tag starts on a 4 byte boundary
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        offset = 128 + 4 + 12 * count + data.len();
+    }
+    table.extend_from_slice(b"bTRC");
+    table.extend_from_slice(&(last.0 as u32).to_be_bytes());
+    table.extend_from_slice(&(last.1 as u32).to_be_bytes());
+
+    let size = 128 + table.len() + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // no preferred CMM
+    profile.extend_from_slice(&[2, 0x10, 0, 0]);
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    // 2024-01-01 00:00:00
+    for part in [2024u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&part.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    // platform, flags, manufacturer, model, attributes and perceptual intent
+    profile.extend_from_slice(&[0; 4 + 4 + 4 + 4 + 8 + 4]);
+    for value in [0.9642, 1.0, 0.8249] {
+        profile.extend_from_slice(&s15_fixed16(value));
+    }
+    // creator, the profile ID version 2 leaves out and the reserved bytes
+    profile.extend_from_slice(&[0; 4 + 16 + 28]);
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+fn s15_fixed16(value: f32) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
@@ -0,0 +1,30 @@
+/// Stages of a conversion, reported in this order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// reading the document relationships and properties
+    RelMap,
+    ImagePreprocessing,
+    /// laying out document.xml, pages are written as they fill
+    Parsing,
+    /// page tree, metadata, xref and the optional linearization pass
+    Writing,
+}
+
+/// Receives progress while a document is converted
+///
+/// Images are processed in parallel, so `image_processed` can be called
+/// from several threads at once. Every method defaults to doing nothing.
+pub trait ProgressSink: Sync {
+    fn phase(&self, _phase: Phase) {}
+
+    /// `done` out of `total` images have been decoded and re-encoded
+    fn image_processed(&self, _done: usize, _total: usize) {}
+
+    /// page number `page` (1 based) has been written
+    fn page_written(&self, _page: usize) {}
+}
+
+/// The sink used when the caller doesn't care about progress
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
@@ -0,0 +1,868 @@
+use anyhow::{anyhow, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder, ImageFormat};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use tiny_skia::{
+    Color, FillRule, FilterQuality, IntSize, LineCap, LineJoin, Mask, Paint, PathBuilder, Pixmap,
+    PixmapPaint, Stroke, StrokeDash, Transform,
+};
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use super::font_metrics::{PdfFont, StandardFont, TrueTypeFont, LIGATURES};
+use super::pdf_string::win_ansi_char;
+
+/// Resolution of page images unless set otherwise
+const DEFAULT_DPI: u32 = 150;
+
+/// Page images aren't rendered at more dots per inch than this
+pub const MAX_DPI: u32 = 1200;
+
+/// JPEG quality of page images unless set otherwise
+const DEFAULT_QUALITY: u8 = 85;
+
+/// Image file formats pages can be rendered to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RasterFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl RasterFormat {
+    /// the usual file extension, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            RasterFormat::Png => "png",
+            RasterFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Receives the image of every page written
+///
+/// Called in page order from the thread writing the PDF, an error leaves
+/// the image out with a `Warning::DroppedContent`.
+pub trait PageImageSink: fmt::Debug + Send + Sync {
+    /// `image` is the encoded file, `page` where the page goes in the PDF, 1 based
+    fn page_image(&self, page: usize, image: Vec<u8>) -> io::Result<()>;
+}
+
+/// Renders every page written to an image as well, for thumbnails and
+/// previews; `check` with these options gives the images without a PDF
+///
+/// Pages are drawn from their content streams, as laid out and turned by
+/// any /Rotate. Form fields aren't drawn, and text in a standard font only
+/// where the font provider has a TrueType font for a family like it, e.g.
+/// Arial or Liberation Sans for Helvetica.
+#[derive(Clone, Debug)]
+pub struct PageImages {
+    pub format: RasterFormat,
+    /// pixels per inch, up to `MAX_DPI`
+    pub dpi: u32,
+    /// JPEG quality (1-100)
+    pub quality: u8,
+    pub sink: Arc<dyn PageImageSink>,
+}
+
+impl PageImages {
+    /// 150 dpi images in `format`, handed to `sink`
+    pub fn new(format: RasterFormat, sink: impl PageImageSink + 'static) -> Self {
+        Self {
+            format,
+            dpi: DEFAULT_DPI,
+            quality: DEFAULT_QUALITY,
+            sink: Arc::new(sink),
+        }
+    }
+
+    pub fn dpi(mut self, dpi: u32) -> Self {
+        self.dpi = dpi;
+        self
+    }
+
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+}
+
+/// TrueType families asked for the outlines of a standard font, in order
+pub fn outline_families(font: StandardFont) -> &'static [&'static str] {
+    match font {
+        StandardFont::Helvetica => &[
+            "Helvetica",
+            "Arial",
+            "Liberation Sans",
+            "Nimbus Sans",
+            "DejaVu Sans",
+        ],
+        StandardFont::Courier => &[
+            "Courier New",
+            "Liberation Mono",
+            "Nimbus Mono PS",
+            "DejaVu Sans Mono",
+        ],
+    }
+}
+
+/// A font of the page: what its codes measure, and the font its glyphs are
+/// drawn from, None when there is nothing to draw them with
+pub struct RasterFont<'a> {
+    pub metrics: &'a PdfFont,
+    pub outlines: Option<&'a TrueTypeFont>,
+}
+
+/// What the content stream of a page is drawn on and refers to
+pub struct RasterPage<'a> {
+    /// in points, before /Rotate
+    pub width: f32,
+    pub height: f32,
+    pub rotate: u16,
+    /// by /F index, 0 based
+    pub fonts: Vec<RasterFont<'a>>,
+    /// the JPEG data of the images, by name
+    pub images: HashMap<&'a str, Vec<u8>>,
+}
+
+/// `content` drawn on a white page, encoded as `images` says
+pub fn render_page(content: &str, page: &RasterPage, images: &PageImages) -> Result<Vec<u8>> {
+    let scale = images.dpi as f32 / 72.0;
+    let width = (page.width * scale).round().max(1.0);
+    let height = (page.height * scale).round().max(1.0);
+    // PDF space has y going up, the image y going down
+    let mut device = Transform::from_row(scale, 0.0, 0.0, -scale, 0.0, height);
+    let (width, height) = match page.rotate {
+        90 => {
+            device = device.post_concat(Transform::from_row(0.0, 1.0, -1.0, 0.0, height, 0.0));
+            (height, width)
+        }
+        180 => {
+            device = device.post_concat(Transform::from_row(-1.0, 0.0, 0.0, -1.0, width, height));
+            (width, height)
+        }
+        270 => {
+            device = device.post_concat(Transform::from_row(0.0, -1.0, 1.0, 0.0, 0.0, width));
+            (height, width)
+        }
+        _ => (width, height),
+    };
+    let mut pixmap = Pixmap::new(width as u32, height as u32)
+        .ok_or_else(|| anyhow!("{}x{} pixels is too large an image", width, height))?;
+    pixmap.fill(Color::WHITE);
+
+    let mut canvas = Canvas::new(pixmap, page, device);
+    let mut lexer = Lexer {
+        data: content.as_bytes(),
+        pos: 0,
+    };
+    // the operands so far, and those of the arrays around them
+    let mut operands = Vec::new();
+    let mut arrays: Vec<Vec<Operand>> = Vec::new();
+    while let Some(token) = lexer.next_token() {
+        match token {
+            Token::Operand(operand) => operands.push(operand),
+            Token::Open => arrays.push(std::mem::take(&mut operands)),
+            Token::Close => {
+                let array = std::mem::take(&mut operands);
+                operands = arrays.pop().unwrap_or_default();
+                operands.push(Operand::Array(array));
+            }
+            Token::Operator(operator) => {
+                canvas.run(operator, &operands);
+                operands.clear();
+            }
+        }
+    }
+    encode(&canvas.pixmap, images)
+}
+
+/// The page is opaque, its premultiplied pixels are the colors as they are
+fn encode(pixmap: &Pixmap, images: &PageImages) -> Result<Vec<u8>> {
+    let rgb: Vec<u8> = pixmap
+        .data()
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let mut data = Vec::new();
+    match images.format {
+        RasterFormat::Png => {
+            PngEncoder::new(&mut data).write_image(&rgb, width, height, ExtendedColorType::Rgb8)?
+        }
+        RasterFormat::Jpeg => JpegEncoder::new_with_quality(&mut data, images.quality).encode(
+            &rgb,
+            width,
+            height,
+            ExtendedColorType::Rgb8,
+        )?,
+    }
+    Ok(data)
+}
+
+#[derive(Debug)]
+enum Operand {
+    Number(f32),
+    Name(String),
+    String(Vec<u8>),
+    Array(Vec<Operand>),
+}
+
+enum Token<'a> {
+    Operand(Operand),
+    /// `[`, or `<<` whose dictionary is kept as an array
+    Open,
+    Close,
+    Operator(&'a str),
+}
+
+/// Splits a content stream into operands and operators
+struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(
+        b,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+impl<'a> Lexer<'a> {
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        loop {
+            let &b = self.data.get(self.pos)?;
+            if is_whitespace(b) {
+                self.pos += 1;
+            } else if b == b'%' {
+                while self
+                    .data
+                    .get(self.pos)
+                    .is_some_and(|&b| b != b'\n' && b != b'\r')
+                {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let b = self.data[self.pos];
+        let next = self.data.get(self.pos + 1).copied();
+        Some(match b {
+            b'(' => Token::Operand(Operand::String(self.literal_string())),
+            b'<' if next == Some(b'<') => {
+                self.pos += 2;
+                Token::Open
+            }
+            b'>' if next == Some(b'>') => {
+                self.pos += 2;
+                Token::Close
+            }
+            b'<' => Token::Operand(Operand::String(self.hex_string())),
+            b'[' | b'{' => {
+                self.pos += 1;
+                Token::Open
+            }
+            b']' | b'}' => {
+                self.pos += 1;
+                Token::Close
+            }
+            b'/' => {
+                self.pos += 1;
+                Token::Operand(Operand::Name(self.regular().to_string()))
+            }
+            // a stray `)` or `>`
+            b')' | b'>' => {
+                self.pos += 1;
+                Token::Operator("")
+            }
+            _ => {
+                let word = self.regular();
+                match word.parse() {
+                    Ok(number) if !word.starts_with(char::is_alphabetic) => {
+                        Token::Operand(Operand::Number(number))
+                    }
+                    _ => Token::Operator(word),
+                }
+            }
+        })
+    }
+
+    /// the run of regular characters at the position
+    fn regular(&mut self) -> &'a str {
+        let start = self.pos;
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|&b| !is_whitespace(b) && !is_delimiter(b))
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.pos]).unwrap_or_default()
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut depth = 0;
+        self.pos += 1;
+        while let Some(&b) = self.data.get(self.pos) {
+            self.pos += 1;
+            match b {
+                b'(' => depth += 1,
+                b')' if depth == 0 => break,
+                b')' => depth -= 1,
+                b'\\' => {
+                    let Some(&escaped) = self.data.get(self.pos) else {
+                        break;
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'b' => bytes.push(8),
+                        b'f' => bytes.push(12),
+                        b'0'..=b'7' => {
+                            let mut code = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.data.get(self.pos) {
+                                    Some(&digit @ b'0'..=b'7') => {
+                                        code = code * 8 + (digit - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            bytes.push(code as u8);
+                        }
+                        // a line break after the backslash continues the string
+                        b'\r' | b'\n' => {
+                            if escaped == b'\r' && self.data.get(self.pos) == Some(&b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        other => bytes.push(other),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            bytes.push(b);
+        }
+        bytes
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        self.pos += 1;
+        while let Some(&b) = self.data.get(self.pos) {
+            self.pos += 1;
+            if b == b'>' {
+                break;
+            }
+            if let Some(digit) = (b as char).to_digit(16) {
+                digits.push(digit as u8);
+            }
+        }
+        // an odd last digit is followed by a 0
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+            .collect()
+    }
+}
+
+/// The graphics state `q` saves and `Q` restores, with the text state
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Transform,
+    fill: Color,
+    stroke: Color,
+    line: Stroke,
+    clip: Option<Arc<Mask>>,
+    font: Option<usize>,
+    font_size: f32,
+    leading: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+    rise: f32,
+    invisible: bool, // text render mode 3
+}
+
+struct Canvas<'a> {
+    pixmap: Pixmap,
+    page: &'a RasterPage<'a>,
+    faces: Vec<Option<Face<'a>>>, // by /F index
+    images: HashMap<String, Option<Pixmap>>,
+    state: GraphicsState,
+    saved: Vec<GraphicsState>,
+    path: PathBuilder,
+    current_point: (f32, f32),
+    clip_rule: Option<FillRule>, // W or W* waiting for the path to be painted
+    text_matrix: Transform,
+    line_matrix: Transform,
+}
+
+/// The numbers at the end of `operands`, None when there aren't `N`
+fn numbers<const N: usize>(operands: &[Operand]) -> Option<[f32; N]> {
+    let start = operands.len().checked_sub(N)?;
+    let mut values = [0.0; N];
+    for (value, operand) in values.iter_mut().zip(&operands[start..]) {
+        match operand {
+            Operand::Number(number) => *value = *number,
+            _ => return None,
+        }
+    }
+    Some(values)
+}
+
+fn gray(value: f32) -> Color {
+    rgb([value; 3])
+}
+
+fn rgb([r, g, b]: [f32; 3]) -> Color {
+    Color::from_rgba(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), 1.0)
+        .unwrap_or(Color::BLACK)
+}
+
+fn cmyk([c, m, y, k]: [f32; 4]) -> Color {
+    rgb([
+        (1.0 - c) * (1.0 - k),
+        (1.0 - m) * (1.0 - k),
+        (1.0 - y) * (1.0 - k),
+    ])
+}
+
+fn paint(color: Color) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    paint.anti_alias = true;
+    paint
+}
+
+/// The glyph `code` shows in `face`: a ligature or its WinAnsi character
+fn glyph(face: &Face, code: u8) -> Option<GlyphId> {
+    let c = match LIGATURES.iter().find(|ligature| ligature.3 == code) {
+        Some(ligature) => ligature.1,
+        None => win_ansi_char(code)?,
+    };
+    face.glyph_index(c)
+}
+
+/// A glyph outline collected into a path, in font units
+struct GlyphPath(PathBuilder);
+
+impl OutlineBuilder for GlyphPath {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+impl<'a> Canvas<'a> {
+    fn new(pixmap: Pixmap, page: &'a RasterPage<'a>, device: Transform) -> Self {
+        let faces = page
+            .fonts
+            .iter()
+            .map(|font| {
+                font.outlines
+                    .and_then(|font| Face::parse(&font.data, 0).ok())
+            })
+            .collect();
+        Self {
+            pixmap,
+            page,
+            faces,
+            images: HashMap::new(),
+            state: GraphicsState {
+                ctm: device,
+                fill: Color::BLACK,
+                stroke: Color::BLACK,
+                line: Stroke::default(),
+                clip: None,
+                font: None,
+                font_size: 0.0,
+                leading: 0.0,
+                char_spacing: 0.0,
+                word_spacing: 0.0,
+                horizontal_scale: 1.0,
+                rise: 0.0,
+                invisible: false,
+            },
+            saved: Vec::new(),
+            path: PathBuilder::new(),
+            current_point: (0.0, 0.0),
+            clip_rule: None,
+            text_matrix: Transform::identity(),
+            line_matrix: Transform::identity(),
+        }
+    }
+
+    /// Runs `operator` on its `operands`, what isn't understood is skipped
+    fn run(&mut self, operator: &str, operands: &[Operand]) {
+        let state = &mut self.state;
+        match operator {
+            "q" => self.saved.push(state.clone()),
+            "Q" => {
+                if let Some(saved) = self.saved.pop() {
+                    self.state = saved;
+                }
+            }
+            "cm" => {
+                if let Some([a, b, c, d, e, f]) = numbers(operands) {
+                    state.ctm = state.ctm.pre_concat(Transform::from_row(a, b, c, d, e, f));
+                }
+            }
+            "w" => {
+                if let Some([width]) = numbers(operands) {
+                    state.line.width = width.max(0.0);
+                }
+            }
+            "J" => {
+                if let Some([cap]) = numbers(operands) {
+                    state.line.line_cap = match cap as i32 {
+                        1 => LineCap::Round,
+                        2 => LineCap::Square,
+                        _ => LineCap::Butt,
+                    };
+                }
+            }
+            "j" => {
+                if let Some([join]) = numbers(operands) {
+                    state.line.line_join = match join as i32 {
+                        1 => LineJoin::Round,
+                        2 => LineJoin::Bevel,
+                        _ => LineJoin::Miter,
+                    };
+                }
+            }
+            "M" => {
+                if let Some([limit]) = numbers(operands) {
+                    state.line.miter_limit = limit.max(1.0);
+                }
+            }
+            "d" => {
+                if let [.., Operand::Array(array), Operand::Number(phase)] = operands {
+                    let array = array
+                        .iter()
+                        .filter_map(|operand| match operand {
+                            Operand::Number(number) => Some(*number),
+                            _ => None,
+                        })
+                        .collect();
+                    // an empty array is a solid line
+                    state.line.dash = StrokeDash::new(array, *phase);
+                }
+            }
+            "g" => state.fill = numbers(operands).map_or(Color::BLACK, |[v]| gray(v)),
+            "G" => state.stroke = numbers(operands).map_or(Color::BLACK, |[v]| gray(v)),
+            "rg" => state.fill = numbers(operands).map_or(Color::BLACK, rgb),
+            "RG" => state.stroke = numbers(operands).map_or(Color::BLACK, rgb),
+            "k" => state.fill = numbers(operands).map_or(Color::BLACK, cmyk),
+            "K" => state.stroke = numbers(operands).map_or(Color::BLACK, cmyk),
+            "m" => {
+                if let Some([x, y]) = numbers(operands) {
+                    self.path.move_to(x, y);
+                    self.current_point = (x, y);
+                }
+            }
+            "l" => {
+                if let Some([x, y]) = numbers(operands) {
+                    self.path.line_to(x, y);
+                    self.current_point = (x, y);
+                }
+            }
+            "c" => {
+                if let Some([x1, y1, x2, y2, x, y]) = numbers(operands) {
+                    self.path.cubic_to(x1, y1, x2, y2, x, y);
+                    self.current_point = (x, y);
+                }
+            }
+            "v" => {
+                if let Some([x2, y2, x, y]) = numbers(operands) {
+                    let (x1, y1) = self.current_point;
+                    self.path.cubic_to(x1, y1, x2, y2, x, y);
+                    self.current_point = (x, y);
+                }
+            }
+            "y" => {
+                if let Some([x1, y1, x, y]) = numbers(operands) {
+                    self.path.cubic_to(x1, y1, x, y, x, y);
+                    self.current_point = (x, y);
+                }
+            }
+            "h" => self.path.close(),
+            "re" => {
+                if let Some([x, y, width, height]) = numbers(operands) {
+                    self.path.move_to(x, y);
+                    self.path.line_to(x + width, y);
+                    self.path.line_to(x + width, y + height);
+                    self.path.line_to(x, y + height);
+                    self.path.close();
+                    self.current_point = (x, y);
+                }
+            }
+            "W" => self.clip_rule = Some(FillRule::Winding),
+            "W*" => self.clip_rule = Some(FillRule::EvenOdd),
+            "f" | "F" => self.paint_path(Some(FillRule::Winding), false),
+            "f*" => self.paint_path(Some(FillRule::EvenOdd), false),
+            "S" => self.paint_path(None, true),
+            "s" => {
+                self.path.close();
+                self.paint_path(None, true);
+            }
+            "B" => self.paint_path(Some(FillRule::Winding), true),
+            "B*" => self.paint_path(Some(FillRule::EvenOdd), true),
+            "b" | "b*" => {
+                self.path.close();
+                let rule = if operator == "b" {
+                    FillRule::Winding
+                } else {
+                    FillRule::EvenOdd
+                };
+                self.paint_path(Some(rule), true);
+            }
+            "n" => self.paint_path(None, false),
+            "BT" => {
+                self.text_matrix = Transform::identity();
+                self.line_matrix = Transform::identity();
+            }
+            "Tf" => {
+                if let [.., Operand::Name(name), Operand::Number(size)] = operands {
+                    state.font = name
+                        .strip_prefix('F')
+                        .and_then(|index| index.parse::<usize>().ok())
+                        .and_then(|index| index.checked_sub(1));
+                    state.font_size = *size;
+                }
+            }
+            "Tc" => state.char_spacing = numbers(operands).map_or(0.0, |[v]| v),
+            "Tw" => state.word_spacing = numbers(operands).map_or(0.0, |[v]| v),
+            "Tz" => state.horizontal_scale = numbers(operands).map_or(100.0, |[v]| v) / 100.0,
+            "TL" => state.leading = numbers(operands).map_or(0.0, |[v]| v),
+            "Ts" => state.rise = numbers(operands).map_or(0.0, |[v]| v),
+            "Tr" => state.invisible = numbers(operands).is_some_and(|[mode]| mode == 3.0),
+            "Td" | "TD" => {
+                if let Some([x, y]) = numbers(operands) {
+                    if operator == "TD" {
+                        state.leading = -y;
+                    }
+                    self.next_line(x, y);
+                }
+            }
+            "Tm" => {
+                if let Some([a, b, c, d, e, f]) = numbers(operands) {
+                    self.line_matrix = Transform::from_row(a, b, c, d, e, f);
+                    self.text_matrix = self.line_matrix;
+                }
+            }
+            "T*" => self.next_line(0.0, -self.state.leading),
+            "Tj" => {
+                if let [.., Operand::String(bytes)] = operands {
+                    self.show(bytes);
+                }
+            }
+            "'" => {
+                self.next_line(0.0, -self.state.leading);
+                if let [.., Operand::String(bytes)] = operands {
+                    self.show(bytes);
+                }
+            }
+            "\"" => {
+                if let [Operand::Number(word), Operand::Number(char), Operand::String(bytes)] =
+                    operands
+                {
+                    state.word_spacing = *word;
+                    state.char_spacing = *char;
+                    self.next_line(0.0, -self.state.leading);
+                    self.show(bytes);
+                }
+            }
+            "TJ" => {
+                if let [.., Operand::Array(parts)] = operands {
+                    for part in parts {
+                        match part {
+                            Operand::String(bytes) => self.show(bytes),
+                            // moves the next glyph left, in 1/1000 em
+                            Operand::Number(number) => {
+                                let state = &self.state;
+                                let dx =
+                                    -number / 1000.0 * state.font_size * state.horizontal_scale;
+                                self.text_matrix = self
+                                    .text_matrix
+                                    .pre_concat(Transform::from_translate(dx, 0.0));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            "Do" => {
+                if let [.., Operand::Name(name)] = operands {
+                    self.draw_image(name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn next_line(&mut self, x: f32, y: f32) {
+        self.line_matrix = self.line_matrix.pre_concat(Transform::from_translate(x, y));
+        self.text_matrix = self.line_matrix;
+    }
+
+    /// Fills and strokes the path as asked, then clips to it if a W came before
+    fn paint_path(&mut self, fill: Option<FillRule>, stroke: bool) {
+        let path = std::mem::take(&mut self.path).finish();
+        let clip_rule = self.clip_rule.take();
+        let Some(path) = path else {
+            return;
+        };
+        let state = &self.state;
+        let mask = state.clip.as_deref();
+        if let Some(rule) = fill {
+            self.pixmap
+                .fill_path(&path, &paint(state.fill), rule, state.ctm, mask);
+        }
+        if stroke {
+            self.pixmap
+                .stroke_path(&path, &paint(state.stroke), &state.line, state.ctm, mask);
+        }
+        if let Some(rule) = clip_rule {
+            let mut clip = match &state.clip {
+                Some(clip) => Mask::clone(clip),
+                None => {
+                    let Some(mut clip) = Mask::new(self.pixmap.width(), self.pixmap.height())
+                    else {
+                        return;
+                    };
+                    clip.fill_path(&path, rule, true, state.ctm);
+                    self.state.clip = Some(Arc::new(clip));
+                    return;
+                }
+            };
+            clip.intersect_path(&path, rule, true, state.ctm);
+            self.state.clip = Some(Arc::new(clip));
+        }
+    }
+
+    /// Draws `bytes` in the current font from the text position, moving it on
+    fn show(&mut self, bytes: &[u8]) {
+        let state = &self.state;
+        let Some(index) = state.font else {
+            return;
+        };
+        let Some(font) = self.page.fonts.get(index) else {
+            return;
+        };
+        let face = self.faces[index].as_ref().filter(|_| !state.invisible);
+        let fill = paint(state.fill);
+        for &code in bytes {
+            if let Some(face) = face
+                && let Some(glyph) = glyph(face, code)
+            {
+                let mut outline = GlyphPath(PathBuilder::new());
+                if face.outline_glyph(glyph, &mut outline).is_some()
+                    && let Some(path) = outline.0.finish()
+                {
+                    let units = state.font_size / face.units_per_em() as f32;
+                    let transform =
+                        state
+                            .ctm
+                            .pre_concat(self.text_matrix)
+                            .pre_concat(Transform::from_row(
+                                units * state.horizontal_scale,
+                                0.0,
+                                0.0,
+                                units,
+                                0.0,
+                                state.rise,
+                            ));
+                    self.pixmap.fill_path(
+                        &path,
+                        &fill,
+                        FillRule::Winding,
+                        transform,
+                        state.clip.as_deref(),
+                    );
+                }
+            }
+            let mut advance =
+                font.metrics.advance(code) as f32 / 1000.0 * state.font_size + state.char_spacing;
+            // Tw only stretches the single byte space
+            if code == b' ' {
+                advance += state.word_spacing;
+            }
+            self.text_matrix = self.text_matrix.pre_concat(Transform::from_translate(
+                advance * state.horizontal_scale,
+                0.0,
+            ));
+        }
+    }
+
+    /// Draws the image `name` into the unit square of the current space
+    fn draw_image(&mut self, name: &str) {
+        let page = self.page;
+        let image = self.images.entry(name.to_string()).or_insert_with(|| {
+            let data = page.images.get(name)?;
+            decode_jpeg(data).ok()
+        });
+        let Some(image) = image else {
+            return;
+        };
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        // the first row of the image is at the top of the square
+        let transform = self.state.ctm.pre_concat(Transform::from_row(
+            1.0 / width,
+            0.0,
+            0.0,
+            -1.0 / height,
+            0.0,
+            1.0,
+        ));
+        let paint = PixmapPaint {
+            quality: FilterQuality::Bilinear,
+            ..PixmapPaint::default()
+        };
+        self.pixmap.draw_pixmap(
+            0,
+            0,
+            image.as_ref(),
+            &paint,
+            transform,
+            self.state.clip.as_deref(),
+        );
+    }
+}
+
+/// A JPEG as the opaque pixmap it draws
+fn decode_jpeg(data: &[u8]) -> Result<Pixmap> {
+    let image = image::load_from_memory_with_format(data, ImageFormat::Jpeg)?.to_rgba8();
+    let size =
+        IntSize::from_wh(image.width(), image.height()).ok_or_else(|| anyhow!("empty image"))?;
+    Pixmap::from_vec(image.into_raw(), size).ok_or_else(|| anyhow!("unusable image size"))
+}
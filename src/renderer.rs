@@ -0,0 +1,2375 @@
+use anyhow::Result;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use rustybuzz::Script;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{Read, Seek, Write};
+use std::sync::Arc;
+use tracing::{debug, debug_span, info_span, trace, Span};
+
+use super::border_drawing::{draw_border, thickness};
+use super::chart_drawing::draw_chart;
+use super::conversion_cache::{resolve_font_uncached, ConversionCache};
+use super::diagram_drawing::draw_diagram;
+use super::document_model::{
+    Alignment, Block, Border, Chart, ControlField, Diagram, Document, EmbeddedObject, FormField,
+    FormFieldKind, Inline, LabelSuffix, MergeField, Omitted, Paragraph, ParagraphBorders,
+    Reference, RowHeight, Run, Section, Table, TableCell, TableRow, TextDirection, VerticalAlign,
+};
+use super::emoji::{glyph_outline, is_emoji, EMOJI_FAMILIES};
+use super::error::ConvertError;
+use super::font_metrics::{PdfFont, StandardFont, TrueTypeFont};
+use super::font_provider::{FontProvider, FontRequest};
+use super::image_preprocessor::ImageParams;
+use super::indic::{indic_script, is_joiner, shaped_outline, IndicScript};
+use super::letterhead::{Letterhead, LetterheadPlacement};
+use super::object_placeholder;
+use super::page_hooks::{PageCanvas, PageHooks};
+use super::page_layout::{PageLayout, WrappedLine};
+use super::page_range::PageRanges;
+use super::pdf_document::{
+    image_operators, num, text_operators, ContentEncoder, PageLabel, PageLabelStyle, PdfDocument,
+    PreparedStream, TextLine,
+};
+use super::pdf_form::{FormWidget, WidgetKind};
+use super::progress::ProgressSink;
+use super::raster::{outline_families, render_page, PageImages, RasterFont, RasterPage};
+use super::report::Warning;
+use super::table_layout::{column_count, column_widths, table_content, ContentWidth};
+use super::Options;
+
+struct RenderEnv<'a, W: Write + Seek> {
+    pdf_document: &'a mut PdfDocument<W>,
+    progress: &'a dyn ProgressSink,
+    seen_rid: HashSet<String>,
+    current_page: Vec<PageItem>,
+    pending: Vec<PendingPage>, // laid out, waiting to be written
+    layout: PageLayout,
+    pages: Option<&'a PageRanges>,
+    laid_out: usize,             // pages laid out so far, written or not
+    written: Vec<usize>,         // the laid out page (0 based) behind each written page
+    page_labels: Vec<PageLabel>, // by laid out page
+    next_number: u32,            // first page number of a section that doesn't restart numbering
+    warnings: Vec<Warning>,
+    font_provider: &'a dyn FontProvider,
+    page_hooks: &'a [Arc<dyn PageHooks>],
+    letterhead: Option<&'a Letterhead>,
+    page_images: Option<&'a PageImages>,
+    cache: Option<&'a ConversionCache>,
+    drop_images: bool,
+    mark_unsupported: bool,
+    interactive_forms: bool,
+    // per family, bold and italic: the /F index, the font and whether it is the one asked for
+    resolved_fonts: HashMap<(String, bool, bool), (usize, PdfFont, bool)>,
+    substituted_fonts: BTreeMap<String, String>,
+    // only finding the pages bookmarks end up on, nothing is written
+    dry_run: bool,
+    bookmarks: &'a BTreeMap<String, String>,
+    pending_bookmarks: Vec<String>, // passed, waiting for the next thing placed
+    bookmark_pages: HashMap<String, usize>, // laid out page (0 based) of each bookmark
+    page_refs: HashMap<String, String>, // page label of each bookmark, from the dry run
+    merge_values: &'a BTreeMap<String, String>,
+    unmerged: BTreeSet<String>, // merge fields already warned about
+    // form fields in the text being wrapped, each stands in it as a run of FIELD_MARK
+    pending_fields: VecDeque<FormField>,
+    form_fields: usize, // placed so far, to name the ones without a name
+    paragraph_box: Option<ParagraphBox>,
+    // laying out a table cell, what is placed is kept for the row to move
+    // where it fits
+    in_cell: bool,
+    // the parts of the cell being laid out that ended on earlier pages of
+    // a row split across them, with their heights
+    cell_parts: Vec<(Vec<PageItem>, f32)>,
+    first_line: Option<FirstLine>,
+    alignment: Alignment, // of the paragraph being written
+    emoji_fonts: Option<Vec<Arc<TrueTypeFont>>>, // looked up with the first emoji
+    // the outline of each emoji at the body size and its advance, None
+    // when no emoji font has one
+    emoji_glyphs: HashMap<char, Option<(String, f32)>>,
+    // the fonts of each Indic script, looked up with its first run
+    script_fonts: HashMap<Script, Vec<Arc<TrueTypeFont>>>,
+    // what the standard fonts are drawn with in page images, looked up with
+    // the first page
+    outline_fonts: HashMap<StandardFont, Option<Arc<TrueTypeFont>>>,
+}
+
+/// The first line of the paragraph being written, until it is laid out
+#[derive(Default)]
+struct FirstLine {
+    /// where its text starts, from the left indent
+    indent: f32,
+    /// a list label hung out in front of it, and where it starts from the
+    /// left indent
+    label: Option<(String, f32)>,
+}
+
+/// A paragraph with borders or shading being laid out, the part of it on
+/// the current page is drawn when the page or the paragraph ends
+struct ParagraphBox {
+    borders: ParagraphBorders,
+    shading: Option<[u8; 3]>,
+    top: Option<Border>, // the top or between line, None inside a box of several
+    bottom: Option<Border>, // None when the next paragraph is in the same box
+    extent: Option<(f32, f32)>, // top and bottom y of what is on the current page
+    start: usize,        // the first item of the current page in the box
+    first: bool,         // the extent starts with the paragraph
+    placed: bool,        // anything after the space above the text
+}
+
+/// Holds the place of a form field in the text of a paragraph, written as
+/// '?' it is measured like any other character
+const FIELD_MARK: char = '\u{fffc}';
+
+/// Text fields are at least this wide, in points, to leave room for typing
+const MIN_FIELD_WIDTH: f32 = 72.0;
+
+/// Laid out pages held back to be written together, the content streams of a
+/// batch are serialized and compressed in parallel
+const PAGE_BATCH: usize = 64;
+
+/// Something placed on the page being laid out
+enum PageItem {
+    Text {
+        lines: Vec<TextLine>,
+        font: TextFont,
+        x: f32,
+        y: f32, // first baseline
+    },
+    Image(PlacedImage),
+    /// content stream operators, charts
+    Graphics(String),
+    /// a fillable form field
+    Field(FormWidget),
+}
+
+struct PlacedImage {
+    rid: String,
+    params: ImageParams,
+    rect: (f32, f32, f32, f32),
+}
+
+/// A laid out page waiting to be written
+struct PendingPage {
+    laid_out: usize, // 0 based
+    items: Vec<PageItem>,
+}
+
+/// A pending page with its content stream ready for its object id
+struct PreparedPage {
+    laid_out: usize,
+    // the page hooks' drawing, the items and what the hooks draw over them
+    content: Option<Result<PreparedStream>>,
+    // images are copied into the output as they are written, by name
+    images: Vec<(String, PlacedImage)>,
+    // fields and their appearances are written right before their page
+    fields: Vec<FormWidget>,
+    // the content as it is drawn, kept for the page images
+    raster: Option<String>,
+}
+
+/// A paragraph's text is set in one font, the one its first run asks for
+#[derive(Clone)]
+struct TextFont {
+    index: usize,
+    font: PdfFont,
+}
+
+/// Labels the pages laid out for `section`, starting at `first_page`
+fn label_section<W: Write + Seek>(env: &mut RenderEnv<W>, section: &Section, first_page: usize) {
+    let pages = env.laid_out.saturating_sub(first_page) as u32;
+    if pages > 0 {
+        // without an explicit start numbering continues from the previous section
+        let start = section.number_start.unwrap_or(env.next_number);
+        env.page_labels.push(PageLabel {
+            first_page,
+            style: section.number_format.unwrap_or(PageLabelStyle::Decimal),
+            start,
+        });
+        env.next_number = start + pages;
+    }
+}
+
+fn page_has_content<W: Write + Seek>(env: &RenderEnv<W>) -> bool {
+    !env.layout.is_empty()
+}
+
+/// Whether the page being laid out goes into the PDF, content on the
+/// others is placed but never written
+fn page_selected<W: Write + Seek>(env: &RenderEnv<W>) -> bool {
+    !env.dry_run
+        && (env.in_cell
+            || env
+                .pages
+                .is_none_or(|pages| pages.contains(env.laid_out + 1)))
+}
+
+/// Every page left to lay out is outside the selection
+fn past_selection<W: Write + Seek>(env: &RenderEnv<W>) -> bool {
+    env.pages
+        .and_then(PageRanges::last)
+        .is_some_and(|last| env.laid_out >= last)
+}
+
+/// Queues the page being laid out to be written, the queue is written once
+/// it holds a batch
+fn flush_page<W: Write + Seek>(env: &mut RenderEnv<W>) {
+    draw_paragraph_box(env, false);
+    // a cell of a row split across pages carries on in its next part
+    if env.in_cell {
+        let items = std::mem::take(&mut env.current_page);
+        env.cell_parts.push((items, env.layout.used()));
+        env.layout.reset();
+        return;
+    }
+    if page_selected(env) {
+        env.pending.push(PendingPage {
+            laid_out: env.laid_out,
+            items: std::mem::take(&mut env.current_page),
+        });
+        if env.pending.len() >= PAGE_BATCH {
+            write_pending(env);
+        }
+    }
+    env.laid_out += 1;
+    env.current_page.clear();
+    env.layout.reset();
+}
+
+/// Writes the queued pages, their content streams are prepared on the rayon
+/// pool while object ids are handed out here, in page order
+fn write_pending<W: Write + Seek>(env: &mut RenderEnv<W>) {
+    let pages = std::mem::take(&mut env.pending);
+    if pages.is_empty() {
+        return;
+    }
+    let encoder = env.pdf_document.content_encoder();
+    let page_hooks = env.page_hooks;
+    let letterhead = env.letterhead;
+    let keep_content = env.page_images.is_some();
+    // every page of the batch is written, in order
+    let written = env.pdf_document.page_count();
+    let span = Span::current();
+    // prepared one after the other without the `rayon` feature
+    #[cfg(feature = "rayon")]
+    let pages = pages.into_par_iter().enumerate();
+    #[cfg(not(feature = "rayon"))]
+    let pages = pages.into_iter().enumerate();
+    let prepared: Vec<PreparedPage> = pages
+        .map(|(i, page)| {
+            let _span =
+                debug_span!(parent: &span, "prepare_page", page = page.laid_out + 1).entered();
+            let output_page = written + i + 1;
+            prepare_page(
+                page,
+                output_page,
+                &encoder,
+                page_hooks,
+                letterhead,
+                keep_content,
+            )
+        })
+        .collect();
+    for page in prepared {
+        write_page(env, page);
+    }
+}
+
+/// Serializes and compresses the content of `page` as a single stream,
+/// its images named after the page and their place on it. It is the
+/// `output_page`th page of the PDF, 1 based, drawn over or under the
+/// letterhead. The content is kept as it is with `keep_content`
+fn prepare_page(
+    page: PendingPage,
+    output_page: usize,
+    encoder: &ContentEncoder,
+    page_hooks: &[Arc<dyn PageHooks>],
+    letterhead: Option<&Letterhead>,
+    keep_content: bool,
+) -> PreparedPage {
+    let setup = encoder.page_setup();
+    let pages = (page.laid_out + 1, output_page);
+    let (underlay, overlay) = run_page_hooks(page_hooks, encoder, pages);
+    let letterhead = letterhead.map(|letterhead| {
+        let operators = letterhead.operators(output_page, setup.width, setup.height);
+        (letterhead.placement, operators)
+    });
+    let mut content = Vec::new();
+    if let Some((LetterheadPlacement::Underlay, operators)) = &letterhead {
+        content.push(operators.clone());
+    }
+    content.push(underlay);
+    let mut images = Vec::new();
+    let mut fields = Vec::new();
+    for item in page.items {
+        match item {
+            PageItem::Text { lines, font, x, y } => {
+                let font = (font.index, &font.font);
+                content.push(text_operators(setup, &lines, font, x, y));
+            }
+            PageItem::Image(image) => {
+                let name = format!("Im{}_{}", output_page, images.len() + 1);
+                content.push(image_operators(&name, image.rect));
+                images.push((name, image));
+            }
+            PageItem::Graphics(graphics) => content.push(graphics),
+            PageItem::Field(widget) => fields.push(widget),
+        }
+    }
+    content.push(overlay);
+    if let Some((LetterheadPlacement::Overlay, operators)) = letterhead {
+        content.push(operators);
+    }
+    content.retain(|part| !part.is_empty());
+    let content = content.join("\n");
+    PreparedPage {
+        laid_out: page.laid_out,
+        content: (!content.is_empty()).then(|| encoder.content(&content)),
+        images,
+        fields,
+        raster: keep_content.then_some(content),
+    }
+}
+
+/// Lets the page hooks draw on the page, what the start hooks draw goes in
+/// front of the page content and what the end hooks draw over it. `pages`
+/// are the page number and where the page goes in the PDF, both 1 based
+fn run_page_hooks(
+    page_hooks: &[Arc<dyn PageHooks>],
+    encoder: &ContentEncoder,
+    pages: (usize, usize),
+) -> (String, String) {
+    if page_hooks.is_empty() {
+        return (String::new(), String::new());
+    }
+    let setup = encoder.page_setup();
+    let mut canvas = PageCanvas::new(pages, setup.width, setup.height, setup.font);
+    let mut underlay = String::new();
+    for hook in page_hooks {
+        hook.on_page_start(&mut canvas);
+        underlay.extend(canvas.take_content());
+    }
+    let mut overlay = String::new();
+    for hook in page_hooks {
+        hook.on_page_end(&mut canvas);
+        overlay.extend(canvas.take_content());
+    }
+    (underlay, overlay)
+}
+
+fn write_page<W: Write + Seek>(env: &mut RenderEnv<W>, page: PreparedPage) {
+    let _span = debug_span!("write_page", page = page.laid_out + 1).entered();
+    for (name, image) in &page.images {
+        write_placed_image(env, name, image);
+    }
+    for widget in &page.fields {
+        if let Err(e) = env.pdf_document.add_form_field(widget) {
+            let err = ConvertError::pdf_write("form field", e);
+            skipped(
+                env,
+                Warning::DroppedContent {
+                    what: format!("form field {}", widget.name),
+                    reason: Warning::reason(&err),
+                },
+            );
+        }
+    }
+    let content = page
+        .content
+        .map(|stream| stream.and_then(|stream| env.pdf_document.write_prepared(stream)))
+        .transpose();
+    let content = match content {
+        Ok(content) => content,
+        Err(e) => {
+            let err = ConvertError::pdf_write("content stream", e);
+            let what = format!("content of page {}", page.laid_out + 1);
+            skipped(
+                env,
+                Warning::DroppedContent {
+                    what,
+                    reason: Warning::reason(&err),
+                },
+            );
+            None
+        }
+    };
+    match env.pdf_document.new_page_obj(content) {
+        Ok(_) => {
+            env.written.push(page.laid_out);
+            let output_page = env.pdf_document.page_count();
+            if let Some(content) = &page.raster {
+                write_page_image(env, content, &page.images, output_page);
+            }
+            env.progress.page_written(output_page);
+        }
+        Err(e) => {
+            let what = format!("page {}", page.laid_out + 1);
+            let err = ConvertError::pdf_write("page object", e);
+            skipped(
+                env,
+                Warning::DroppedContent {
+                    what,
+                    reason: Warning::reason(&err),
+                },
+            );
+        }
+    }
+}
+
+/// Renders the page written as `output_page` from its `content` and hands
+/// the image to the page image sink
+fn write_page_image<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    content: &str,
+    images: &[(String, PlacedImage)],
+    output_page: usize,
+) {
+    let Some(page_images) = env.page_images else {
+        return;
+    };
+    let standard: Vec<StandardFont> = env
+        .pdf_document
+        .fonts()
+        .iter()
+        .filter_map(|font| match font {
+            PdfFont::Standard(font) => Some(*font),
+            PdfFont::TrueType(_) => None,
+        })
+        .collect();
+    for font in standard {
+        outline_font(env, font);
+    }
+    let rendered = {
+        let fonts = env
+            .pdf_document
+            .fonts()
+            .iter()
+            .map(|font| RasterFont {
+                metrics: font,
+                outlines: match font {
+                    PdfFont::Standard(font) => env.outline_fonts[font].as_deref(),
+                    PdfFont::TrueType(font) => Some(font),
+                },
+            })
+            .collect();
+        // those that can't be read were reported when they were embedded
+        let images = images
+            .iter()
+            .filter_map(|(name, image)| {
+                let mut data = Vec::with_capacity(image.params.len);
+                image.params.open().ok()?.read_to_end(&mut data).ok()?;
+                Some((name.as_str(), data))
+            })
+            .collect();
+        let setup = env.pdf_document.page_setup();
+        let page = RasterPage {
+            width: setup.width,
+            height: setup.height,
+            rotate: env.pdf_document.page_rotation(),
+            fonts,
+            images,
+        };
+        render_page(content, &page, page_images)
+            .and_then(|image| Ok(page_images.sink.page_image(output_page, image)?))
+    };
+    if let Err(e) = rendered {
+        skipped(
+            env,
+            Warning::DroppedContent {
+                what: format!("image of page {}", output_page),
+                reason: format!("{:#}", e),
+            },
+        );
+    }
+}
+
+/// The TrueType font standard font `font` is drawn with in page images, the
+/// first the provider has of families like it; text without one is left
+/// out of the images with a warning
+fn outline_font<W: Write + Seek>(env: &mut RenderEnv<W>, font: StandardFont) {
+    if env.outline_fonts.contains_key(&font) {
+        return;
+    }
+    let outlines = family_fonts(env.font_provider, env.cache, outline_families(font))
+        .into_iter()
+        .next();
+    if outlines.is_none() {
+        skipped(
+            env,
+            Warning::DroppedContent {
+                what: format!("{} text in the page images", font.base_font()),
+                reason: "no TrueType font to draw it with".to_string(),
+            },
+        );
+    }
+    env.outline_fonts.insert(font, outlines);
+}
+
+/// Embeds an image placed on the page as `name`, or a blank in its place
+/// when it can't be read or written
+fn write_placed_image<W: Write + Seek>(env: &mut RenderEnv<W>, name: &str, image: &PlacedImage) {
+    let params = &image.params;
+    let written = match params.open() {
+        Ok(f) => env
+            .pdf_document
+            .new_img_obj(f, params.w, params.h, params.components, params.len, name)
+            .map_err(|e| ConvertError::pdf_write(&format!("image {}", image.rid), e)),
+        Err(e) => Err(ConvertError::Io(e)),
+    };
+    if let Err(err) = written {
+        let reason = Warning::reason(&err);
+        skipped(
+            env,
+            Warning::SkippedImage {
+                rid: image.rid.clone(),
+                reason,
+            },
+        );
+        // a writer failing here fails the page object as well, reported there
+        let _ = env.pdf_document.blank_xobject(name);
+    }
+}
+
+/// Content that could not be converted is left out, the conversion carries on
+fn skipped<W: Write + Seek>(env: &mut RenderEnv<W>, warning: Warning) {
+    debug!("{}", warning);
+    env.warnings.push(warning);
+}
+
+/// Reserves `height` points on the current page, starting a new page when full
+fn place_block<W: Write + Seek>(env: &mut RenderEnv<W>, height: f32) -> f32 {
+    let y = match env.layout.place(height) {
+        Some(y) => y,
+        None => {
+            flush_page(env);
+            env.layout
+                .place(height)
+                .expect("an empty page always accepts a block")
+        }
+    };
+    mark_bookmarks(env);
+    extend_paragraph_box(env, y, height);
+    y
+}
+
+/// Starts the box of `paragraph` when it has borders or shading, `above`
+/// and `below` when the paragraphs around it have the same borders and
+/// share the box. Room for the line above is kept before the text
+fn start_paragraph_box<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    paragraph: &Paragraph,
+    above: bool,
+    below: bool,
+) {
+    let borders = &paragraph.borders;
+    if borders.is_empty() && paragraph.shading.is_none() {
+        return;
+    }
+    let top = if above { borders.between } else { borders.top };
+    env.paragraph_box = Some(ParagraphBox {
+        borders: borders.clone(),
+        shading: paragraph.shading,
+        top,
+        bottom: if below { None } else { borders.bottom },
+        extent: None,
+        start: 0,
+        first: true,
+        placed: false,
+    });
+    if let Some(top) = top {
+        place_block(env, top.space + thickness(&top));
+    }
+    if let Some(paragraph_box) = env.paragraph_box.as_mut() {
+        paragraph_box.placed = false;
+    }
+}
+
+/// Ends the box of the paragraph just laid out, an empty paragraph keeps a
+/// line so its border or shading still shows, e.g. as a horizontal rule
+fn end_paragraph_box<W: Write + Seek>(env: &mut RenderEnv<W>) {
+    let Some(paragraph_box) = &env.paragraph_box else {
+        return;
+    };
+    let (placed, bottom) = (paragraph_box.placed, paragraph_box.bottom);
+    if !placed {
+        place_block(env, env.layout.setup().line_height());
+    }
+    if let Some(bottom) = bottom {
+        place_block(env, bottom.space + thickness(&bottom));
+    }
+    draw_paragraph_box(env, true);
+    env.paragraph_box = None;
+}
+
+/// The `height` points placed at `y` are inside the current paragraph box
+fn extend_paragraph_box<W: Write + Seek>(env: &mut RenderEnv<W>, y: f32, height: f32) {
+    let items = env.current_page.len();
+    if let Some(paragraph_box) = env.paragraph_box.as_mut() {
+        paragraph_box.placed = true;
+        paragraph_box.extent = Some(match paragraph_box.extent {
+            Some((top, _)) => (top, y),
+            None => {
+                paragraph_box.start = items;
+                (y + height, y)
+            }
+        });
+    }
+}
+
+/// Draws the part of the paragraph box on the current page: the shading
+/// under its text, the sides, the line above where the paragraph starts
+/// and the one below when it is `last`
+fn draw_paragraph_box<W: Write + Seek>(env: &mut RenderEnv<W>, last: bool) {
+    let selected = page_selected(env);
+    let (left, width) = (env.layout.left(), env.layout.content_width());
+    let Some(paragraph_box) = env.paragraph_box.as_mut() else {
+        return;
+    };
+    let Some((top, bottom)) = paragraph_box.extent.take() else {
+        return;
+    };
+    let first = std::mem::replace(&mut paragraph_box.first, false);
+    if !selected {
+        return;
+    }
+    let borders = &paragraph_box.borders;
+    // the sides are outside the text, the lines across reach their outer
+    // edges and the shading their inner ones
+    let right = left + width;
+    let (mut inner_left, mut inner_right) = (left, right);
+    let (mut outer_left, mut outer_right) = (left, right);
+    let mut lines = Vec::new();
+    if let Some(border) = &borders.left {
+        inner_left = left - border.space;
+        outer_left = inner_left - thickness(border);
+        let x = inner_left - thickness(border) / 2.0;
+        lines.push(draw_border(border, (x, top), (x, bottom)));
+    }
+    if let Some(border) = &borders.right {
+        inner_right = right + border.space;
+        outer_right = inner_right + thickness(border);
+        let x = inner_right + thickness(border) / 2.0;
+        lines.push(draw_border(border, (x, top), (x, bottom)));
+    }
+    if first && let Some(border) = &paragraph_box.top {
+        let y = top - thickness(border) / 2.0;
+        lines.push(draw_border(border, (outer_left, y), (outer_right, y)));
+    }
+    if last && let Some(border) = &paragraph_box.bottom {
+        let y = bottom + thickness(border) / 2.0;
+        lines.push(draw_border(border, (outer_left, y), (outer_right, y)));
+    }
+    if let Some(color) = paragraph_box.shading {
+        let rect = (inner_left, bottom, inner_right - inner_left, top - bottom);
+        let fill = fill_rect(color, rect);
+        // under the text placed since the box started on this page
+        let start = paragraph_box.start.min(env.current_page.len());
+        env.current_page.insert(start, PageItem::Graphics(fill));
+    }
+    env.current_page
+        .extend(lines.into_iter().map(PageItem::Graphics));
+}
+
+/// Operators filling `rect`, as x, y, width and height, with `color`
+fn fill_rect([r, g, b]: [u8; 3], (x, y, width, height): (f32, f32, f32, f32)) -> String {
+    let c = |v: u8| num(v as f32 / 255.0);
+    format!(
+        "q {} {} {} rg {} {} {} {} re f Q",
+        c(r),
+        c(g),
+        c(b),
+        num(x),
+        num(y),
+        num(width),
+        num(height)
+    )
+}
+
+/// The bookmarks passed since the last thing placed are on the current page,
+/// those in a table cell once its row is placed
+fn mark_bookmarks<W: Write + Seek>(env: &mut RenderEnv<W>) {
+    if env.in_cell {
+        return;
+    }
+    for name in env.pending_bookmarks.drain(..) {
+        env.bookmark_pages.entry(name).or_insert(env.laid_out);
+    }
+}
+
+/// What a REF or PAGEREF field shows: the bookmarked text or the label of
+/// the page the bookmark is on, the result Word stored when it has neither
+fn reference_text<W: Write + Seek>(env: &mut RenderEnv<W>, reference: &Reference) -> String {
+    let resolved = if reference.page {
+        env.page_refs.get(&reference.bookmark).cloned()
+    } else {
+        env.bookmarks
+            .get(&reference.bookmark)
+            .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+    };
+    if let Some(text) = resolved {
+        return text;
+    }
+    if !env.dry_run && reference.cached.trim().is_empty() {
+        skipped(
+            env,
+            Warning::DroppedContent {
+                what: format!("reference to {}", reference.bookmark),
+                reason: "no such bookmark".to_string(),
+            },
+        );
+    }
+    reference.cached.clone()
+}
+
+/// The run of FIELD_MARK a form field takes up in the text, as wide as its
+/// value or its check box
+fn field_placeholder<W: Write + Seek>(
+    env: &RenderEnv<W>,
+    field: &FormField,
+    font: &TextFont,
+) -> String {
+    let setup = env.layout.setup();
+    let width = |text: &str| font.font.measure(text, setup.font_size);
+    let wanted = match field.kind {
+        FormFieldKind::Text => (width(&field.value) + 4.0).max(MIN_FIELD_WIDTH),
+        FormFieldKind::CheckBox => field.size.unwrap_or(setup.font_size),
+        // room for the longest entry and the arrow a viewer draws
+        FormFieldKind::DropDown | FormFieldKind::ComboBox => {
+            let longest = field
+                .items
+                .iter()
+                .map(|(display, _)| width(display))
+                .fold(width(&field.value), f32::max);
+            longest.max(MIN_FIELD_WIDTH / 2.0) + setup.font_size + 4.0
+        }
+    };
+    let mark = width(FIELD_MARK.encode_utf8(&mut [0; 4])).max(1.0);
+    let count = (wanted.min(env.layout.content_width()) / mark)
+        .ceil()
+        .max(1.0);
+    std::iter::repeat_n(FIELD_MARK, count as usize).collect()
+}
+
+/// The widget for the next pending form field, taking up `width` points
+/// from `x` on the line with its baseline at `baseline`
+fn field_widget<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    x: f32,
+    baseline: f32,
+    width: f32,
+) -> Option<FormWidget> {
+    let field = env.pending_fields.pop_front()?;
+    env.form_fields += 1;
+    let font_size = env.layout.setup().font_size;
+    let (kind, default_name, rect) = match field.kind {
+        FormFieldKind::Text => (
+            WidgetKind::Text {
+                value: field.value,
+                default: field.default,
+                max_length: field.max_length,
+            },
+            "Text",
+            (x, baseline - font_size * 0.25, width, font_size * 1.15),
+        ),
+        FormFieldKind::CheckBox => {
+            let size = field.size.unwrap_or(font_size).min(width);
+            (
+                WidgetKind::CheckBox {
+                    checked: field.checked,
+                    default: field.default_checked,
+                },
+                "Check",
+                (x, baseline - size * 0.15, size, size),
+            )
+        }
+        FormFieldKind::DropDown | FormFieldKind::ComboBox => {
+            // the text shown picks the entry, by its display text or value
+            let selected = field
+                .items
+                .iter()
+                .find(|(display, value)| *display == field.value || *value == field.value);
+            let (shown, value) = match selected {
+                Some((display, value)) => (display.clone(), value.clone()),
+                None => (field.value.clone(), field.value),
+            };
+            (
+                WidgetKind::Choice {
+                    options: field.items,
+                    value,
+                    shown,
+                    editable: field.kind == FormFieldKind::ComboBox,
+                },
+                "Dropdown",
+                (x, baseline - font_size * 0.25, width, font_size * 1.15),
+            )
+        }
+    };
+    let name = field
+        .name
+        .unwrap_or_else(|| format!("{}{}", default_name, env.form_fields));
+    Some(FormWidget {
+        name,
+        kind,
+        rect,
+        font_size,
+    })
+}
+
+/// Places a line holding form fields piece by piece from `x`, the text
+/// between the fields as text and each run of FIELD_MARK as the next
+/// pending field
+fn write_field_line<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    line: &str,
+    font: &TextFont,
+    x: f32,
+    baseline: f32,
+) {
+    let setup = *env.layout.setup();
+    let width = |text: &str| font.font.measure(text, setup.font_size);
+    let selected = page_selected(env);
+    let mut rest = line;
+    let mut x = x;
+    while !rest.is_empty() {
+        let split = match rest.find(FIELD_MARK) {
+            Some(0) => rest.find(|c| c != FIELD_MARK).unwrap_or(rest.len()),
+            Some(at) => at,
+            None => rest.len(),
+        };
+        let (piece, tail) = rest.split_at(split);
+        let piece_width = width(piece);
+        if piece.starts_with(FIELD_MARK) {
+            // fields on pages left out are taken off the queue all the same
+            if let Some(widget) = field_widget(env, x, baseline, piece_width)
+                && selected
+            {
+                env.current_page.push(PageItem::Field(widget));
+            }
+        } else if selected {
+            write_piece(env, piece, font, x, baseline);
+        }
+        x += piece_width;
+        rest = tail;
+    }
+}
+
+/// What a MERGEFIELD shows: the value given for its name, matched without
+/// regard to case like Word does, or else its placeholder
+fn merge_text<W: Write + Seek>(env: &mut RenderEnv<W>, field: &MergeField) -> String {
+    let value = env.merge_values.get(&field.name).or_else(|| {
+        env.merge_values
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&field.name))
+            .map(|(_, value)| value)
+    });
+    match value {
+        Some(value) if value.is_empty() => String::new(),
+        Some(value) => format!("{}{}{}", field.before, value, field.after),
+        None => {
+            // only worth a warning when values were given at all
+            if !env.dry_run
+                && !env.merge_values.is_empty()
+                && env.unmerged.insert(field.name.clone())
+            {
+                env.warnings.push(Warning::MissingMergeValue {
+                    field: field.name.clone(),
+                });
+            }
+            format!("\u{ab}{}\u{bb}", field.name)
+        }
+    }
+}
+
+/// Adds the lines of a paragraph that ended up on the current page, the
+/// first starting `first_line` points further in than the others; lines
+/// holding form fields or tabs are set from the left, those with emoji or
+/// Indic text aligned without spreading their spaces and the others aligned
+fn write_lines<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    lines: &[WrappedLine],
+    font: &TextFont,
+    baseline: Option<f32>,
+    first_line: f32,
+) {
+    let Some(baseline) = baseline else {
+        return;
+    };
+    let setup = *env.layout.setup();
+    let line_height = setup.line_height();
+    let (left, width) = (env.layout.left(), env.layout.content_width());
+    let mut plain = Vec::new(); // the lines since the last one with a field
+    let mut first = baseline;
+    for (i, line) in lines.iter().enumerate() {
+        let indent = match i {
+            0 => first_line,
+            _ => 0.0,
+        };
+        let y = baseline - i as f32 * line_height;
+        if line.text.contains(FIELD_MARK) {
+            write_plain_lines(env, std::mem::take(&mut plain), font, left, first);
+            write_field_line(env, &line.text, font, left + indent, y);
+            first = y - line_height;
+        } else if line.text.contains('\t') {
+            write_plain_lines(env, std::mem::take(&mut plain), font, left, first);
+            write_tab_line(env, &line.text, font, (left, indent), y);
+            first = y - line_height;
+        } else if line.text.contains(is_drawn) {
+            write_plain_lines(env, std::mem::take(&mut plain), font, left, first);
+            let spare = width - indent - font.font.measure(&line.text, setup.font_size);
+            let aligned = align_line(line, indent, spare.max(0.0), env.alignment);
+            if page_selected(env) {
+                write_piece(env, &line.text, font, left + aligned.offset, y);
+            }
+            first = y - line_height;
+        } else {
+            let spare = width - indent - font.font.measure(&line.text, setup.font_size);
+            plain.push(align_line(line, indent, spare.max(0.0), env.alignment));
+        }
+    }
+    write_plain_lines(env, plain, font, left, first);
+}
+
+/// Places a line holding tabs piece by piece, the first piece starting
+/// `indent` points from `left`, the left indent, and the others where their
+/// tab takes them, with the room a tab takes filled with its leader
+fn write_tab_line<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    line: &str,
+    font: &TextFont,
+    (left, indent): (f32, f32),
+    baseline: f32,
+) {
+    if !page_selected(env) {
+        return;
+    }
+    let font_size = env.layout.setup().font_size;
+    let width = |text: &str| font.font.measure(text, font_size);
+    let mut at = indent;
+    for (i, piece) in line.split('\t').enumerate() {
+        if i > 0 {
+            let (advance, stop) = env.layout.tab(at, piece, &font.font);
+            let leader = stop.and_then(|stop| stop.leader.glyph()).map(String::from);
+            if let Some(leader) = leader
+                && width(&leader) > 0.0
+            {
+                // as many as fit, ending where the text after the tab starts
+                let fill = leader.repeat((advance / width(&leader)).floor() as usize);
+                let x = left + at + advance - width(&fill);
+                env.current_page.push(PageItem::Text {
+                    lines: vec![fill.into()],
+                    font: font.clone(),
+                    x,
+                    y: baseline,
+                });
+            }
+            at += advance;
+        }
+        write_piece(env, piece, font, left + at, baseline);
+        at += width(piece);
+    }
+}
+
+/// Emoji and Indic text, which are drawn as glyph outlines rather than shown
+fn is_drawn(c: char) -> bool {
+    is_emoji(c) || indic_script(c).is_some()
+}
+
+/// Places `text` from `x` as text, but for its emoji, each drawn as the
+/// outline of its glyph in an emoji font centered in one em, or as '?'
+/// when no emoji font has it, and its runs of Indic text, shaped and drawn
+/// in the room their characters take in `font`
+fn write_piece<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    text: &str,
+    font: &TextFont,
+    x: f32,
+    baseline: f32,
+) {
+    let font_size = env.layout.setup().font_size;
+    let width = |text: &str| font.font.measure(text, font_size);
+    let mut rest = text;
+    let mut x = x;
+    while let Some(c) = rest.chars().next() {
+        let script = indic_script(c);
+        let split = match (rest.find(is_drawn), script) {
+            (Some(0), Some(script)) => rest
+                .find(|c| !is_joiner(c) && indic_script(c).is_none_or(|s| s.tag != script.tag))
+                .unwrap_or(rest.len()),
+            (Some(0), None) => c.len_utf8(),
+            (Some(at), _) => at,
+            (None, _) => rest.len(),
+        };
+        let (piece, tail) = rest.split_at(split);
+        if let Some(script) = script {
+            let room = width(piece);
+            let item = match shaped_run(env, piece, script, font) {
+                // squeezed into the room the layout gave it where wider
+                Some((path, advance)) => PageItem::Graphics(format!(
+                    "q {} 0 0 1 {} {} cm {}f Q",
+                    num((room / advance).min(1.0)),
+                    num(x),
+                    num(baseline),
+                    path
+                )),
+                None => PageItem::Text {
+                    lines: vec![piece.to_string().into()],
+                    font: font.clone(),
+                    x,
+                    y: baseline,
+                },
+            };
+            env.current_page.push(item);
+            x += room;
+        } else if is_emoji(c) {
+            let item = match emoji_glyph(env, c) {
+                Some((path, advance)) => PageItem::Graphics(format!(
+                    "q 1 0 0 1 {} {} cm {}f Q",
+                    num(x + (font_size - advance) / 2.0),
+                    num(baseline),
+                    path
+                )),
+                None => PageItem::Text {
+                    lines: vec![String::from("?").into()],
+                    font: font.clone(),
+                    x: x + (font_size - width("?")) / 2.0,
+                    y: baseline,
+                },
+            };
+            env.current_page.push(item);
+            x += font_size;
+        } else {
+            // variation selectors and joiners left between emoji take no room
+            if !piece.trim().is_empty() && width(piece) > 0.0 {
+                env.current_page.push(PageItem::Text {
+                    lines: vec![piece.to_string().into()],
+                    font: font.clone(),
+                    x,
+                    y: baseline,
+                });
+            }
+            x += width(piece);
+        }
+        rest = tail;
+    }
+}
+
+/// The outline of emoji `c` from the first emoji font that has a glyph for
+/// it, at the body size, and its advance; the fonts are asked for once
+fn emoji_glyph<W: Write + Seek>(env: &mut RenderEnv<W>, c: char) -> Option<(String, f32)> {
+    if let Some(glyph) = env.emoji_glyphs.get(&c) {
+        return glyph.clone();
+    }
+    let (provider, cache) = (env.font_provider, env.cache);
+    let fonts = env
+        .emoji_fonts
+        .get_or_insert_with(|| family_fonts(provider, cache, &EMOJI_FAMILIES));
+    let font_size = env.layout.setup().font_size;
+    let glyph = fonts
+        .iter()
+        .find_map(|font| glyph_outline(font, c, font_size));
+    env.emoji_glyphs.insert(c, glyph.clone());
+    glyph
+}
+
+/// `text` in `script` shaped with the run's font where it has the glyphs, or
+/// else with the first of the script's fonts that does, at the body size
+fn shaped_run<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    text: &str,
+    script: &IndicScript,
+    font: &TextFont,
+) -> Option<(String, f32)> {
+    let font_size = env.layout.setup().font_size;
+    if let PdfFont::TrueType(own) = &font.font
+        && let Some(shaped) = shaped_outline(own, text, script, font_size)
+    {
+        return Some(shaped);
+    }
+    let (provider, cache) = (env.font_provider, env.cache);
+    env.script_fonts
+        .entry(script.tag)
+        .or_insert_with(|| family_fonts(provider, cache, script.families))
+        .iter()
+        .find_map(|font| shaped_outline(font, text, script, font_size))
+}
+
+/// The embedded fonts the provider has for `families`, exact matches only
+fn family_fonts(
+    provider: &dyn FontProvider,
+    cache: Option<&ConversionCache>,
+    families: &[&str],
+) -> Vec<Arc<TrueTypeFont>> {
+    let resolve = |family: &&str| {
+        let request = FontRequest {
+            family,
+            bold: false,
+            italic: false,
+        };
+        match cache {
+            Some(cache) => cache.font(provider, &request),
+            None => resolve_font_uncached(provider, &request),
+        }
+    };
+    families
+        .iter()
+        .filter_map(|family| match resolve(family) {
+            Some((PdfFont::TrueType(font), true)) => Some(font),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `line` starting `indent` points from the left indent, placed as
+/// `alignment` says with `spare` points left over before the right one
+fn align_line(line: &WrappedLine, indent: f32, spare: f32, alignment: Alignment) -> TextLine {
+    let spaces = line.text.matches(' ').count();
+    let (offset, word_spacing) = match alignment {
+        Alignment::Left => (0.0, 0.0),
+        Alignment::Center => (spare / 2.0, 0.0),
+        Alignment::Right => (spare, 0.0),
+        // the last line and lines ending in a break are left as they are
+        Alignment::Justify if line.broken && spaces > 0 => (0.0, spare / spaces as f32),
+        Alignment::Justify => (0.0, 0.0),
+    };
+    TextLine {
+        text: line.text.clone(),
+        offset: indent + offset,
+        word_spacing,
+    }
+}
+
+fn write_plain_lines<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    lines: Vec<TextLine>,
+    font: &TextFont,
+    x: f32,
+    baseline: f32,
+) {
+    if lines.is_empty() || !page_selected(env) {
+        return;
+    }
+    env.current_page.push(PageItem::Text {
+        lines,
+        font: font.clone(),
+        x,
+        y: baseline,
+    });
+}
+
+/// Wraps `text` to the content width, one text object per page it spans
+fn write_text<W: Write + Seek>(env: &mut RenderEnv<W>, text: &str, font: &TextFont) {
+    // a tab alone may still draw its leader, a signature line
+    if text
+        .trim_matches(|c: char| c.is_whitespace() && c != '\t')
+        .is_empty()
+    {
+        return;
+    }
+    trace!(text = text.trim(), "paragraph");
+    let setup = *env.layout.setup();
+    let line_height = setup.line_height();
+    let first = env.first_line.take().unwrap_or_default();
+    let lines = env.layout.wrap_lines(text, &font.font, first.indent);
+    let first_line = |chunk_start: usize| match chunk_start {
+        0 => first.indent,
+        _ => 0.0,
+    };
+    let mut chunk_start = 0;
+    let mut first_baseline = None;
+    for i in 0..lines.len() {
+        let y = match env.layout.place(line_height) {
+            Some(y) => {
+                extend_paragraph_box(env, y, line_height);
+                y
+            }
+            None => {
+                let baseline = first_baseline.take();
+                write_lines(
+                    env,
+                    &lines[chunk_start..i],
+                    font,
+                    baseline,
+                    first_line(chunk_start),
+                );
+                chunk_start = i;
+                place_block(env, line_height)
+            }
+        };
+        // the line bottom leaves room for descenders below the baseline
+        let baseline = *first_baseline.get_or_insert(y + (line_height - setup.font_size));
+        if i == 0 {
+            mark_bookmarks(env);
+            if let Some((label, at)) = &first.label
+                && page_selected(env)
+            {
+                env.current_page.push(PageItem::Text {
+                    lines: vec![label.clone().into()],
+                    font: font.clone(),
+                    x: env.layout.left() + at,
+                    y: baseline,
+                });
+            }
+        }
+    }
+    let baseline = first_baseline;
+    write_lines(
+        env,
+        &lines[chunk_start..],
+        font,
+        baseline,
+        first_line(chunk_start),
+    );
+}
+
+/// The font for `run`, resolved through the provider once per request,
+/// the body font when the provider has nothing or the font is unusable
+fn resolve_font<W: Write + Seek>(env: &mut RenderEnv<W>, run: &Run) -> (usize, PdfFont, bool) {
+    let body = PdfFont::Standard(env.layout.setup().font);
+    let Some(family) = &run.font else {
+        return (0, body, true);
+    };
+    let key = (family.clone(), run.bold, run.italic);
+    if let Some(resolved) = env.resolved_fonts.get(&key) {
+        return resolved.clone();
+    }
+    let request = FontRequest {
+        family,
+        bold: run.bold,
+        italic: run.italic,
+    };
+    let resolved = match env.cache {
+        Some(cache) => cache.font(env.font_provider, &request),
+        None => resolve_font_uncached(env.font_provider, &request),
+    };
+    let resolved = match resolved {
+        Some((font, exact)) => (env.pdf_document.add_font(font.clone()), font, exact),
+        None => (0, body, false),
+    };
+    env.resolved_fonts.insert(key, resolved.clone());
+    resolved
+}
+
+/// Places the image behind `rid` at its natural size, scaled down to fit the content area
+fn write_image<W, F>(env: &mut RenderEnv<W>, rid: &str, media_lookup: &F)
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    if env.seen_rid.contains(rid) {
+        return;
+    }
+    match media_lookup(rid) {
+        Ok((entry, params)) => {
+            trace!(%rid, %entry, "placing image");
+            // checked before any space is taken, the image is read again once the page is written
+            let readable = params.open().map(drop);
+            match readable {
+                Ok(()) => {
+                    // laid out at the size of the media, not of a downsampled copy
+                    let left = env.layout.left();
+                    let (draw_w, draw_h) = env.layout.image_size(params.source_w, params.source_h);
+                    let y = place_block(env, draw_h);
+                    if page_selected(env) {
+                        env.current_page.push(PageItem::Image(PlacedImage {
+                            rid: rid.to_string(),
+                            rect: (left, y, draw_w, draw_h),
+                            params,
+                        }));
+                    }
+                }
+                Err(e) => {
+                    let reason = Warning::reason(&ConvertError::Io(e));
+                    skipped(
+                        env,
+                        Warning::SkippedImage {
+                            rid: rid.to_string(),
+                            reason,
+                        },
+                    );
+                    write_omitted(env, &Omitted::new("image", None));
+                }
+            }
+        }
+        Err(e) => {
+            let reason = Warning::reason(&e);
+            skipped(
+                env,
+                Warning::SkippedImage {
+                    rid: rid.to_string(),
+                    reason,
+                },
+            );
+            write_omitted(env, &Omitted::new("image", None));
+        }
+    }
+    env.seen_rid.insert(rid.to_string());
+}
+
+/// Draws `chart` at the size of its drawing, or the content width at 5:3
+/// when it has none, shrunk to fit the content area
+fn write_chart<W: Write + Seek>(env: &mut RenderEnv<W>, chart: &Chart) {
+    let setup = *env.layout.setup();
+    let (left, content_width) = (env.layout.left(), env.layout.content_width());
+    let (width, height) = chart
+        .size
+        .filter(|&(w, h)| w > 0.0 && h > 0.0)
+        .unwrap_or((content_width, content_width * 0.6));
+    let (width, height) = env.layout.fit_size(width, height);
+    trace!(rid = %chart.rid, kind = ?chart.kind, "drawing chart");
+    let y = place_block(env, height);
+    if page_selected(env) {
+        let content = draw_chart(chart, (left, y, width, height), setup.font);
+        env.current_page.push(PageItem::Graphics(content));
+    }
+}
+
+fn write_diagram<W: Write + Seek>(env: &mut RenderEnv<W>, diagram: &Diagram) {
+    let setup = *env.layout.setup();
+    let (left, content_width) = (env.layout.left(), env.layout.content_width());
+    let (width, height) = diagram
+        .size
+        .filter(|&(w, h)| w > 0.0 && h > 0.0)
+        .unwrap_or((content_width, content_width * 0.4));
+    let (width, height) = env.layout.fit_size(width, height);
+    trace!(rid = %diagram.rid, nodes = diagram.nodes.len(), "drawing SmartArt diagram");
+    let y = place_block(env, height);
+    if page_selected(env) {
+        let content = draw_diagram(diagram, (left, y, width, height), setup.font);
+        env.current_page.push(PageItem::Graphics(content));
+    }
+}
+
+/// Places the preview of `object` like an image, or a labeled box at the
+/// size of its shape when it has none that can be embedded
+fn write_object<W, F>(env: &mut RenderEnv<W>, object: &EmbeddedObject, media_lookup: &F)
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    if !env.drop_images
+        && let Some(rid) = &object.preview_rid
+        && media_lookup(rid).is_ok()
+    {
+        write_image(env, rid, media_lookup);
+        return;
+    }
+    let label = object_placeholder::label(object.prog_id.as_deref());
+    trace!(prog_id = ?object.prog_id, "drawing placeholder for embedded object");
+    write_placeholder(env, &label, object.size);
+}
+
+/// Marks where `omitted` was with a labeled box, when asked to
+fn write_omitted<W: Write + Seek>(env: &mut RenderEnv<W>, omitted: &Omitted) {
+    if !env.mark_unsupported {
+        return;
+    }
+    trace!(what = %omitted.what, "marking omitted content");
+    write_placeholder(env, &format!("{} omitted", omitted.what), omitted.size);
+}
+
+/// A box with `label` in it at `size`, or half the content width when
+/// there is none
+fn write_placeholder<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    label: &str,
+    size: Option<(f32, f32)>,
+) {
+    let setup = *env.layout.setup();
+    let (left, content_width) = (env.layout.left(), env.layout.content_width());
+    let (width, height) = size
+        .filter(|&(w, h)| w > 0.0 && h > 0.0)
+        .unwrap_or((content_width / 2.0, 48.0));
+    let (width, height) = env.layout.fit_size(width, height.max(24.0));
+    let y = place_block(env, height);
+    if page_selected(env) {
+        let rect = (left, y, width, height);
+        let content = object_placeholder::draw_placeholder(label, rect, setup.font);
+        env.current_page.push(PageItem::Graphics(content));
+    }
+}
+
+/// The first run of `paragraph` with any text
+fn first_run(paragraph: &Paragraph) -> Option<&Run> {
+    paragraph.content.iter().find_map(|inline| match inline {
+        Inline::Run(run) if !run.text.trim().is_empty() => Some(run),
+        _ => None,
+    })
+}
+
+/// The font the text of `paragraph` is set in, the one its first run asks for
+fn paragraph_font<W: Write + Seek>(env: &mut RenderEnv<W>, paragraph: &Paragraph) -> TextFont {
+    let (index, font, _) = match first_run(paragraph) {
+        Some(run) => resolve_font(env, run),
+        None => (0, PdfFont::Standard(env.layout.setup().font), true),
+    };
+    TextFont { index, font }
+}
+
+/// Text runs are collected until an image, chart, diagram, object or page break interrupts them
+fn write_paragraph<W, F>(env: &mut RenderEnv<W>, paragraph: &Paragraph, media_lookup: &F)
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let first_run = first_run(paragraph);
+    let paragraph_font = paragraph_font(env, paragraph);
+    let paragraph_family = first_run.and_then(|run| run.font.as_deref());
+
+    // content controls are shown by their content unless they become fields
+    let interactive = env.interactive_forms;
+    let content = paragraph.content.iter().flat_map(|inline| match inline {
+        Inline::ControlField(control) if !interactive => control.content.as_slice(),
+        inline => std::slice::from_ref(inline),
+    });
+    let mut text = String::new();
+    let indent = paragraph.indent;
+    env.first_line = Some(FirstLine {
+        indent: indent.first_line,
+        label: None,
+    });
+    if let Some(label) = &paragraph.label {
+        let font_size = env.layout.setup().font_size;
+        let width = paragraph_font.font.measure(&label.text, font_size);
+        // the tab after a label in a hanging indent wide enough for it goes
+        // to the indent, elsewhere without tab stops it is a space
+        if label.suffix == LabelSuffix::Tab
+            && width < -indent.first_line
+            && !paragraph.text().trim().is_empty()
+        {
+            env.first_line = Some(FirstLine {
+                indent: 0.0,
+                label: Some((label.text.clone(), indent.first_line)),
+            });
+        } else {
+            text.push_str(&label.text);
+            if label.suffix != LabelSuffix::Nothing {
+                text.push(' ');
+            }
+        }
+    }
+    for inline in content {
+        match inline {
+            Inline::Run(run) => {
+                text.push_str(&run.text);
+                text.push(' ');
+                if let Some(family) = &run.font {
+                    let (_, _, exact) = resolve_font(env, run);
+                    let same_family =
+                        paragraph_family.is_some_and(|first| first.eq_ignore_ascii_case(family));
+                    if !exact || !same_family {
+                        env.substituted_fonts
+                            .entry(family.clone())
+                            .or_insert_with(|| paragraph_font.font.base_font().to_string());
+                    }
+                }
+            }
+            // left out on request, without a warning or space kept for it
+            Inline::Image(_) if env.drop_images => {}
+            Inline::Image(image) => {
+                write_text(env, &std::mem::take(&mut text), &paragraph_font);
+                write_image(env, &image.rid, media_lookup);
+            }
+            Inline::Chart(chart) => {
+                write_text(env, &std::mem::take(&mut text), &paragraph_font);
+                write_chart(env, chart);
+            }
+            Inline::Diagram(diagram) => {
+                write_text(env, &std::mem::take(&mut text), &paragraph_font);
+                write_diagram(env, diagram);
+            }
+            Inline::Object(object) => {
+                write_text(env, &std::mem::take(&mut text), &paragraph_font);
+                write_object(env, object, media_lookup);
+            }
+            Inline::LineBreak => text.push('\n'),
+            Inline::Bookmark(name) => env.pending_bookmarks.push(name.clone()),
+            Inline::Reference(reference) => {
+                text.push_str(&reference_text(env, reference));
+                text.push(' ');
+            }
+            Inline::MergeField(field) => {
+                text.push_str(&merge_text(env, field));
+                text.push(' ');
+            }
+            Inline::FormField(field) | Inline::ControlField(ControlField { field, .. }) => {
+                text.push_str(&field_placeholder(env, field, &paragraph_font));
+                text.push(' ');
+                env.pending_fields.push_back(field.clone());
+            }
+            Inline::Omitted(_) if !env.mark_unsupported => {}
+            Inline::Omitted(omitted) => {
+                write_text(env, &std::mem::take(&mut text), &paragraph_font);
+                write_omitted(env, omitted);
+            }
+            // a row can't be broken in two
+            Inline::PageBreak if env.in_cell => {}
+            Inline::PageBreak => {
+                write_text(env, &std::mem::take(&mut text), &paragraph_font);
+                flush_page(env);
+            }
+        }
+    }
+    write_text(env, &text, &paragraph_font);
+    env.pending_fields.clear();
+}
+
+/// Lays out block `i` of `blocks`, a paragraph with the same borders as
+/// the ones next to it shares their box
+fn write_block<W, F>(env: &mut RenderEnv<W>, blocks: &[Block], i: usize, media_lookup: &F)
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    match &blocks[i] {
+        Block::Paragraph(paragraph) => {
+            let same_borders = |other: Option<&Block>| matches!(other, Some(Block::Paragraph(other)) if other.borders == paragraph.borders);
+            let above = same_borders(i.checked_sub(1).and_then(|i| blocks.get(i)));
+            let below = same_borders(blocks.get(i + 1));
+            let indent = paragraph.indent;
+            env.layout.set_indent(indent.left, indent.right);
+            env.layout.set_tabs(paragraph.tabs.clone());
+            env.alignment = paragraph.alignment;
+            start_paragraph_box(env, paragraph, above, below);
+            write_paragraph(env, paragraph, media_lookup);
+            end_paragraph_box(env);
+            env.layout.set_indent(0.0, 0.0);
+            env.layout.set_tabs(Vec::new());
+            env.first_line = None;
+            env.alignment = Alignment::Left;
+        }
+        Block::Table(table) => write_table(env, table, media_lookup),
+        Block::Omitted(omitted) => write_omitted(env, omitted),
+    }
+}
+
+/// Lays out `table` in columns sized to its grid and content, each row as
+/// tall as its tallest cell and split or moved to the next page when it
+/// doesn't fit
+fn write_table<W, F>(env: &mut RenderEnv<W>, table: &Table, media_lookup: &F)
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let columns = column_count(table);
+    if columns == 0 {
+        return;
+    }
+    let contents = cell_contents(env, table);
+    let widths = column_widths(table, env.layout.content_width(), &contents);
+    // where each grid column starts, and the last one ends
+    let mut edges = vec![env.layout.left()];
+    for width in &widths {
+        edges.push(edges[edges.len() - 1] + width);
+    }
+    // the header rows at the top repeat on every page the table goes on to,
+    // unless they are all there is or take up most of a page
+    let headers = table.rows.iter().take_while(|row| row.header).count();
+    let headers = match headers < table.rows.len() {
+        true => &table.rows[..headers],
+        false => &[],
+    };
+    let content_height = env.layout.setup().content_height();
+    let mut repeated = Headers {
+        rows: headers,
+        height: 0.0,
+    };
+    for (i, row) in table.rows.iter().enumerate() {
+        if i < headers.len() {
+            repeated.height += write_row(env, row, &edges, None, media_lookup);
+            continue;
+        }
+        let headers = match repeated.height < content_height / 2.0 {
+            true => repeated,
+            false => Headers::default(),
+        };
+        write_row(env, row, &edges, Some(headers), media_lookup);
+    }
+}
+
+/// The rows repeated at the top of every page a table goes on to, and the
+/// height they take there
+#[derive(Clone, Copy, Default)]
+struct Headers<'t> {
+    rows: &'t [TableRow],
+    height: f32,
+}
+
+/// A cell of a row laid out `width` points wide at `x`, in the parts it
+/// takes on the pages the row goes over with their heights
+struct RowCell {
+    x: f32,
+    width: f32,
+    parts: Vec<(Vec<PageItem>, f32)>,
+}
+
+/// Lays out `row` with its cells between the column `edges` they span.
+/// A body row, with the `headers` repeated below the top of each page, is
+/// split across pages when it runs past the bottom of one, unless it is
+/// marked `w:cantSplit` and fits on a page. Other rows move to the next
+/// page whole when they don't fit. Returns its height
+fn write_row<W, F>(
+    env: &mut RenderEnv<W>,
+    row: &TableRow,
+    edges: &[f32],
+    headers: Option<Headers>,
+    media_lookup: &F,
+) -> f32
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let setup = *env.layout.setup();
+    let line_height = setup.line_height();
+    // rows of nested tables and rows with an exact height are kept whole
+    let exact = matches!(row.height, Some(RowHeight::Exact(_)));
+    let splittable = headers.filter(|_| !env.in_cell && !exact);
+    if let Some(headers) = splittable
+        && !row.cant_split
+    {
+        // a line of every cell at least goes on this page
+        let margins = row.cells.iter().fold(0.0f32, |most, cell| {
+            most.max(cell.margins.top + cell.margins.bottom)
+        });
+        if !env.layout.fits(line_height + margins) && !env.layout.is_empty() {
+            next_table_page(env, edges, headers.rows, media_lookup);
+        }
+    }
+    // laid out from where the row would start on this page, and where it
+    // carries on from on the next ones
+    let mut top = env.layout.cursor();
+    let split =
+        |env: &RenderEnv<W>, headers: Headers| (env.layout.bottom(), setup.top() - headers.height);
+    let first = splittable
+        .filter(|_| !row.cant_split)
+        .map(|headers| split(env, headers));
+    let mut cells = lay_out_row(env, row, edges, top, first, media_lookup);
+    let mut next_top = first.map(|(_, next_top)| next_top);
+    if let Some(headers) = splittable
+        && row.cant_split
+        && row_height(&cells, 0, line_height) > setup.content_height() - headers.height
+    {
+        // too tall for any page, so split all the same
+        if !env.layout.is_empty() {
+            next_table_page(env, edges, headers.rows, media_lookup);
+        }
+        top = env.layout.cursor();
+        let split = split(env, headers);
+        cells = lay_out_row(env, row, edges, top, Some(split), media_lookup);
+        next_top = Some(split.1);
+    }
+    let parts = cells.iter().map(|cell| cell.parts.len()).max().unwrap_or(1);
+    if let Some(next_top) = next_top
+        && parts > 1
+    {
+        let headers = splittable.unwrap_or_default().rows;
+        return place_split_row(
+            env,
+            row,
+            edges,
+            cells,
+            (top, next_top),
+            headers,
+            media_lookup,
+        );
+    }
+    let mut height = row_height(&cells, 0, line_height);
+    // an exact height cuts off what doesn't fit
+    let clipped = match row.height {
+        Some(RowHeight::Exact(exact)) => {
+            let clipped = height > exact;
+            height = exact;
+            clipped
+        }
+        Some(RowHeight::AtLeast(least)) => {
+            height = height.max(least);
+            false
+        }
+        None => false,
+    };
+    if let Some(headers) = headers.filter(|headers| !headers.rows.is_empty())
+        && !env.layout.fits(height)
+        && !env.layout.is_empty()
+    {
+        next_table_page(env, edges, headers.rows, media_lookup);
+    }
+    let y = place_block(env, height);
+    if !page_selected(env) {
+        return height;
+    }
+    for (cell, laid) in row.cells.iter().zip(cells) {
+        let (items, cell_height) = laid.parts.into_iter().next().unwrap_or_default();
+        let below = aligned(cell, height, cell_height);
+        let rect = (laid.x, y, laid.width, height);
+        draw_cell(env, cell, rect, items, y + height - top - below, clipped);
+    }
+    height
+}
+
+/// Places the parts of `row` split across pages, the first laid out from
+/// the `tops` of the row on this page and the others from that of the
+/// next ones, where they go below the `headers`. The cells are aligned
+/// vertically in the last part. Returns the height of all of them
+fn place_split_row<W, F>(
+    env: &mut RenderEnv<W>,
+    row: &TableRow,
+    edges: &[f32],
+    mut cells: Vec<RowCell>,
+    tops: (f32, f32),
+    headers: &[TableRow],
+    media_lookup: &F,
+) -> f32
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let line_height = env.layout.setup().line_height();
+    let parts = cells.iter().map(|cell| cell.parts.len()).max().unwrap_or(1);
+    let mut total = 0.0;
+    for part in 0..parts {
+        let top = match part {
+            0 => tops.0,
+            _ => {
+                next_table_page(env, edges, headers, media_lookup);
+                tops.1
+            }
+        };
+        let height = row_height(&cells, part, line_height);
+        let y = place_block(env, height);
+        total += height;
+        if !page_selected(env) {
+            continue;
+        }
+        for (cell, laid) in row.cells.iter().zip(&mut cells) {
+            let (items, cell_height) = laid
+                .parts
+                .get_mut(part)
+                .map(std::mem::take)
+                .unwrap_or_default();
+            let below = match part + 1 == parts && laid.parts.len() == parts {
+                true => aligned(cell, height, cell_height),
+                false => 0.0,
+            };
+            let rect = (laid.x, y, laid.width, height);
+            draw_cell(env, cell, rect, items, y + height - top - below, false);
+        }
+    }
+    total
+}
+
+/// Flushes the page a table goes on from, repeating its `headers` at the
+/// top of the next one
+fn next_table_page<W, F>(
+    env: &mut RenderEnv<W>,
+    edges: &[f32],
+    headers: &[TableRow],
+    media_lookup: &F,
+) where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    flush_page(env);
+    for header in headers {
+        write_row(env, header, edges, None, media_lookup);
+    }
+}
+
+/// Lays out the cells of `row` from `top`, carrying on from the next page
+/// at `split`, the bottom of this one and the top of the next, when given
+fn lay_out_row<W, F>(
+    env: &mut RenderEnv<W>,
+    row: &TableRow,
+    edges: &[f32],
+    top: f32,
+    split: Option<(f32, f32)>,
+    media_lookup: &F,
+) -> Vec<RowCell>
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let columns = edges.len() - 1;
+    let mut cells = Vec::new();
+    let mut at = 0;
+    for cell in &row.cells {
+        let x = edges[at.min(columns)];
+        at += cell.span.max(1);
+        let width = edges[at.min(columns)] - x;
+        let parts = match cell.text_direction {
+            TextDirection::Horizontal => {
+                lay_out_cell(env, cell, x, width, top, split, media_lookup)
+            }
+            _ => {
+                let length = rotated_length(env, cell, row.height);
+                lay_out_rotated_cell(env, cell, (x, width), top, length, media_lookup)
+            }
+        };
+        cells.push(RowCell { x, width, parts });
+    }
+    cells
+}
+
+/// The height of the tallest `part` of `cells`, a line at least
+fn row_height(cells: &[RowCell], part: usize, line_height: f32) -> f32 {
+    cells
+        .iter()
+        .filter_map(|cell| cell.parts.get(part))
+        .fold(line_height, |height, (_, part)| height.max(*part))
+}
+
+/// How far up from the bottom of a row `height` points high the content
+/// of `cell`, `cell_height` points high, goes. Turned text starts from the
+/// bottom or the top of the cell, its alignment is across the cell
+fn aligned(cell: &TableCell, height: f32, cell_height: f32) -> f32 {
+    let below = match (cell.text_direction, cell.vertical_align) {
+        (TextDirection::BottomToTop, _) => height - cell_height,
+        (TextDirection::TopToBottom, _) => 0.0,
+        (_, VerticalAlign::Top) => 0.0,
+        (_, VerticalAlign::Center) => (height - cell_height) / 2.0,
+        (_, VerticalAlign::Bottom) => height - cell_height,
+    };
+    below.max(0.0)
+}
+
+/// How wide the content of each cell of `table` can be, by row
+fn cell_contents<W: Write + Seek>(env: &mut RenderEnv<W>, table: &Table) -> Vec<Vec<ContentWidth>> {
+    let mut contents = Vec::new();
+    for row in &table.rows {
+        let mut widths = Vec::new();
+        for cell in &row.cells {
+            let width = match cell.text_direction {
+                TextDirection::Horizontal => content_width(env, &cell.blocks),
+                _ => rotated_content(env, cell, row.height),
+            };
+            widths.push(width);
+        }
+        contents.push(widths);
+    }
+    contents
+}
+
+/// The longest word and the longest unbroken line of `blocks`, nested
+/// tables as narrow and as wide as their columns let them be
+fn content_width<W: Write + Seek>(env: &mut RenderEnv<W>, blocks: &[Block]) -> ContentWidth {
+    let font_size = env.layout.setup().font_size;
+    let mut content = ContentWidth::default();
+    for block in blocks {
+        let width = match block {
+            Block::Paragraph(paragraph) => {
+                let font = paragraph_font(env, paragraph).font;
+                let width = |text: &str| font.measure(text, font_size);
+                let space = width(" ");
+                // summed up like wrapping does, with a hair to spare so
+                // rounding in the column edges doesn't break the line
+                let (mut min, mut max) = (0.0f32, 0.0);
+                for (i, word) in paragraph.text().split_whitespace().enumerate() {
+                    let word = width(&word.replace('\u{ad}', ""));
+                    if i > 0 {
+                        max += space;
+                    }
+                    max += word;
+                    min = min.max(word);
+                }
+                let indent = paragraph.indent;
+                let sides = indent.left + indent.right;
+                ContentWidth {
+                    min: min + sides + 0.01,
+                    max: max + sides + indent.first_line.max(0.0) + 0.01,
+                }
+            }
+            Block::Table(table) => {
+                let cells = cell_contents(env, table);
+                table_content(table, &cells)
+            }
+            Block::Omitted(_) => ContentWidth::default(),
+        };
+        content.min = content.min.max(width.min);
+        content.max = content.max.max(width.max);
+    }
+    content
+}
+
+/// Lays out the blocks of `cell`, `width` points wide at `x` from `top`
+/// down inside its margins, going on to the next pages past the bottom
+/// given with `split`. Returns what was placed on each page and the height
+/// it took there with the margins
+fn lay_out_cell<W, F>(
+    env: &mut RenderEnv<W>,
+    cell: &TableCell,
+    x: f32,
+    width: f32,
+    top: f32,
+    split: Option<(f32, f32)>,
+    media_lookup: &F,
+) -> Vec<(Vec<PageItem>, f32)>
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let setup = *env.layout.setup();
+    let margins = &cell.margins;
+    let (left, width) = (x + margins.left, width - margins.left - margins.right);
+    let column = match split {
+        Some((bottom, next_top)) => PageLayout::split_column(
+            setup,
+            left,
+            width,
+            top - margins.top,
+            bottom + margins.bottom,
+            next_top - margins.top,
+        ),
+        None => PageLayout::column(setup, left, width, top - margins.top),
+    };
+    let layout = std::mem::replace(&mut env.layout, column);
+    let page = std::mem::take(&mut env.current_page);
+    let in_cell = std::mem::replace(&mut env.in_cell, true);
+    let outer = std::mem::take(&mut env.cell_parts);
+    for i in 0..cell.blocks.len() {
+        write_block(env, &cell.blocks, i, media_lookup);
+    }
+    let height = env.layout.used() + margins.top + margins.bottom;
+    let items = std::mem::replace(&mut env.current_page, page);
+    let mut parts = std::mem::replace(&mut env.cell_parts, outer);
+    for (_, part) in &mut parts {
+        *part += margins.top;
+    }
+    parts.push((items, height));
+    env.in_cell = in_cell;
+    env.layout = layout;
+    parts
+}
+
+/// How long the lines of turned `cell` can be: as long as its row is high
+/// when that is set, otherwise as its longest paragraph unbroken, but no
+/// longer than a page is high
+fn rotated_length<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    cell: &TableCell,
+    height: Option<RowHeight>,
+) -> f32 {
+    let margins = cell.margins.top + cell.margins.bottom;
+    let natural = content_width(env, &cell.blocks).max;
+    let length = match height {
+        Some(RowHeight::Exact(exact)) => exact - margins,
+        Some(RowHeight::AtLeast(least)) => natural.max(least - margins),
+        None => natural,
+    };
+    length.min(env.layout.setup().content_height() - margins)
+}
+
+/// How wide turned `cell` is: its lines stacked, broken as long as they can
+/// be, a line for each nested table
+fn rotated_content<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    cell: &TableCell,
+    height: Option<RowHeight>,
+) -> ContentWidth {
+    let setup = *env.layout.setup();
+    let length = rotated_length(env, cell, height);
+    let mut lines = 0;
+    for block in &cell.blocks {
+        lines += match block {
+            Block::Paragraph(paragraph) => {
+                let font = paragraph_font(env, paragraph).font;
+                let text = paragraph.text();
+                let tabs = &paragraph.tabs;
+                let wrapped = setup.wrap_lines(&text, &font, length, length, tabs, (0.0, 0.0));
+                wrapped.len().max(1)
+            }
+            Block::Table(_) => 1,
+            Block::Omitted(_) => 0,
+        };
+    }
+    let width = lines as f32 * setup.line_height();
+    ContentWidth {
+        min: width,
+        max: width,
+    }
+}
+
+/// Lays out the blocks of `cell`, whose text is turned a quarter, in lines
+/// `length` points long, and turns them to run up or down the cell, `width`
+/// points wide at `x`, from `top` down inside its margins. Such a cell is
+/// kept whole on one page. Images and form fields stay upright where the
+/// turn takes them
+fn lay_out_rotated_cell<W, F>(
+    env: &mut RenderEnv<W>,
+    cell: &TableCell,
+    (x, width): (f32, f32),
+    top: f32,
+    length: f32,
+    media_lookup: &F,
+) -> Vec<(Vec<PageItem>, f32)>
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let setup = *env.layout.setup();
+    let margins = &cell.margins;
+    // laid out from the origin, along x and down from 0
+    let column = PageLayout::column(setup, 0.0, length, 0.0);
+    let layout = std::mem::replace(&mut env.layout, column);
+    let page = std::mem::take(&mut env.current_page);
+    let in_cell = std::mem::replace(&mut env.in_cell, true);
+    let outer = std::mem::take(&mut env.cell_parts);
+    for i in 0..cell.blocks.len() {
+        write_block(env, &cell.blocks, i, media_lookup);
+    }
+    let used = env.layout.used();
+    let items = std::mem::replace(&mut env.current_page, page);
+    env.cell_parts = outer;
+    env.in_cell = in_cell;
+    env.layout = layout;
+    // the first line goes along the left edge of the cell reading up, or
+    // along the right one reading down
+    let across = width - margins.left - margins.right;
+    let offset = match cell.vertical_align {
+        VerticalAlign::Top => 0.0,
+        VerticalAlign::Center => (across - used) / 2.0,
+        VerticalAlign::Bottom => across - used,
+    }
+    .max(0.0);
+    let (top, left) = (top - margins.top, x + margins.left);
+    let [a, b, c, d, e, f] = match cell.text_direction {
+        TextDirection::TopToBottom => [0.0, -1.0, 1.0, 0.0, left + across - offset, top],
+        _ => [0.0, 1.0, -1.0, 0.0, left + offset, top - length],
+    };
+    let turn = |(u, v): (f32, f32)| (a * u + c * v + e, b * u + d * v + f);
+    // the lower left corner of `rect` turned
+    let upright = |(x, y, width, height): (f32, f32, f32, f32)| {
+        let corners = [(x, y), (x + width, y + height)].map(turn);
+        let x = corners[0].0.min(corners[1].0);
+        let y = corners[0].1.min(corners[1].1);
+        (x, y, width, height)
+    };
+    let mut turned = Vec::new();
+    let mut kept = Vec::new();
+    for item in items {
+        match item {
+            PageItem::Text { lines, font, x, y } => {
+                let font = (font.index, &font.font);
+                turned.push(text_operators(&setup, &lines, font, x, y));
+            }
+            PageItem::Graphics(content) => turned.push(content),
+            PageItem::Image(mut image) => {
+                image.rect = upright(image.rect);
+                kept.push(PageItem::Image(image));
+            }
+            PageItem::Field(mut widget) => {
+                widget.rect = upright(widget.rect);
+                kept.push(PageItem::Field(widget));
+            }
+        }
+    }
+    if !turned.is_empty() {
+        let matrix = [a, b, c, d, e, f].map(num).join(" ");
+        let content = format!("q {} cm\n{}\nQ", matrix, turned.join("\n"));
+        kept.insert(0, PageItem::Graphics(content));
+    }
+    vec![(kept, length + margins.top + margins.bottom)]
+}
+
+/// Draws `cell` in `rect`, as x, y, width and height: its shading, the
+/// `items` laid out for it moved up `dy` points to where its row went, cut
+/// off at its edges when `clipped`, and its borders
+fn draw_cell<W: Write + Seek>(
+    env: &mut RenderEnv<W>,
+    cell: &TableCell,
+    rect: (f32, f32, f32, f32),
+    items: Vec<PageItem>,
+    dy: f32,
+    clipped: bool,
+) {
+    if let Some(color) = cell.shading {
+        env.current_page
+            .push(PageItem::Graphics(fill_rect(color, rect)));
+    }
+    let setup = *env.layout.setup();
+    for item in items {
+        let item = shift_item(item, dy);
+        env.current_page.push(match item {
+            PageItem::Text { lines, font, x, y } if clipped => {
+                let font = (font.index, &font.font);
+                clip(rect, &text_operators(&setup, &lines, font, x, y))
+            }
+            PageItem::Graphics(content) if clipped => clip(rect, &content),
+            // images and form fields stay whole
+            item => item,
+        });
+    }
+    let (x, y, width, height) = rect;
+    let (right, top) = (x + width, y + height);
+    let borders = &cell.borders;
+    let edges = [
+        (&borders.top, (x, top), (right, top)),
+        (&borders.bottom, (x, y), (right, y)),
+        (&borders.left, (x, y), (x, top)),
+        (&borders.right, (right, y), (right, top)),
+    ];
+    for (border, from, to) in edges {
+        if let Some(border) = border {
+            env.current_page
+                .push(PageItem::Graphics(draw_border(border, from, to)));
+        }
+    }
+}
+
+/// `content` drawn only inside `rect`
+fn clip((x, y, width, height): (f32, f32, f32, f32), content: &str) -> PageItem {
+    PageItem::Graphics(format!(
+        "q {} {} {} {} re W n\n{}\nQ",
+        num(x),
+        num(y),
+        num(width),
+        num(height),
+        content
+    ))
+}
+
+/// `item` moved up `dy` points
+fn shift_item(item: PageItem, dy: f32) -> PageItem {
+    if dy == 0.0 {
+        return item;
+    }
+    match item {
+        PageItem::Text { lines, font, x, y } => PageItem::Text {
+            lines,
+            font,
+            x,
+            y: y + dy,
+        },
+        PageItem::Image(mut image) => {
+            image.rect.1 += dy;
+            PageItem::Image(image)
+        }
+        PageItem::Graphics(content) => {
+            PageItem::Graphics(format!("q 1 0 0 1 0 {} cm\n{}\nQ", num(dy), content))
+        }
+        PageItem::Field(mut widget) => {
+            widget.rect.1 += dy;
+            PageItem::Field(widget)
+        }
+    }
+}
+
+/// The numbering style and number of laid out page `laid_out` (0 based)
+fn label_at(labels: &[PageLabel], laid_out: usize) -> (PageLabelStyle, u32) {
+    match labels.iter().rev().find(|l| l.first_page <= laid_out) {
+        Some(label) => (
+            label.style,
+            label.start + (laid_out - label.first_page) as u32,
+        ),
+        None => (PageLabelStyle::Decimal, laid_out as u32 + 1),
+    }
+}
+
+/// Labels for the `written` pages from the `labels` of all laid out pages,
+/// starting a new range wherever the numbering doesn't simply continue
+fn selected_labels(labels: &[PageLabel], written: &[usize]) -> Vec<PageLabel> {
+    let mut selected: Vec<PageLabel> = Vec::new();
+    let mut previous = None;
+    for (page, &laid_out) in written.iter().enumerate() {
+        let (style, number) = label_at(labels, laid_out);
+        if previous != Some((style, number.wrapping_sub(1))) {
+            selected.push(PageLabel {
+                first_page: page,
+                style,
+                start: number,
+            });
+        }
+        previous = Some((style, number));
+    }
+    selected
+}
+
+/// Turns what the model couldn't represent and the fonts it asked for into warnings
+fn summarize<W: Write + Seek>(env: &mut RenderEnv<W>, document: &Document) {
+    for (element, &count) in &document.unsupported {
+        env.warnings.push(Warning::UnsupportedElement {
+            element: element.clone(),
+            count,
+        });
+    }
+    for (requested, used) in std::mem::take(&mut env.substituted_fonts) {
+        env.warnings
+            .push(Warning::SubstitutedFont { requested, used });
+    }
+}
+
+fn new_env<'a, W: Write + Seek>(
+    pdf_document: &'a mut PdfDocument<W>,
+    document: &'a Document,
+    opts: &'a Options,
+    progress: &'a dyn ProgressSink,
+) -> RenderEnv<'a, W> {
+    let layout = PageLayout::new(*pdf_document.page_setup());
+    RenderEnv {
+        pdf_document,
+        progress,
+        seen_rid: HashSet::new(),
+        current_page: Vec::new(),
+        pending: Vec::new(),
+        layout,
+        pages: opts.pages.as_ref(),
+        laid_out: 0,
+        written: Vec::new(),
+        page_labels: Vec::new(),
+        next_number: 1,
+        warnings: Vec::new(),
+        font_provider: &*opts.fonts,
+        page_hooks: &opts.page_hooks,
+        letterhead: opts.letterhead.as_ref(),
+        page_images: opts.page_images.as_ref(),
+        cache: opts.cache.as_deref(),
+        drop_images: opts.drop_images,
+        mark_unsupported: opts.mark_unsupported,
+        interactive_forms: opts.interactive_forms,
+        resolved_fonts: HashMap::new(),
+        substituted_fonts: BTreeMap::new(),
+        dry_run: false,
+        bookmarks: &document.bookmarks,
+        merge_values: &opts.merge_values,
+        unmerged: BTreeSet::new(),
+        pending_bookmarks: Vec::new(),
+        bookmark_pages: HashMap::new(),
+        page_refs: HashMap::new(),
+        pending_fields: VecDeque::new(),
+        form_fields: 0,
+        paragraph_box: None,
+        in_cell: false,
+        cell_parts: Vec::new(),
+        first_line: None,
+        alignment: Alignment::Left,
+        emoji_fonts: None,
+        emoji_glyphs: HashMap::new(),
+        script_fonts: HashMap::new(),
+        outline_fonts: HashMap::new(),
+    }
+}
+
+/// Places the sections of `document` one after the other, each starting on a new page
+fn lay_out<W, F>(
+    env: &mut RenderEnv<W>,
+    document: &Document,
+    media_lookup: &F,
+    opts: &Options,
+) -> Result<()>
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    for section in &document.sections {
+        env.pdf_document.set_landscape(section.landscape);
+        // the section starts on a new page, laid out on its own page size
+        let setup = *env.pdf_document.page_setup();
+        if setup != *env.layout.setup() {
+            env.layout = PageLayout::new(setup);
+        }
+        let first_page = env.laid_out;
+        let blocks = &section.blocks;
+        for i in 0..blocks.len() {
+            opts.cancel.check()?;
+            if past_selection(env) {
+                break;
+            }
+            write_block(env, blocks, i, media_lookup);
+        }
+        // every section starts on a new page
+        if page_has_content(env) {
+            flush_page(env);
+        }
+        // the next section may turn its pages, the queued ones go out as they are
+        write_pending(env);
+        label_section(env, section, first_page);
+        if past_selection(env) {
+            debug!(
+                pages = env.laid_out,
+                "rest of the document is outside the page selection"
+            );
+            break;
+        }
+    }
+    // bookmarks at the very end are on the last page
+    let last_page = env.laid_out.saturating_sub(1);
+    for name in env.pending_bookmarks.drain(..) {
+        env.bookmark_pages.entry(name).or_insert(last_page);
+    }
+    Ok(())
+}
+
+/// Lays out `document` page by page into `pdf_document`, returning what had to be left out
+pub fn render_document<W, F>(
+    document: &Document,
+    pdf_document: &mut PdfDocument<W>,
+    media_lookup: F,
+    opts: &Options,
+    progress: &dyn ProgressSink,
+) -> Result<Vec<Warning>>
+where
+    W: Write + Seek,
+    F: Fn(&str) -> Result<(String, ImageParams), ConvertError>,
+{
+    let _span = info_span!("render_document").entered();
+
+    // PAGEREF fields need the pages of bookmarks that may come after them
+    let has_page_refs = document.sections.iter().any(|section| {
+        section
+            .blocks
+            .iter()
+            .flat_map(Block::paragraphs)
+            .any(|paragraph| {
+                paragraph
+                    .content
+                    .iter()
+                    .any(|inline| matches!(inline, Inline::Reference(r) if r.page))
+            })
+    });
+    let mut resolved_fonts = HashMap::new();
+    let mut page_refs = HashMap::new();
+    if has_page_refs {
+        let _span = debug_span!("find_bookmark_pages").entered();
+        let mut env = new_env(pdf_document, document, opts, progress);
+        env.dry_run = true;
+        env.pages = None;
+        lay_out(&mut env, document, &media_lookup, opts)?;
+        page_refs = env
+            .bookmark_pages
+            .iter()
+            .map(|(name, &laid_out)| {
+                let (style, number) = label_at(&env.page_labels, laid_out);
+                (name.clone(), style.format(number))
+            })
+            .collect();
+        // fonts were added to the document already, the same /F indexes are kept
+        resolved_fonts = env.resolved_fonts;
+    }
+
+    let mut env = new_env(pdf_document, document, opts, progress);
+    env.resolved_fonts = resolved_fonts;
+    env.page_refs = page_refs;
+    lay_out(&mut env, document, &media_lookup, opts)?;
+
+    if let Some(pages) = env.pages {
+        if env.written.is_empty() {
+            return Err(ConvertError::InvalidOptions(format!(
+                "no pages in {}, the document has {}",
+                pages, env.laid_out
+            ))
+            .into());
+        }
+        // the extracted pages keep the numbers they had in the whole document
+        let labels = selected_labels(&env.page_labels, &env.written);
+        env.pdf_document.set_page_labels(labels);
+    } else {
+        // only documents with explicit numbering get /PageLabels
+        let custom_numbering = document
+            .sections
+            .iter()
+            .any(|s| s.number_format.is_some() || s.number_start.is_some());
+        if custom_numbering {
+            env.pdf_document
+                .set_page_labels(std::mem::take(&mut env.page_labels));
+        }
+    }
+
+    summarize(&mut env, document);
+    Ok(env.warnings)
+}
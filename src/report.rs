@@ -0,0 +1,103 @@
+use serde::Serialize;
+use std::error::Error as StdError;
+use std::fmt;
+
+/// What a conversion produced
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Report {
+    pub pages: usize,
+    pub images: usize,
+    /// content that did not make it into the PDF as it is in the DOCX
+    pub warnings: Vec<Warning>,
+}
+
+/// Something the conversion left out or changed, the PDF is written anyway
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// an image that was referenced but could not be placed
+    SkippedImage { rid: String, reason: String },
+    /// text or a page that failed to be written
+    DroppedContent { what: String, reason: String },
+    /// an element the converter does not render, counted per element name
+    UnsupportedElement { element: String, count: usize },
+    /// a font used in the document that was rendered with a standard font
+    SubstitutedFont { requested: String, used: String },
+    /// a MERGEFIELD the merge values have nothing for, its placeholder is shown
+    MissingMergeValue { field: String },
+    /// something that keeps a PDF written with a print profile from being PDF/X-4
+    NotPrintReady { what: String },
+    /// a feature the target PDF version lacks, the file is written as `written`
+    RaisedVersion {
+        feature: String,
+        target: String,
+        written: String,
+    },
+    /// malformed XML in a part that was skipped past, `count` times
+    MalformedXml {
+        part: String,
+        count: usize,
+        first_error: String,
+    },
+}
+
+impl Warning {
+    /// `err` followed by its sources, as one line
+    pub(crate) fn reason(err: &dyn StdError) -> String {
+        let mut reason = err.to_string();
+        let mut source = err.source();
+        while let Some(err) = source {
+            reason.push_str(": ");
+            reason.push_str(&err.to_string());
+            source = err.source();
+        }
+        reason
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::SkippedImage { rid, reason } => {
+                write!(f, "skipped image {}: {}", rid, reason)
+            }
+            Warning::DroppedContent { what, reason } => {
+                write!(f, "dropped {}: {}", what, reason)
+            }
+            Warning::UnsupportedElement { element, count } => {
+                write!(f, "unsupported {} left out ({}x)", element, count)
+            }
+            Warning::SubstitutedFont { requested, used } => {
+                write!(f, "font {} rendered as {}", requested, used)
+            }
+            Warning::MissingMergeValue { field } => {
+                write!(f, "no merge value for {}, showing its placeholder", field)
+            }
+            Warning::NotPrintReady { what } => {
+                write!(f, "not PDF/X-4: {}", what)
+            }
+            Warning::RaisedVersion {
+                feature,
+                target,
+                written,
+            } => {
+                write!(
+                    f,
+                    "{} is not in PDF {}, written as PDF {}",
+                    feature, target, written
+                )
+            }
+            Warning::MalformedXml {
+                part,
+                count,
+                first_error,
+            } => {
+                write!(
+                    f,
+                    "malformed XML in {} skipped ({}x), first {}",
+                    part, count, first_error
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,707 @@
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+use super::document_model::{Alignment, Border, Indent, TabAlignment, TabLeader, TabStop};
+use super::docx_parser::{attribute_value, read_alignment, read_border, read_shading, toggle_on};
+use super::xml_errors::XmlErrors;
+
+/// `w:basedOn` chains longer than this are cut off, they only get this long
+/// when they loop
+const MAX_STYLE_DEPTH: usize = 32;
+
+/// Indents are cut down to this, in points, Word's largest page width
+const MAX_INDENT: f32 = 1584.0;
+
+/// Word sets at most this many tab stops on a paragraph
+const MAX_TAB_STOPS: usize = 64;
+
+/// The run formatting of a style, the document defaults or a run's own
+/// `w:rPr`, None where it says nothing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunProperties {
+    /// `w:rFonts w:ascii`
+    pub font: Option<String>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+}
+
+impl RunProperties {
+    /// Takes what `base` says wherever these say nothing
+    fn inherit(&mut self, base: &RunProperties) {
+        if self.font.is_none() {
+            self.font.clone_from(&base.font);
+        }
+        self.bold = self.bold.or(base.bold);
+        self.italic = self.italic.or(base.italic);
+    }
+
+    /// Reads the run property element `name` into these, others are ignored
+    pub fn read(&mut self, e: &BytesStart, name: &[u8]) {
+        match name {
+            b"w:b" => self.bold = Some(toggle_on(e)),
+            b"w:i" => self.italic = Some(toggle_on(e)),
+            b"w:rFonts" => {
+                if let Some(font) = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.as_ref() == b"w:ascii")
+                {
+                    self.font = Some(String::from_utf8_lossy(&font.value).into_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The indents of a paragraph, its style or a list level, `w:ind`, None
+/// where it says nothing
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IndentProperties {
+    pub left: Option<f32>,
+    pub right: Option<f32>,
+    pub first_line: Option<f32>,
+}
+
+impl IndentProperties {
+    /// Takes what `base` says wherever these say nothing
+    pub fn inherit(&mut self, base: &IndentProperties) {
+        self.left = self.left.or(base.left);
+        self.right = self.right.or(base.right);
+        self.first_line = self.first_line.or(base.first_line);
+    }
+
+    /// Reads `<w:ind w:left="720" w:hanging="360"/>`, in twentieths of a
+    /// point; a hanging indent wins over a first line one
+    pub fn read(&mut self, e: &BytesStart) {
+        let twips = |key: &[u8]| {
+            attribute_value(e, key)
+                .and_then(|value| value.trim().parse::<f32>().ok())
+                .filter(|value| value.is_finite())
+                .map(|value| (value / 20.0).clamp(-MAX_INDENT, MAX_INDENT))
+        };
+        if let Some(left) = twips(b"w:left").or_else(|| twips(b"w:start")) {
+            self.left = Some(left);
+        }
+        if let Some(right) = twips(b"w:right").or_else(|| twips(b"w:end")) {
+            self.right = Some(right);
+        }
+        if let Some(first_line) = twips(b"w:hanging")
+            .map(|hanging| -hanging)
+            .or_else(|| twips(b"w:firstLine"))
+        {
+            self.first_line = Some(first_line);
+        }
+    }
+
+    /// The indents, zero where nothing says otherwise
+    pub fn indent(&self) -> Indent {
+        Indent {
+            left: self.left.unwrap_or(0.0),
+            right: self.right.unwrap_or(0.0),
+            first_line: self.first_line.unwrap_or(0.0),
+        }
+    }
+}
+
+/// The `w:tabs` of a paragraph or its style: the stops it sets and the
+/// positions of those it clears of what it inherits
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TabProperties {
+    set: Vec<TabStop>,
+    cleared: Vec<f32>,
+}
+
+impl TabProperties {
+    /// Reads `<w:tab w:val="right" w:leader="dot" w:pos="9350"/>`, the
+    /// position in twentieths of a point; bar tabs draw a line rather than
+    /// stop the text and are left out
+    pub fn read(&mut self, e: &BytesStart) {
+        let Some(position) = attribute_value(e, b"w:pos")
+            .and_then(|value| value.trim().parse::<f32>().ok())
+            .filter(|value| value.is_finite())
+            .map(|value| (value / 20.0).clamp(-MAX_INDENT, MAX_INDENT))
+        else {
+            return;
+        };
+        let alignment = match attribute_value(e, b"w:val").as_deref() {
+            Some("clear") => {
+                self.cleared.push(position);
+                return;
+            }
+            Some("bar") => return,
+            Some("center") => TabAlignment::Center,
+            Some("right") | Some("end") => TabAlignment::Right,
+            Some("decimal") => TabAlignment::Decimal,
+            _ => TabAlignment::Left,
+        };
+        let leader = match attribute_value(e, b"w:leader").as_deref() {
+            Some("dot") => TabLeader::Dot,
+            Some("hyphen") => TabLeader::Hyphen,
+            Some("underscore") | Some("heavy") => TabLeader::Underscore,
+            Some("middleDot") => TabLeader::MiddleDot,
+            _ => TabLeader::None,
+        };
+        if self.set.len() < MAX_TAB_STOPS {
+            self.set.push(TabStop {
+                position,
+                alignment,
+                leader,
+            });
+        }
+    }
+
+    /// Puts these over the stops `stops` inherits, a stop at the position
+    /// of one replaces it
+    pub fn apply(&self, stops: &mut Vec<TabStop>) {
+        let same = |a: f32, b: f32| (a - b).abs() < 0.05;
+        stops.retain(|stop| {
+            let replaced = self.set.iter().any(|set| same(set.position, stop.position));
+            !replaced && !self.cleared.iter().any(|&at| same(at, stop.position))
+        });
+        stops.extend(&self.set);
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        stops.truncate(MAX_TAB_STOPS);
+    }
+}
+
+/// Borders a table style or a table sets, by edge. Some(None) takes a
+/// border away, None leaves it to what comes before
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EdgeBorders {
+    pub top: Option<Option<Border>>,
+    pub bottom: Option<Option<Border>>,
+    pub left: Option<Option<Border>>,
+    pub right: Option<Option<Border>>,
+    /// between the rows of the table or part
+    pub inside_h: Option<Option<Border>>,
+    /// between its columns
+    pub inside_v: Option<Option<Border>>,
+}
+
+impl EdgeBorders {
+    /// Takes what `base` says wherever these say nothing
+    fn inherit(&mut self, base: &EdgeBorders) {
+        self.top = self.top.or(base.top);
+        self.bottom = self.bottom.or(base.bottom);
+        self.left = self.left.or(base.left);
+        self.right = self.right.or(base.right);
+        self.inside_h = self.inside_h.or(base.inside_h);
+        self.inside_v = self.inside_v.or(base.inside_v);
+    }
+
+    /// Reads the border element `name` of a `w:tblBorders` or `w:tcBorders`
+    pub fn read(&mut self, e: &BytesStart, name: &[u8]) {
+        let edge = match name {
+            b"w:top" => &mut self.top,
+            b"w:bottom" => &mut self.bottom,
+            b"w:left" | b"w:start" => &mut self.left,
+            b"w:right" | b"w:end" => &mut self.right,
+            b"w:insideH" => &mut self.inside_h,
+            b"w:insideV" => &mut self.inside_v,
+            _ => return,
+        };
+        *edge = Some(read_border(e));
+    }
+}
+
+/// What a table style gives the cells of the whole table or of one of its
+/// parts, None where it says nothing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CellFormat {
+    pub run: RunProperties,
+    /// Some(None) for no background
+    pub shading: Option<Option<[u8; 3]>>,
+    pub borders: EdgeBorders,
+}
+
+impl CellFormat {
+    fn inherit(&mut self, base: &CellFormat) {
+        self.run.inherit(&base.run);
+        self.shading = self.shading.or(base.shading);
+        self.borders.inherit(&base.borders);
+    }
+}
+
+/// `w:tblLook`, the parts of a table its style formats differently
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableLook {
+    pub first_row: bool,
+    pub last_row: bool,
+    pub first_column: bool,
+    pub last_column: bool,
+    pub no_h_band: bool,
+    pub no_v_band: bool,
+}
+
+impl TableLook {
+    /// Reads `<w:tblLook w:firstRow="1" .. w:noVBand="1"/>`, or the older
+    /// bit mask `w:val="04A0"`
+    pub fn read(e: &BytesStart) -> Self {
+        let mask = attribute_value(e, b"w:val")
+            .and_then(|value| u32::from_str_radix(&value, 16).ok())
+            .unwrap_or(0);
+        let flag = |key: &[u8], bit: u32| match attribute_value(e, key) {
+            Some(value) => matches!(value.as_str(), "1" | "true" | "on"),
+            None => mask & bit != 0,
+        };
+        TableLook {
+            first_row: flag(b"w:firstRow", 0x20),
+            last_row: flag(b"w:lastRow", 0x40),
+            first_column: flag(b"w:firstColumn", 0x80),
+            last_column: flag(b"w:lastColumn", 0x100),
+            no_h_band: flag(b"w:noHBand", 0x200),
+            no_v_band: flag(b"w:noVBand", 0x400),
+        }
+    }
+}
+
+/// Where a cell is in its table, as (index, count) of its row and column
+#[derive(Clone, Copy, Debug)]
+pub struct CellPosition {
+    pub row: (usize, usize),
+    pub column: (usize, usize),
+}
+
+/// A table style with what the styles it is based on add
+#[derive(Clone, Debug, Default)]
+pub struct TableStyle {
+    whole: CellFormat,
+    /// by `w:tblStylePr w:type`, e.g. `firstRow` or `band1Horz`
+    parts: HashMap<String, CellFormat>,
+    row_band: Option<usize>,
+    column_band: Option<usize>,
+}
+
+/// Cells a table style part covers, as first and last row and first and
+/// last column; its outer borders go along the edges of the rectangle and
+/// its inside borders between the cells in it
+type Cells = (usize, usize, usize, usize);
+
+impl TableStyle {
+    /// The formatting of the cell `at` in a table with `look` and its own
+    /// `borders`: the whole table, then the bands, the first and last
+    /// columns, the first and last rows and the corner cells, each over the
+    /// ones before, as Word applies them
+    pub fn cell(&self, look: &TableLook, borders: &EdgeBorders, at: CellPosition) -> CellFormat {
+        let (row, rows) = at.row;
+        let (column, columns) = at.column;
+        let (last_row, last_column) = (rows.saturating_sub(1), columns.saturating_sub(1));
+        let everything = (0, last_row, 0, last_column);
+        let whole_row = (row, row, 0, last_column);
+        let whole_column = (0, last_row, column, column);
+        let this_cell = (row, row, column, column);
+
+        let mut format = CellFormat::default();
+        let mut apply = |part: &CellFormat, (top, bottom, left, right): Cells| {
+            let mut run = part.run.clone();
+            run.inherit(&format.run);
+            format.run = run;
+            format.shading = part.shading.or(format.shading);
+            let edges = &part.borders;
+            let edge = |outer: bool, side: Option<Option<Border>>, inside| {
+                if outer {
+                    side
+                } else {
+                    inside
+                }
+            };
+            let cell = &mut format.borders;
+            cell.top = edge(row == top, edges.top, edges.inside_h).or(cell.top);
+            cell.bottom = edge(row == bottom, edges.bottom, edges.inside_h).or(cell.bottom);
+            cell.left = edge(column == left, edges.left, edges.inside_v).or(cell.left);
+            cell.right = edge(column == right, edges.right, edges.inside_v).or(cell.right);
+        };
+        apply(&self.whole, everything);
+        let table = CellFormat {
+            borders: *borders,
+            ..CellFormat::default()
+        };
+        apply(&table, everything);
+
+        // bands leave out the first row and column when those are formatted
+        let band = |index: usize, skip: bool, size: Option<usize>| {
+            let index = if skip { index.checked_sub(1)? } else { index };
+            Some((index / size.unwrap_or(1).max(1)).is_multiple_of(2))
+        };
+        let mut parts: Vec<(&str, Cells)> = Vec::new();
+        if !look.no_v_band
+            && let Some(odd) = band(column, look.first_column, self.column_band)
+        {
+            parts.push((if odd { "band1Vert" } else { "band2Vert" }, whole_column));
+        }
+        if !look.no_h_band
+            && let Some(odd) = band(row, look.first_row, self.row_band)
+        {
+            parts.push((if odd { "band1Horz" } else { "band2Horz" }, whole_row));
+        }
+        let (first, last) = (look.first_row && row == 0, look.last_row && row == last_row);
+        let (left, right) = (
+            look.first_column && column == 0,
+            look.last_column && column == last_column,
+        );
+        let conditions = [
+            ("firstCol", left, whole_column),
+            ("lastCol", right, whole_column),
+            ("firstRow", first, whole_row),
+            ("lastRow", last, whole_row),
+            ("neCell", first && right, this_cell),
+            ("nwCell", first && left, this_cell),
+            ("seCell", last && right, this_cell),
+            ("swCell", last && left, this_cell),
+        ];
+        parts.extend(
+            conditions
+                .into_iter()
+                .filter(|&(_, applies, _)| applies)
+                .map(|(name, _, cells)| (name, cells)),
+        );
+        for (name, cells) in parts {
+            if let Some(part) = self.parts.get(name) {
+                apply(part, cells);
+            }
+        }
+        format
+    }
+}
+
+/// A style of `word/styles.xml`
+#[derive(Clone, Debug, Default)]
+struct Style {
+    based_on: Option<String>,
+    run: RunProperties,
+    /// what a table style gives the cells of the whole table, but for
+    /// their run formatting, which is `run`
+    cells: CellFormat,
+    parts: HashMap<String, CellFormat>,
+    row_band: Option<usize>,
+    column_band: Option<usize>,
+    /// the `w:numId` and `w:ilvl` of the `w:numPr` of a paragraph style
+    list: Option<String>,
+    level: Option<usize>,
+    indent: IndentProperties,
+    /// `w:jc` of its `w:pPr`
+    alignment: Option<Alignment>,
+    tabs: TabProperties,
+}
+
+/// The styles part, as far as run and table cell formatting goes
+#[derive(Clone, Debug, Default)]
+pub struct Styles {
+    /// `w:docDefaults`
+    defaults: RunProperties,
+    /// by `w:styleId`
+    styles: HashMap<String, Style>,
+    /// the paragraph style of paragraphs without a `w:pStyle`, usually Normal
+    default_paragraph: Option<String>,
+    /// the table style of tables without a `w:tblStyle`, usually Table Normal
+    default_table: Option<String>,
+}
+
+impl Styles {
+    /// What style `id` and the ones it is based on say, the nearest first
+    fn chain(&self, id: &str) -> RunProperties {
+        let mut run = RunProperties::default();
+        let mut next = Some(id);
+        for _ in 0..MAX_STYLE_DEPTH {
+            let Some(style) = next.and_then(|id| self.styles.get(id)) else {
+                break;
+            };
+            run.inherit(&style.run);
+            next = style.based_on.as_deref();
+        }
+        run
+    }
+
+    /// The list (`w:numId`) and level paragraphs of `paragraph_style`, or
+    /// of the default paragraph style, are in, through the styles it is
+    /// based on
+    pub fn numbering(&self, paragraph_style: Option<&str>) -> (Option<String>, Option<usize>) {
+        let (mut list, mut level) = (None, None);
+        let mut next = paragraph_style.or(self.default_paragraph.as_deref());
+        for _ in 0..MAX_STYLE_DEPTH {
+            let Some(style) = next.and_then(|id| self.styles.get(id)) else {
+                break;
+            };
+            list = list.or_else(|| style.list.clone());
+            level = level.or(style.level);
+            next = style.based_on.as_deref();
+        }
+        (list, level)
+    }
+
+    /// The indents of paragraphs of `paragraph_style`, or of the default
+    /// paragraph style, through the styles it is based on
+    pub fn indent(&self, paragraph_style: Option<&str>) -> IndentProperties {
+        let mut indent = IndentProperties::default();
+        let mut next = paragraph_style.or(self.default_paragraph.as_deref());
+        for _ in 0..MAX_STYLE_DEPTH {
+            let Some(style) = next.and_then(|id| self.styles.get(id)) else {
+                break;
+            };
+            indent.inherit(&style.indent);
+            next = style.based_on.as_deref();
+        }
+        indent
+    }
+
+    /// How paragraphs of `paragraph_style`, or of the default paragraph
+    /// style, are aligned, through the styles it is based on
+    pub fn alignment(&self, paragraph_style: Option<&str>) -> Option<Alignment> {
+        let mut next = paragraph_style.or(self.default_paragraph.as_deref());
+        for _ in 0..MAX_STYLE_DEPTH {
+            let style = next.and_then(|id| self.styles.get(id))?;
+            if style.alignment.is_some() {
+                return style.alignment;
+            }
+            next = style.based_on.as_deref();
+        }
+        None
+    }
+
+    /// The tab stops of paragraphs of `paragraph_style`, or of the default
+    /// paragraph style, through the styles it is based on
+    pub fn tabs(&self, paragraph_style: Option<&str>) -> Vec<TabStop> {
+        let mut chain = Vec::new();
+        let mut next = paragraph_style.or(self.default_paragraph.as_deref());
+        for _ in 0..MAX_STYLE_DEPTH {
+            let Some(style) = next.and_then(|id| self.styles.get(id)) else {
+                break;
+            };
+            chain.push(&style.tabs);
+            next = style.based_on.as_deref();
+        }
+        let mut stops = Vec::new();
+        for tabs in chain.into_iter().rev() {
+            tabs.apply(&mut stops);
+        }
+        stops
+    }
+
+    /// The table style `id`, or the default one, through the styles it is based on
+    pub fn table_style(&self, id: Option<&str>) -> TableStyle {
+        let mut table = TableStyle::default();
+        let mut next = id.or(self.default_table.as_deref());
+        for _ in 0..MAX_STYLE_DEPTH {
+            let Some(style) = next.and_then(|id| self.styles.get(id)) else {
+                break;
+            };
+            let whole = CellFormat {
+                run: style.run.clone(),
+                ..style.cells.clone()
+            };
+            table.whole.inherit(&whole);
+            for (name, part) in &style.parts {
+                table.parts.entry(name.clone()).or_default().inherit(part);
+            }
+            table.row_band = table.row_band.or(style.row_band);
+            table.column_band = table.column_band.or(style.column_band);
+            next = style.based_on.as_deref();
+        }
+        table
+    }
+
+    /// The formatting of a run with `direct` formatting and `run_style` in a
+    /// paragraph of `paragraph_style`, in a table cell the table style gives
+    /// `table`. Direct formatting wins over the paragraph and character
+    /// styles, which win over the table style and then the document
+    /// defaults; bold and italic set in both styles toggle each other off,
+    /// as in Word
+    pub fn resolve(
+        &self,
+        paragraph_style: Option<&str>,
+        run_style: Option<&str>,
+        table: &RunProperties,
+        direct: &RunProperties,
+    ) -> RunProperties {
+        let paragraph = paragraph_style
+            .or(self.default_paragraph.as_deref())
+            .map(|id| self.chain(id))
+            .unwrap_or_default();
+        let character = run_style.map(|id| self.chain(id)).unwrap_or_default();
+        let toggle = |paragraph: Option<bool>, character: Option<bool>| match (paragraph, character)
+        {
+            (Some(a), Some(b)) => Some(a ^ b),
+            (a, b) => a.or(b),
+        };
+        let mut run = direct.clone();
+        run.inherit(&RunProperties {
+            font: character.font.or(paragraph.font),
+            bold: toggle(paragraph.bold, character.bold),
+            italic: toggle(paragraph.italic, character.italic),
+        });
+        run.inherit(table);
+        run.inherit(&self.defaults);
+        run
+    }
+}
+
+/// Where `read_styles` is in the styles part
+#[derive(Default)]
+struct StylesState {
+    styles: Styles,
+    in_defaults: bool,                  // in `w:rPrDefault`
+    style: Option<(String, Style)>,     // the `w:style` being read
+    part: Option<(String, CellFormat)>, // the `w:tblStylePr` being read
+    in_run_props: bool,
+    in_paragraph_props: bool, // in the `w:pPr` of a style, not of a table style part
+    in_borders: bool,         // in a `w:tblBorders` or `w:tcBorders`
+    in_cell_props: bool,      // in a `w:tcPr`
+}
+
+impl StylesState {
+    /// The cell formatting being read, of a table style part or the whole table
+    fn cells(&mut self) -> Option<&mut CellFormat> {
+        match (&mut self.part, &mut self.style) {
+            (Some((_, part)), _) => Some(part),
+            (None, Some((_, style))) => Some(&mut style.cells),
+            (None, None) => None,
+        }
+    }
+
+    /// The run formatting being read
+    fn run(&mut self) -> Option<&mut RunProperties> {
+        if self.in_defaults {
+            return Some(&mut self.styles.defaults);
+        }
+        match (&mut self.part, &mut self.style) {
+            (Some((_, part)), _) => Some(&mut part.run),
+            (None, Some((_, style))) => Some(&mut style.run),
+            (None, None) => None,
+        }
+    }
+}
+
+fn start_style_element(state: &mut StylesState, e: &BytesStart, empty: bool) {
+    let name = e.name();
+    let name = name.as_ref();
+    match name {
+        b"w:rPrDefault" if !empty => state.in_defaults = true,
+        b"w:style" if !empty => {
+            let Some(id) = attribute_value(e, b"w:styleId") else {
+                return;
+            };
+            let default = attribute_value(e, b"w:default")
+                .is_some_and(|value| matches!(value.as_str(), "1" | "true"));
+            let styles = &mut state.styles;
+            let default_of = match attribute_value(e, b"w:type").as_deref() {
+                Some("paragraph") => Some(&mut styles.default_paragraph),
+                Some("table") => Some(&mut styles.default_table),
+                _ => None,
+            };
+            if default
+                && let Some(slot) = default_of
+                && slot.is_none()
+            {
+                *slot = Some(id.clone());
+            }
+            state.style = Some((id, Style::default()));
+        }
+        b"w:basedOn" => {
+            if let Some((_, style)) = &mut state.style {
+                style.based_on = attribute_value(e, b"w:val");
+            }
+        }
+        b"w:tblStylePr" if !empty => {
+            let kind = attribute_value(e, b"w:type").unwrap_or_default();
+            state.part = Some((kind, CellFormat::default()));
+        }
+        b"w:tblStyleRowBandSize" | b"w:tblStyleColBandSize" => {
+            let size = attribute_value(e, b"w:val").and_then(|value| value.parse().ok());
+            if let Some((_, style)) = &mut state.style {
+                match name {
+                    b"w:tblStyleRowBandSize" => style.row_band = size,
+                    _ => style.column_band = size,
+                }
+            }
+        }
+        b"w:numId" | b"w:ilvl" => {
+            if let Some((_, style)) = &mut state.style {
+                let value = attribute_value(e, b"w:val");
+                match name {
+                    b"w:numId" => style.list = value,
+                    _ => style.level = value.and_then(|value| value.trim().parse().ok()),
+                }
+            }
+        }
+        b"w:ind" => {
+            if let Some((_, style)) = &mut state.style {
+                style.indent.read(e);
+            }
+        }
+        b"w:pPr" if !empty && state.part.is_none() => state.in_paragraph_props = true,
+        b"w:jc" if state.in_paragraph_props => {
+            if let Some((_, style)) = &mut state.style {
+                style.alignment = read_alignment(e);
+            }
+        }
+        b"w:tab" if state.in_paragraph_props => {
+            if let Some((_, style)) = &mut state.style {
+                style.tabs.read(e);
+            }
+        }
+        b"w:rPr" if !empty => state.in_run_props = true,
+        b"w:tcPr" if !empty => state.in_cell_props = true,
+        b"w:tblBorders" | b"w:tcBorders" if !empty => state.in_borders = true,
+        _ if state.in_run_props => {
+            if let Some(run) = state.run() {
+                run.read(e, name);
+            }
+        }
+        _ if state.in_borders => {
+            if let Some(cells) = state.cells() {
+                cells.borders.read(e, name);
+            }
+        }
+        b"w:shd" if state.in_cell_props => {
+            if let Some(cells) = state.cells() {
+                cells.shading = Some(read_shading(e));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn end_style_element(state: &mut StylesState, name: &[u8]) {
+    match name {
+        b"w:rPr" => state.in_run_props = false,
+        b"w:pPr" => state.in_paragraph_props = false,
+        b"w:tcPr" => state.in_cell_props = false,
+        b"w:rPrDefault" => state.in_defaults = false,
+        b"w:tblBorders" | b"w:tcBorders" => state.in_borders = false,
+        b"w:tblStylePr" => {
+            if let (Some((kind, part)), Some((_, style))) = (state.part.take(), &mut state.style) {
+                style.parts.insert(kind, part);
+            }
+        }
+        b"w:style" => {
+            if let Some((id, style)) = state.style.take() {
+                state.styles.styles.insert(id, style);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads the document defaults and what every style in `xml`, the styles
+/// part, gives runs and table cells
+pub fn read_styles(xml: &str, errors: &mut XmlErrors) -> Result<Styles> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut state = StylesState::default();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => start_style_element(&mut state, &e, false),
+            Ok(Event::Empty(e)) => start_style_element(&mut state, &e, true),
+            Ok(Event::End(e)) => end_style_element(&mut state, e.name().as_ref()),
+            Err(e) => errors.recover(e, reader.error_position())?,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(state.styles)
+}
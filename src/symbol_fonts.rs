@@ -0,0 +1,120 @@
+//! Symbol characters Word writes as `w:sym`, mapped to Unicode
+//!
+//! The symbol fonts put their glyphs at character codes that stand for
+//! letters in any other font, Word stores them shifted into the private use
+//! area at U+F000.
+
+/// The Symbol font from 0x20 on, '\0' where it has nothing useful
+const SYMBOL: [char; 224] = [
+    ' ', '!', '∀', '#', '∃', '%', '&', '∋', '(', ')', '∗', '+', ',', '−', '.', '/', // 0x20
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?', // 0x30
+    '≅', 'Α', 'Β', 'Χ', 'Δ', 'Ε', 'Φ', 'Γ', 'Η', 'Ι', 'ϑ', 'Κ', 'Λ', 'Μ', 'Ν', 'Ο', // 0x40
+    'Π', 'Θ', 'Ρ', 'Σ', 'Τ', 'Υ', 'ς', 'Ω', 'Ξ', 'Ψ', 'Ζ', '[', '∴', ']', '⊥', '_', // 0x50
+    '\0', 'α', 'β', 'χ', 'δ', 'ε', 'φ', 'γ', 'η', 'ι', 'ϕ', 'κ', 'λ', 'μ', 'ν', 'ο', // 0x60
+    'π', 'θ', 'ρ', 'σ', 'τ', 'υ', 'ϖ', 'ω', 'ξ', 'ψ', 'ζ', '{', '|', '}', '∼', '\0', // 0x70
+    '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+    '\0', // 0x80
+    '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+    '\0', // 0x90
+    '€', 'ϒ', '′', '≤', '⁄', '∞', 'ƒ', '♣', '♦', '♥', '♠', '↔', '←', '↑', '→', '↓', // 0xA0
+    '°', '±', '″', '≥', '×', '∝', '∂', '•', '÷', '≠', '≡', '≈', '…', '\0', '\0', '↵', // 0xB0
+    'ℵ', 'ℑ', 'ℜ', '℘', '⊗', '⊕', '∅', '∩', '∪', '⊃', '⊇', '⊄', '⊂', '⊆', '∈', '∉', // 0xC0
+    '∠', '∇', '®', '©', '™', '∏', '√', '⋅', '¬', '∧', '∨', '⇔', '⇐', '⇑', '⇒', '⇓', // 0xD0
+    '◊', '〈', '®', '©', '™', '∑', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+    '\0', // 0xE0
+    '\0', '〉', '∫', '⌠', '\0', '⌡', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+    '\0', // 0xF0
+];
+
+/// The Wingdings glyphs documents commonly use, as bullets, check boxes and
+/// arrows; the pictograms without a Unicode counterpart are left out
+const WINGDINGS: [(u8, char); 47] = [
+    (0x21, '✏'),
+    (0x22, '✂'),
+    (0x28, '☎'),
+    (0x3F, '✍'),
+    (0x41, '✌'),
+    (0x43, '👍'),
+    (0x44, '👎'),
+    (0x4A, '☺'),
+    (0x4C, '☹'),
+    (0x4E, '☠'),
+    (0x52, '☼'),
+    (0x54, '❄'),
+    (0x58, '✠'),
+    (0x5B, '☯'),
+    (0x6C, '●'),
+    (0x6D, '❍'),
+    (0x6E, '■'),
+    (0x6F, '□'),
+    (0x70, '◻'),
+    (0x71, '❑'),
+    (0x72, '❒'),
+    (0x73, '⬧'),
+    (0x74, '⧫'),
+    (0x75, '◆'),
+    (0x76, '❖'),
+    (0x77, '⬥'),
+    (0x9F, '•'),
+    (0xA1, '○'),
+    (0xA7, '▪'),
+    (0xA8, '☐'),
+    (0xAB, '★'),
+    (0xD8, '➢'),
+    (0xDF, '←'),
+    (0xE0, '→'),
+    (0xE1, '↑'),
+    (0xE2, '↓'),
+    (0xE8, '➔'),
+    (0xEF, '⇦'),
+    (0xF0, '⇨'),
+    (0xF1, '⇧'),
+    (0xF2, '⇩'),
+    (0xF3, '⬄'),
+    (0xF4, '⇳'),
+    (0xFB, '✗'),
+    (0xFC, '✓'),
+    (0xFD, '☒'),
+    (0xFE, '☑'),
+];
+
+/// The character `w:sym w:font=".." w:char=".."` stands for, `code` as the
+/// hex of `w:char`. None for symbol fonts other than Symbol and Wingdings
+/// and for glyphs without a Unicode counterpart
+pub fn symbol_char(font: &str, code: u32) -> Option<char> {
+    let symbol_font = |name: &str| font.trim().eq_ignore_ascii_case(name);
+    // codes in the private use area are the font's own
+    let own = match code {
+        0xF020..=0xF0FF => Some((code - 0xF000) as u8),
+        0x20..=0xFF => Some(code as u8),
+        _ => None,
+    };
+    if symbol_font("Symbol") {
+        let c = SYMBOL[own?.checked_sub(0x20)? as usize];
+        (c != '\0').then_some(c)
+    } else if symbol_font("Wingdings") {
+        let own = own?;
+        WINGDINGS.iter().find(|(at, _)| *at == own).map(|&(_, c)| c)
+    } else if (0xF000..=0xF0FF).contains(&code) {
+        // other symbol fonts, e.g. Webdings
+        None
+    } else {
+        // a plain character of a text font
+        char::from_u32(code).filter(|c| !c.is_control())
+    }
+}
+
+/// Whether `font` is one of the symbol fonts, which put their glyphs at
+/// the codes of letters
+pub fn is_symbol_font(font: &str) -> bool {
+    let font = font.trim();
+    [
+        "Symbol",
+        "Wingdings",
+        "Wingdings 2",
+        "Wingdings 3",
+        "Webdings",
+    ]
+    .iter()
+    .any(|name| font.eq_ignore_ascii_case(name))
+}
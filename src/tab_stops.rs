@@ -0,0 +1,29 @@
+use super::document_model::{TabAlignment, TabStop};
+
+/// Past the custom tab stops, tabs go to every half inch, Word's default
+/// `w:defaultTabStop`
+const DEFAULT_TAB_STOP: f32 = 36.0;
+
+/// Where a tab `at` points from the left edge of the column takes the text
+/// after it, `next` up to the next tab, measured by `width`: the first
+/// stop past `at`, where that text starts, is centered, ends or has its
+/// decimal point, never going back before `at`; with no stop past it, the
+/// next default stop. Returns how far the tab reaches and the stop
+pub fn tab_advance<'a>(
+    stops: &'a [TabStop],
+    at: f32,
+    next: &str,
+    width: impl Fn(&str) -> f32,
+) -> (f32, Option<&'a TabStop>) {
+    let Some(stop) = stops.iter().find(|stop| stop.position > at + 0.01) else {
+        let next_stop = ((at + 0.01) / DEFAULT_TAB_STOP).floor() + 1.0;
+        return (next_stop * DEFAULT_TAB_STOP - at, None);
+    };
+    let before = match stop.alignment {
+        TabAlignment::Left => 0.0,
+        TabAlignment::Center => width(next) / 2.0,
+        TabAlignment::Right => width(next),
+        TabAlignment::Decimal => width(next.split('.').next().unwrap_or_default()),
+    };
+    ((stop.position - before - at).max(0.0), Some(stop))
+}
@@ -0,0 +1,155 @@
+use super::document_model::{Table, Width};
+
+/// How wide the content of a cell can be, in points: `min` with every line
+/// broken, as wide as its longest word, and `max` with none
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContentWidth {
+    pub min: f32,
+    pub max: f32,
+}
+
+/// What a grid column needs and asks for, with the cell margins
+#[derive(Clone, Copy, Debug, Default)]
+struct Column {
+    min: f32,
+    max: f32,
+    preferred: Option<f32>,
+}
+
+/// `width` in points, a percentage of `whole`
+fn points(width: Width, whole: f32) -> f32 {
+    match width {
+        Width::Points(points) => points,
+        Width::Percent(percent) => whole * percent / 100.0,
+    }
+}
+
+/// The number of grid columns of `table`, those of its grid or of its
+/// widest row when that is wider
+pub fn column_count(table: &Table) -> usize {
+    table
+        .rows
+        .iter()
+        .map(|row| row.cells.iter().map(|cell| cell.span.max(1)).sum())
+        .max()
+        .unwrap_or(0)
+        .max(table.grid.len())
+}
+
+/// The columns of `table` with what the cells in them need, `content` by
+/// row and cell. A cell's `w:tcW` is preferred over the grid; cells
+/// spanning several columns widen them evenly where they need more than
+/// the columns give them
+fn columns(table: &Table, whole: f32, content: &[Vec<ContentWidth>]) -> Vec<Column> {
+    let mut columns = vec![Column::default(); column_count(table)];
+    let mut asked: Vec<Option<f32>> = vec![None; columns.len()];
+    let mut spanning = Vec::new();
+    for (row, widths) in table.rows.iter().zip(content) {
+        let mut at = 0;
+        for (cell, width) in row.cells.iter().zip(widths) {
+            let span = cell.span.max(1);
+            let margins = cell.margins.left + cell.margins.right;
+            let (min, max) = (width.min + margins, width.max + margins);
+            if span == 1 {
+                let column = &mut columns[at];
+                column.min = column.min.max(min);
+                column.max = column.max.max(max);
+                if let Some(width) = cell.width.map(|width| points(width, whole)) {
+                    asked[at] = Some(asked[at].map_or(width, |asked| asked.max(width)));
+                }
+            } else {
+                spanning.push((at, span, min, max));
+            }
+            at += span;
+        }
+    }
+    for (index, (column, asked)) in columns.iter_mut().zip(asked).enumerate() {
+        let grid = table.grid.get(index).copied().filter(|w| *w > 0.0);
+        column.preferred = asked.or(grid);
+    }
+    for (at, span, min, max) in spanning {
+        let covered = &mut columns[at..at + span];
+        let (have_min, have_max) = covered.iter().fold((0.0, 0.0), |(min, max), column| {
+            (min + column.min, max + column.max)
+        });
+        for column in covered {
+            column.min += (min - have_min).max(0.0) / span as f32;
+            column.max += (max - have_max).max(0.0) / span as f32;
+        }
+    }
+    columns
+}
+
+/// How narrow and how wide `table` can be laid out, for a table nested in
+/// a cell of another one
+pub fn table_content(table: &Table, content: &[Vec<ContentWidth>]) -> ContentWidth {
+    if let Some(Width::Points(width)) = table.width {
+        return ContentWidth {
+            min: width,
+            max: width,
+        };
+    }
+    columns(table, 0.0, content)
+        .iter()
+        .fold(ContentWidth::default(), |total, column| ContentWidth {
+            min: total.min + column.min,
+            max: total.max + column.preferred.unwrap_or(column.max).max(column.min),
+        })
+}
+
+/// The widths of the grid columns of `table` laid out in `available`
+/// points, `content` by row and cell.
+///
+/// A fixed layout keeps the widths the table asks for and splits what is
+/// left between the columns without one. Otherwise columns get their
+/// preferred width, or the width of their content without a line broken,
+/// but never less than their longest word; when that makes the table too
+/// wide each column gives up room in proportion to what it has over its
+/// minimum, and a table asking for more width shares it out by column width
+pub fn column_widths(table: &Table, available: f32, content: &[Vec<ContentWidth>]) -> Vec<f32> {
+    let target = table
+        .width
+        .map(|width| points(width, available).min(available));
+    let columns = columns(table, target.unwrap_or(available), content);
+    if columns.is_empty() {
+        return Vec::new();
+    }
+    let mut widths: Vec<f32> = if table.fixed {
+        let known: f32 = columns.iter().filter_map(|column| column.preferred).sum();
+        let unknown = columns.iter().filter(|c| c.preferred.is_none()).count();
+        let share = (target.unwrap_or(available) - known).max(0.0) / unknown.max(1) as f32;
+        columns
+            .iter()
+            .map(|column| column.preferred.unwrap_or(share))
+            .collect()
+    } else {
+        columns
+            .iter()
+            .map(|column| column.preferred.unwrap_or(column.max).max(column.min))
+            .collect()
+    };
+    let total: f32 = widths.iter().sum();
+    if total <= 0.0 {
+        let share = target.unwrap_or(available) / widths.len() as f32;
+        return vec![share; widths.len()];
+    }
+    let goal = target.unwrap_or(total.min(available));
+    if total > goal && !table.fixed {
+        let least: f32 = columns.iter().map(|column| column.min).sum();
+        if least >= goal {
+            return columns
+                .iter()
+                .map(|column| column.min * goal / least.max(f32::EPSILON))
+                .collect();
+        }
+        let slack = total - least;
+        for (width, column) in widths.iter_mut().zip(&columns) {
+            *width = column.min + (*width - column.min) * (goal - least) / slack;
+        }
+    } else if total != goal {
+        for width in &mut widths {
+            *width *= goal / total;
+        }
+    }
+    widths
+}
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, bail, Result};
+use quick_xml::events::BytesText;
+use std::io;
+use std::sync::Arc;
+use tracing::debug;
+
+use super::report::Warning;
+
+/// More errors than this in one part is no longer a damaged document worth reading on
+const MAX_ERRORS: usize = 1000;
+
+/// Malformed XML met while reading one part of the DOCX
+///
+/// Outside strict mode the parser skips past each error and carries on with
+/// the next element it can read, the errors end up in a single warning for the
+/// part. Content swallowed by the broken markup, e.g. everything after an
+/// unterminated attribute value, is lost.
+pub(crate) struct XmlErrors {
+    part: String,
+    strict: bool,
+    count: usize,
+    first: Option<String>,
+}
+
+impl XmlErrors {
+    pub fn new(part: &str, strict: bool) -> Self {
+        Self {
+            part: part.to_string(),
+            strict,
+            count: 0,
+            first: None,
+        }
+    }
+
+    /// Ok when reading can carry on past `err` at byte `position`
+    pub fn recover(&mut self, err: quick_xml::Error, position: u64) -> Result<()> {
+        match err {
+            // a truncated or corrupt entry, reading on would hit it again
+            // unwrapped where possible so a `LimitExceeded` inside stays recognisable
+            quick_xml::Error::Io(e) => Err(Arc::try_unwrap(e)
+                .unwrap_or_else(|e| io::Error::new(e.kind(), e))
+                .into()),
+            err if self.strict => Err(anyhow!(err).context(format!("at byte {}", position))),
+            err => {
+                debug!(
+                    part = self.part.as_str(),
+                    position, "skipping malformed XML: {}", err
+                );
+                self.count += 1;
+                if self.count > MAX_ERRORS {
+                    bail!(
+                        "more than {} XML errors, the last at byte {}",
+                        MAX_ERRORS,
+                        position
+                    );
+                }
+                self.first
+                    .get_or_insert_with(|| format!("at byte {}: {}", position, err));
+                Ok(())
+            }
+        }
+    }
+
+    /// The text with its entities replaced, unknown ones are kept as they are written
+    pub fn unescape(&mut self, text: &BytesText, position: u64) -> Result<String> {
+        match text.unescape() {
+            Ok(text) => Ok(text.into_owned()),
+            Err(e) => {
+                self.recover(e, position)?;
+                Ok(String::from_utf8_lossy(text).into_owned())
+            }
+        }
+    }
+
+    /// Adds the warning for the errors skipped, if there were any
+    pub fn finish(self, warnings: &mut Vec<Warning>) {
+        if let Some(first_error) = self.first {
+            warnings.push(Warning::MalformedXml {
+                part: self.part,
+                count: self.count,
+                first_error,
+            });
+        }
+    }
+}